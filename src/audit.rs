@@ -0,0 +1,156 @@
+use std::collections::HashSet;
+
+use gltf;
+
+use crate::importdata::ImportData;
+
+/// Resources that are declared but not used (`--audit`), as glTF indices
+#[derive(Debug, Default, PartialEq)]
+pub struct Audit {
+    pub materials: Vec<usize>,
+    pub textures: Vec<usize>,
+    pub images: Vec<usize>,
+    /// not used by any primitive, animation or skin
+    pub accessors: Vec<usize>,
+    /// not used by any accessor or image
+    pub buffer_views: Vec<usize>,
+    /// no mesh, camera, light or children (and no skin joint or animation target)
+    pub empty_nodes: Vec<usize>,
+}
+
+impl Audit {
+    pub fn from_import(imp: &ImportData) -> Audit {
+        let doc = &imp.doc;
+        let mut materials = HashSet::new();
+        let mut textures = HashSet::new();
+        let mut images = HashSet::new();
+        let mut accessors = HashSet::new();
+        let mut views = HashSet::new();
+        let mut referenced_nodes = HashSet::new();
+
+        for primitive in doc.meshes().flat_map(|mesh| mesh.primitives()) {
+            accessors.extend(primitive.attributes().map(|(_, accessor)| accessor.index()));
+            accessors.extend(primitive.indices().map(|accessor| accessor.index()));
+            for target in primitive.morph_targets() {
+                accessors.extend(target.positions().map(|accessor| accessor.index()));
+                accessors.extend(target.normals().map(|accessor| accessor.index()));
+                accessors.extend(target.tangents().map(|accessor| accessor.index()));
+            }
+            materials.extend(primitive.material().index());
+        }
+        // KHR_materials_variants mappings
+        for mesh in imp.json["meshes"].as_array().map(|m| m.as_slice()).unwrap_or_default() {
+            for primitive in mesh["primitives"].as_array().map(|p| p.as_slice()).unwrap_or_default() {
+                let mappings = &primitive["extensions"]["KHR_materials_variants"]["mappings"];
+                for mapping in mappings.as_array().map(|m| m.as_slice()).unwrap_or_default() {
+                    materials.extend(mapping["material"].as_u64().map(|m| m as usize));
+                }
+            }
+        }
+        for channel in doc.animations().flat_map(|animation| animation.channels()) {
+            accessors.insert(channel.sampler().input().index());
+            accessors.insert(channel.sampler().output().index());
+            referenced_nodes.insert(channel.target().node().index());
+        }
+        for skin in doc.skins() {
+            accessors.extend(skin.inverse_bind_matrices().map(|accessor| accessor.index()));
+            referenced_nodes.extend(skin.joints().map(|joint| joint.index()));
+        }
+
+        for material in doc.materials().filter(|m| matches!(m.index(), Some(i) if materials.contains(&i))) {
+            let pbr = material.pbr_metallic_roughness();
+            textures.extend(pbr.base_color_texture().map(|info| info.texture().index()));
+            textures.extend(pbr.metallic_roughness_texture().map(|info| info.texture().index()));
+            textures.extend(material.normal_texture().map(|info| info.texture().index()));
+            textures.extend(material.occlusion_texture().map(|info| info.texture().index()));
+            textures.extend(material.emissive_texture().map(|info| info.texture().index()));
+            // textures of material extensions (e.g. `clearcoatTexture`)
+            if let Some(extensions) = material.index().and_then(|i| imp.json["materials"][i].get("extensions")) {
+                collect_texture_indices(extensions, &mut textures);
+            }
+        }
+        for texture in doc.textures().filter(|t| textures.contains(&t.index())) {
+            images.insert(texture.source().index());
+        }
+
+        for accessor in doc.accessors().filter(|a| accessors.contains(&a.index())) {
+            views.extend(accessor.view().map(|view| view.index()));
+            if let Some(sparse) = accessor.sparse() {
+                views.insert(sparse.indices().view().index());
+                views.insert(sparse.values().view().index());
+            }
+        }
+        for image in doc.images().filter(|i| images.contains(&i.index())) {
+            if let gltf::image::Source::View { view, .. } = image.source() {
+                views.insert(view.index());
+            }
+        }
+
+        let unused = |count: usize, used: &HashSet<usize>| -> Vec<usize> { (0..count).filter(|i| !used.contains(i)).collect() };
+        Audit {
+            materials: unused(doc.materials().len(), &materials),
+            textures: unused(doc.textures().len(), &textures),
+            images: unused(doc.images().len(), &images),
+            accessors: unused(doc.accessors().len(), &accessors),
+            buffer_views: unused(doc.views().len(), &views),
+            empty_nodes: doc.nodes()
+                .filter(|node| node.mesh().is_none() && node.camera().is_none() && node.children().next().is_none())
+                .filter(|node| imp.extension("nodes", node.index(), "KHR_lights_punctual").is_none())
+                .filter(|node| !referenced_nodes.contains(&node.index()))
+                .map(|node| node.index())
+                .collect(),
+        }
+    }
+
+    pub fn unused_count(&self) -> usize {
+        self.materials.len() + self.textures.len() + self.images.len() + self.accessors.len()
+            + self.buffer_views.len() + self.empty_nodes.len()
+    }
+
+    pub fn print(&self, doc: &gltf::Document) {
+        if self.unused_count() == 0 {
+            println!("Audit: no unused resources");
+            return
+        }
+        println!("Audit: {} unused resource(s)", self.unused_count());
+        let names = |indices: &[usize], name: &dyn Fn(usize) -> Option<String>| -> String {
+            indices.iter()
+                .map(|&i| match name(i) {
+                    Some(name) => format!("{} ({})", i, name),
+                    None => i.to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let print = |label: &str, indices: &[usize], name: &dyn Fn(usize) -> Option<String>| {
+            if !indices.is_empty() {
+                println!("  {} {}: {}", indices.len(), label, names(indices, name));
+            }
+        };
+        let no_name = |_: usize| -> Option<String> { None };
+        print("materials not used by any primitive", &self.materials,
+            &|i| doc.materials().nth(i).and_then(|m| m.name().map(|s| s.to_owned())));
+        print("textures not used by any material", &self.textures, &no_name);
+        print("images not used by any texture", &self.images,
+            &|i| doc.images().nth(i).and_then(|image| image.name().map(|s| s.to_owned())));
+        print("accessors not used by any primitive, animation or skin", &self.accessors,
+            &|i| doc.accessors().nth(i).and_then(|a| a.name().map(|s| s.to_owned())));
+        print("buffer views not used by any accessor or image", &self.buffer_views, &no_name);
+        print("nodes without mesh, camera, light or children", &self.empty_nodes,
+            &|i| doc.nodes().nth(i).and_then(|n| n.name().map(|s| s.to_owned())));
+    }
+}
+
+/// Adds the `index` of all texture infos (objects under a key ending with "Texture")
+fn collect_texture_indices(value: &serde_json::Value, textures: &mut HashSet<usize>) {
+    if let Some(object) = value.as_object() {
+        for (key, child) in object {
+            if key.ends_with("Texture") {
+                if let Some(index) = child["index"].as_u64() {
+                    textures.insert(index as usize);
+                }
+            }
+            collect_texture_indices(child, textures);
+        }
+    }
+}
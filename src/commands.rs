@@ -0,0 +1,138 @@
+use std::str::FromStr;
+
+use cgmath::Deg;
+
+use crate::controls::CameraState;
+use crate::render::math::*;
+
+/// Newline-delimited commands for scripting the viewer (see `--stdin`)
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    /// `load <path>`: replace the scene with another file
+    Load(String),
+    /// `camera {"position": [x, y, z], "target": [x, y, z], "fovy": degrees}` (all optional)
+    Camera(CameraUpdate),
+    /// `camera` (without argument): print the current camera in the JSON format above, e.g. to restore it later
+    PrintCamera,
+    /// `frame`: determine the camera view from the bounding box (like after loading)
+    Frame,
+    /// `screenshot <path>`: save a screenshot (OpenEXR for `.exr`, PNG otherwise)
+    Screenshot(String),
+    /// `scene <name or index>`: show another scene of the file (without reloading it)
+    Scene(String),
+    /// `animation <name or index>`: select (and restart) an animation
+    Animation(String),
+    /// `time <seconds>`: set the animation time
+    Time(f32),
+    /// `play` / `pause`: resume / stop advancing the animation time
+    Play,
+    Pause,
+    /// `quit`
+    Quit,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct CameraUpdate {
+    pub position: Option<Vector3>,
+    pub target: Option<Vector3>,
+    pub fovy: Option<f32>,
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let line = line.trim();
+        let (name, argument) = match line.find(char::is_whitespace) {
+            Some(index) => (&line[..index], line[index..].trim()),
+            None => (line, ""),
+        };
+        let require_argument = || {
+            if argument.is_empty() {
+                Err(format!("missing argument for '{}'", name))
+            } else {
+                Ok(argument.to_owned())
+            }
+        };
+
+        match name {
+            "load" => Ok(Command::Load(require_argument()?)),
+            "camera" if argument.is_empty() => Ok(Command::PrintCamera),
+            "camera" => parse_camera(argument).map(Command::Camera),
+            "frame" => Ok(Command::Frame),
+            "screenshot" => Ok(Command::Screenshot(require_argument()?)),
+            "scene" => Ok(Command::Scene(require_argument()?)),
+            "animation" => Ok(Command::Animation(require_argument()?)),
+            "time" => argument.parse()
+                .map(Command::Time)
+                .map_err(|err| format!("invalid time '{}': {}", argument, err)),
+            "play" => Ok(Command::Play),
+            "pause" => Ok(Command::Pause),
+            "quit" | "exit" => Ok(Command::Quit),
+            _ => Err(format!("unknown command '{}'", name)),
+        }
+    }
+}
+
+impl From<CameraState> for CameraUpdate {
+    fn from(state: CameraState) -> Self {
+        CameraUpdate {
+            position: Some(state.position.to_vec()),
+            target: Some(state.target.to_vec()),
+            fovy: Some(state.fovy.0),
+        }
+    }
+}
+
+impl CameraUpdate {
+    /// `state` with the values of this update
+    pub fn apply(&self, state: CameraState) -> CameraState {
+        CameraState {
+            position: self.position.map(Point3::from_vec).unwrap_or(state.position),
+            target: self.target.map(Point3::from_vec).unwrap_or(state.target),
+            fovy: self.fovy.map(Deg).unwrap_or(state.fovy),
+        }
+    }
+
+    /// The JSON format of the `camera` command
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut value = serde_json::json!({});
+        if let Some(p) = self.position {
+            value["position"] = serde_json::json!([p.x, p.y, p.z]);
+        }
+        if let Some(t) = self.target {
+            value["target"] = serde_json::json!([t.x, t.y, t.z]);
+        }
+        if let Some(fovy) = self.fovy {
+            value["fovy"] = serde_json::json!(fovy);
+        }
+        value
+    }
+}
+
+pub fn parse_camera(json: &str) -> Result<CameraUpdate, String> {
+    let value: serde_json::Value = serde_json::from_str(json)
+        .map_err(|err| format!("invalid camera JSON: {}", err))?;
+    let vector = |key: &str| -> Result<Option<Vector3>, String> {
+        match value.get(key) {
+            None => Ok(None),
+            Some(v) => {
+                let components: Option<Vec<f32>> = v.as_array()
+                    .map(|array| array.iter().filter_map(|c| c.as_f64()).map(|c| c as f32).collect());
+                match components {
+                    Some(ref c) if c.len() == 3 => Ok(Some(vec3(c[0], c[1], c[2]))),
+                    _ => Err(format!("'{}' must be an array of 3 numbers", key)),
+                }
+            }
+        }
+    };
+
+    Ok(CameraUpdate {
+        position: vector("position")?,
+        target: vector("target")?,
+        fovy: match value.get("fovy") {
+            None => None,
+            Some(fovy) => Some(fovy.as_f64().ok_or("'fovy' must be a number")? as f32),
+        },
+    })
+}
@@ -1,6 +1,7 @@
 use std::f32::consts::PI;
+use std::str::FromStr;
 
-use cgmath::{vec3, Deg};
+use cgmath::{vec3, Deg, Rad};
 use cgmath::prelude::*;
 
 use log::{warn, trace};
@@ -23,6 +24,8 @@ pub enum CameraMovement {
     BACKWARD,
     LEFT,
     RIGHT,
+    UP,
+    DOWN,
 }
 use self::CameraMovement::*;
 
@@ -37,14 +40,146 @@ pub struct CameraParams {
 const SPEED: f32 = 2.5;
 const ZOOM_SENSITIVITY: f32 = 0.1;
 pub const ZOOM: f32 = 45.0;
-const MIN_ZOOM: f32 = 1.0;
-const MAZ_ZOOM: f32 = 170.0;
+pub const MIN_ZOOM: f32 = 1.0;
+pub const MAX_ZOOM: f32 = 170.0;
+
+// Default distance factor per mouse wheel step (see `OrbitControls::zoom_factor`)
+pub const ZOOM_FACTOR: f32 = 1.2;
+// Scroll offset of one mouse wheel step (see `GltfViewer::process_events`)
+const SCROLL_STEP: f32 = 3.0;
+
+// Defaults for `OrbitControls::rotate_speed`, `pan_speed` and `zoom_speed`
+pub const ROTATE_SPEED: f32 = 1.0;
+pub const PAN_SPEED: f32 = 1.0;
+pub const ZOOM_SPEED: f32 = 1.0;
+
+// Default for `OrbitControls::auto_rotate_speed` (degrees per second)
+pub const AUTO_ROTATE_SPEED: f32 = 30.0;
+
+// Default for `OrbitControls::damping_factor`
+pub const DAMPING_FACTOR: f32 = 0.1;
+
+// Default dolly limits (see `OrbitControls::set_distance_limits`)
+pub const MIN_DISTANCE: f32 = 0.001;
+pub const MAX_DISTANCE: f32 = f32::INFINITY;
+
+// Default angular increment for snapped orbit rotation (see `OrbitControls::snap_angle`)
+pub const SNAP_ANGLE: f32 = 15.0;
+
+// Pan offsets smaller than this (relative to the target distance) are rounding residue
+const PAN_EPSILON: f32 = 1e-6;
+// Minimum angle to the Y axis (radians)
+const POLAR_EPSILON: f32 = 0.0001;
+// Damped rotation stops below this angle per update (radians)
+const ANGLE_EPSILON: f32 = 1e-5;
+// Target distance changes smaller than this (relative) are rounding errors, not zoom
+const RADIUS_EPSILON: f32 = 1e-4;
+
+// Ignore box zoom rectangles smaller than this (in normalized device coordinates)
+const MIN_BOX_ZOOM_SIZE: f32 = 0.01;
+
+/// Ease-out curves for gliding to a stop
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Easing {
+    Quadratic,
+    Cubic,
+    Exponential,
+}
+
+impl FromStr for Easing {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "quad" => Ok(Easing::Quadratic),
+            "cubic" => Ok(Easing::Cubic),
+            "expo" => Ok(Easing::Exponential),
+            _ => Err(format!("unknown easing: {}", s)),
+        }
+    }
+}
+
+impl Easing {
+    /// Progress in [0, 1] at time `t` in [0, 1], fast at first and decelerating
+    pub fn ease_out(self, t: f32) -> f32 {
+        let t = clamp(t, 0.0, 1.0);
+        match self {
+            Easing::Quadratic => 1.0 - (1.0 - t).powi(2),
+            Easing::Cubic => 1.0 - (1.0 - t).powi(3),
+            // normalized to reach exactly 1
+            Easing::Exponential => (1.0 - 2f32.powf(-10.0 * t)) / (1.0 - 2f32.powi(-10)),
+        }
+    }
+}
+
+/// Scroll zoom that glides to a stop instead of jumping (`--smooth-zoom`)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SmoothZoom {
+    pub easing: Easing,
+    /// seconds from a wheel step until the zoom stops (the longer, the softer)
+    pub duration: f32,
+}
+
+impl SmoothZoom {
+    fn progress(&self, elapsed: f32) -> f32 {
+        if self.duration > 0.0 {
+            self.easing.ease_out(elapsed / self.duration)
+        } else {
+            1.0
+        }
+    }
+}
+
+// A scroll zoom in progress (see `SmoothZoom`)
+#[derive(Clone, Copy)]
+struct ZoomGlide {
+    // natural logarithm of the whole distance factor
+    log_scale: f32,
+    elapsed: f32,
+}
+
+/// A camera viewpoint, e.g. the home view to return to with `OrbitControls::reset` or a bookmark
+/// (see `OrbitControls::save_state`). The orbit angles follow from position and target.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CameraState {
+    pub position: Point3,
+    pub target: Point3,
+    pub fovy: Deg<f32>,
+}
+
+// animated camera move (see `OrbitControls::transition_to`)
+#[derive(Clone, Copy)]
+struct Transition {
+    from: CameraState,
+    to: CameraState,
+    duration: f32,
+    elapsed: f32,
+}
+
+impl Transition {
+    /// The view at `t` in [0, 1]: the position moves linearly, the view direction is slerped
+    fn at(&self, t: f32) -> CameraState {
+        let (from, to) = (self.from.target - self.from.position, self.to.target - self.to.position);
+        let arc = Quaternion::from_arc(from.normalize(), to.normalize(), None);
+        let direction = Quaternion::one().slerp(arc, t).rotate_vector(from.normalize());
+        let position = self.from.position + (self.to.position - self.from.position) * t;
+        let distance = from.magnitude() + (to.magnitude() - from.magnitude()) * t;
+        CameraState {
+            position,
+            target: position + direction * distance,
+            fovy: self.from.fovy + (self.to.fovy - self.from.fovy) * t,
+        }
+    }
+}
 
 #[derive(Clone)]
 pub enum NavState {
     None,
     Rotating,
     Panning,
+    BoxZooming,
+    /// one or more fingers on a touch screen (see `handle_touch_start`)
+    Touching,
 }
 
 /// Inspirted by `ThreeJS` `OrbitControls`
@@ -56,6 +191,11 @@ pub struct OrbitControls {
     // "target" sets the location of focus, where the object orbits around
     pub target: Point3,
 
+    // world up vector (see `set_up`) and the rotation from it to "y-axis-is-up" space and back
+    up: Vector3,
+    quat: Quaternion,
+    quat_inverse: Quaternion,
+
     pub state: NavState,
 
     // current position in spherical coordinates
@@ -68,13 +208,71 @@ pub struct OrbitControls {
     rotate_start: Option<Vector2>,
     rotate_end: Vector2,
 
+    /// Increment for snapped rotation (modifier held while dragging)
+    pub snap_angle: Deg<f32>,
+    // unsnapped (theta, phi) accumulated while snapping
+    snap_rotation: Option<Vector2>,
+
     pan_start: Option<Vector2>,
     pan_end: Vector2,
 
+    cursor_position: Vector2,
+    box_zoom_start: Option<Vector2>,
+
+    // fingers on a touch screen by id, and the last centroid and spread of the first two
+    touches: Vec<(u64, Vector2)>,
+    pinch: Option<(Vector2, f32)>,
+
+    /// Mouse sensitivity multipliers (1: dragging across the window rotates 360 degrees;
+    /// `zoom_speed` scales the wheel steps, for dollying and `fov_zoom` alike)
+    pub rotate_speed: f32,
+    pub pan_speed: f32,
+    pub zoom_speed: f32,
+
+    /// Orbit the target on its own at `auto_rotate_speed` (paused while rotating with the mouse)
+    pub auto_rotate: bool,
+    /// degrees per second (negative: the other way around)
+    pub auto_rotate_speed: f32,
+
+    /// Scrolling moves the camera towards or away from the target by this factor per wheel step,
+    /// i.e. the zoom speed is proportional to the distance
+    pub zoom_factor: f32,
+    /// Zoom by changing the field of view instead (the original linear zoom)
+    pub fov_zoom: bool,
+    /// Let rotation and panning coast to a stop after releasing the mouse button
+    /// (requires calling `update` every frame)
+    pub enable_damping: bool,
+    /// Part of the remaining motion that is lost per `update` (0: no friction, 1: no coasting)
+    pub damping_factor: f32,
+    /// Range of the horizontal rotation angle around the target (radians, 0: looking along -Z,
+    /// see `Spherical::theta`). Only used if both are finite; may straddle ±PI, e.g. [3/4 PI, -3/4 PI].
+    pub min_azimuth_angle: f32,
+    pub max_azimuth_angle: f32,
+    // range of the vertical angle (from straight above), see `set_polar_limits`
+    min_polar_angle: f32,
+    max_polar_angle: f32,
+    // range of the distance to the target when zooming
+    min_distance: f32,
+    max_distance: f32,
+    /// Ease scroll zooming out over time (applied in `frame_update`) instead of jumping
+    pub smooth_zoom: Option<SmoothZoom>,
+    zoom_glide: Option<ZoomGlide>,
+    transition: Option<Transition>,
+
+    // framing after loading
+    auto_home: Option<CameraState>,
+    // set by the user, takes precedence over `auto_home`
+    user_home: Option<CameraState>,
+
+    /// Keep the camera at or above this height (Y), e.g. the floor of a building; the target may be lower
+    pub ground_plane_y: Option<f32>,
+
     // for keyboard nav
-    // pub moving_up: bool,
+    /// Walk instead of fly: forward/backward stay at the same height (see `frame_update`)
+    pub walk_mode: bool,
+    pub moving_up: bool,
     pub moving_left: bool,
-    // pub moving_down: bool,
+    pub moving_down: bool,
     pub moving_right: bool,
     pub moving_forward: bool,
     pub moving_backward: bool,
@@ -84,12 +282,16 @@ pub struct OrbitControls {
 
 impl OrbitControls {
     pub fn new(position: Point3, screen_size: PhysicalSize) -> Self {
-        OrbitControls {
+        let mut controls = OrbitControls {
             camera: Camera::default(),
 
             position,
             target: Point3::new(0.0, 0.0, 0.0),
 
+            up: Vector3::unit_y(),
+            quat: Quaternion::one(),
+            quat_inverse: Quaternion::one(),
+
             state: NavState::None,
 
             // current position in spherical coordinates
@@ -102,18 +304,57 @@ impl OrbitControls {
             rotate_start: None,
             rotate_end: Vector2::zero(),
 
+            snap_angle: Deg(SNAP_ANGLE),
+            snap_rotation: None,
+
             pan_start: None,
             pan_end: Vector2::zero(),
 
-            // moving_up: false,
+            cursor_position: Vector2::zero(),
+            box_zoom_start: None,
+
+            touches: Vec::new(),
+            pinch: None,
+
+            rotate_speed: ROTATE_SPEED,
+            pan_speed: PAN_SPEED,
+            zoom_speed: ZOOM_SPEED,
+
+            auto_rotate: false,
+            auto_rotate_speed: AUTO_ROTATE_SPEED,
+
+            zoom_factor: ZOOM_FACTOR,
+            fov_zoom: false,
+            enable_damping: false,
+            damping_factor: DAMPING_FACTOR,
+            min_azimuth_angle: f32::NEG_INFINITY,
+            max_azimuth_angle: f32::INFINITY,
+            min_polar_angle: 0.0,
+            max_polar_angle: PI,
+            min_distance: MIN_DISTANCE,
+            max_distance: MAX_DISTANCE,
+            smooth_zoom: None,
+            zoom_glide: None,
+            transition: None,
+
+            auto_home: None,
+            user_home: None,
+
+            ground_plane_y: None,
+
+            walk_mode: false,
+            moving_up: false,
             moving_left: false,
-            // moving_down: false,
+            moving_down: false,
             moving_right: false,
             moving_forward: false,
             moving_backward: false,
 
             screen_size,
-        }
+        };
+        // until the viewer determines the initial view
+        controls.set_auto_home();
+        controls
     }
 
     // NOTE: could be cached
@@ -125,19 +366,32 @@ impl OrbitControls {
         }
     }
 
+    /// Updates the screen size and the camera aspect ratio (with it the projection) together,
+    /// so that the next frame isn't stretched. Empty sizes (minimized window) are ignored.
+    pub fn set_screen_size(&mut self, screen_size: PhysicalSize) {
+        if screen_size.width < 1.0 || screen_size.height < 1.0 {
+            return
+        }
+        self.screen_size = screen_size;
+        self.camera.update_aspect_ratio((screen_size.width / screen_size.height) as f32);
+    }
+
     fn view_matrix(&self) -> Matrix4 {
-        Matrix4::look_at(self.position, self.target, vec3(0.0, 1.0, 0.0))
+        Matrix4::look_at(self.position, self.target, self.up)
     }
 
-    pub fn handle_mouse_move(&mut self, pos: PhysicalPosition) {
+    /// `snap`: quantize the rotation to `snap_angle` increments
+    pub fn handle_mouse_move(&mut self, pos: PhysicalPosition, snap: bool) {
+        self.cursor_position = vec2(pos.x as f32, pos.y as f32);
         match self.state {
-            NavState::Rotating => self.handle_mouse_move_rotate(pos),
+            NavState::Rotating => self.handle_mouse_move_rotate(pos, snap),
             NavState::Panning => self.handle_mouse_move_pan(pos),
-            NavState::None => ()
+            NavState::BoxZooming | NavState::Touching | NavState::None => ()
         }
     }
 
-    fn handle_mouse_move_rotate(&mut self, pos: PhysicalPosition) {
+    fn handle_mouse_move_rotate(&mut self, pos: PhysicalPosition, snap: bool) {
+        self.transition = None;
         self.rotate_end.x = pos.x as f32;
         self.rotate_end.y = pos.y as f32;
         let rotate_delta = if let Some(rotate_start) = self.rotate_start {
@@ -147,21 +401,93 @@ impl OrbitControls {
         };
 
         // rotating across whole screen goes 360 degrees around
-        let rotate_speed = 1.0; // TODO: const/param/remove?
-        let angle = 2.0 * PI * rotate_delta.x / self.screen_size.width as f32 * rotate_speed;
-        self.rotate_left(angle);
+        let angle_left = 2.0 * PI * rotate_delta.x / self.screen_size.width as f32 * self.rotate_speed;
 
         // rotating up and down along whole screen attempts to go 360, but limited to 180
-        let angle = 2.0 * PI * rotate_delta.y / self.screen_size.height as f32 * rotate_speed;
-        self.rotate_up(angle);
+        let angle_up = 2.0 * PI * rotate_delta.y / self.screen_size.height as f32 * self.rotate_speed;
+
+        let step = Rad::from(self.snap_angle).0;
+        if snap && step > 0.0 {
+            // accumulate the unsnapped angles and jump to the nearest increment
+            let current = Spherical::from_vec3(self.position - self.target);
+            let mut rotation = self.snap_rotation.unwrap_or_else(|| vec2(current.theta, current.phi));
+            rotation.x -= angle_left;
+            rotation.y -= angle_up;
+            self.snap_rotation = Some(rotation);
+
+            let snapped = |angle: f32| (angle / step).round() * step;
+            self.spherical_delta.theta = snapped(rotation.x) - current.theta;
+            self.spherical_delta.phi = snapped(rotation.y) - current.phi;
+        } else {
+            self.snap_rotation = None;
+            self.rotate_left(angle_left);
+            self.rotate_up(angle_up);
+        }
 
         self.rotate_start = Some(self.rotate_end);
 
-        self.update();
+        self.update(0.0);
+    }
+
+    /// A finger touches the screen. Like with ThreeJS `OrbitControls`, one finger rotates,
+    /// two fingers pan (moving together) and dolly (pinching).
+    pub fn handle_touch_start(&mut self, id: u64, pos: PhysicalPosition) {
+        self.touches.retain(|&(touch, _)| touch != id);
+        self.touches.push((id, vec2(pos.x as f32, pos.y as f32)));
+        self.begin_touch_gesture();
+    }
+
+    pub fn handle_touch_move(&mut self, id: u64, pos: PhysicalPosition) {
+        match self.touches.iter_mut().find(|(touch, _)| *touch == id) {
+            Some(touch) => touch.1 = vec2(pos.x as f32, pos.y as f32),
+            None => return,
+        }
+        match self.touches.len() {
+            1 => self.handle_mouse_move_rotate(pos, false),
+            _ => {
+                let (a, b) = (self.touches[0].1, self.touches[1].1);
+                let (centroid, spread) = ((a + b) / 2.0, (a - b).magnitude());
+                if let Some((last_centroid, last_spread)) = self.pinch {
+                    if spread > 0.0 && last_spread > 0.0 {
+                        self.dolly_in(spread / last_spread);
+                    }
+                    self.pan(centroid - last_centroid);
+                    self.update(0.0);
+                }
+                self.pinch = Some((centroid, spread));
+            },
+        }
+    }
+
+    /// A finger is lifted (or the touch is cancelled)
+    pub fn handle_touch_end(&mut self, id: u64) {
+        self.touches.retain(|&(touch, _)| touch != id);
+        self.begin_touch_gesture();
+    }
+
+    // (re)start the gesture from the current fingers, so that adding or lifting one doesn't jump
+    fn begin_touch_gesture(&mut self) {
+        self.transition = None;
+        self.snap_rotation = None;
+        self.rotate_start = None;
+        self.pinch = None;
+        match self.touches.len() {
+            0 => self.state = NavState::None,
+            1 => {
+                self.state = NavState::Touching;
+                self.rotate_start = Some(self.touches[0].1);
+            },
+            _ => {
+                self.state = NavState::Touching;
+                let (a, b) = (self.touches[0].1, self.touches[1].1);
+                self.pinch = Some(((a + b) / 2.0, (a - b).magnitude()));
+            },
+        }
     }
 
     pub fn handle_mouse_up(&mut self) {
         self.rotate_start = None;
+        self.snap_rotation = None;
         self.pan_start = None;
     }
 
@@ -171,13 +497,14 @@ impl OrbitControls {
 
     pub fn rotate_object(&mut self, angle: f32) {
         self.rotate_left(angle);
-        self.update();
+        self.update(0.0);
     }
     fn rotate_up(&mut self, angle: f32) {
         self.spherical_delta.phi -= angle;
     }
 
     fn handle_mouse_move_pan(&mut self, pos: PhysicalPosition) {
+        self.transition = None;
         self.pan_end.x = pos.x as f32;
         self.pan_end.y = pos.y as f32;
 
@@ -187,11 +514,11 @@ impl OrbitControls {
             Vector2::zero()
         };
 
-        self.pan(pan_delta);
+        self.pan(pan_delta * self.pan_speed);
 
         self.pan_start = Some(self.pan_end);
 
-        self.update();
+        self.update(0.0);
     }
 
     fn pan(&mut self, delta: Vector2) {
@@ -208,8 +535,13 @@ impl OrbitControls {
             let distance = 50.0 * delta.y * target_distance / self.screen_size.height as f32;
             self.pan_up(-distance);
         } else {
-            // TODO!: orthographic camera pan
-            warn!("unimplemented: orthographic camera pan")
+            // the view is 2 * xmag wide and 2 * ymag high (see `Camera::update_projection_matrix`),
+            // halved because `update` doubles the pan offset: the scene follows the cursor
+            let (xmag, ymag) = (self.camera.xmag.unwrap_or(1.0), self.camera.ymag.unwrap_or(1.0));
+            let distance = delta.x * xmag / self.screen_size.width as f32;
+            self.pan_left(-distance);
+            let distance = delta.y * ymag / self.screen_size.height as f32;
+            self.pan_up(-distance);
         }
     }
 
@@ -222,75 +554,410 @@ impl OrbitControls {
     }
 
     // Processes input received from a mouse scroll-wheel event. Only requires input on the vertical wheel-axis
-    pub fn process_mouse_scroll(&mut self, mut yoffset: f32) {
-        yoffset *= ZOOM_SENSITIVITY;
-        if self.camera.fovy.0 >= MIN_ZOOM && self.camera.fovy.0 <= MAZ_ZOOM {
-            self.camera.fovy.0 -= yoffset;
+    /// By default, this dollies the camera towards the target (`zoom_factor` per wheel step, eased with
+    /// `smooth_zoom`), only with `fov_zoom` the field of view changes instead.
+    pub fn process_mouse_scroll(&mut self, yoffset: f32) {
+        let yoffset = yoffset * self.zoom_speed;
+        if self.fov_zoom {
+            self.zoom_fovy(yoffset);
+            return
         }
-        if self.camera.fovy.0 <= MIN_ZOOM {
-            self.camera.fovy.0 = MIN_ZOOM;
+        self.transition = None;
+        // exponential: each wheel step changes the distance to the target by the same factor
+        match self.smooth_zoom {
+            Some(smooth_zoom) => {
+                let log_scale = self.zoom_factor.ln() * -yoffset / SCROLL_STEP;
+                // continue with what's left of the current glide, from full speed
+                let remaining = self.zoom_glide
+                    .map(|glide| glide.log_scale * (1.0 - smooth_zoom.progress(glide.elapsed)))
+                    .unwrap_or(0.0);
+                self.zoom_glide = Some(ZoomGlide { log_scale: remaining + log_scale, elapsed: 0.0 });
+            },
+            None => {
+                if yoffset > 0.0 {
+                    self.dolly_in(self.zoom_factor.powf(yoffset / SCROLL_STEP));
+                } else {
+                    self.dolly_out(self.zoom_factor.powf(-yoffset / SCROLL_STEP));
+                }
+                self.update(0.0);
+            }
         }
-        if self.camera.fovy.0 >= MAZ_ZOOM {
-            self.camera.fovy.0 = MAZ_ZOOM;
+    }
+
+    /// Move towards the target, dividing the distance by `dolly_scale` (> 1) with the next `update`
+    pub fn dolly_in(&mut self, dolly_scale: f32) {
+        self.scale /= dolly_scale;
+    }
+
+    /// Move away from the target, multiplying the distance by `dolly_scale` (> 1) with the next `update`
+    pub fn dolly_out(&mut self, dolly_scale: f32) {
+        self.scale *= dolly_scale;
+    }
+
+    /// Advance a `smooth_zoom` glide
+    fn update_zoom_glide(&mut self, delta_time: f32) {
+        let (smooth_zoom, mut glide) = match (self.smooth_zoom, self.zoom_glide) {
+            (Some(smooth_zoom), Some(glide)) => (smooth_zoom, glide),
+            _ => return
+        };
+        let before = smooth_zoom.progress(glide.elapsed);
+        glide.elapsed += delta_time;
+        let after = smooth_zoom.progress(glide.elapsed);
+        self.zoom_glide = if after < 1.0 { Some(glide) } else { None };
+
+        self.dolly_out((glide.log_scale * (after - before)).exp());
+        self.update(0.0);
+    }
+
+    /// Zoom by linearly changing the field of view (`fov_zoom`)
+    fn zoom_fovy(&mut self, yoffset: f32) {
+        let step = Deg(yoffset * ZOOM_SENSITIVITY);
+        match self.smooth_zoom {
+            Some(smooth_zoom) => {
+                // continue from where a running glide ends
+                let fovy = self.transition.map(|transition| transition.to.fovy).unwrap_or(self.camera.fovy);
+                self.animate_fovy(fovy - step, smooth_zoom.duration);
+            },
+            None => {
+                self.transition = None;
+                let fovy = self.camera.fovy - step;
+                self.camera.set_fovy(fovy);
+            }
         }
-        self.camera.update_projection_matrix();
     }
 
-    /// Update camera after processing mouse events
-    fn update(&mut self) {
+    /// Tween the field of view from the current one to `fovy` (clamped) over `duration` seconds,
+    /// e.g. for a cinematic zoom (a `transition_to` that only changes the field of view)
+    pub fn animate_fovy(&mut self, fovy: Deg<f32>, duration: f32) {
+        let state = CameraState { fovy: Camera::clamp_fovy(fovy), ..self.save_state() };
+        self.transition_to(state, duration);
+    }
+
+    /// Update camera after processing mouse events (`delta_time`: 0) and every frame with the
+    /// seconds since the last one, for `auto_rotate` and to let the motion coast to a stop with `enable_damping`
+    pub fn update(&mut self, delta_time: f32) {
+        if let Some(mut transition) = self.transition.take() {
+            transition.elapsed += delta_time;
+            let t = (transition.elapsed / transition.duration).min(1.0);
+            // ease in and out
+            let state = transition.at(t * t * (3.0 - 2.0 * t));
+            self.position = state.position;
+            self.target = state.target;
+            if self.camera.fovy != state.fovy {
+                self.camera.set_fovy(state.fovy);
+            }
+            if t < 1.0 {
+                self.transition = Some(transition);
+            }
+        }
+        if self.auto_rotate && self.transition.is_none() && !matches!(self.state, NavState::Rotating | NavState::Touching) {
+            self.rotate_left(Rad::from(Deg(self.auto_rotate_speed * delta_time)).0);
+        }
+
         let mut offset = self.position - self.target;
 
-        // NOTE: skipping rotate offset to "y-axis-is-up" space
+        // rotate offset to "y-axis-is-up" space
+        offset = self.quat.rotate_vector(offset);
 
         // angle from z-axis around y-axis
+        let radius = self.spherical.radius;
         self.spherical = Spherical::from_vec3(offset);
+        // keep the previous distance if it only differs by rounding errors of `position - target`,
+        // which otherwise accumulate while panning (especially far from the origin)
+        if (self.spherical.radius - radius).abs() <= radius * RADIUS_EPSILON {
+            self.spherical.radius = radius;
+        }
+        let theta = self.spherical.theta;
 
         self.spherical.theta += self.spherical_delta.theta;
         self.spherical.phi += self.spherical_delta.phi;
 
-        // NOTE!: left out theta restrictions / make_safe for now
-
-        // restrict phi to be between desired limits
-        let epsilon = 0.0001;
-        self.spherical.phi = clamp(self.spherical.phi, epsilon, PI - epsilon);
+        // restrict theta to be between desired limits
+        let (mut min, mut max) = (self.min_azimuth_angle, self.max_azimuth_angle);
+        if min.is_finite() && max.is_finite() {
+            let wrap = |angle: f32| if angle < -PI { angle + 2.0 * PI } else if angle > PI { angle - 2.0 * PI } else { angle };
+            min = wrap(min);
+            max = wrap(max);
+            let theta = wrap(self.spherical.theta);
+            self.spherical.theta = if min <= max {
+                clamp(theta, min, max)
+            } else if theta > (min + max) / 2.0 {
+                // the allowed range straddles ±PI: [min, PI] and [-PI, max]
+                theta.max(min)
+            } else {
+                theta.min(max)
+            };
+        }
 
-        self.spherical.radius *= self.scale;
+        // restrict phi to be between desired limits (never exactly at the poles, where the view is undefined)
+        self.spherical.phi = clamp(self.spherical.phi,
+            self.min_polar_angle.max(POLAR_EPSILON), self.max_polar_angle.min(PI - POLAR_EPSILON));
 
-        // TODO?: restrict radius to be between desired limits?
+        if self.camera.is_perspective() {
+            self.spherical.radius *= self.scale;
+            self.spherical.radius = clamp(self.spherical.radius, self.min_distance, self.max_distance);
+        } else if self.scale != 1.0 {
+            // the distance doesn't change the size of an orthographic view, the extents do
+            self.camera.scale_magnification(self.scale);
+        }
 
         // move target to panned location
         // NOTE: quite different from original
-        // NOTE: skipped from original: rotate offset back to "camera-up-vector-is-up" space
-        let pan_speed = 2.0; // TODO!!: test on non-retina display
-        self.pan_offset *= pan_speed;
-        let right = offset.cross(Vector3::unit_y()).normalize();
-        let up = right.cross(offset).normalize();
-        self.position += right * self.pan_offset.x;
-        self.position += up * self.pan_offset.y;
-        self.target += right * self.pan_offset.x;
-        self.target += up * self.pan_offset.y;
-
-        // apply rotation
-        offset = self.spherical.to_vec3();
-        self.position = self.target + offset;
+        let pan = self.pan_offset * 2.0; // TODO!!: test on non-retina display
+        if pan.magnitude() < self.spherical.radius * PAN_EPSILON {
+            self.pan_offset = Vector3::zero();
+        } else {
+            // same as `offset.cross(Vector3::unit_y())`, but also defined when looking straight down/up
+            let right = vec3(-theta.cos(), 0.0, theta.sin());
+            let up = self.quat_inverse.rotate_vector(right.cross(offset)).normalize();
+            let right = self.quat_inverse.rotate_vector(right);
+            // only the target moves, the position follows from it below (keeping the distance)
+            self.target += right * pan.x;
+            self.target += up * pan.y;
+        }
 
-        // TODO?: if enable_damping...?
-        self.spherical_delta = Spherical::from_vec3(Vector3::zero());
+        // apply rotation, and rotate offset back to "camera-up-vector-is-up" space
+        offset = self.quat_inverse.rotate_vector(self.spherical.to_vec3());
+        self.position = self.target + offset;
+        self.clamp_to_ground();
+
+        if self.enable_damping {
+            // keep a part of the motion for the next update
+            let keep = 1.0 - self.damping_factor;
+            let damp = |delta: f32| if (delta * keep).abs() < ANGLE_EPSILON { 0.0 } else { delta * keep };
+            self.spherical_delta.theta = damp(self.spherical_delta.theta);
+            self.spherical_delta.phi = damp(self.spherical_delta.phi);
+            self.pan_offset *= keep;
+        } else {
+            self.spherical_delta = Spherical::from_vec3(Vector3::zero());
+            self.pan_offset = Vector3::zero();
+        }
 
         self.scale = 1.0;
-        self.pan_offset = Vector3::zero();
 
         // NOTE: skip zoomChanged stuff
 
         trace!("Position: {:?}\tTarget: {:?}\tfovy: {:?}", self.position, self.target, Deg(self.camera.fovy));
     }
 
+    /// Look at the center of the box from far enough away (along the current view direction) that
+    /// its bounding sphere fits into the viewport, horizontally as well as vertically
+    pub fn frame_bounds(&mut self, min: Vector3, max: Vector3) {
+        let center = Point3::from_vec((min + max) / 2.0);
+        let radius = (max - min).magnitude() / 2.0;
+        let offset = self.position - self.target;
+        let direction = if offset.magnitude2() > 0.0 { offset.normalize() } else { Vector3::unit_z() };
+        let aspect_ratio = self.camera.aspect_ratio();
+
+        let distance = if self.camera.is_perspective() {
+            let half_fovy = Rad::from(self.camera.fovy).0 / 2.0;
+            let half_fovx = (half_fovy.tan() * aspect_ratio).atan();
+            radius / half_fovy.min(half_fovx).sin()
+        } else {
+            let ymag = if aspect_ratio < 1.0 { radius / aspect_ratio } else { radius };
+            self.camera.ymag = Some(ymag);
+            self.camera.xmag = Some(ymag * aspect_ratio);
+            self.camera.update_projection_matrix();
+            // in front of the sphere, the distance doesn't matter otherwise
+            radius * 2.0
+        };
+        self.target = center;
+        self.position = center + direction * distance.max(self.min_distance);
+        self.update(0.0);
+    }
+
+    /// Restrict the vertical rotation: the angle (radians) from looking straight down (0) to straight up (PI),
+    /// e.g. `max` = PI/2 to never look at the underside of a model. Applied right away.
+    pub fn set_polar_limits(&mut self, min: f32, max: f32) {
+        let (min, max) = (clamp(min, 0.0, PI), clamp(max, 0.0, PI));
+        if min > max {
+            warn!("Invalid polar angle limits ({} > {}), swapping them", min, max);
+        }
+        self.min_polar_angle = min.min(max);
+        self.max_polar_angle = max.max(min);
+        self.update(0.0);
+    }
+
+    /// Restrict the distance to the target when zooming (e.g. to the bounding radius of a small model;
+    /// orthographic cameras zoom by scaling their extents instead).
+    /// Applied right away.
+    pub fn set_distance_limits(&mut self, min: f32, max: f32) {
+        self.min_distance = min.min(max);
+        self.max_distance = max.max(min);
+        self.update(0.0);
+    }
+
+    /// Orbit around `up` instead of the Y axis (e.g. `Vector3::unit_z()` for Z-up models).
+    /// Azimuth and polar angles (and their limits) are then relative to it. Applied right away.
+    pub fn set_up(&mut self, up: Vector3) {
+        if up.magnitude2() == 0.0 {
+            warn!("Ignoring zero up vector");
+            return
+        }
+        self.up = up.normalize();
+        self.quat = Quaternion::from_arc(self.up, Vector3::unit_y(), None);
+        self.quat_inverse = self.quat.invert();
+        self.update(0.0);
+    }
+
+    pub fn save_state(&self) -> CameraState {
+        CameraState {
+            position: self.position,
+            target: self.target,
+            fovy: self.camera.fovy,
+        }
+    }
+
+    /// Jump to a saved viewpoint (stopping any coasting, zoom glide or transition)
+    pub fn load_state(&mut self, state: &CameraState) {
+        self.stop_motion();
+        self.position = state.position;
+        self.target = state.target;
+        self.camera.set_fovy(state.fovy);
+        self.update(0.0);
+    }
+
+    /// Remember the current view as the load-time home (see `reset`)
+    pub fn set_auto_home(&mut self) {
+        self.auto_home = Some(self.save_state());
+    }
+
+    /// Set a user-defined home view, overriding the load-time one (`None`: current view)
+    pub fn set_home(&mut self, view: Option<CameraState>) {
+        self.user_home = Some(view.unwrap_or_else(|| self.save_state()));
+    }
+
+    pub fn home(&self) -> Option<CameraState> {
+        self.user_home.or(self.auto_home)
+    }
+
+    /// Switch between perspective and orthographic projection (see `Camera::toggle_projection`)
+    pub fn toggle_projection(&mut self) {
+        let distance = (self.target - self.position).magnitude();
+        self.camera.toggle_projection(distance);
+    }
+
+    /// Glide to a viewpoint over `duration` seconds, advanced by `update`.
+    /// Rotating, panning, zooming or moving with the keyboard cancels it.
+    pub fn transition_to(&mut self, state: CameraState, duration: f32) {
+        if duration <= 0.0 {
+            self.load_state(&state);
+            return
+        }
+        self.stop_motion();
+        self.transition = Some(Transition { from: self.save_state(), to: state, duration, elapsed: 0.0 });
+    }
+
+    fn stop_motion(&mut self) {
+        self.zoom_glide = None;
+        self.transition = None;
+        self.spherical_delta = Spherical::from_vec3(Vector3::zero());
+        self.pan_offset = Vector3::zero();
+    }
+
+    /// Return to the home view (see `load_state`)
+    pub fn reset(&mut self) {
+        if let Some(home) = self.home() {
+            self.load_state(&home);
+        }
+    }
+
+    /// Move the orbit target to `center` (e.g. the scene center), undoing any accumulated pan.
+    /// The view direction and distance stay the same.
+    pub fn recenter(&mut self, center: Point3) {
+        self.position = center + (self.position - self.target);
+        self.target = center;
+    }
+
+    /// Start dragging a rubber-band rectangle at the current cursor position
+    pub fn start_box_zoom(&mut self) {
+        self.state = NavState::BoxZooming;
+        self.box_zoom_start = Some(self.cursor_position);
+    }
+
+    /// The current rubber-band rectangle (min, max) in normalized device coordinates
+    pub fn box_zoom_rect(&self) -> Option<(Vector2, Vector2)> {
+        self.box_zoom_start.map(|start| {
+            let a = self.to_ndc(start);
+            let b = self.to_ndc(self.cursor_position);
+            (vec2(a.x.min(b.x), a.y.min(b.y)), vec2(a.x.max(b.x), a.y.max(b.y)))
+        })
+    }
+
+    /// Frame the rubber-band rectangle so that it fills the viewport (preserving the aspect ratio).
+    /// The view direction stays the same, the rectangle is unprojected at the depth of the target.
+    pub fn finish_box_zoom(&mut self) {
+        let rect = self.box_zoom_rect();
+        self.state = NavState::None;
+        self.box_zoom_start = None;
+
+        let (min, max) = match rect {
+            Some(rect) => rect,
+            None => return
+        };
+        let size = max - min;
+        if size.x < MIN_BOX_ZOOM_SIZE || size.y < MIN_BOX_ZOOM_SIZE {
+            return
+        }
+        // the whole viewport is 2 units wide/high in NDC
+        let scale = (size.x / 2.0).max(size.y / 2.0);
+
+        let (origin, direction) = match self.unproject_ray((min + max) / 2.0) {
+            Some(ray) => ray,
+            None => return
+        };
+        let offset = self.target - self.position;
+        let distance = offset.magnitude();
+        let front = offset / distance;
+        let t = (self.target - origin).dot(front) / direction.dot(front);
+        let new_target = origin + direction * t;
+
+        if self.camera.is_perspective() {
+            self.position = new_target - front * distance * scale;
+        } else {
+            self.position = new_target - front * distance;
+            self.camera.xmag = self.camera.xmag.map(|xmag| xmag * scale);
+            self.camera.ymag = self.camera.ymag.map(|ymag| ymag * scale);
+            self.camera.update_projection_matrix();
+        }
+        self.target = new_target;
+    }
+
+    /// Ray (origin, normalized direction) through a point given in normalized device coordinates
+    pub fn unproject_ray(&self, ndc: Vector2) -> Option<(Point3, Vector3)> {
+        let inverse = (self.camera.projection_matrix * self.view_matrix()).invert()?;
+        // NOTE: using z = 0 instead of the far plane, which might be at infinity
+        let near = Point3::from_homogeneous(inverse * vec4(ndc.x, ndc.y, -1.0, 1.0));
+        let mid = Point3::from_homogeneous(inverse * vec4(ndc.x, ndc.y, 0.0, 1.0));
+        Some((near, (mid - near).normalize()))
+    }
+
+    /// Cursor position in window pixels (origin top left)
+    pub fn cursor_position(&self) -> Vector2 {
+        self.cursor_position
+    }
+
+    /// Cursor position in normalized device coordinates
+    pub fn cursor_ndc(&self) -> Vector2 {
+        self.to_ndc(self.cursor_position)
+    }
+
+    /// Convert a window position (pixels, origin top left) to normalized device coordinates
+    fn to_ndc(&self, pos: Vector2) -> Vector2 {
+        vec2(
+            2.0 * pos.x / self.screen_size.width as f32 - 1.0,
+            1.0 - 2.0 * pos.y / self.screen_size.height as f32,
+        )
+    }
+
     pub fn process_keyboard(&mut self, direction: CameraMovement, pressed: bool) {
         match direction {
             FORWARD => self.moving_forward = pressed,
             BACKWARD => self.moving_backward= pressed,
             LEFT => self.moving_left = pressed,
             RIGHT => self.moving_right = pressed,
+            UP => self.moving_up = pressed,
+            DOWN => self.moving_down = pressed,
         }
     }
 
@@ -298,7 +965,19 @@ impl OrbitControls {
     pub fn frame_update(&mut self, delta_time: f64) {
         let velocity = SPEED * delta_time as f32;
 
-        let front = (self.target - self.position).normalize();
+        self.update_zoom_glide(delta_time as f32);
+
+        if self.moving_forward || self.moving_backward || self.moving_left || self.moving_right
+            || self.moving_up || self.moving_down {
+            self.transition = None;
+        }
+
+        let mut front = (self.target - self.position).normalize();
+        if self.walk_mode {
+            // horizontal projection, nothing to walk along when looking straight down/up
+            front -= self.up * front.dot(self.up);
+            front = if front.magnitude2() > 1e-6 { front.normalize() } else { Vector3::zero() };
+        }
         if self.moving_forward {
             self.position += front * velocity;
             self.target += front * velocity;
@@ -308,7 +987,7 @@ impl OrbitControls {
             self.target += -(front * velocity);
         }
 
-        let right = front.cross(Vector3::unit_y()).normalize();
+        let right = (self.target - self.position).cross(self.up).normalize();
         if self.moving_left {
             self.position += -(right * velocity);
             self.target += -(right * velocity);
@@ -317,8 +996,26 @@ impl OrbitControls {
             self.position += right * velocity;
             self.target += right * velocity;
         }
+
+        // along the world up vector, i.e. without tilting the view
+        if self.moving_up {
+            self.position += self.up * velocity;
+            self.target += self.up * velocity;
+        }
+        if self.moving_down {
+            self.position += -(self.up * velocity);
+            self.target += -(self.up * velocity);
+        }
+        self.clamp_to_ground();
+    }
+
+    fn clamp_to_ground(&mut self) {
+        if let Some(y) = self.ground_plane_y {
+            self.position.y = self.position.y.max(y);
+        }
     }
 
+    /// Use a glTF camera. The orbit target is put in front of it at the current orbit distance.
     pub fn set_camera(&mut self, camera: &Camera, transform: &Matrix4) {
         // spec: If no transformation is specified, the location of the camera is at the origin.
         let pos = transform * vec4(0.0, 0.0, 0.0, 1.0);
@@ -326,8 +1023,10 @@ impl OrbitControls {
         // spec: ... the lens looks towards the local -Z axis ...
         let look_at = transform * vec4(0.0, 0.0, -1.0, 0.0);
 
+        // (the look direction, not a point: the target is relative to the position)
+        let distance = (self.target - self.position).magnitude();
         self.position = Point3::new(pos.x, pos.y, pos.z);
-        self.target = Point3::new(look_at.x, look_at.y, look_at.z);
+        self.target = self.position + look_at.truncate().normalize() * distance;
 
         // TODO!!: retaining current window aspect ratio for now... later maybe resize window accordingly?
         let mut camera = camera.clone();
@@ -335,5 +1034,447 @@ impl OrbitControls {
         self.camera = camera;
 
         self.camera.update_projection_matrix();
+        // `reset` returns to the glTF camera
+        self.set_auto_home();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resize_keeps_proportions() {
+        let mut controls = OrbitControls::new(Point3::new(0.0, 0.0, 2.0), PhysicalSize::new(800.0, 600.0));
+        controls.set_screen_size(PhysicalSize::new(800.0, 600.0));
+        controls.set_screen_size(PhysicalSize::new(400.0, 800.0));
+        assert_eq!(controls.screen_size, PhysicalSize::new(400.0, 800.0));
+
+        // the first frame after resizing: a square is as wide as high in pixels
+        let projection = controls.camera_params().projection_matrix;
+        let (width_px, height_px) = (projection[0][0] * 400.0, projection[1][1] * 800.0);
+        assert!((width_px - height_px).abs() < 1e-3, "{} x {} pixels", width_px, height_px);
+
+        // minimizing doesn't break the projection
+        controls.set_screen_size(PhysicalSize::new(400.0, 0.0));
+        assert_eq!(controls.screen_size, PhysicalSize::new(400.0, 800.0));
+        assert!((controls.camera.aspect_ratio() - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn scroll_zoom_is_proportional_to_distance() {
+        let mut controls = OrbitControls::new(Point3::new(0.0, 0.0, 10.0), PhysicalSize::new(800.0, 600.0));
+        controls.zoom_factor = 2.0;
+        controls.process_mouse_scroll(SCROLL_STEP);
+        assert!((controls.position.z - 5.0).abs() < 1e-4, "{:?}", controls.position);
+        controls.process_mouse_scroll(SCROLL_STEP);
+        assert!((controls.position.z - 2.5).abs() < 1e-4, "{:?}", controls.position);
+        controls.process_mouse_scroll(-2.0 * SCROLL_STEP);
+        assert!((controls.position.z - 10.0).abs() < 1e-3, "{:?}", controls.position);
+    }
+
+    #[test]
+    fn smooth_zoom_decelerates() {
+        let mut controls = OrbitControls::new(Point3::new(0.0, 0.0, 10.0), PhysicalSize::new(800.0, 600.0));
+        controls.zoom_factor = 2.0;
+        controls.smooth_zoom = Some(SmoothZoom { easing: Easing::Cubic, duration: 0.5 });
+        controls.process_mouse_scroll(SCROLL_STEP);
+        assert_eq!(controls.position.z, 10.0, "zoom should start with the next frame");
+
+        let mut steps = vec![];
+        let mut distance = 10.0;
+        for _ in 0..20 {
+            controls.frame_update(0.05);
+            steps.push(distance - controls.position.z);
+            distance = controls.position.z;
+        }
+        assert!((distance - 5.0).abs() < 1e-3, "{}", distance);
+        // moving right away, then slower and slower until stopped
+        assert!(steps[0] > 0.0);
+        for pair in steps.windows(2) {
+            assert!(pair[1] <= pair[0] + 1e-6, "{:?}", steps);
+        }
+        assert_eq!(*steps.last().unwrap(), 0.0);
+    }
+
+    #[test]
+    fn damped_rotation_coasts_to_a_stop() {
+        let mut controls = OrbitControls::new(Point3::new(0.0, 0.0, 5.0), PhysicalSize::new(800.0, 600.0));
+        controls.enable_damping = true;
+        controls.damping_factor = 0.2;
+        controls.rotate_object(0.1);
+
+        let angle = |controls: &OrbitControls| controls.position.x.atan2(controls.position.z);
+        let mut last = angle(&controls);
+        let mut steps = vec![];
+        for _ in 0..200 {
+            controls.update(0.0);
+            steps.push((angle(&controls) - last).abs());
+            last = angle(&controls);
+        }
+        assert!(steps[0] > 0.0, "should keep rotating after the input");
+        for pair in steps.windows(2) {
+            assert!(pair[1] <= pair[0] + 1e-6, "{:?}", steps);
+        }
+        assert_eq!(*steps.last().unwrap(), 0.0);
+        // 0.1 * (1 + 0.8 + 0.8^2 + ...) = 0.5 in total
+        assert!((last.abs() - 0.5).abs() < 1e-3, "{}", last);
+    }
+
+    #[test]
+    fn azimuth_limits() {
+        let mut controls = OrbitControls::new(Point3::new(0.0, 0.0, 5.0), PhysicalSize::new(800.0, 600.0));
+        let theta = |controls: &OrbitControls| controls.position.x.atan2(controls.position.z);
+        controls.min_azimuth_angle = -PI / 4.0;
+        controls.max_azimuth_angle = PI / 4.0;
+        controls.rotate_object(-1.0);
+        assert!((theta(&controls) - PI / 4.0).abs() < 1e-4, "{}", theta(&controls));
+        controls.rotate_object(2.0);
+        assert!((theta(&controls) + PI / 4.0).abs() < 1e-4, "{}", theta(&controls));
+
+        // only behind the object: [3/4 PI, PI] and [-PI, -3/4 PI]
+        let mut controls = OrbitControls::new(Point3::new(0.0, 0.0, -5.0), PhysicalSize::new(800.0, 600.0));
+        controls.min_azimuth_angle = 0.75 * PI;
+        controls.max_azimuth_angle = -0.75 * PI;
+        controls.rotate_object(-1.0); // crossing PI
+        assert!((theta(&controls) + 0.75 * PI).abs() < 1e-4, "{}", theta(&controls));
+        controls.rotate_object(2.0); // back across PI and beyond
+        assert!((theta(&controls) - 0.75 * PI).abs() < 1e-4, "{}", theta(&controls));
+    }
+
+    #[test]
+    fn polar_limits() {
+        let mut controls = OrbitControls::new(Point3::new(0.0, 0.0, 5.0), PhysicalSize::new(800.0, 600.0));
+        controls.set_polar_limits(PI / 2.0, PI / 4.0); // swapped
+        // can't look from below
+        controls.rotate_up(-1.0);
+        controls.update(0.0);
+        assert!(controls.position.y.abs() < 1e-4, "{:?}", controls.position);
+        controls.rotate_up(1.0);
+        controls.update(0.0);
+        assert!((controls.position.y - 5.0 * (PI / 4.0).cos()).abs() < 1e-4, "{:?}", controls.position);
+        // straight down is only approached
+        controls.set_polar_limits(-1.0, 4.0);
+        controls.rotate_up(10.0);
+        controls.update(0.0);
+        assert!(controls.position.x.is_finite() && controls.position.z > 0.0, "{:?}", controls.position);
+    }
+
+    #[test]
+    fn frame_bounds_fits_sphere() {
+        let mut controls = OrbitControls::new(Point3::new(3.0, 4.0, 5.0), PhysicalSize::new(800.0, 400.0));
+        let (min, max) = (vec3(1.0, 1.0, 1.0), vec3(3.0, 5.0, 2.0));
+        let radius = (max - min).magnitude() / 2.0;
+        controls.frame_bounds(min, max);
+        assert_eq!(controls.target, Point3::new(2.0, 3.0, 1.5));
+        // the view direction stays the same
+        let direction = (controls.position - controls.target).normalize();
+        assert!((direction - vec3(3.0, 4.0, 5.0).normalize()).magnitude() < 1e-4);
+        // wide window: the sphere touches the top and bottom
+        let distance = (controls.position - controls.target).magnitude();
+        let half_fovy = Rad::from(controls.camera.fovy).0 / 2.0;
+        assert!((distance * half_fovy.sin() - radius).abs() < 1e-3, "{}", distance);
+
+        // narrow window: the sphere touches the sides
+        controls.set_screen_size(PhysicalSize::new(200.0, 800.0));
+        controls.frame_bounds(min, max);
+        let distance = (controls.position - controls.target).magnitude();
+        let half_fovx = (half_fovy.tan() * 0.25).atan();
+        assert!((distance * half_fovx.sin() - radius).abs() < 1e-3, "{}", distance);
+    }
+
+    #[test]
+    fn z_up_orbits_around_z() {
+        let mut controls = OrbitControls::new(Point3::new(0.0, -5.0, 1.0), PhysicalSize::new(800.0, 600.0));
+        controls.set_up(Vector3::unit_z());
+        let start = controls.position;
+        assert!((start - Point3::new(0.0, -5.0, 1.0)).magnitude() < 1e-4, "{:?}", start);
+
+        // horizontal rotation keeps the height above the XY plane
+        controls.rotate_object(1.0);
+        assert!((controls.position.z - 1.0).abs() < 1e-4, "{:?}", controls.position);
+        assert!((controls.position.to_vec().magnitude() - start.to_vec().magnitude()).abs() < 1e-4);
+        // the view is upright: the up vector points to the top of the screen
+        let up = controls.view_matrix() * vec4(0.0, 0.0, 1.0, 0.0);
+        assert!(up.y > 0.0 && up.x.abs() < 1e-4, "{:?}", up);
+
+        // vertical rotation stops at the Z pole, not the Y one
+        controls.rotate_up(10.0);
+        controls.update(0.0);
+        let direction = controls.position.to_vec().normalize();
+        assert!(direction.z > 0.99, "{:?}", direction);
+    }
+
+    #[test]
+    fn rotate_speed_scales_rotation() {
+        let theta_delta = |rotate_speed: f32| {
+            let mut controls = OrbitControls::new(Point3::new(0.0, 0.0, 5.0), PhysicalSize::new(800.0, 600.0));
+            controls.rotate_speed = rotate_speed;
+            // no friction: the delta is kept after `update`
+            controls.enable_damping = true;
+            controls.damping_factor = 0.0;
+            controls.state = NavState::Rotating;
+            controls.rotate_start = Some(vec2(100.0, 100.0));
+            controls.handle_mouse_move(PhysicalPosition::new(140.0, 100.0), false);
+            controls.spherical_delta.theta
+        };
+        let (single, double) = (theta_delta(1.0), theta_delta(2.0));
+        assert!((single + 2.0 * PI * 40.0 / 800.0).abs() < 1e-5, "{}", single);
+        assert!((double - 2.0 * single).abs() < 1e-5, "{} vs. {}", double, single);
+    }
+
+    #[test]
+    fn walk_mode_keeps_height() {
+        let mut controls = OrbitControls::new(Point3::new(0.0, 2.0, 2.0), PhysicalSize::new(800.0, 600.0));
+        controls.walk_mode = true;
+        controls.process_keyboard(FORWARD, true);
+        controls.frame_update(1.0);
+        assert!((controls.position.y - 2.0).abs() < 1e-5, "{:?}", controls.position);
+        assert!((controls.position.z - (2.0 - SPEED)).abs() < 1e-5, "{:?}", controls.position);
+        assert!(controls.target.y.abs() < 1e-5, "{:?}", controls.target);
+        controls.process_keyboard(FORWARD, false);
+
+        // only up/down change it
+        controls.process_keyboard(UP, true);
+        controls.frame_update(0.5);
+        assert!((controls.position.y - (2.0 + SPEED / 2.0)).abs() < 1e-5, "{:?}", controls.position);
+    }
+
+    #[test]
+    fn load_state_stops_coasting() {
+        let mut controls = OrbitControls::new(Point3::new(1.0, 2.0, 5.0), PhysicalSize::new(800.0, 600.0));
+        controls.enable_damping = true;
+        let saved = controls.save_state();
+        controls.rotate_object(0.5);
+        controls.load_state(&saved);
+        let restored = controls.save_state();
+        assert!((restored.position - saved.position).magnitude() < 1e-4, "{:?}", restored);
+        controls.update(0.1);
+        assert!((controls.position - restored.position).magnitude() < 1e-6, "{:?}", controls.position);
+    }
+
+    #[test]
+    fn transition_glides_and_is_cancelled_by_input() {
+        let mut controls = OrbitControls::new(Point3::new(0.0, 0.0, 4.0), PhysicalSize::new(800.0, 600.0));
+        let to = CameraState {
+            position: Point3::new(4.0, 0.0, 0.0),
+            target: Point3::new(0.0, 0.0, 0.0),
+            fovy: Deg(60.0),
+        };
+        controls.transition_to(to, 1.0);
+        controls.update(0.5);
+        // halfway: the position in between, still looking at the target (which moves less than the camera)
+        assert!((controls.position - Point3::new(2.0, 0.0, 2.0)).magnitude() < 1e-4, "{:?}", controls.position);
+        let direction = (controls.target - controls.position).normalize();
+        assert!((direction - vec3(-1.0, 0.0, -1.0).normalize()).magnitude() < 1e-4, "{:?}", direction);
+        assert!((controls.camera.fovy.0 - (ZOOM + 60.0) / 2.0).abs() < 1e-3);
+
+        controls.update(0.6);
+        let state = controls.save_state();
+        assert!((state.position - to.position).magnitude() < 1e-4, "{:?}", state);
+        assert!((state.target - to.target).magnitude() < 1e-4, "{:?}", state);
+        assert_eq!(state.fovy, to.fovy);
+
+        // zooming stops a transition where it is
+        controls.transition_to(CameraState { position: Point3::new(0.0, 0.0, 4.0), ..to }, 1.0);
+        controls.update(0.5);
+        controls.process_mouse_scroll(0.0);
+        let stopped = controls.position;
+        controls.update(0.5);
+        assert!((controls.position - stopped).magnitude() < 1e-6, "{:?}", controls.position);
+    }
+
+    #[test]
+    fn set_camera_looks_at_target() {
+        let mut controls = OrbitControls::new(Point3::new(0.0, 0.0, 2.0), PhysicalSize::new(800.0, 600.0));
+        // away from the origin, turned to look along -X
+        let transform = Matrix4::from_translation(vec3(5.0, 1.0, -3.0)) * Matrix4::from_angle_y(Deg(90.0));
+        controls.set_camera(&Camera::default(), &transform);
+        assert_eq!(controls.position, Point3::new(5.0, 1.0, -3.0));
+        assert!((controls.target - Point3::new(3.0, 1.0, -3.0)).magnitude() < 1e-5, "{:?}", controls.target);
+
+        // the target is straight ahead in view space
+        let target = controls.view_matrix().transform_point(controls.target);
+        assert!(target.x.abs() < 1e-5 && target.y.abs() < 1e-5 && target.z < 0.0, "{:?}", target);
+    }
+
+    #[test]
+    fn fovy_is_clamped_and_animated() {
+        let mut controls = OrbitControls::new(Point3::new(0.0, 0.0, 2.0), PhysicalSize::new(800.0, 600.0));
+        controls.camera.set_fovy(Deg(200.0));
+        assert_eq!(controls.camera.fovy, Deg(MAX_ZOOM));
+        // the scroll wheel uses the same limits
+        controls.fov_zoom = true;
+        controls.process_mouse_scroll(10000.0);
+        assert_eq!(controls.camera.fovy, Deg(MIN_ZOOM));
+
+        controls.camera.set_fovy(Deg(60.0));
+        controls.animate_fovy(Deg(20.0), 2.0);
+        controls.update(1.0);
+        assert!((controls.camera.fovy.0 - 40.0).abs() < 1e-3, "{:?}", controls.camera.fovy);
+        assert!((controls.position - Point3::new(0.0, 0.0, 2.0)).magnitude() < 1e-5, "{:?}", controls.position);
+        controls.update(1.0);
+        assert_eq!(controls.camera.fovy, Deg(20.0));
+    }
+
+    #[test]
+    fn orthographic_zoom_scales_extents() {
+        let mut controls = OrbitControls::new(Point3::new(0.0, 0.0, 5.0), PhysicalSize::new(800.0, 600.0));
+        controls.camera = Camera::new_orthographic(4.0, 3.0, 0.1, 100.0);
+        assert!(!controls.camera.is_perspective());
+        controls.process_mouse_scroll(SCROLL_STEP);
+        let ymag = controls.camera.ymag.unwrap();
+        assert!((ymag - 3.0 / ZOOM_FACTOR).abs() < 1e-4, "{}", ymag);
+        assert!((controls.camera.xmag.unwrap() / ymag - 4.0 / 3.0).abs() < 1e-4);
+        assert!(((controls.position - controls.target).magnitude() - 5.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn touch_gestures() {
+        let mut controls = OrbitControls::new(Point3::new(0.0, 0.0, 4.0), PhysicalSize::new(800.0, 600.0));
+        let distance = |controls: &OrbitControls| (controls.position - controls.target).magnitude();
+
+        // pinching apart to twice the spread halves the distance
+        controls.handle_touch_start(1, PhysicalPosition::new(300.0, 300.0));
+        controls.handle_touch_start(2, PhysicalPosition::new(500.0, 300.0));
+        controls.handle_touch_move(1, PhysicalPosition::new(200.0, 300.0));
+        controls.handle_touch_move(2, PhysicalPosition::new(600.0, 300.0));
+        assert!((distance(&controls) - 2.0).abs() < 1e-4, "{}", distance(&controls));
+
+        // lifting a finger continues with the other one from where it is, without a jump
+        controls.handle_touch_end(1);
+        let position = controls.position;
+        controls.handle_touch_move(2, PhysicalPosition::new(600.0, 300.0));
+        assert!((controls.position - position).magnitude() < 1e-6, "{:?}", controls.position);
+        // and it rotates
+        controls.handle_touch_move(2, PhysicalPosition::new(640.0, 300.0));
+        assert!((controls.position - controls.target).x.abs() > 0.1, "{:?}", controls.position);
+        assert!((distance(&controls) - 2.0).abs() < 1e-4);
+
+        controls.handle_touch_end(2);
+        assert!(matches!(controls.state, NavState::None));
+    }
+
+    #[test]
+    fn ground_plane_keeps_camera_above_floor() {
+        let mut controls = OrbitControls::new(Point3::new(0.0, 1.0, 4.0), PhysicalSize::new(800.0, 600.0));
+        controls.ground_plane_y = Some(0.5);
+        // orbiting below the floor
+        controls.rotate_up(-1.0);
+        controls.update(0.0);
+        assert!(controls.position.y >= 0.5, "{:?}", controls.position);
+        assert_eq!(controls.target, Point3::new(0.0, 0.0, 0.0));
+
+        // moving down with the keyboard
+        controls.process_keyboard(DOWN, true);
+        controls.frame_update(10.0);
+        assert!(controls.position.y >= 0.5, "{:?}", controls.position);
+    }
+
+    #[test]
+    fn reset_returns_to_initial_view() {
+        let mut controls = OrbitControls::new(Point3::new(0.0, 0.0, 5.0), PhysicalSize::new(800.0, 600.0));
+        let initial = controls.save_state();
+        controls.rotate_object(1.0);
+        controls.pan(vec2(10.0, 5.0));
+        controls.update(0.0);
+        controls.dolly_in(2.0);
+        controls.update(0.0);
+        assert_ne!(controls.save_state(), initial);
+        controls.reset();
+        let view = controls.save_state();
+        assert!((view.position - initial.position).magnitude() < 1e-4, "{:?}", view);
+        assert_eq!(view.target, initial.target);
+
+        // glTF camera: back to its pose, not the default one
+        let transform = Matrix4::from_translation(vec3(1.0, 2.0, 3.0));
+        let mut camera = Camera::default();
+        camera.fovy = Deg(40.0);
+        controls.set_camera(&camera, &transform);
+        let gltf_view = controls.save_state();
+        controls.rotate_object(-0.5);
+        controls.reset();
+        let view = controls.save_state();
+        assert!((view.position - gltf_view.position).magnitude() < 1e-4, "{:?}", view);
+        assert!((view.target - gltf_view.target).magnitude() < 1e-4, "{:?}", view);
+        assert_eq!(view.fovy, Deg(40.0));
+    }
+
+    #[test]
+    fn auto_rotate_is_frame_rate_independent_and_pauses() {
+        let mut controls = OrbitControls::new(Point3::new(0.0, 0.0, 5.0), PhysicalSize::new(800.0, 600.0));
+        controls.auto_rotate = true;
+        controls.auto_rotate_speed = 90.0;
+        let theta = |controls: &OrbitControls| controls.position.x.atan2(controls.position.z);
+        for _ in 0..10 {
+            controls.update(0.05);
+        }
+        controls.update(0.5);
+        assert!((theta(&controls).abs() - PI / 2.0).abs() < 1e-4, "{}", theta(&controls));
+
+        controls.state = NavState::Rotating;
+        controls.update(1.0);
+        assert!((theta(&controls).abs() - PI / 2.0).abs() < 1e-4, "{}", theta(&controls));
+        controls.state = NavState::None;
+        controls.update(1.0);
+        assert!((theta(&controls).abs() - PI).abs() < 1e-3, "{}", theta(&controls));
+    }
+
+    #[test]
+    fn zoom_stays_within_distance_limits() {
+        let mut controls = OrbitControls::new(Point3::new(0.0, 0.0, 10.0), PhysicalSize::new(800.0, 600.0));
+        controls.set_distance_limits(2.0, 20.0);
+        for _ in 0..50 {
+            controls.process_mouse_scroll(SCROLL_STEP);
+        }
+        assert!((controls.position.z - 2.0).abs() < 1e-4, "{:?}", controls.position);
+        for _ in 0..50 {
+            controls.process_mouse_scroll(-SCROLL_STEP);
+        }
+        assert!((controls.position.z - 20.0).abs() < 1e-3, "{:?}", controls.position);
+    }
+
+    #[test]
+    fn orthographic_pan_follows_cursor() {
+        let mut controls = OrbitControls::new(Point3::new(0.0, 0.0, 5.0), PhysicalSize::new(800.0, 600.0));
+        controls.camera.xmag = Some(2.0);
+        controls.camera.ymag = Some(1.5);
+        // dragging across half of the window moves the view by half of its width (2 * xmag)
+        controls.pan(vec2(400.0, 0.0));
+        controls.update(0.0);
+        assert!((controls.target.x + 2.0).abs() < 1e-4, "{:?}", controls.target);
+        assert!(((controls.position - controls.target).magnitude() - 5.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn pan_then_rotate_keeps_radius() {
+        let mut controls = OrbitControls::new(Point3::new(0.0, 0.0, 5.0), PhysicalSize::new(800.0, 600.0));
+        controls.update(0.0);
+        for i in 0..1000 {
+            controls.pan(vec2(37.0, if i % 2 == 0 { 11.0 } else { -13.0 }));
+            controls.update(0.0);
+            controls.rotate_up(0.01);
+            controls.rotate_object(0.05);
+        }
+        let radius = (controls.position - controls.target).magnitude();
+        assert!((radius - 5.0).abs() < 1e-3, "radius {}", radius);
+        assert!(controls.target.to_vec().magnitude() > 1.0, "didn't pan: {:?}", controls.target);
+
+        // looking straight down
+        let mut controls = OrbitControls::new(Point3::new(0.0, 5.0, 0.0), PhysicalSize::new(800.0, 600.0));
+        controls.pan(vec2(10.0, 0.0));
+        controls.update(0.0);
+        assert!(controls.target.x.is_finite() && controls.position.x.is_finite(), "{:?}", controls.position);
+
+        // tiny residual offsets don't move the target
+        let target = controls.target;
+        controls.pan_left(1e-9);
+        controls.update(0.0);
+        assert_eq!(controls.target, target);
+
+        controls.recenter(Point3::new(0.0, 0.0, 0.0));
+        assert_eq!(controls.target, Point3::new(0.0, 0.0, 0.0));
+        let radius = (controls.position - controls.target).magnitude();
+        assert!((radius - 5.0).abs() < 1e-3, "radius {}", radius);
     }
 }
@@ -1,29 +1,82 @@
 use std::ptr;
 
 use gl;
+use gl::types::GLenum;
 
 #[derive(Debug)]
 pub struct Framebuffer {
-    pub id: u32
+    pub id: u32,
+    texture_colorbuffer: u32,
+    /// instead of the texture for multisampled framebuffers
+    color_rbo: u32,
+    rbo: u32,
 }
 
 impl Framebuffer {
     pub fn new(width: u32, height: u32) -> Framebuffer {
+        Self::with_format(width, height, gl::RGBA, gl::UNSIGNED_BYTE)
+    }
+
+    /// Framebuffer with a 32 bit float color attachment, i.e. values aren't clamped to [0, 1]
+    pub fn new_hdr(width: u32, height: u32) -> Framebuffer {
+        Self::with_format(width, height, gl::RGBA32F, gl::FLOAT)
+    }
+
+    /// Framebuffer with `samples` samples per pixel (8 bit per channel), for rendering with MSAA.
+    /// Resolve it by blitting into a framebuffer of the same size.
+    pub fn new_multisampled(width: u32, height: u32, samples: u32) -> Framebuffer {
         let mut framebuffer = 0;
+        let mut color_rbo = 0;
+        let mut rbo = 0;
+        unsafe {
+            gl::GenFramebuffers(1, &mut framebuffer);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, framebuffer);
+            gl::GenRenderbuffers(1, &mut color_rbo);
+            gl::BindRenderbuffer(gl::RENDERBUFFER, color_rbo);
+            gl::RenderbufferStorageMultisample(gl::RENDERBUFFER, samples as i32, gl::RGBA8, width as i32, height as i32);
+            gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::RENDERBUFFER, color_rbo);
+            gl::GenRenderbuffers(1, &mut rbo);
+            gl::BindRenderbuffer(gl::RENDERBUFFER, rbo);
+            gl::RenderbufferStorageMultisample(gl::RENDERBUFFER, samples as i32, gl::DEPTH24_STENCIL8,
+                width as i32, height as i32);
+            gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, gl::DEPTH_STENCIL_ATTACHMENT, gl::RENDERBUFFER, rbo);
+            if gl::CheckFramebufferStatus(gl::FRAMEBUFFER) != gl::FRAMEBUFFER_COMPLETE {
+                panic!("ERROR::FRAMEBUFFER:: Multisampled framebuffer is not complete!");
+            }
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+
+        Framebuffer {
+            id: framebuffer,
+            texture_colorbuffer: 0,
+            color_rbo,
+            rbo,
+        }
+    }
+
+    /// Maximum sample count of `new_multisampled`
+    pub fn max_samples() -> u32 {
+        let mut max_samples = 0;
+        unsafe { gl::GetIntegerv(gl::MAX_SAMPLES, &mut max_samples) }
+        max_samples.max(0) as u32
+    }
+
+    fn with_format(width: u32, height: u32, internal_format: GLenum, type_: GLenum) -> Framebuffer {
+        let mut framebuffer = 0;
+        let mut texture_colorbuffer = 0;
+        let mut rbo = 0;
         unsafe {
             gl::GenFramebuffers(1, &mut framebuffer);
             gl::BindFramebuffer(gl::FRAMEBUFFER, framebuffer);
             // create a color attachment texture
-            let mut texture_colorbuffer = 0;
             gl::GenTextures(1, &mut texture_colorbuffer);
             gl::BindTexture(gl::TEXTURE_2D, texture_colorbuffer);
-            gl::TexImage2D(gl::TEXTURE_2D, 0, gl::RGBA as i32, width as i32, height as i32,
-                0, gl::RGBA, gl::UNSIGNED_BYTE, ptr::null());
+            gl::TexImage2D(gl::TEXTURE_2D, 0, internal_format as i32, width as i32, height as i32,
+                0, gl::RGBA, type_, ptr::null());
             gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
             gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
             gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, texture_colorbuffer, 0);
             // create a renderbuffer object for depth and stencil attachment (we won't be sampling these)
-            let mut rbo = 0;
             gl::GenRenderbuffers(1, &mut rbo);
             gl::BindRenderbuffer(gl::RENDERBUFFER, rbo);
             gl::RenderbufferStorage(gl::RENDERBUFFER, gl::DEPTH24_STENCIL8, width as i32, height as i32); // use a single renderbuffer object for both a depth AND stencil buffer.
@@ -36,7 +89,10 @@ impl Framebuffer {
         }
 
         Framebuffer {
-            id: framebuffer
+            id: framebuffer,
+            texture_colorbuffer,
+            color_rbo: 0,
+            rbo,
         }
     }
 
@@ -48,4 +104,15 @@ impl Framebuffer {
     pub fn unbind(&self) {
         unsafe { gl::BindFramebuffer(gl::FRAMEBUFFER, 0) }
     }
+
+    /// Free the GPU resources (not done on drop, since the headless framebuffer
+    /// is only kept bound, not stored)
+    pub fn delete(self) {
+        unsafe {
+            gl::DeleteFramebuffers(1, &self.id);
+            gl::DeleteTextures(1, &self.texture_colorbuffer);
+            gl::DeleteRenderbuffers(1, &self.color_rbo);
+            gl::DeleteRenderbuffers(1, &self.rbo);
+        }
+    }
 }
@@ -1,9 +1,224 @@
+use std::fs;
+use std::io;
+use std::ops::Deref;
+use std::sync::Arc;
+
 use gltf;
+use gltf::mesh::{Mode, Semantic};
+use log::{error, info};
+use memmap::Mmap;
+
+use crate::viewer::{LoadOptions, UpAxis};
 
 /// Helps to simplify the signature of import related functions.
 pub struct ImportData {
     pub doc: gltf::Document,
-    pub buffers: Vec<gltf::buffer::Data>,
+    pub buffers: Vec<BufferData>,
+    /// NOTE: empty when memory-mapped (textures are decoded from the buffers/files directly)
     pub images: Vec<gltf::image::Data>,
+    pub options: LoadOptions,
+    /// The raw document JSON, for extensions the `gltf` crate doesn't support
+    pub json: serde_json::Value,
+}
+
+/// Contents of a buffer: read into memory, or a slice of a memory-mapped .glb (`--mmap`)
+pub enum BufferData {
+    Owned(gltf::buffer::Data),
+    Mapped { map: Arc<Mmap>, offset: usize, length: usize },
+}
+
+impl Deref for BufferData {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            BufferData::Owned(data) => &data.0,
+            BufferData::Mapped { map, offset, length } => &map[*offset..*offset + *length],
+        }
+    }
+}
+
+/// Parses the JSON of a .gltf file or the JSON chunk of a .glb (`Null` if invalid)
+pub fn parse_json(bytes: &[u8]) -> serde_json::Value {
+    let json = if bytes.starts_with(b"glTF") && bytes.len() >= 20 {
+        // 12 byte header, then the JSON chunk (length, type, data)
+        let length = u32::from_le_bytes([bytes[12], bytes[13], bytes[14], bytes[15]]) as usize;
+        &bytes[20..bytes.len().min(20 + length)]
+    } else {
+        bytes
+    };
+    serde_json::from_slice(json).unwrap_or(serde_json::Value::Null)
+}
+
+/// Extensions implemented by the viewer (on top of the core spec), i.e. files requiring only these can be loaded
+pub const SUPPORTED_EXTENSIONS: &[&str] = &[
+    "EXT_mesh_gpu_instancing",
+    "KHR_lights_punctual",
+    "KHR_materials_emissive_strength",
+    "KHR_materials_unlit",
+    "KHR_materials_variants",
+    "KHR_mesh_quantization",
+    "KHR_texture_transform",
+    "MSFT_lod",
+];
+
+/// `extensionsRequired` of the JSON that aren't in `SUPPORTED_EXTENSIONS`
+pub fn unsupported_required_extensions(json: &serde_json::Value) -> Vec<&str> {
+    json["extensionsRequired"].as_array()
+        .map(|extensions| extensions.iter()
+            .filter_map(|extension| extension.as_str())
+            .filter(|extension| !SUPPORTED_EXTENSIONS.contains(extension))
+            .collect())
+        .unwrap_or_default()
+}
+
+const DRACO: &str = "KHR_draco_mesh_compression";
+
+/// Fails if the file requires an unsupported extension (the geometry or materials couldn't be
+/// displayed correctly), with an `InvalidData` error naming the extensions
+fn check_required_extensions(json: &serde_json::Value) -> gltf::Result<()> {
+    let unsupported = unsupported_required_extensions(json);
+    if unsupported.is_empty() {
+        if json["extensionsUsed"].as_array().into_iter().flatten().any(|extension| extension == DRACO) {
+            info!("Using the uncompressed fallback data of {} primitives", DRACO);
+        }
+        return Ok(())
+    }
+    if unsupported.contains(&DRACO) {
+        error!("Hint: Draco-compressed geometry can't be decoded yet; re-export the model without Draco compression");
+    }
+    let message = format!("unsupported required extension(s): {}", unsupported.join(", "));
+    Err(gltf::Error::Io(io::Error::new(io::ErrorKind::InvalidData, message)))
 }
 
+/// Generators (lower case) that export Z-up by default, like IfcConvert without `--y-up`
+const Z_UP_GENERATORS: &[&str] = &["ifcopenshell"];
+
+impl ImportData {
+    /// Imports the document, buffers and images.
+    /// Doesn't touch OpenGL, so it can run on a background thread.
+    pub fn import(source: &str, options: &LoadOptions) -> gltf::Result<ImportData> {
+        if options.mmap {
+            if let Some(imp) = Self::import_mapped(source, options)? {
+                check_required_extensions(&imp.json)?;
+                return Ok(imp)
+            }
+            info!("Not memory-mapping {} (only .glb files with all data in the binary chunk)", source);
+        }
+        let (doc, buffers, images) = gltf::import(source)?;
+        let json = fs::read(source)
+            .map(|bytes| parse_json(&bytes))
+            .unwrap_or(serde_json::Value::Null);
+        check_required_extensions(&json)?;
+        let buffers = buffers.into_iter().map(BufferData::Owned).collect();
+        Ok(ImportData { doc, buffers, images, options: options.clone(), json })
+    }
+
+    /// Memory-maps a .glb and uses its binary chunk as buffer without copying it.
+    /// `None` if the file isn't a .glb or has buffers elsewhere (external files, data URIs).
+    fn import_mapped(source: &str, options: &LoadOptions) -> gltf::Result<Option<ImportData>> {
+        let file = fs::File::open(source).map_err(gltf::Error::Io)?;
+        // NOTE: the file must not be modified while mapped
+        let map = unsafe { Mmap::map(&file) }.map_err(gltf::Error::Io)?;
+        if !map.starts_with(b"glTF") || map.len() < 20 {
+            return Ok(None)
+        }
+        let read_u32 = |offset: usize| {
+            u32::from_le_bytes([map[offset], map[offset + 1], map[offset + 2], map[offset + 3]]) as usize
+        };
+        // 12 byte header, JSON chunk (length, type, data), optional BIN chunk
+        let json_length = read_u32(12);
+        let json_end = 20 + json_length;
+        if json_end > map.len() {
+            return Err(gltf::Error::Io(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated JSON chunk")))
+        }
+        let json_bytes = &map[20..json_end];
+        let doc = gltf::Gltf::from_slice(json_bytes)?.document;
+
+        let bin = if json_end + 8 <= map.len() {
+            let length = read_u32(json_end).min(map.len() - json_end - 8);
+            Some((json_end + 8, length))
+        } else {
+            None
+        };
+        let mut buffers = Vec::new();
+        for buffer in doc.buffers() {
+            match (buffer.source(), bin) {
+                (gltf::buffer::Source::Bin, Some((offset, length))) => buffers.push((offset, length)),
+                _ => return Ok(None),
+            }
+        }
+
+        let json = serde_json::from_slice(json_bytes).unwrap_or(serde_json::Value::Null);
+        let map = Arc::new(map);
+        let buffers = buffers.into_iter()
+            .map(|(offset, length)| BufferData::Mapped { map: Arc::clone(&map), offset, length })
+            .collect();
+        info!("Memory-mapped {}", source);
+        Ok(Some(ImportData { doc, buffers, images: Vec::new(), options: options.clone(), json }))
+    }
+
+    /// Raw JSON of the extension `name` of an object,
+    /// e.g. `extension("materials", 0, "KHR_materials_emissive_strength")`
+    pub fn extension(&self, collection: &str, index: usize, name: &str) -> Option<&serde_json::Value> {
+        self.json.get(collection)?.get(index)?.get("extensions")?.get(name)
+    }
+
+    /// The up axis given with `--up`, or for `auto`, as hinted by the file:
+    /// an `upAxis` (or `up_axis`) asset extra, or a generator known to export Z-up
+    pub fn up_axis(&self) -> UpAxis {
+        if self.options.up != UpAxis::Auto {
+            return self.options.up
+        }
+        let asset = &self.json["asset"];
+        let extras = &asset["extras"];
+        let hint = extras["upAxis"].as_str().or_else(|| extras["up_axis"].as_str());
+        if let Some(hint) = hint {
+            match hint.to_lowercase().as_str() {
+                "z" => return UpAxis::Z,
+                "y" => return UpAxis::Y,
+                _ => (),
+            }
+        }
+        let generator = asset["generator"].as_str().unwrap_or("").to_lowercase();
+        if Z_UP_GENERATORS.iter().any(|g| generator.contains(g)) {
+            return UpAxis::Z
+        }
+        UpAxis::Y
+    }
+
+    /// Total number of triangles of all meshes, determined from the accessor sizes only.
+    pub fn triangle_count(&self) -> usize {
+        self.doc.meshes()
+            .flat_map(|mesh| mesh.primitives())
+            .map(|prim| {
+                let count = prim.indices()
+                    .or_else(|| prim.get(&Semantic::Positions))
+                    .map(|accessor| accessor.count())
+                    .unwrap_or(0);
+                match prim.mode() {
+                    Mode::Triangles => count / 3,
+                    Mode::TriangleStrip | Mode::TriangleFan => count.saturating_sub(2),
+                    _ => 0,
+                }
+            })
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unsupported_required_extensions_are_reported() {
+        let json = serde_json::json!({
+            "extensionsUsed": ["KHR_texture_transform", "KHR_draco_mesh_compression", "KHR_materials_sheen"],
+            "extensionsRequired": ["KHR_texture_transform", "KHR_draco_mesh_compression"],
+        });
+        assert_eq!(unsupported_required_extensions(&json), vec!["KHR_draco_mesh_compression"]);
+        assert!(check_required_extensions(&json).is_err());
+        // only used: the uncompressed fallback is loaded
+        assert!(check_required_extensions(&serde_json::json!({"extensionsUsed": ["KHR_draco_mesh_compression"]})).is_ok());
+    }
+}
@@ -0,0 +1,95 @@
+use std::str::FromStr;
+
+use collision::Aabb;
+
+use crate::render::math::*;
+
+/// Placement of several models passed at once (`--layout`)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Layout {
+    /// side by side along the X axis
+    Row,
+    /// in a (roughly square) grid on the XZ plane
+    Grid,
+    /// as they are in their files (e.g. for composing)
+    None,
+}
+
+impl FromStr for Layout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "row" => Ok(Layout::Row),
+            "grid" => Ok(Layout::Grid),
+            "none" => Ok(Layout::None),
+            _ => Err(format!("unknown layout: {}", s)),
+        }
+    }
+}
+
+impl Layout {
+    /// Translations for models with the given bounds so that they don't overlap.
+    /// The first model stays in place, the others are aligned to its bottom (and center).
+    pub fn offsets(self, bounds: &[Aabb3]) -> Vec<Vector3> {
+        let first = match bounds.first() {
+            Some(first) => first,
+            None => return vec![],
+        };
+        let largest = bounds.iter()
+            .map(|b| b.max - b.min)
+            .fold(Vector3::zero(), |a, b| vec3(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z)));
+        let gap = (largest.magnitude() * 0.1).max(0.01);
+        let align = |b: &Aabb3, center: Point3| vec3(center.x - b.center().x, first.min.y - b.min.y, center.z - b.center().z);
+
+        match self {
+            Layout::Row => {
+                let mut x = first.max.x + gap;
+                let mut offsets = vec![Vector3::zero()];
+                for b in &bounds[1..] {
+                    let width = b.max.x - b.min.x;
+                    offsets.push(align(b, Point3::new(x + width / 2.0, 0.0, first.center().z)));
+                    x += width + gap;
+                }
+                offsets
+            },
+            Layout::Grid => {
+                let columns = (bounds.len() as f32).sqrt().ceil() as usize;
+                let cell = vec2(largest.x + gap, largest.z + gap);
+                let start = first.center();
+                bounds.iter().enumerate()
+                    .map(|(i, b)| if i == 0 {
+                        Vector3::zero()
+                    } else {
+                        let (column, row) = ((i % columns) as f32, (i / columns) as f32);
+                        align(b, Point3::new(start.x + column * cell.x, 0.0, start.z + row * cell.y))
+                    })
+                    .collect()
+            },
+            Layout::None => vec![Vector3::zero(); bounds.len()],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn row_layout_does_not_overlap() {
+        let unit = Aabb3::new(Point3::new(-0.5, -0.5, -0.5), Point3::new(0.5, 0.5, 0.5));
+        let raised = Aabb3::new(Point3::new(2.0, 1.0, 3.0), Point3::new(4.0, 2.0, 4.0));
+        let offsets = Layout::Row.offsets(&[unit, raised, unit]);
+        assert_eq!(offsets[0], Vector3::zero());
+
+        let placed: Vec<Aabb3> = [unit, raised, unit].iter().zip(&offsets)
+            .map(|(b, offset)| Aabb3::new(b.min + *offset, b.max + *offset))
+            .collect();
+        for pair in placed.windows(2) {
+            assert!(pair[1].min.x > pair[0].max.x, "{:?}", pair);
+            // standing on the same ground
+            assert!((pair[1].min.y - pair[0].min.y).abs() < 1e-6, "{:?}", pair);
+        }
+        assert!((placed[1].center().z - unit.center().z).abs() < 1e-6);
+    }
+}
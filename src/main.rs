@@ -3,25 +3,37 @@
 // #![feature(test)]
 #![allow(clippy::cast_lossless, clippy::cyclomatic_complexity)]
 
+use std::io;
+use std::path::Path;
+use std::process;
+
 use clap::crate_version;
 use cgmath;
 use cgmath::Deg;
 
-use clap::{Arg, App, AppSettings};
+use clap::{Arg, App, AppSettings, ArgMatches};
 
-use log::warn;
+use log::{error, warn};
 
 use simplelog::{TermLogger, LevelFilter, ConfigBuilder as LogConfigBuilder, TerminalMode};
 
 mod utils;
 mod viewer;
-use crate::viewer::{GltfViewer, CameraOptions};
+use crate::viewer::{Background, GltfViewer, CameraOptions, LoadOptions, RenderOptions, WindowOptions};
 
 mod shader;
+mod audit;
+mod commands;
 mod controls;
+use crate::controls::SmoothZoom;
 mod framebuffer;
 mod macros;
 mod importdata;
+mod layout;
+mod playlist;
+use crate::playlist::Playlist;
+mod screenshot;
+use crate::screenshot::ColorSpace;
 // TODO!: adapt Source...
 // mod http_source;
 // use http_source::HttpSource;
@@ -29,16 +41,162 @@ mod render;
 use crate::render::math::*;
 
 pub fn main() {
-    let args = App::new("gltf-viewer")
+    let args = app().get_matches();
+
+    let width: u32 = args.value_of("WIDTH").unwrap().parse().unwrap();
+    let height: u32 = args.value_of("HEIGHT").unwrap().parse().unwrap();
+    let count: u32 = args.value_of("COUNT").unwrap().parse().unwrap();
+
+    let load_options = load_options(&args);
+
+    let render_options = RenderOptions {
+        sort_front_to_back: args.is_present("sort-front-to-back"),
+        background: args.value_of("BACKGROUND").map(|value| value.parse().unwrap()),
+        wireframe: args.value_of("wireframe").unwrap().parse().unwrap(),
+        wire_color: args.value_of("WIRE-COLOR").map(|v| parse_vec3(v).unwrap()).unwrap(),
+        wire_width: args.value_of("WIRE-WIDTH").unwrap().parse().unwrap(),
+        ssao_radius: args.value_of("SSAO-RADIUS").map(|n| n.parse().unwrap()),
+        ssao_intensity: args.value_of("SSAO-INTENSITY").unwrap().parse().unwrap(),
+        shadow_bias: args.value_of("SHADOW-BIAS").unwrap().parse().unwrap(),
+        msaa: args.value_of("MSAA").unwrap().parse().unwrap(),
+        render_scale: args.value_of("RENDER-SCALE").unwrap().parse().unwrap(),
+        upscale: args.value_of("upscale").unwrap().parse().unwrap(),
+        tone_map: args.value_of("tonemap").unwrap().parse().unwrap(),
+        exposure: args.value_of("EXPOSURE").unwrap().parse().unwrap(),
+        clear_on_resize: !args.is_present("no-clear-on-resize"),
+    };
+
+    let camera_options = CameraOptions {
+        index: args.value_of("CAM-INDEX").map(|n| n.parse().unwrap()).unwrap(),
+        position: args.value_of("CAM-POS").map(|v| parse_vec3(v).unwrap()),
+        target: args.value_of("CAM-TARGET").map(|v| parse_vec3(v).unwrap()),
+        up: args.value_of("CAM-UP").map(|v| parse_vec3(v).unwrap()),
+        fovy: args.value_of("CAM-FOVY").map(|n| Deg(n.parse().unwrap())).unwrap(),
+        straight: args.is_present("straight"),
+        walk: args.is_present("walk"),
+        ground_plane: args.value_of("GROUND-PLANE").map(|n| n.parse().unwrap()),
+        snap_angle: args.value_of("SNAP-ANGLE").map(|n| Deg(n.parse().unwrap())).unwrap(),
+        rpm: args.value_of("RPM").map(|n| n.parse().unwrap()),
+        zoom_factor: args.value_of("ZOOM-FACTOR").unwrap().parse().unwrap(),
+        rotate_speed: args.value_of("ROTATE-SPEED").unwrap().parse().unwrap(),
+        pan_speed: args.value_of("PAN-SPEED").unwrap().parse().unwrap(),
+        zoom_speed: args.value_of("ZOOM-SPEED").unwrap().parse().unwrap(),
+        fov_zoom: args.is_present("fov-zoom"),
+        polar_limits: args.value_of("POLAR-LIMITS")
+            .map(|v| parse_range(v).map(|(min, max)| (Deg(min), Deg(max))).unwrap()),
+        min_distance: args.value_of("MIN-DISTANCE").map(|n| n.parse().unwrap()).unwrap_or(controls::MIN_DISTANCE),
+        max_distance: args.value_of("MAX-DISTANCE").map(|n| n.parse().unwrap()).unwrap_or(controls::MAX_DISTANCE),
+        damping: args.value_of("DAMPING").map(|n| n.parse().unwrap()),
+        smooth_zoom: args.value_of("smooth-zoom")
+            .or_else(|| if args.is_present("DAMPING") { Some("expo") } else { None })
+            .map(|easing| SmoothZoom {
+                easing: easing.parse().unwrap(),
+                duration: args.value_of("SMOOTH-ZOOM-DURATION").unwrap().parse().unwrap(),
+            }),
+    };
+
+    let log_level = match args.occurrences_of("verbose") {
+        0 => LevelFilter::Warn,
+        1 => LevelFilter::Info,
+        2 => LevelFilter::Debug,
+        _ => LevelFilter::Trace
+    };
+
+    let _ = TermLogger::init(
+        log_level,
+        LogConfigBuilder::new()
+            .set_time_level(LevelFilter::Off)
+            .set_target_level(LevelFilter::Off)
+            .set_thread_level(LevelFilter::Off)
+            .build(),
+        TerminalMode::Stdout);
+
+    let playlist = args.value_of("playlist").map(|dir| {
+        match Playlist::from_dir(Path::new(dir), load_options.clone()) {
+            Ok(ref playlist) if playlist.is_empty() => {
+                error!("No .gltf/.glb files found in {}", dir);
+                process::exit(1)
+            },
+            Ok(playlist) => playlist,
+            Err(err) => {
+                error!("Failed to read playlist directory {}: {}", dir, err);
+                process::exit(1)
+            },
+        }
+    });
+    let sources: Vec<String> = match playlist {
+        Some(ref playlist) => vec![playlist.current().to_string_lossy().into_owned()],
+        None => args.values_of("FILE").unwrap().map(|s| s.to_owned()).collect(),
+    };
+    let source = &sources[0];
+
+    let screenshot = args.value_of("screenshot").or_else(|| args.value_of("output"));
+    if args.is_present("headless") && screenshot.is_none() && !args.is_present("stdin") {
+        error!("--headless needs a screenshot file (-o) or --stdin, there is no window to show");
+        process::exit(1)
+    }
+
+    let window_options = WindowOptions {
+        width,
+        height,
+        headless: args.is_present("headless"),
+        visible: screenshot.is_none() && !args.is_present("stdin"),
+    };
+    let mut viewer = GltfViewer::new(&sources, window_options, camera_options, load_options, render_options);
+
+    if let Some(path) = args.value_of("REPORT") {
+        viewer.write_report(path, source);
+    }
+
+    if args.is_present("stdin") {
+        let stdin = io::stdin();
+        viewer.run_commands(stdin.lock());
+        return;
+    }
+
+    if let Some(filename) = screenshot {
+        let color_space: ColorSpace = args.value_of("color-space").unwrap().parse().unwrap();
+
+        let extension = color_space.file_extension();
+        if !filename.to_lowercase().ends_with(&format!(".{}", extension)) {
+            warn!("filename should end with .{}", extension);
+        }
+        if count > 1 {
+            viewer.multiscreenshot(filename, count, color_space)
+        } else {
+            viewer.screenshot(filename, color_space)
+        }
+        return;
+    }
+
+    if let Some(playlist) = playlist {
+        viewer.set_playlist(playlist);
+    }
+    if let Some(path) = args.value_of("HOME-FILE") {
+        viewer.set_home_file(path);
+    }
+
+    viewer.start_render_loop();
+}
+
+/// The command line interface
+fn app() -> App<'static, 'static> {
+    App::new("gltf-viewer")
         .version(option_env!("VERSION").unwrap_or(crate_version!()))
         .setting(AppSettings::UnifiedHelpMessage)
         .setting(AppSettings::DeriveDisplayOrder)
         .before_help("glTF 2.0 viewer\n\nNavigate with the mouse (left/right click + drag, mouse wheel) \
                     or WASD/cursor keys.")
         .arg(Arg::with_name("FILE") // TODO!: re-add URL when fixed...
-            .required(true)
+            .required_unless("playlist")
             .takes_value(true)
-            .help("glTF file name"))
+            .multiple(true)
+            .help("glTF file name(s). Several files are shown together in one scene (see --layout)"))
+        .arg(Arg::with_name("playlist")
+            .long("playlist")
+            .value_name("DIR")
+            .conflicts_with("FILE")
+            .help("View all .gltf/.glb files of a directory, switching with PageDown/PageUp"))
         .arg(Arg::with_name("verbose")
             .long("verbose")
             .short("v")
@@ -48,7 +206,19 @@ pub fn main() {
             .long("screenshot")
             .short("s")
             .value_name("FILE")
-            .help("Create screenshot (PNG)"))
+            .help("Create screenshot (PNG, or EXR with `--color-space linear`)"))
+        .arg(Arg::with_name("output")
+            .long("output")
+            .short("o")
+            .value_name("FILE")
+            .conflicts_with("screenshot")
+            .help("Same as --screenshot, e.g. for `--headless model.glb -o out.png`"))
+        .arg(Arg::with_name("color-space")
+            .long("color-space")
+            .takes_value(true)
+            .possible_values(&["srgb", "linear", "rec709"])
+            .default_value("srgb")
+            .help("Color encoding of screenshots: sRGB (tagged PNG), linear (EXR) or Rec.709 (PNG)"))
         .arg(Arg::with_name("WIDTH")
             .long("width")
             .short("w")
@@ -67,6 +237,10 @@ pub fn main() {
             .default_value("1")
             .help("Saves N screenshots of size WxH, rotating evenly spaced around the object")
             .validator(|value| value.parse::<u32>().map(|_| ()).map_err(|err| err.to_string())))
+        .arg(Arg::with_name("stdin")
+            .long("stdin")
+            .help("Read commands from stdin (load <path>, camera <json>, frame, screenshot <path>, \
+                scene <name>, animation <name>, time <seconds>, play, pause, quit) instead of opening an interactive window"))
         .arg(Arg::with_name("headless")
             .long("headless")
             .help("Use real headless rendering for screenshots (default is a hidden window) [EXPERIMENTAL - see README for details]"))
@@ -75,9 +249,218 @@ pub fn main() {
             .help("Position camera in front of model if using default camera (i.e. glTF doesn't contain a camera or `--cam-index -1` is passed)"))
         .arg(Arg::with_name("scene")
             .long("scene")
+            .takes_value(true)
+            .value_name("INDEX")
+            .help("Index of the scene to load [default: the file's default scene]")
+            .validator(|value| value.parse::<u32>().map(|_| ()).map_err(|err| err.to_string())))
+        .arg(Arg::with_name("MAX-TRIANGLES")
+            .long("max-triangles")
+            .takes_value(true)
+            .help("Abort loading if the file contains more than N triangles (counted before uploading to the GPU)")
+            .validator(|value| value.parse::<usize>().map(|_| ()).map_err(|err| err.to_string())))
+        .arg(Arg::with_name("DEBUG-ATTRIBUTE")
+            .long("debug-attribute")
+            .takes_value(true)
+            .help("Visualize the vertex attribute with the given name (e.g. _BATCHID) as color instead of shading"))
+        .arg(Arg::with_name("INSTANCE-COLOR")
+            .long("instance-color")
+            .takes_value(true)
+            .default_value("_COLOR")
+            .help("Per-instance attribute (EXT_mesh_gpu_instancing) to tint the instances with: \
+                   an RGB(A) color, or a scalar like a feature ID (one color per value)"))
+        .arg(Arg::with_name("force-two-sided-normals")
+            .long("force-two-sided-normals")
+            .help("Light thin surfaces from both sides, even if their material isn't doubleSided (diagnostic)"))
+        .arg(Arg::with_name("audit")
+            .long("audit")
+            .help("Report declared but unused resources: materials, textures, images, accessors, buffer views \
+                   and empty nodes"))
+        .arg(Arg::with_name("REPORT")
+            .long("report")
+            .takes_value(true)
+            .help("Write a JSON report of the loaded scene to the given file: bounds (box + sphere), camera, \
+                   render stats and glTF extensions"))
+        .arg(Arg::with_name("mmap")
+            .long("mmap")
+            .help("Memory-map .glb files instead of reading them into memory (lower peak memory usage for \
+                   huge files; others are read as usual)"))
+        .arg(Arg::with_name("DEFAULT-COLOR")
+            .long("default-color")
+            .takes_value(true)
+            .help("Base color of primitives without a material as comma-separated RGB in [0, 1] \
+                   [default: 1,1,1 (glTF default material)]")
+            .validator(|value| match value.split(',').count() {
+                3 => parse_vec3(&value).map(|_| ()).map_err(|err| err.to_string()),
+                _ => Err("expected 3 comma-separated values".to_owned()),
+            }))
+        .arg(Arg::with_name("DEFAULT-METALLIC")
+            .long("default-metallic")
+            .takes_value(true)
+            .help("Metallic factor of primitives without a material [default: 1 (glTF default material)]")
+            .validator(validate_unit_interval))
+        .arg(Arg::with_name("DEFAULT-ROUGHNESS")
+            .long("default-roughness")
+            .takes_value(true)
+            .help("Roughness factor of primitives without a material [default: 1 (glTF default material)]")
+            .validator(validate_unit_interval))
+        .arg(Arg::with_name("auto-fix-winding")
+            .long("auto-fix-winding")
+            .help("Flip the triangle winding of primitives whose vertex normals mostly disagree with it \
+                   (rescue for inconsistently exported assets, logs each repair)"))
+        .arg(Arg::with_name("smooth-normals")
+            .long("smooth-normals")
+            .help("Shade primitives without normals with averaged vertex normals (default: flat normals)"))
+        .arg(Arg::with_name("up")
+            .long("up")
+            .takes_value(true)
+            .possible_values(&["auto", "y", "z"])
+            .default_value("auto")
+            .help("Up axis of the model; Z-up models are rotated to Y-up. \
+                auto: Y-up unless the file hints otherwise (asset extras `upAxis`, known Z-up generators)"))
+        .arg(Arg::with_name("layout")
+            .long("layout")
+            .takes_value(true)
+            .possible_values(&["row", "grid", "none"])
+            .default_value("row")
+            .help("Placement of several models so that they don't overlap (none: as they are in their files)"))
+        .arg(Arg::with_name("LOD")
+            .long("lod")
+            .takes_value(true)
+            .default_value("0")
+            .help("MSFT_lod level to draw (0: highest detail, switch with L)")
+            .validator(|value| value.parse::<usize>().map(|_| ()).map_err(|err| err.to_string())))
+        .arg(Arg::with_name("ANISOTROPY")
+            .long("anisotropy")
+            .takes_value(true)
+            .help("Anisotropic texture filtering with this maximum anisotropy, e.g. 16 (sharper textures at grazing angles)")
+            .validator(|value| value.parse::<f32>().map(|_| ()).map_err(|err| err.to_string())))
+        .arg(Arg::with_name("MIP-BIAS")
+            .long("mip-bias")
+            .takes_value(true)
+            .allow_hyphen_values(true)
+            .default_value("0")
+            .help("Texture mipmap level bias: negative is sharper (at the cost of aliasing), positive softer")
+            .validator(|value| value.parse::<f32>().map(|_| ()).map_err(|err| err.to_string())))
+        .arg(Arg::with_name("sort-front-to-back")
+            .long("sort-front-to-back")
+            .help("Draw opaque primitives sorted front-to-back (faster for heavy scenes due to early depth rejection)"))
+        .arg(Arg::with_name("wireframe")
+            .long("wireframe")
+            .takes_value(true)
+            .possible_values(&["off", "on", "overlay"])
+            .default_value("off")
+            .help("Initial wireframe mode (cycle with F2), overlay: on top of the shaded scene"))
+        .arg(Arg::with_name("WIRE-COLOR")
+            .long("wire-color")
+            .takes_value(true)
+            .default_value("1,1,1")
+            .help("Color of the wireframe overlays (F2) as comma-separated RGB in [0, 1]. Example: 1,1,0")
+            .validator(|value| match value.split(',').count() {
+                3 => parse_vec3(&value).map(|_| ()).map_err(|err| err.to_string()),
+                _ => Err("expected 3 comma-separated values".to_owned()),
+            }))
+        .arg(Arg::with_name("WIRE-WIDTH")
+            .long("wire-width")
+            .takes_value(true)
+            .default_value("1")
+            .help("Line width in pixels of the wireframe and debug overlays (clamped to the supported range)")
+            .validator(|value| value.parse::<f32>().map(|_| ()).map_err(|err| err.to_string())))
+        .arg(Arg::with_name("ssao")
+            .long("ssao")
+            .help("Screen-space ambient occlusion: darken creases and corners (multiplied into the ambient light)"))
+        .arg(Arg::with_name("SSAO-RADIUS")
+            .long("ssao-radius")
+            .takes_value(true)
+            .help("SSAO sample radius in world units [default: 5% of the model's bounding box diagonal]")
+            .validator(|value| match value.parse::<f32>() {
+                Ok(radius) if radius > 0.0 => Ok(()),
+                Ok(_) => Err("must be positive".to_owned()),
+                Err(err) => Err(err.to_string()),
+            }))
+        .arg(Arg::with_name("SSAO-INTENSITY")
+            .long("ssao-intensity")
+            .takes_value(true)
+            .default_value("1")
+            .help("Strength of the SSAO darkening (0: none, 1: full)")
+            .validator(|value| value.parse::<f32>().map(|_| ()).map_err(|err| err.to_string())))
+        .arg(Arg::with_name("tonemap")
+            .long("tonemap")
+            .takes_value(true)
+            .possible_values(&["aces", "reinhard", "none"])
+            .default_value("aces")
+            .help("Tone mapping of the HDR shading to the display (not applied to EXR screenshots)"))
+        .arg(Arg::with_name("EXPOSURE")
+            .long("exposure")
+            .takes_value(true)
+            .default_value("1")
+            .help("Multiplier of the shading before tone mapping (e.g. 2 for one stop brighter)")
+            .validator(validate_positive))
+        .arg(Arg::with_name("ENVIRONMENT")
+            .long("environment")
+            .takes_value(true)
+            .help("Image-based lighting from an equirectangular .hdr panorama (instead of a constant ambient light)"))
+        .arg(Arg::with_name("BACKGROUND")
+            .long("background")
+            .takes_value(true)
+            .help("Background: a color as comma-separated RGB(A) in [0, 1], a vertical gradient as top:bottom color \
+                   (e.g. 0.4,0.4,0.5:0.1,0.1,0.1) or 'skybox' for the --environment \
+                   [default: the environment if given, else 0.1,0.2,0.3; transparent for headless screenshots]")
+            .validator(|value| value.parse::<Background>().map(|_| ())))
+        .arg(Arg::with_name("shadows")
+            .long("shadows")
+            .takes_value(true)
+            .possible_values(&["on", "off"])
+            .default_value("off")
+            .help("Shadows of the primary light (the first directional light, or the default light)"))
+        .arg(Arg::with_name("SHADOW-RESOLUTION")
+            .long("shadow-resolution")
+            .takes_value(true)
+            .default_value("2048")
+            .help("Width and height of the shadow map in pixels")
+            .validator(|value| match value.parse::<u32>() {
+                Ok(size) if (16..=16384).contains(&size) => Ok(()),
+                Ok(_) => Err("must be in [16, 16384]".to_owned()),
+                Err(err) => Err(err.to_string()),
+            }))
+        .arg(Arg::with_name("SHADOW-BIAS")
+            .long("shadow-bias")
+            .takes_value(true)
+            .default_value("0.005")
+            .help("Depth bias of the shadows as a fraction of the scene depth: increase against stripes on lit \
+                   surfaces (acne), decrease if shadows detach from their casters (peter-panning)")
+            .validator(|value| match value.parse::<f32>() {
+                Ok(bias) if bias >= 0.0 => Ok(()),
+                Ok(_) => Err("must not be negative".to_owned()),
+                Err(err) => Err(err.to_string()),
+            }))
+        .arg(Arg::with_name("MSAA")
+            .long("msaa")
+            .takes_value(true)
             .default_value("0")
-            .help("Index of the scene to load")
+            .help("Multisample anti-aliasing with this many samples per pixel, e.g. 4 (reduced to the supported \
+                   maximum; 0: off)")
             .validator(|value| value.parse::<u32>().map(|_| ()).map_err(|err| err.to_string())))
+        .arg(Arg::with_name("RENDER-SCALE")
+            .long("render-scale")
+            .takes_value(true)
+            .default_value("1")
+            .help("Render the scene at this fraction of the window resolution and upscale it \
+                   (e.g. 0.25 for a pixelated look, or for performance)")
+            .validator(|value| match value.parse::<f32>() {
+                Ok(scale) if scale > 0.0 && scale <= 1.0 => Ok(()),
+                Ok(_) => Err("must be in (0, 1]".to_owned()),
+                Err(err) => Err(err.to_string()),
+            }))
+        .arg(Arg::with_name("upscale")
+            .long("upscale")
+            .takes_value(true)
+            .possible_values(&["nearest", "linear"])
+            .default_value("linear")
+            .help("Filter for upscaling with --render-scale"))
+        .arg(Arg::with_name("no-clear-on-resize")
+            .long("no-clear-on-resize")
+            .help("Don't clear the window immediately when it's resized (avoids flicker while resizing, \
+                   but may briefly show a stretched frame)"))
         .arg(Arg::with_name("CAM-INDEX")
             .long("cam-index")
             .takes_value(true)
@@ -98,66 +481,185 @@ pub fn main() {
             .takes_value(true)
             .allow_hyphen_values(true)
             .help("Camera target (aka center) override as comma-separated Vector3. Example: 1.2,3.4,5.6"))
+        .arg(Arg::with_name("CAM-UP")
+            .long("cam-up")
+            .takes_value(true)
+            .allow_hyphen_values(true)
+            .help("Up vector to orbit around as comma-separated Vector3, e.g. 0,0,1 to orbit a Z-up model as it is \
+                   (see also --up) [default: 0,1,0]")
+            .validator(|value| match value.split(',').count() {
+                3 => parse_vec3(&value).map(|_| ()).map_err(|err| err.to_string()),
+                _ => Err("expected 3 comma-separated values".to_owned()),
+            }))
         .arg(Arg::with_name("CAM-FOVY")
             .long("cam-fovy")
             .takes_value(true)
             .default_value("75")
             .help("Vertical field of view ('zoom') in degrees.")
             .validator(|value| value.parse::<u32>().map(|_| ()).map_err(|err| err.to_string())))
-        .get_matches();
-    let source = args.value_of("FILE").unwrap();
-
-    let width: u32 = args.value_of("WIDTH").unwrap().parse().unwrap();
-    let height: u32 = args.value_of("HEIGHT").unwrap().parse().unwrap();
-    let count: u32 = args.value_of("COUNT").unwrap().parse().unwrap();
-
-    let scene: usize = args.value_of("scene").unwrap().parse().unwrap();
-
-    let camera_options = CameraOptions {
-        index: args.value_of("CAM-INDEX").map(|n| n.parse().unwrap()).unwrap(),
-        position: args.value_of("CAM-POS").map(|v| parse_vec3(v).unwrap()),
-        target: args.value_of("CAM-TARGET").map(|v| parse_vec3(v).unwrap()),
-        fovy: args.value_of("CAM-FOVY").map(|n| Deg(n.parse().unwrap())).unwrap(),
-        straight: args.is_present("straight"),
-    };
-
-    let log_level = match args.occurrences_of("verbose") {
-        0 => LevelFilter::Warn,
-        1 => LevelFilter::Info,
-        2 => LevelFilter::Debug,
-        _ => LevelFilter::Trace
-    };
-
-    let _ = TermLogger::init(
-        log_level,
-        LogConfigBuilder::new()
-            .set_time_level(LevelFilter::Off)
-            .set_target_level(LevelFilter::Off)
-            .set_thread_level(LevelFilter::Off)
-            .build(),
-        TerminalMode::Stdout);
+        .arg(Arg::with_name("RPM")
+            .long("rpm")
+            .takes_value(true)
+            .allow_hyphen_values(true)
+            .help("Turntable: rotate endlessly around the orbit target at this many rotations per minute \
+                   (independent of the frame rate; negative: opposite direction)")
+            .validator(|value| value.parse::<f32>().map(|_| ()).map_err(|err| err.to_string())))
+        .arg(Arg::with_name("ZOOM-FACTOR")
+            .long("zoom-factor")
+            .takes_value(true)
+            .default_value("1.2")
+            .help("Factor by which the distance to the orbit target changes per mouse wheel step \
+                   (i.e. zooming is faster further away)")
+            .validator(|value| match value.parse::<f32>() {
+                Ok(factor) if factor > 1.0 => Ok(()),
+                Ok(_) => Err("must be greater than 1".to_owned()),
+                Err(err) => Err(err.to_string()),
+            }))
+        .arg(Arg::with_name("ROTATE-SPEED")
+            .long("rotate-speed")
+            .takes_value(true)
+            .default_value("1")
+            .help("Mouse rotation speed (1: dragging across the window rotates 360 degrees)")
+            .validator(validate_positive))
+        .arg(Arg::with_name("PAN-SPEED")
+            .long("pan-speed")
+            .takes_value(true)
+            .default_value("1")
+            .help("Mouse panning speed multiplier")
+            .validator(validate_positive))
+        .arg(Arg::with_name("ZOOM-SPEED")
+            .long("zoom-speed")
+            .takes_value(true)
+            .default_value("1")
+            .help("Mouse wheel zoom speed (multiplies the wheel steps, also with --fov-zoom)")
+            .validator(validate_positive))
+        .arg(Arg::with_name("fov-zoom")
+            .long("fov-zoom")
+            .help("Zoom by changing the field of view (linearly) instead of the camera distance"))
+        .arg(Arg::with_name("walk")
+            .long("walk")
+            .help("Keyboard movement walks at a constant height instead of flying along the view direction \
+                   (E/Q still move up/down)"))
+        .arg(Arg::with_name("GROUND-PLANE")
+            .long("ground-plane")
+            .takes_value(true)
+            .value_name("Y")
+            .allow_hyphen_values(true)
+            .help("Keep the camera at or above this height, e.g. the floor when walking through a building")
+            .validator(|value| value.parse::<f32>().map(|_| ()).map_err(|err| err.to_string())))
+        .arg(Arg::with_name("POLAR-LIMITS")
+            .long("polar-limits")
+            .takes_value(true)
+            .help("Range of the vertical orbit angle in degrees, from looking straight down (0) to straight up (180). \
+                   Example: 0,90 (never look at the underside)")
+            .validator(|value| parse_range(&value).map(|_| ())))
+        .arg(Arg::with_name("MIN-DISTANCE")
+            .long("min-distance")
+            .takes_value(true)
+            .help("Minimum distance of the camera to the orbit target when zooming [default: 0.001]")
+            .validator(|value| value.parse::<f32>().map(|_| ()).map_err(|err| err.to_string())))
+        .arg(Arg::with_name("MAX-DISTANCE")
+            .long("max-distance")
+            .takes_value(true)
+            .help("Maximum distance of the camera to the orbit target when zooming [default: unlimited]")
+            .validator(|value| value.parse::<f32>().map(|_| ()).map_err(|err| err.to_string())))
+        .arg(Arg::with_name("DAMPING")
+            .long("damping")
+            .takes_value(true)
+            .value_name("FACTOR")
+            .help("Let rotating and panning coast to a stop after releasing the mouse button, losing this part \
+                   of the motion per frame (e.g. 0.1). Also enables --smooth-zoom")
+            .validator(|value| match value.parse::<f32>() {
+                Ok(factor) if factor > 0.0 && factor <= 1.0 => Ok(()),
+                Ok(_) => Err("must be in (0, 1]".to_owned()),
+                Err(err) => Err(err.to_string()),
+            }))
+        .arg(Arg::with_name("smooth-zoom")
+            .long("smooth-zoom")
+            .value_name("EASING")
+            .possible_values(&["quad", "cubic", "expo"])
+            .help("Let scroll zooming glide to a stop with this ease-out curve instead of jumping \
+                   [default with --damping: expo]"))
+        .arg(Arg::with_name("SMOOTH-ZOOM-DURATION")
+            .long("smooth-zoom-duration")
+            .takes_value(true)
+            .default_value("0.3")
+            .help("Seconds until a smooth zoom stops (the longer, the softer)")
+            .validator(|value| match value.parse::<f32>() {
+                Ok(duration) if duration >= 0.0 => Ok(()),
+                Ok(_) => Err("must not be negative".to_owned()),
+                Err(err) => Err(err.to_string()),
+            }))
+        .arg(Arg::with_name("HOME-FILE")
+            .long("home-file")
+            .takes_value(true)
+            .help("Save the home view (set with Shift + Home) to this file and restore it from there on startup"))
+        .arg(Arg::with_name("SNAP-ANGLE")
+            .long("snap-angle")
+            .takes_value(true)
+            .default_value("15")
+            .help("Increment in degrees for snapped orbit rotation (hold Alt while dragging)")
+            .validator(|value| match value.parse::<f32>() {
+                Ok(angle) if angle > 0.0 => Ok(()),
+                Ok(_) => Err("must be positive".to_owned()),
+                Err(err) => Err(err.to_string()),
+            }))
+}
 
-    let mut viewer = GltfViewer::new(source, width, height,
-        args.is_present("headless"),
-        !args.is_present("screenshot"),
-        camera_options,
-        scene);
+fn load_options(args: &ArgMatches<'_>) -> LoadOptions {
+    LoadOptions {
+        scene: args.value_of("scene").map(|index| index.parse().unwrap()),
+        max_triangles: args.value_of("MAX-TRIANGLES").map(|n| n.parse().unwrap()),
+        debug_attribute: args.value_of("DEBUG-ATTRIBUTE").map(|s| s.to_owned()),
+        instance_color_attribute: args.value_of("INSTANCE-COLOR").unwrap().to_owned(),
+        force_two_sided_normals: args.is_present("force-two-sided-normals"),
+        up: args.value_of("up").unwrap().parse().unwrap(),
+        layout: args.value_of("layout").unwrap().parse().unwrap(),
+        lod: args.value_of("LOD").unwrap().parse().unwrap(),
+        audit: args.is_present("audit"),
+        mmap: args.is_present("mmap"),
+        default_color: args.value_of("DEFAULT-COLOR").map(|v| parse_vec3(v).unwrap()),
+        default_metallic: args.value_of("DEFAULT-METALLIC").map(|n| n.parse().unwrap()),
+        default_roughness: args.value_of("DEFAULT-ROUGHNESS").map(|n| n.parse().unwrap()),
+        auto_fix_winding: args.is_present("auto-fix-winding"),
+        smooth_normals: args.is_present("smooth-normals"),
+        ssao: args.is_present("ssao"),
+        environment: args.value_of("ENVIRONMENT").map(|path| path.to_owned()),
+        shadows: match args.value_of("shadows").unwrap() {
+            "on" => Some(args.value_of("SHADOW-RESOLUTION").unwrap().parse().unwrap()),
+            _ => None,
+        },
+        anisotropy: args.value_of("ANISOTROPY").map(|n| n.parse().unwrap()),
+        mip_bias: args.value_of("MIP-BIAS").unwrap().parse().unwrap(),
+    }
+}
 
-    if args.is_present("screenshot") {
-        let filename = args.value_of("screenshot").unwrap();
+/// "min,max"
+fn parse_range(value: &str) -> Result<(f32, f32), String> {
+    let numbers: Vec<&str> = value.split(',').collect();
+    match numbers.as_slice() {
+        [min, max] => Ok((
+            min.trim().parse().map_err(|err: std::num::ParseFloatError| err.to_string())?,
+            max.trim().parse().map_err(|err: std::num::ParseFloatError| err.to_string())?,
+        )),
+        _ => Err("must be two comma-separated numbers (min,max)".to_owned()),
+    }
+}
 
-        if !filename.to_lowercase().ends_with(".png") {
-            warn!("filename should end with .png");
-        }
-        if count > 1 {
-            viewer.multiscreenshot(filename, count)
-        } else {
-            viewer.screenshot(filename)
-        }
-        return;
+fn validate_unit_interval(value: String) -> Result<(), String> {
+    match value.parse::<f32>() {
+        Ok(v) if (0.0..=1.0).contains(&v) => Ok(()),
+        Ok(_) => Err("must be in [0, 1]".to_owned()),
+        Err(err) => Err(err.to_string()),
     }
+}
 
-    viewer.start_render_loop();
+fn validate_positive(value: String) -> Result<(), String> {
+    match value.parse::<f32>() {
+        Ok(v) if v > 0.0 => Ok(()),
+        Ok(_) => Err("must be greater than 0".to_owned()),
+        Err(err) => Err(err.to_string()),
+    }
 }
 
 #[cfg(test)]
@@ -180,6 +682,16 @@ mod tests {
         println!("Vec<Node>:      {:>3}", std::mem::size_of::<Vec<render::Node>>());
     }
 
+    #[test]
+    fn scene_option_takes_an_index() {
+        let args = app().get_matches_from(vec!["gltf-viewer", "--scene", "1", "model.gltf"]);
+        assert_eq!(load_options(&args).scene, Some(1));
+        assert_eq!(args.values_of("FILE").unwrap().collect::<Vec<_>>(), vec!["model.gltf"]);
+
+        let args = app().get_matches_from(vec!["gltf-viewer", "model.gltf"]);
+        assert_eq!(load_options(&args).scene, None);
+    }
+
 //     extern crate test;
 //     use self::test::Bencher;
 //     #[bench]
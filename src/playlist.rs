@@ -0,0 +1,96 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread::{self, JoinHandle};
+
+use gltf;
+use log::info;
+
+use crate::importdata::ImportData;
+use crate::viewer::LoadOptions;
+
+/// The .gltf/.glb files of a directory, stepped through with the next one
+/// being imported on a background thread (the OpenGL upload happens on switching).
+pub struct Playlist {
+    files: Vec<PathBuf>,
+    current: usize,
+    options: LoadOptions,
+    prefetched: Option<(usize, JoinHandle<gltf::Result<ImportData>>)>,
+}
+
+impl Playlist {
+    pub fn from_dir(dir: &Path, options: LoadOptions) -> io::Result<Playlist> {
+        let mut files: Vec<PathBuf> = fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                let extension = path.extension()
+                    .map(|ext| ext.to_string_lossy().to_lowercase())
+                    .unwrap_or_default();
+                extension == "gltf" || extension == "glb"
+            })
+            .collect();
+        files.sort();
+
+        Ok(Playlist {
+            files,
+            current: 0,
+            options,
+            prefetched: None,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Index of the current file
+    pub fn position(&self) -> usize {
+        self.current
+    }
+
+    pub fn current(&self) -> &Path {
+        &self.files[self.current]
+    }
+
+    /// Go to the next (or previous) file, wrapping around at the ends, and return its import result.
+    /// Uses the prefetched data if available and starts prefetching the one after it.
+    pub fn step(&mut self, forward: bool) -> gltf::Result<ImportData> {
+        let len = self.files.len();
+        self.current = if forward { (self.current + 1) % len } else { (self.current + len - 1) % len };
+
+        let result = match self.prefetched.take() {
+            Some((index, handle)) if index == self.current => {
+                handle.join().expect("prefetch thread panicked")
+            },
+            _ => ImportData::import(&self.current().to_string_lossy(), &self.options),
+        };
+
+        let next = if forward { (self.current + 1) % len } else { (self.current + len - 1) % len };
+        self.prefetch(next);
+
+        result
+    }
+
+    /// Import the file with the given index on a background thread
+    pub fn prefetch(&mut self, index: usize) {
+        if self.files.len() < 2 {
+            return
+        }
+        let source = self.files[index].to_string_lossy().into_owned();
+        let options = self.options.clone();
+        info!("Prefetching {}", source);
+        let handle = thread::spawn(move || ImportData::import(&source, &options));
+        // a previous unused prefetch just finishes in the background
+        self.prefetched = Some((index, handle));
+    }
+
+    /// Start prefetching the file after the current one
+    pub fn prefetch_next(&mut self) {
+        let next = (self.current + 1) % self.files.len();
+        self.prefetch(next);
+    }
+}
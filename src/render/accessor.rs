@@ -0,0 +1,255 @@
+use std::ops::Deref;
+
+use gltf;
+use gltf::accessor::DataType;
+use gltf::accessor::sparse::IndexType;
+use log::warn;
+
+/// Reads any accessor as up to 4 f32 components per element (missing components are 0).
+/// Integer components are normalized if the accessor says so, otherwise just converted.
+/// Used for attributes the `gltf` reader doesn't know about (e.g. custom `_ATTRIBUTES`)
+/// or can't dequantize (`KHR_mesh_quantization`), and for sparse accessors.
+/// `None` (with a warning) if elements lie outside of their buffer view (malformed or truncated file).
+pub fn read_f32x4<B: Deref<Target = [u8]>>(accessor: &gltf::Accessor<'_>, buffers: &[B]) -> Option<Vec<[f32; 4]>> {
+    let data_type = accessor.data_type();
+    let component_size = data_type.size();
+    let components = accessor.dimensions().multiplicity().min(4);
+    let read_element = |data: &[u8], start: usize| {
+        let mut element = [0.0; 4];
+        for (c, value) in element.iter_mut().enumerate().take(components) {
+            let offset = start + c * component_size;
+            *value = read_component(data.get(offset..offset + component_size)?, data_type, accessor.normalized());
+        }
+        Some(element)
+    };
+
+    let elements = match accessor.view() {
+        Some(view) => {
+            let data = view_data(&view, buffers).unwrap_or(&[]);
+            let stride = view.stride().unwrap_or_else(|| accessor.size());
+            (0..accessor.count())
+                .map(|i| read_element(data, accessor.offset() + i * stride))
+                .collect()
+        },
+        None => Some(vec![[0.0; 4]; accessor.count()]),
+    };
+    let mut elements = match elements {
+        Some(elements) => elements,
+        None => {
+            warn!("Ignoring accessor {}: its elements exceed their buffer view", accessor.index());
+            return None
+        }
+    };
+    if let Some(SparseValues { indices, data, start }) = sparse_values(accessor, buffers) {
+        for (i, index) in indices.into_iter().enumerate() {
+            if let Some(element) = index.and_then(|index| elements.get_mut(index)) {
+                match read_element(data, start + i * accessor.size()) {
+                    Some(value) => *element = value,
+                    None => {
+                        warn!("Ignoring accessor {}: its sparse values exceed their buffer view", accessor.index());
+                        return None
+                    }
+                }
+            }
+        }
+    }
+    Some(elements)
+}
+
+/// Reads an index accessor with an out-of-spec component type (signed or float instead of
+/// unsigned integer) or type (not SCALAR, read component by component) as u32.
+/// Values that can't be converted unambiguously (negative, fractional, NaN) or lie outside of
+/// their buffer view become 0; their number is returned along with the indices.
+pub fn read_indices<B: Deref<Target = [u8]>>(accessor: &gltf::Accessor<'_>, buffers: &[B]) -> (Vec<u32>, usize) {
+    let components = accessor.dimensions().multiplicity();
+    let data_type = accessor.data_type();
+    let component_size = data_type.size();
+    let mut invalid = 0;
+    let mut read_element = |data: &[u8], start: usize, element: &mut [u32]| {
+        for (c, value) in element.iter_mut().enumerate() {
+            let offset = start + c * component_size;
+            let index = data.get(offset..offset + component_size)
+                .and_then(|bytes| read_index(bytes, data_type));
+            if index.is_none() {
+                invalid += 1;
+            }
+            *value = index.unwrap_or(0);
+        }
+    };
+
+    let mut indices = vec![0; accessor.count() * components];
+    if let Some(view) = accessor.view() {
+        let data = view_data(&view, buffers).unwrap_or(&[]);
+        let stride = view.stride().unwrap_or_else(|| accessor.size());
+        for (i, element) in indices.chunks_mut(components).enumerate() {
+            read_element(data, accessor.offset() + i * stride, element);
+        }
+    }
+    if let Some(SparseValues { indices: sparse_indices, data, start }) = sparse_values(accessor, buffers) {
+        for (i, index) in sparse_indices.into_iter().enumerate() {
+            if let Some(element) = index.and_then(|index| indices.chunks_mut(components).nth(index)) {
+                read_element(data, start + i * accessor.size(), element);
+            }
+        }
+    }
+    (indices, invalid)
+}
+
+/// The replaced elements of a sparse accessor
+struct SparseValues<'a> {
+    /// element indices (`None` if outside of their buffer view)
+    indices: Vec<Option<usize>>,
+    /// buffer view data and offset of the (tightly packed) values, each `accessor.size()` bytes
+    data: &'a [u8],
+    start: usize,
+}
+
+fn sparse_values<'a, B: Deref<Target = [u8]>>(accessor: &gltf::Accessor<'_>, buffers: &'a [B])
+    -> Option<SparseValues<'a>>
+{
+    let sparse = accessor.sparse()?;
+    let indices = sparse.indices();
+    let data = view_data(&indices.view(), buffers).unwrap_or(&[]);
+    let start = indices.offset() as usize;
+    let index_type = indices.index_type();
+    let size = index_type.size();
+    let element_indices = (0..sparse.count() as usize)
+        .map(|i| {
+            let bytes = data.get(start + i * size..start + (i + 1) * size)?;
+            Some(match index_type {
+                IndexType::U8 => usize::from(bytes[0]),
+                IndexType::U16 => usize::from(u16::from_le_bytes([bytes[0], bytes[1]])),
+                IndexType::U32 => u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize,
+            })
+        })
+        .collect();
+
+    let values = sparse.values();
+    let data = view_data(&values.view(), buffers).unwrap_or(&[]);
+    Some(SparseValues { indices: element_indices, data, start: values.offset() as usize })
+}
+
+/// The bytes of a buffer view (`None` if it exceeds its buffer)
+fn view_data<'a, B: Deref<Target = [u8]>>(view: &gltf::buffer::View<'_>, buffers: &'a [B]) -> Option<&'a [u8]> {
+    buffers[view.buffer().index()].get(view.offset()..view.offset() + view.length())
+}
+
+fn read_index(bytes: &[u8], data_type: DataType) -> Option<u32> {
+    match data_type {
+        DataType::U8 => Some(u32::from(bytes[0])),
+        DataType::U16 => Some(u32::from(u16::from_le_bytes([bytes[0], bytes[1]]))),
+        DataType::U32 => Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+        DataType::I8 => {
+            let v = bytes[0] as i8;
+            if v >= 0 { Some(v as u32) } else { None }
+        },
+        DataType::I16 => {
+            let v = i16::from_le_bytes([bytes[0], bytes[1]]);
+            if v >= 0 { Some(v as u32) } else { None }
+        },
+        DataType::F32 => {
+            let v = f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+            if v >= 0.0 && v.fract() == 0.0 && v <= u32::MAX as f32 { Some(v as u32) } else { None }
+        },
+    }
+}
+
+fn read_component(bytes: &[u8], data_type: DataType, normalized: bool) -> f32 {
+    match data_type {
+        DataType::I8 => {
+            let v = f32::from(bytes[0] as i8);
+            if normalized { (v / 127.0).max(-1.0) } else { v }
+        },
+        DataType::U8 => {
+            let v = f32::from(bytes[0]);
+            if normalized { v / 255.0 } else { v }
+        },
+        DataType::I16 => {
+            let v = f32::from(i16::from_le_bytes([bytes[0], bytes[1]]));
+            if normalized { (v / 32767.0).max(-1.0) } else { v }
+        },
+        DataType::U16 => {
+            let v = f32::from(u16::from_le_bytes([bytes[0], bytes[1]]));
+            if normalized { v / 65535.0 } else { v }
+        },
+        DataType::U32 => u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f32,
+        DataType::F32 => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_quantized_attributes() {
+        // KHR_mesh_quantization: normalized u16 positions and i8 normals
+        let (document, buffers, _) = gltf::import("tests/QuantizedTriangle.gltf").unwrap();
+        let primitive = document.meshes().next().unwrap().primitives().next().unwrap();
+
+        let positions = read_f32x4(&primitive.get(&gltf::mesh::Semantic::Positions).unwrap(), &buffers).unwrap();
+        assert_eq!(positions, vec![
+            [0.0, 0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+        ]);
+
+        let normals = read_f32x4(&primitive.get(&gltf::mesh::Semantic::Normals).unwrap(), &buffers).unwrap();
+        assert!(normals.iter().all(|n| *n == [0.0, 0.0, 1.0, 0.0]));
+    }
+
+    #[test]
+    fn read_malformed_indices() {
+        // float indices and signed short indices (including a negative one)
+        let (document, buffers, _) = gltf::import("tests/MalformedIndices.gltf").unwrap();
+        let indices: Vec<_> = document.meshes().next().unwrap().primitives()
+            .map(|primitive| read_indices(&primitive.indices().unwrap(), &buffers))
+            .collect();
+        assert_eq!(indices, vec![
+            (vec![0, 1, 2], 0),
+            (vec![0, 1, 2, 0], 1),
+        ]);
+    }
+
+    #[test]
+    fn read_out_of_bounds_indices() {
+        // 3 floats in the buffer view: a fourth index, and a sparse index past its (1 byte) view
+        let (document, buffers, _) = gltf::import("tests/OutOfBoundsAccessors.gltf").unwrap();
+        let accessors: Vec<_> = document.accessors().collect();
+        assert_eq!(read_indices(&accessors[0], &buffers), (vec![0, 1, 2, 0], 1));
+        assert_eq!(read_indices(&accessors[2], &buffers), (vec![1, 1, 2], 0));
+    }
+
+    #[test]
+    fn skip_out_of_bounds_attributes() {
+        // a VEC3 attribute with 2 elements in a buffer view of 3 floats
+        let (document, buffers, _) = gltf::import("tests/OutOfBoundsAccessors.gltf").unwrap();
+        let accessors: Vec<_> = document.accessors().collect();
+        assert_eq!(read_f32x4(&accessors[1], &buffers), None);
+        // the sparse index past its view is skipped, the other one is applied
+        assert_eq!(read_f32x4(&accessors[2], &buffers), Some(vec![
+            [1.0, 0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0, 0.0],
+            [2.0, 0.0, 0.0, 0.0],
+        ]));
+    }
+
+    #[test]
+    fn read_sparse_accessors() {
+        // overrides of a buffer view (u16 indices) and of zeros without a buffer view (u8 indices)
+        let (document, buffers, _) = gltf::import("tests/SparseAccessor.gltf").unwrap();
+        let accessors: Vec<_> = document.accessors().collect();
+        assert_eq!(read_f32x4(&accessors[0], &buffers).unwrap(), vec![
+            [0.0, 0.0, 0.0, 0.0],
+            [5.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [7.0, 7.0, 7.0, 0.0],
+        ]);
+        assert_eq!(read_f32x4(&accessors[1], &buffers).unwrap(), vec![
+            [0.0, 0.0, 0.0, 0.0],
+            [0.0, 0.0, 0.0, 0.0],
+            [1.0, 2.0, 3.0, 0.0],
+            [0.0, 0.0, 0.0, 0.0],
+        ]);
+    }
+}
@@ -0,0 +1,356 @@
+use std::ops::{Add, Mul};
+
+use gltf;
+use gltf::animation::Interpolation;
+use gltf::animation::util::ReadOutputs;
+use log::{debug, warn};
+
+use crate::importdata::ImportData;
+use crate::render::Root;
+use crate::render::math::*;
+
+/// Range of the playback speed multiplier (negative: reverse)
+pub const MIN_ANIMATION_SPEED: f32 = -4.0;
+pub const MAX_ANIMATION_SPEED: f32 = 4.0;
+
+enum Outputs {
+    Translations(Vec<Vector3>),
+    Rotations(Vec<Quaternion>),
+    Scales(Vec<Vector3>),
+    /// `targets` weights per output
+    MorphTargetWeights { weights: Vec<f32>, targets: usize },
+}
+
+struct Channel {
+    node: usize,
+    interpolation: Interpolation,
+    inputs: Vec<f32>,
+    outputs: Outputs,
+}
+
+impl Channel {
+    fn from_gltf(g_channel: &gltf::animation::Channel<'_>, imp: &ImportData) -> Option<Channel> {
+        let buffers = &imp.buffers;
+        let reader = g_channel.reader(|buffer| Some(&buffers[buffer.index()][..]));
+        let inputs: Vec<f32> = reader.read_inputs()?.collect();
+        if inputs.is_empty() {
+            return None
+        }
+        let outputs = match reader.read_outputs()? {
+            ReadOutputs::Translations(translations) =>
+                Outputs::Translations(translations.map(Vector3::from).collect()),
+            ReadOutputs::Rotations(rotations) =>
+                Outputs::Rotations(rotations.into_f32()
+                    .map(|r| Quaternion::new(r[3], r[0], r[1], r[2])) // NOTE: different element order!
+                    .collect()),
+            ReadOutputs::Scales(scales) =>
+                Outputs::Scales(scales.map(Vector3::from).collect()),
+            ReadOutputs::MorphTargetWeights(weights) => {
+                let weights: Vec<f32> = weights.into_f32().collect();
+                let outputs_per_input = match g_channel.sampler().interpolation() {
+                    Interpolation::CubicSpline => 3,
+                    _ => 1,
+                };
+                let targets = weights.len() / (inputs.len() * outputs_per_input);
+                if targets == 0 {
+                    debug!("Ignoring morph target weight animation channel without weights");
+                    return None
+                }
+                Outputs::MorphTargetWeights { weights, targets }
+            }
+        };
+
+        Some(Channel {
+            node: g_channel.target().node().index(),
+            interpolation: g_channel.sampler().interpolation(),
+            inputs,
+            outputs,
+        })
+    }
+
+    /// Keyframes before and after `time` and the interpolation factor between them
+    fn keyframes(&self, time: f32) -> (usize, usize, f32) {
+        let last = self.inputs.len() - 1;
+        if time <= self.inputs[0] {
+            return (0, 0, 0.0)
+        }
+        if time >= self.inputs[last] {
+            return (last, last, 0.0)
+        }
+        let next = self.inputs.iter().position(|&t| t > time).unwrap();
+        let prev = next - 1;
+        let factor = match self.interpolation {
+            Interpolation::Step => 0.0,
+            _ => (time - self.inputs[prev]) / (self.inputs[next] - self.inputs[prev]),
+        };
+        (prev, next, factor)
+    }
+
+    /// Value at (`prev`, `next`, `factor`) of `keyframes`. `output(i)` is the i-th output element,
+    /// which are (in-tangent, value, out-tangent) triplets for cubic splines.
+    /// `lerp` interpolates linearly (e.g. slerp for rotations).
+    fn sample<T>(&self, output: impl Fn(usize) -> T, (prev, next, factor): (usize, usize, f32),
+        lerp: impl Fn(T, T, f32) -> T) -> T
+        where T: Add<Output = T> + Mul<f32, Output = T>
+    {
+        match self.interpolation {
+            Interpolation::CubicSpline if prev != next => {
+                let delta = self.inputs[next] - self.inputs[prev];
+                hermite(output(prev * 3 + 1), output(prev * 3 + 2) * delta,
+                    output(next * 3 + 1), output(next * 3) * delta, factor)
+            },
+            Interpolation::CubicSpline => output(prev * 3 + 1),
+            _ => lerp(output(prev), output(next), factor),
+        }
+    }
+
+    fn apply(&self, time: f32, root: &mut Root) {
+        let keyframes = self.keyframes(time);
+        let node = &mut root.nodes[self.node];
+        match self.outputs {
+            Outputs::Translations(ref values) => {
+                node.translation = self.sample(|i| values[i], keyframes, Vector3::lerp);
+            },
+            Outputs::Rotations(ref values) => {
+                node.rotation = self.sample(|i| values[i], keyframes, |a: Quaternion, mut b: Quaternion, t| {
+                    if a.dot(b) < 0.0 {
+                        b = -b; // take the shorter path
+                    }
+                    a.slerp(b, t)
+                }).normalize();
+            },
+            Outputs::Scales(ref values) => {
+                node.scale = self.sample(|i| values[i], keyframes, Vector3::lerp);
+            },
+            Outputs::MorphTargetWeights { ref weights, targets } => {
+                // NOTE: the weights are stored in the mesh, i.e. shared by all nodes using it
+                if let Some(ref mesh) = node.mesh {
+                    for (target, weight) in mesh.weights.borrow_mut().iter_mut().enumerate().take(targets) {
+                        *weight = self.sample(|i| weights[i * targets + target], keyframes, |a, b, t| a + (b - a) * t);
+                    }
+                }
+            },
+        }
+    }
+}
+
+/// Cubic Hermite spline from `p0` to `p1` with the tangents `m0` and `m1` (already scaled
+/// by the keyframe duration), see Appendix C of the glTF spec
+fn hermite<T>(p0: T, m0: T, p1: T, m1: T, t: f32) -> T
+    where T: Add<Output = T> + Mul<f32, Output = T>
+{
+    let t2 = t * t;
+    let t3 = t2 * t;
+    p0 * (2.0 * t3 - 3.0 * t2 + 1.0) + m0 * (t3 - 2.0 * t2 + t) + p1 * (-2.0 * t3 + 3.0 * t2) + m1 * (t3 - t2)
+}
+
+pub struct Animation {
+    pub name: Option<String>,
+    /// in seconds
+    pub duration: f32,
+    channels: Vec<Channel>,
+}
+
+impl Animation {
+    pub fn from_gltf(g_animation: &gltf::Animation<'_>, imp: &ImportData) -> Animation {
+        let channels: Vec<_> = g_animation.channels()
+            .filter_map(|g_channel| Channel::from_gltf(&g_channel, imp))
+            .collect();
+        let duration = channels.iter()
+            .map(|channel| channel.inputs[channel.inputs.len() - 1])
+            .fold(0.0, f32::max);
+
+        Animation {
+            name: g_animation.name().map(|s| s.into()),
+            duration,
+            channels,
+        }
+    }
+
+    /// Set the animated node properties to their values at `time`
+    pub fn apply(&self, time: f32, root: &mut Root) {
+        for channel in &self.channels {
+            channel.apply(time, root);
+        }
+    }
+}
+
+/// Playback state of an `Animator`, e.g. for synchronizing it with the timeline
+/// of a host application (see `Animator::state` and `Animator::set_state`)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AnimationState {
+    /// index into `Animator::animations`
+    pub active: Option<usize>,
+    /// in seconds
+    pub time: f32,
+    pub playing: bool,
+    /// wrap around at the end (otherwise stop there)
+    pub looping: bool,
+    pub speed: f32,
+}
+
+/// Plays back the (node TRS and morph target weight) animations of a glTF file
+pub struct Animator {
+    pub animations: Vec<Animation>,
+    pub active: Option<usize>,
+    /// current animation time in seconds
+    pub time: f32,
+    speed: f32,
+    playing: bool,
+    looping: bool,
+    // the state was set, so the next `update` applies it even if not playing
+    state_changed: bool,
+}
+
+impl Animator {
+    pub fn from_gltf(imp: &ImportData) -> Animator {
+        let animations: Vec<_> = imp.doc.animations()
+            .map(|g_animation| Animation::from_gltf(&g_animation, imp))
+            .collect();
+        if animations.iter().any(|animation| animation.channels.is_empty()) {
+            warn!("Some animations have no supported channels");
+        }
+
+        Animator {
+            active: if animations.is_empty() { None } else { Some(0) },
+            animations,
+            time: 0.0,
+            speed: 1.0,
+            playing: true,
+            looping: true,
+            state_changed: false,
+        }
+    }
+
+    pub fn state(&self) -> AnimationState {
+        AnimationState {
+            active: self.active,
+            time: self.time,
+            playing: self.playing,
+            looping: self.looping,
+            speed: self.speed,
+        }
+    }
+
+    /// Takes effect on the next `update`. An invalid `active` index is ignored (with a warning),
+    /// the speed is clamped (see `set_speed`).
+    pub fn set_state(&mut self, state: AnimationState) {
+        match state.active {
+            Some(index) if index >= self.animations.len() => {
+                warn!("Ignoring animation index {} (only {} animations)", index, self.animations.len());
+            }
+            active => self.active = active,
+        }
+        self.time = state.time;
+        self.playing = state.playing;
+        self.looping = state.looping;
+        self.set_speed(state.speed);
+        self.state_changed = true;
+    }
+
+    /// Time-scale multiplier applied when advancing the animation time
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    /// Negative values play the animation in reverse.
+    /// Clamped to `MIN_ANIMATION_SPEED..=MAX_ANIMATION_SPEED`.
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed.clamp(MIN_ANIMATION_SPEED, MAX_ANIMATION_SPEED);
+    }
+
+    /// Advance the animation time (if playing) and apply the active animation.
+    /// Returns whether any node was changed, i.e. transforms need to be updated.
+    pub fn update(&mut self, delta_time: f32, root: &mut Root) -> bool {
+        let animation = match self.active {
+            Some(index) => &self.animations[index],
+            None => return false,
+        };
+        if !self.playing && !self.state_changed {
+            return false
+        }
+        self.state_changed = false;
+        if self.playing {
+            self.time += delta_time * self.speed;
+        }
+        if animation.duration > 0.0 {
+            if self.looping {
+                self.time = self.time.rem_euclid(animation.duration);
+            } else if self.time < 0.0 || self.time > animation.duration {
+                self.time = self.time.max(0.0).min(animation.duration);
+                self.playing = false;
+            }
+        }
+        animation.apply(self.time, root);
+        !animation.channels.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn channel(interpolation: Interpolation, inputs: Vec<f32>, translations: Vec<Vector3>) -> Channel {
+        Channel { node: 0, interpolation, inputs, outputs: Outputs::Translations(translations) }
+    }
+
+    fn translation_at(channel: &Channel, time: f32) -> Vector3 {
+        match channel.outputs {
+            Outputs::Translations(ref values) =>
+                channel.sample(|i| values[i], channel.keyframes(time), Vector3::lerp),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn step_holds_the_previous_keyframe() {
+        let step = channel(Interpolation::Step, vec![0.0, 1.0], vec![vec3(0.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0)]);
+        assert_eq!(translation_at(&step, 0.9), vec3(0.0, 0.0, 0.0));
+        assert_eq!(translation_at(&step, 1.0), vec3(1.0, 0.0, 0.0));
+
+        let linear = channel(Interpolation::Linear, vec![0.0, 1.0], vec![vec3(0.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0)]);
+        assert_eq!(translation_at(&linear, 0.25), vec3(0.25, 0.0, 0.0));
+    }
+
+    #[test]
+    fn cubic_spline_uses_the_tangents() {
+        let zero = Vector3::zero();
+        // (in-tangent, value, out-tangent) per keyframe, 2 seconds apart
+        let cubic = channel(Interpolation::CubicSpline, vec![0.0, 2.0], vec![
+            zero, vec3(0.0, 0.0, 0.0), vec3(0.0, 1.0, 0.0),
+            zero, vec3(1.0, 0.0, 0.0), zero,
+        ]);
+        // x: smoothstep without tangents; y: out-tangent 1 (per second) times 2 s * (t³ - 2t² + t)
+        let p = translation_at(&cubic, 0.5);
+        assert!((p - vec3(0.15625, 0.28125, 0.0)).magnitude() < 1e-6, "{:?}", p);
+        let p = translation_at(&cubic, 1.0);
+        assert!((p - vec3(0.5, 0.25, 0.0)).magnitude() < 1e-6, "{:?}", p);
+        // the keyframe values themselves, also outside of the range
+        assert_eq!(translation_at(&cubic, 2.0), vec3(1.0, 0.0, 0.0));
+        assert_eq!(translation_at(&cubic, -1.0), vec3(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn cubic_spline_rotations_are_normalized() {
+        use cgmath::Deg;
+
+        let zero = Quaternion::new(0.0, 0.0, 0.0, 0.0);
+        let a = Quaternion::from_angle_y(Deg(0.0));
+        let b = Quaternion::from_angle_y(Deg(90.0));
+        let channel = Channel {
+            node: 0,
+            interpolation: Interpolation::CubicSpline,
+            inputs: vec![0.0, 1.0],
+            outputs: Outputs::Rotations(vec![zero, a, zero, zero, b, zero]),
+        };
+        let rotation = match channel.outputs {
+            Outputs::Rotations(ref values) =>
+                channel.sample(|i| values[i], channel.keyframes(0.5), Quaternion::slerp).normalize(),
+            _ => unreachable!(),
+        };
+        assert!((rotation.magnitude() - 1.0).abs() < 1e-6);
+        // symmetric: halfway between the keyframes
+        let expected = Quaternion::from_angle_y(Deg(45.0));
+        assert!(rotation.dot(expected) > 1.0 - 1e-6, "{:?}", rotation);
+    }
+}
@@ -0,0 +1,94 @@
+use gl;
+
+use crate::controls::CameraParams;
+use crate::render::math::*;
+use crate::shader::Shader;
+use crate::viewer::ToneMap;
+
+/// Draws the gradient and skybox backgrounds (`--background`) as fullscreen triangles.
+/// Call it after clearing and before the scene: it writes no depth, so it never occludes the model.
+pub struct BackgroundRenderer {
+    gradient_shader: Shader,
+    skybox_shader: Shader,
+    // empty, for the fullscreen triangles
+    vao: u32,
+
+    u_top: i32,
+    u_bottom: i32,
+    u_inverse_view_projection: i32,
+    u_tone_map: i32,
+    u_exposure: i32,
+    u_srgb_output: i32,
+}
+
+impl BackgroundRenderer {
+    pub fn new() -> Self {
+        let shader = |variant: &str| Shader::from_source(
+            include_str!("../shaders/fullscreen-vert.glsl"),
+            include_str!("../shaders/background-frag.glsl"),
+            &[variant.to_owned()]);
+        let mut gradient_shader = shader("GRADIENT");
+        let mut skybox_shader = shader("SKYBOX");
+        unsafe {
+            let environment_sampler = skybox_shader.uniform_location("u_EnvironmentSampler");
+            skybox_shader.use_program();
+            // NOTE: the specular map of `Environment::bind` (its first level is the unfiltered environment)
+            skybox_shader.set_int(environment_sampler, 8);
+
+            let mut renderer = BackgroundRenderer {
+                vao: 0,
+                u_top: gradient_shader.uniform_location("u_Top"),
+                u_bottom: gradient_shader.uniform_location("u_Bottom"),
+                u_inverse_view_projection: skybox_shader.uniform_location("u_InverseViewProjection"),
+                u_tone_map: skybox_shader.uniform_location("u_ToneMap"),
+                u_exposure: skybox_shader.uniform_location("u_Exposure"),
+                u_srgb_output: skybox_shader.uniform_location("u_SrgbOutput"),
+                gradient_shader,
+                skybox_shader,
+            };
+            gl::GenVertexArrays(1, &mut renderer.vao);
+            renderer
+        }
+    }
+
+    /// Vertical gradient (display-referred colors)
+    pub unsafe fn draw_gradient(&self, top: &Vector3, bottom: &Vector3) {
+        self.gradient_shader.use_program();
+        self.gradient_shader.set_vector3(self.u_top, top);
+        self.gradient_shader.set_vector3(self.u_bottom, bottom);
+        self.draw_fullscreen();
+    }
+
+    /// The environment bound by `Environment::bind`, seen with the camera's rotation
+    /// and the same tone mapping as the scene
+    pub unsafe fn draw_skybox(&self, cam_params: &CameraParams, tone_map: ToneMap, exposure: f32, srgb_output: bool) {
+        let mut rotation = cam_params.view_matrix;
+        rotation.w = vec4(0.0, 0.0, 0.0, 1.0);
+        let inverse_view_projection = (cam_params.projection_matrix * rotation).invert()
+            .unwrap_or_else(Matrix4::identity);
+        self.skybox_shader.use_program();
+        self.skybox_shader.set_mat4(self.u_inverse_view_projection, &inverse_view_projection);
+        self.skybox_shader.set_int(self.u_tone_map, tone_map.shader_index());
+        self.skybox_shader.set_float(self.u_exposure, exposure);
+        self.skybox_shader.set_bool(self.u_srgb_output, srgb_output);
+        self.draw_fullscreen();
+    }
+
+    unsafe fn draw_fullscreen(&self) {
+        gl::Disable(gl::DEPTH_TEST);
+        gl::DepthMask(gl::FALSE);
+        gl::BindVertexArray(self.vao);
+        gl::DrawArrays(gl::TRIANGLES, 0, 3);
+        gl::BindVertexArray(0);
+        gl::DepthMask(gl::TRUE);
+        gl::Enable(gl::DEPTH_TEST);
+    }
+}
+
+impl Drop for BackgroundRenderer {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteVertexArrays(1, &self.vao);
+        }
+    }
+}
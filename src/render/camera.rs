@@ -4,7 +4,7 @@ use gltf;
 use gltf::camera::Projection;
 
 use crate::render::math::*;
-use crate::controls::{ZOOM};
+use crate::controls::{ZOOM, MIN_ZOOM, MAX_ZOOM};
 
 #[derive(Clone)]
 pub struct Camera {
@@ -17,7 +17,7 @@ pub struct Camera {
     pub zfar: Option<f32>,
 
     // perspective camera
-    // TODO!: setters that update...
+    // TODO!: setters that update... (see `set_fovy`)
     pub fovy: Deg<f32>,
     aspect_ratio: f32,
 
@@ -69,21 +69,81 @@ impl Camera {
                 camera.zfar = persp.zfar();
             },
             Projection::Orthographic(ortho) => {
-                camera.xmag = Some(ortho.xmag());
-                camera.ymag = Some(ortho.ymag());
-                camera.znear = ortho.znear();
-                camera.zfar = Some(ortho.zfar());
+                camera = Camera {
+                    index: camera.index,
+                    name: camera.name,
+                    ..Camera::new_orthographic(ortho.xmag(), ortho.ymag(), ortho.znear(), ortho.zfar())
+                };
             }
         }
         camera.update_projection_matrix();
         camera
     }
 
+    /// An orthographic camera showing `2 * xmag` x `2 * ymag` (centered on the view axis, like glTF's)
+    pub fn new_orthographic(xmag: f32, ymag: f32, znear: f32, zfar: f32) -> Self {
+        let mut camera = Camera {
+            znear,
+            zfar: Some(zfar),
+            xmag: Some(xmag),
+            ymag: Some(ymag),
+            aspect_ratio: xmag / ymag,
+            ..Camera::default()
+        };
+        camera.update_projection_matrix();
+        camera
+    }
+
     pub fn update_aspect_ratio(&mut self, aspect_ratio: f32) {
         self.aspect_ratio = aspect_ratio;
+        // like for perspective cameras, the window aspect ratio takes precedence
+        if let Some(ymag) = self.ymag {
+            self.xmag = Some(ymag * aspect_ratio);
+        }
         self.update_projection_matrix();
     }
 
+    /// Switch between perspective and orthographic projection, keeping the apparent size
+    /// of objects at `target_distance` (e.g. the orbit target) the same.
+    pub fn toggle_projection(&mut self, target_distance: f32) {
+        if self.is_perspective() {
+            // half height of the view at the target distance
+            let ymag = target_distance * (Rad::from(self.fovy).0 / 2.0).tan();
+            self.ymag = Some(ymag);
+            self.xmag = Some(ymag * self.aspect_ratio);
+            // orthographic projections need a finite far plane
+            if self.zfar.is_none() {
+                self.zfar = Some(target_distance * 100.0);
+            }
+        } else {
+            let ymag = self.ymag.unwrap_or(1.0);
+            self.fovy = Deg::from(Rad(2.0 * (ymag / target_distance).atan()));
+            self.xmag = None;
+            self.ymag = None;
+        }
+        self.update_projection_matrix();
+    }
+
+    /// Zoom an orthographic camera: `factor` > 1 shows more of the scene (no-op for perspective ones)
+    pub fn scale_magnification(&mut self, factor: f32) {
+        if let (Some(xmag), Some(ymag)) = (self.xmag, self.ymag) {
+            self.xmag = Some(xmag * factor);
+            self.ymag = Some(ymag * factor);
+            self.update_projection_matrix();
+        }
+    }
+
+    /// Sets the vertical field of view, clamped to the valid range (see `clamp_fovy`)
+    pub fn set_fovy(&mut self, fovy: Deg<f32>) {
+        self.fovy = Self::clamp_fovy(fovy);
+        self.update_projection_matrix();
+    }
+
+    /// `fovy` limited to `MIN_ZOOM` - `MAX_ZOOM` degrees
+    pub fn clamp_fovy(fovy: Deg<f32>) -> Deg<f32> {
+        Deg(fovy.0.clamp(MIN_ZOOM, MAX_ZOOM))
+    }
+
     pub fn aspect_ratio(&self) -> f32 {
         self.aspect_ratio
     }
@@ -0,0 +1,184 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::os::raw::c_void;
+use std::ptr;
+
+use gl;
+use image::hdr::HDRDecoder;
+
+use crate::shader::Shader;
+
+/// Resolution of the cubemap the panorama is converted to, and of the prefiltered specular map
+const CUBE_SIZE: i32 = 512;
+/// Mip levels of the specular map (512 to 1), from roughness 0 to 1 (see `mipCount` in the PBR shader)
+const SPECULAR_MIP_LEVELS: i32 = 10;
+const IRRADIANCE_SIZE: i32 = 32;
+const BRDF_LUT_SIZE: i32 = 256;
+
+/// Image-based lighting (`--environment`) from an equirectangular `.hdr` panorama. It is converted
+/// to a cubemap and convolved into the diffuse irradiance and a mip chain of specular reflections
+/// by roughness, which are combined with a BRDF lookup table (the split-sum approximation).
+/// `bind` puts them on texture units 7-9 for the PBR shader (`USE_IBL`).
+pub struct Environment {
+    irradiance: u32,
+    specular: u32,
+    brdf_lut: u32,
+}
+
+impl Environment {
+    pub fn from_hdr(path: &str) -> Result<Environment, String> {
+        let file = File::open(path).map_err(|err| format!("{}: {}", path, err))?;
+        let decoder = HDRDecoder::new(BufReader::new(file)).map_err(|err| format!("{}: {}", path, err))?;
+        let metadata = decoder.metadata();
+        let (width, height) = (metadata.width as i32, metadata.height as i32);
+        let pixels: Vec<f32> = decoder.read_image_hdr()
+            .map_err(|err| format!("{}: {}", path, err))?
+            .iter()
+            .flat_map(|pixel| pixel.data.iter().cloned())
+            .collect();
+
+        unsafe {
+            let mut viewport = [0; 4];
+            gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr());
+            let mut framebuffer = 0;
+            gl::GetIntegerv(gl::FRAMEBUFFER_BINDING, &mut framebuffer);
+            gl::Disable(gl::DEPTH_TEST);
+            gl::Disable(gl::CULL_FACE);
+            gl::Disable(gl::BLEND);
+            gl::Enable(gl::TEXTURE_CUBE_MAP_SEAMLESS);
+
+            // empty, for the fullscreen triangles
+            let mut vao = 0;
+            gl::GenVertexArrays(1, &mut vao);
+            gl::BindVertexArray(vao);
+            let mut fbo = 0;
+            gl::GenFramebuffers(1, &mut fbo);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, fbo);
+
+            let mut panorama = 0;
+            gl::GenTextures(1, &mut panorama);
+            gl::BindTexture(gl::TEXTURE_2D, panorama);
+            gl::TexImage2D(gl::TEXTURE_2D, 0, gl::RGB16F as i32, width, height, 0, gl::RGB, gl::FLOAT,
+                pixels.as_ptr() as *const c_void);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+
+            // panorama -> cubemap (mipmapped for filtered sampling in the convolutions)
+            let mut shader = environment_shader("EQUIRECT_TO_CUBE");
+            let u_face = shader.uniform_location("u_Face");
+            let cube = create_cubemap(CUBE_SIZE, true);
+            gl::ActiveTexture(gl::TEXTURE0);
+            gl::BindTexture(gl::TEXTURE_2D, panorama);
+            render_faces(&shader, u_face, cube, CUBE_SIZE, 0);
+            gl::BindTexture(gl::TEXTURE_CUBE_MAP, cube);
+            gl::GenerateMipmap(gl::TEXTURE_CUBE_MAP);
+
+            let mut shader = environment_shader("IRRADIANCE");
+            let u_face = shader.uniform_location("u_Face");
+            let irradiance = create_cubemap(IRRADIANCE_SIZE, false);
+            gl::BindTexture(gl::TEXTURE_CUBE_MAP, cube);
+            render_faces(&shader, u_face, irradiance, IRRADIANCE_SIZE, 0);
+
+            let mut shader = environment_shader("PREFILTER");
+            let (u_face, u_roughness) = (shader.uniform_location("u_Face"), shader.uniform_location("u_Roughness"));
+            let specular = create_cubemap(CUBE_SIZE, true);
+            gl::BindTexture(gl::TEXTURE_CUBE_MAP, cube);
+            for level in 0..SPECULAR_MIP_LEVELS {
+                shader.use_program();
+                shader.set_float(u_roughness, level as f32 / (SPECULAR_MIP_LEVELS - 1) as f32);
+                render_faces(&shader, u_face, specular, CUBE_SIZE >> level, level);
+            }
+
+            let shader = environment_shader("BRDF_LUT");
+            let mut brdf_lut = 0;
+            gl::GenTextures(1, &mut brdf_lut);
+            gl::BindTexture(gl::TEXTURE_2D, brdf_lut);
+            gl::TexImage2D(gl::TEXTURE_2D, 0, gl::RG16F as i32, BRDF_LUT_SIZE, BRDF_LUT_SIZE, 0, gl::RG, gl::FLOAT,
+                ptr::null());
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+            gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, brdf_lut, 0);
+            gl::Viewport(0, 0, BRDF_LUT_SIZE, BRDF_LUT_SIZE);
+            shader.use_program();
+            gl::DrawArrays(gl::TRIANGLES, 0, 3);
+
+            gl::BindVertexArray(0);
+            gl::DeleteVertexArrays(1, &vao);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, framebuffer as u32);
+            gl::DeleteFramebuffers(1, &fbo);
+            gl::DeleteTextures(1, &panorama);
+            gl::DeleteTextures(1, &cube);
+            gl::Viewport(viewport[0], viewport[1], viewport[2], viewport[3]);
+            gl::Enable(gl::DEPTH_TEST);
+
+            Ok(Environment { irradiance, specular, brdf_lut })
+        }
+    }
+
+    /// Binds the maps for the PBR shader
+    pub unsafe fn bind(&self) {
+        // NOTE: for sampler numbers, see also PbrShader constructor
+        gl::ActiveTexture(gl::TEXTURE7);
+        gl::BindTexture(gl::TEXTURE_CUBE_MAP, self.irradiance);
+        gl::ActiveTexture(gl::TEXTURE8);
+        gl::BindTexture(gl::TEXTURE_CUBE_MAP, self.specular);
+        gl::ActiveTexture(gl::TEXTURE9);
+        gl::BindTexture(gl::TEXTURE_2D, self.brdf_lut);
+        gl::ActiveTexture(gl::TEXTURE0);
+    }
+}
+
+impl Drop for Environment {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteTextures(1, &self.irradiance);
+            gl::DeleteTextures(1, &self.specular);
+            gl::DeleteTextures(1, &self.brdf_lut);
+        }
+    }
+}
+
+/// One of the passes of `environment-frag.glsl`
+fn environment_shader(pass: &str) -> Shader {
+    Shader::from_source(
+        include_str!("../shaders/fullscreen-vert.glsl"),
+        include_str!("../shaders/environment-frag.glsl"),
+        &[pass.to_owned()])
+}
+
+unsafe fn create_cubemap(size: i32, mipmapped: bool) -> u32 {
+    let mut texture = 0;
+    gl::GenTextures(1, &mut texture);
+    gl::BindTexture(gl::TEXTURE_CUBE_MAP, texture);
+    for face in 0..6 {
+        gl::TexImage2D(gl::TEXTURE_CUBE_MAP_POSITIVE_X + face, 0, gl::RGB16F as i32, size, size, 0,
+            gl::RGB, gl::FLOAT, ptr::null());
+    }
+    let min_filter = if mipmapped { gl::LINEAR_MIPMAP_LINEAR } else { gl::LINEAR };
+    gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_MIN_FILTER, min_filter as i32);
+    gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+    for &wrap in &[gl::TEXTURE_WRAP_S, gl::TEXTURE_WRAP_T, gl::TEXTURE_WRAP_R] {
+        gl::TexParameteri(gl::TEXTURE_CUBE_MAP, wrap, gl::CLAMP_TO_EDGE as i32);
+    }
+    if mipmapped {
+        // allocates the levels (overwritten by the prefiltering of the specular map)
+        gl::GenerateMipmap(gl::TEXTURE_CUBE_MAP);
+    }
+    texture
+}
+
+/// Draws the shader into all 6 faces of the given mip level of the cubemap (`u_Face`: 0-5)
+unsafe fn render_faces(shader: &Shader, u_face: i32, cubemap: u32, size: i32, level: i32) {
+    shader.use_program();
+    gl::Viewport(0, 0, size, size);
+    for face in 0..6 {
+        gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0,
+            gl::TEXTURE_CUBE_MAP_POSITIVE_X + face as u32, cubemap, level);
+        shader.set_int(u_face, face);
+        gl::DrawArrays(gl::TRIANGLES, 0, 3);
+    }
+}
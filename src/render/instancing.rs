@@ -0,0 +1,170 @@
+use std::mem::size_of;
+use std::os::raw::c_void;
+
+use gl;
+use gltf;
+use gltf::accessor::Dimensions;
+use log::warn;
+
+use crate::render::accessor;
+use crate::render::math::*;
+use crate::importdata::ImportData;
+
+/// First vertex attribute location of the instance data (see `a_InstanceMatrix` and
+/// `a_InstanceColor` in the vertex shader): 4 for the matrix columns + 1 for the color
+const INSTANCE_LOCATION: u32 = 9;
+
+/// Per-instance data of the vertex buffer: transform + tint
+#[repr(C)]
+struct InstanceData {
+    transform: [[f32; 4]; 4],
+    color: [f32; 4],
+}
+
+/// `EXT_mesh_gpu_instancing`: the mesh of a node is drawn once for each of these transforms
+/// (relative to the node), with a single draw call per primitive.
+pub struct Instances {
+    pub transforms: Vec<Matrix4>,
+    /// from the per-instance attribute given with `--instance-color` (white if absent)
+    pub colors: Vec<Vector4>,
+    vbo: u32,
+}
+
+impl Instances {
+    /// `color_attribute`: per-instance attribute to tint the instances with (e.g. `_COLOR`).
+    /// VEC3/VEC4 attributes are used as (linear) colors, SCALAR ones (e.g. feature IDs)
+    /// get a distinct color per value.
+    pub fn from_gltf(g_node: &gltf::Node<'_>, imp: &ImportData, color_attribute: &str) -> Option<Instances> {
+        let extension = imp.extension("nodes", g_node.index(), "EXT_mesh_gpu_instancing")?;
+        let attributes = extension["attributes"].as_object()?;
+        let attribute = |name: &str| attributes.get(name)
+            .and_then(|index| index.as_u64())
+            .and_then(|index| imp.doc.accessors().nth(index as usize));
+
+        let count = attributes.values()
+            .filter_map(|index| index.as_u64())
+            .filter_map(|index| imp.doc.accessors().nth(index as usize))
+            .map(|accessor| accessor.count())
+            .min()?;
+        if count == 0 {
+            return None
+        }
+        let read = |name: &str| attribute(name).and_then(|a| accessor::read_f32x4(&a, &imp.buffers));
+        let translations = read("TRANSLATION");
+        let rotations = read("ROTATION");
+        let scales = read("SCALE");
+        let transforms: Vec<Matrix4> = (0..count)
+            .map(|i| {
+                let t = translations.as_ref().map(|t| t[i]).unwrap_or([0.0; 4]);
+                let r = rotations.as_ref().map(|r| r[i]).unwrap_or([0.0, 0.0, 0.0, 1.0]);
+                let s = scales.as_ref().map(|s| s[i]).unwrap_or([1.0; 4]);
+                // NOTE: different element order of the quaternion!
+                trs_matrix(vec3(t[0], t[1], t[2]), Quaternion::new(r[3], r[0], r[1], r[2]), vec3(s[0], s[1], s[2]))
+            })
+            .collect();
+
+        let color_values = attribute(color_attribute)
+            .and_then(|a| accessor::read_f32x4(&a, &imp.buffers).map(|values| (a, values)));
+        let colors = match color_values {
+            Some((a, values)) => {
+                match a.dimensions() {
+                    Dimensions::Scalar => values.iter().take(count).map(|v| id_color(v[0])).collect(),
+                    Dimensions::Vec3 => values.iter().take(count).map(|v| vec4(v[0], v[1], v[2], 1.0)).collect(),
+                    Dimensions::Vec4 => values.iter().take(count).map(|v| Vector4::from(*v)).collect(),
+                    dimensions => {
+                        warn!("Ignoring instance attribute {} of node {}: unsupported type {:?}",
+                            color_attribute, g_node.index(), dimensions);
+                        vec![vec4(1.0, 1.0, 1.0, 1.0); count]
+                    }
+                }
+            },
+            None => vec![vec4(1.0, 1.0, 1.0, 1.0); count],
+        };
+
+        let mut instances = Instances { transforms, colors, vbo: 0 };
+        unsafe { instances.setup_buffer() }
+        Some(instances)
+    }
+
+    pub fn count(&self) -> usize {
+        self.transforms.len()
+    }
+
+    unsafe fn setup_buffer(&mut self) {
+        let data: Vec<InstanceData> = self.transforms.iter().zip(&self.colors)
+            .map(|(transform, color)| InstanceData {
+                transform: (*transform).into(),
+                color: (*color).into(),
+            })
+            .collect();
+        gl::GenBuffers(1, &mut self.vbo);
+        gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+        let size = (data.len() * size_of::<InstanceData>()) as isize;
+        gl::BufferData(gl::ARRAY_BUFFER, size, data.as_ptr() as *const c_void, gl::STATIC_DRAW);
+        gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+    }
+
+    /// Sets up the instance attributes of the currently bound vertex array
+    /// (undo with `unbind_attributes` before drawing it without instances again)
+    pub unsafe fn bind_attributes(&self) {
+        gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+        let stride = size_of::<InstanceData>() as i32;
+        for column in 0..5 {
+            let location = INSTANCE_LOCATION + column;
+            gl::EnableVertexAttribArray(location);
+            gl::VertexAttribPointer(location, 4, gl::FLOAT, gl::FALSE, stride,
+                (column as usize * size_of::<[f32; 4]>()) as *const c_void);
+            gl::VertexAttribDivisor(location, 1);
+        }
+        gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+    }
+
+    pub unsafe fn unbind_attributes(&self) {
+        for location in INSTANCE_LOCATION..INSTANCE_LOCATION + 5 {
+            gl::VertexAttribDivisor(location, 0);
+            gl::DisableVertexAttribArray(location);
+        }
+        reset_instance_attributes();
+    }
+}
+
+impl Drop for Instances {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteBuffers(1, &self.vbo);
+        }
+    }
+}
+
+/// Sets the values of the (disabled) instance attributes for non-instanced drawing:
+/// identity transform and no tint. Needed initially and after each instanced draw
+/// (the current attribute values are undefined after drawing with the arrays enabled).
+pub unsafe fn reset_instance_attributes() {
+    set_instance_attributes(&Matrix4::identity(), &vec4(1.0, 1.0, 1.0, 1.0));
+}
+
+/// Sets the (disabled) instance attributes to the values of a single instance,
+/// for drawing the instances one by one (without instancing support)
+pub unsafe fn set_instance_attributes(transform: &Matrix4, color: &Vector4) {
+    for column in 0..4 {
+        let c = transform[column];
+        gl::VertexAttrib4f(INSTANCE_LOCATION + column as u32, c.x, c.y, c.z, c.w);
+    }
+    gl::VertexAttrib4f(INSTANCE_LOCATION + 4, color.x, color.y, color.z, color.w);
+}
+
+/// A distinct (but stable) color for a scalar instance attribute like a feature ID
+fn id_color(id: f32) -> Vector4 {
+    // golden ratio hue steps keep neighbouring IDs apart
+    let hue = (id * 0.618_034).fract().abs() * 6.0;
+    let x = 1.0 - (hue % 2.0 - 1.0).abs();
+    let (r, g, b) = match hue as u32 {
+        0 => (1.0, x, 0.0),
+        1 => (x, 1.0, 0.0),
+        2 => (0.0, 1.0, x),
+        3 => (0.0, x, 1.0),
+        4 => (x, 0.0, 1.0),
+        _ => (1.0, 0.0, x),
+    };
+    vec4(r, g, b, 1.0)
+}
@@ -0,0 +1,226 @@
+use std::os::raw::c_void;
+
+use gl;
+use gl::types::GLenum;
+
+use crate::utils::Capabilities;
+
+/// At the start of every KTX2 file
+const IDENTIFIER: [u8; 12] = [0xAB, b'K', b'T', b'X', b' ', b'2', b'0', 0xBB, b'\r', b'\n', 0x1A, b'\n'];
+/// header + index (DFD, KVD and SGD offsets and lengths), followed by the level index
+const LEVEL_INDEX_OFFSET: usize = 80;
+
+// `GL_EXT_texture_compression_s3tc`, BPTC (OpenGL 4.2) and ETC2 (OpenGL 4.3)
+const COMPRESSED_RGB_S3TC_DXT1: GLenum = 0x83F0;
+const COMPRESSED_RGBA_S3TC_DXT1: GLenum = 0x83F1;
+const COMPRESSED_RGBA_S3TC_DXT5: GLenum = 0x83F3;
+const COMPRESSED_RGBA_BPTC_UNORM: GLenum = 0x8E8C;
+const COMPRESSED_RGB8_ETC2: GLenum = 0x9274;
+const COMPRESSED_RGBA8_ETC2_EAC: GLenum = 0x9278;
+
+/// Formats (`vkFormat`) of KTX2 textures that can be uploaded without transcoding
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Ktx2Format {
+    Rgba8,
+    Bc1Rgb,
+    Bc1Rgba,
+    Bc3,
+    Bc7,
+    Etc2Rgb,
+    Etc2Rgba,
+}
+
+impl Ktx2Format {
+    /// NOTE: the sRGB variants are uploaded like the UNORM ones, the PBR shader decodes sRGB itself
+    fn from_vk_format(vk_format: u32) -> Option<Ktx2Format> {
+        match vk_format {
+            37 | 43 => Some(Ktx2Format::Rgba8),
+            131 | 132 => Some(Ktx2Format::Bc1Rgb),
+            133 | 134 => Some(Ktx2Format::Bc1Rgba),
+            137 | 138 => Some(Ktx2Format::Bc3),
+            145 | 146 => Some(Ktx2Format::Bc7),
+            147 | 148 => Some(Ktx2Format::Etc2Rgb),
+            151 | 152 => Some(Ktx2Format::Etc2Rgba),
+            _ => None,
+        }
+    }
+
+    /// Internal format for `glCompressedTexImage2D` (`None`: uncompressed)
+    fn gl_compressed_format(self) -> Option<GLenum> {
+        match self {
+            Ktx2Format::Rgba8 => None,
+            Ktx2Format::Bc1Rgb => Some(COMPRESSED_RGB_S3TC_DXT1),
+            Ktx2Format::Bc1Rgba => Some(COMPRESSED_RGBA_S3TC_DXT1),
+            Ktx2Format::Bc3 => Some(COMPRESSED_RGBA_S3TC_DXT5),
+            Ktx2Format::Bc7 => Some(COMPRESSED_RGBA_BPTC_UNORM),
+            Ktx2Format::Etc2Rgb => Some(COMPRESSED_RGB8_ETC2),
+            Ktx2Format::Etc2Rgba => Some(COMPRESSED_RGBA8_ETC2_EAC),
+        }
+    }
+
+    pub fn is_supported(self, capabilities: &Capabilities) -> bool {
+        match self {
+            Ktx2Format::Rgba8 => true,
+            Ktx2Format::Bc1Rgb | Ktx2Format::Bc1Rgba | Ktx2Format::Bc3 => capabilities.s3tc,
+            Ktx2Format::Bc7 => capabilities.bptc,
+            Ktx2Format::Etc2Rgb | Ktx2Format::Etc2Rgba => capabilities.etc2,
+        }
+    }
+
+    /// Minimum size in bytes of a mip level (4x4 blocks for the compressed formats)
+    fn level_size(self, width: u32, height: u32) -> usize {
+        let blocks = || (width as usize).div_ceil(4) * (height as usize).div_ceil(4);
+        match self {
+            Ktx2Format::Rgba8 => width as usize * height as usize * 4,
+            Ktx2Format::Bc1Rgb | Ktx2Format::Bc1Rgba | Ktx2Format::Etc2Rgb => blocks() * 8,
+            Ktx2Format::Bc3 | Ktx2Format::Bc7 | Ktx2Format::Etc2Rgba => blocks() * 16,
+        }
+    }
+}
+
+/// A 2D KTX2 texture (`KHR_texture_basisu`) without supercompression, in a format that the GPU
+/// can use directly. Basis Universal textures (BasisLZ / UASTC) need transcoding, which isn't
+/// supported, i.e. `parse` fails for them and the fallback image of the texture is used instead.
+#[derive(Debug)]
+pub struct Ktx2<'a> {
+    pub format: Ktx2Format,
+    pub width: u32,
+    pub height: u32,
+    /// mip levels, largest first
+    pub levels: Vec<&'a [u8]>,
+}
+
+impl<'a> Ktx2<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Ktx2<'a>, String> {
+        if !data.starts_with(&IDENTIFIER) {
+            return Err("not a KTX2 file".to_owned())
+        }
+        if data.len() < LEVEL_INDEX_OFFSET {
+            return Err("truncated header".to_owned())
+        }
+        let u32_at = |offset: usize| {
+            u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+        };
+        let u64_at = |offset: usize| u64::from(u32_at(offset)) | (u64::from(u32_at(offset + 4)) << 32);
+
+        let vk_format = u32_at(12);
+        let (width, height, depth) = (u32_at(20), u32_at(24), u32_at(28));
+        let (layers, faces, level_count) = (u32_at(32), u32_at(36), u32_at(40));
+        let supercompression = u32_at(44);
+        if supercompression != 0 {
+            return Err(format!("supercompression scheme {} (e.g. BasisLZ) isn't supported", supercompression))
+        }
+        if depth > 1 || layers > 1 || faces != 1 || width == 0 || height == 0 {
+            return Err("only 2D textures are supported".to_owned())
+        }
+        let format = match Ktx2Format::from_vk_format(vk_format) {
+            Some(format) => format,
+            None if vk_format == 0 => return Err("Basis Universal (UASTC) transcoding isn't supported".to_owned()),
+            None => return Err(format!("unsupported vkFormat {}", vk_format)),
+        };
+
+        // 0: the mip levels should be generated
+        let level_count = level_count.max(1) as usize;
+        // at most a full mip chain down to 1x1 (also keeps the shifts below in range)
+        let max_level_count = (32 - width.max(height).leading_zeros()) as usize;
+        if level_count > max_level_count {
+            return Err(format!("{} mip levels for a {}x{} texture", level_count, width, height))
+        }
+        if data.len() < LEVEL_INDEX_OFFSET + level_count * 24 {
+            return Err("truncated level index".to_owned())
+        }
+        let mut levels = Vec::with_capacity(level_count);
+        for level in 0..level_count {
+            let entry = LEVEL_INDEX_OFFSET + level * 24;
+            let (offset, length) = (u64_at(entry) as usize, u64_at(entry + 8) as usize);
+            let bytes = offset.checked_add(length)
+                .and_then(|end| data.get(offset..end))
+                .ok_or_else(|| format!("level {} is out of bounds", level))?;
+            if bytes.len() < format.level_size((width >> level).max(1), (height >> level).max(1)) {
+                return Err(format!("level {} is too small", level))
+            }
+            levels.push(bytes);
+        }
+
+        Ok(Ktx2 { format, width, height, levels })
+    }
+
+    /// Uploads the levels into the bound `TEXTURE_2D`. With `generate_mip_maps`, the missing levels
+    /// of a single level RGBA8 texture are generated; otherwise only the given levels are used.
+    pub unsafe fn upload(&self, generate_mip_maps: bool) {
+        for (level, data) in self.levels.iter().enumerate() {
+            let width = (self.width >> level).max(1) as i32;
+            let height = (self.height >> level).max(1) as i32;
+            let pixels = data.as_ptr() as *const c_void;
+            match self.format.gl_compressed_format() {
+                Some(internal_format) => gl::CompressedTexImage2D(gl::TEXTURE_2D, level as i32, internal_format,
+                    width, height, 0, data.len() as i32, pixels),
+                None => gl::TexImage2D(gl::TEXTURE_2D, level as i32, gl::RGBA as i32, width, height, 0,
+                    gl::RGBA, gl::UNSIGNED_BYTE, pixels),
+            }
+        }
+        if generate_mip_maps && self.levels.len() == 1 && self.format == Ktx2Format::Rgba8 {
+            gl::GenerateMipmap(gl::TEXTURE_2D);
+        } else {
+            // mipmap complete with the given levels (compressed textures can't be mipmapped here)
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAX_LEVEL, self.levels.len() as i32 - 1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A single level KTX2 file
+    fn ktx2(vk_format: u32, width: u32, height: u32, supercompression: u32, level: &[u8]) -> Vec<u8> {
+        let mut data = IDENTIFIER.to_vec();
+        for value in &[vk_format, 1, width, height, 0, 0, 1, 1, supercompression] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.resize(LEVEL_INDEX_OFFSET, 0); // no DFD, KVD or SGD
+        let offset = (LEVEL_INDEX_OFFSET + 24) as u64;
+        for value in &[offset, level.len() as u64, level.len() as u64] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.extend_from_slice(level);
+        data
+    }
+
+    #[test]
+    fn parse_uncompressed_and_block_compressed() {
+        let rgba = ktx2(43, 2, 2, 0, &[255; 16]);
+        let texture = Ktx2::parse(&rgba).unwrap();
+        assert_eq!((texture.format, texture.width, texture.height), (Ktx2Format::Rgba8, 2, 2));
+        assert_eq!(texture.levels, vec![&[255u8; 16][..]]);
+
+        // one 4x4 block of 16 bytes, also for smaller textures
+        let bc7 = ktx2(145, 3, 2, 0, &[0; 16]);
+        assert_eq!(Ktx2::parse(&bc7).unwrap().format, Ktx2Format::Bc7);
+        let bc7_too_small = ktx2(145, 8, 4, 0, &[0; 16]);
+        assert!(Ktx2::parse(&bc7_too_small).is_err());
+    }
+
+    #[test]
+    fn basis_universal_is_rejected() {
+        let basis_lz = ktx2(0, 4, 4, 1, &[0; 16]);
+        assert!(Ktx2::parse(&basis_lz).unwrap_err().contains("BasisLZ"));
+        let uastc = ktx2(0, 4, 4, 0, &[0; 16]);
+        assert!(Ktx2::parse(&uastc).unwrap_err().contains("UASTC"));
+
+        let mut truncated = ktx2(37, 2, 2, 0, &[0; 16]);
+        truncated.truncate(truncated.len() - 1);
+        assert!(Ktx2::parse(&truncated).is_err());
+        assert!(Ktx2::parse(b"\x89PNG").is_err());
+    }
+
+    #[test]
+    fn too_many_levels_are_rejected() {
+        // 2x2 has 2 levels (2x2, 1x1); a malformed level count must not overflow the size shifts
+        for &level_count in &[3u32, 32, 40] {
+            let mut data = ktx2(43, 2, 2, 0, &[255; 16]);
+            data[40..44].copy_from_slice(&level_count.to_le_bytes());
+            assert!(Ktx2::parse(&data).unwrap_err().contains("mip levels"), "{}", level_count);
+        }
+    }
+}
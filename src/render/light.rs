@@ -0,0 +1,173 @@
+use std::f32::consts::FRAC_PI_4;
+
+use log::debug;
+
+use crate::render::math::*;
+
+/// Maximum number of lights (see `MAX_LIGHTS` in the PBR shader); any further ones are ignored.
+pub const MAX_LIGHTS: usize = 8;
+
+/// Towards the (directional) default light of scenes without lights
+pub const DEFAULT_LIGHT_DIRECTION: [f32; 3] = [0.0, 0.5, 0.5];
+
+/// Type of a `KHR_lights_punctual` light (see `LIGHT_*` in the PBR shader for the indices)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LightKind {
+    Directional,
+    Point,
+    /// cone angles in radians from the -Z axis of the node: full intensity inside `inner_cone_angle`,
+    /// falling off smoothly to zero at `outer_cone_angle`
+    Spot { inner_cone_angle: f32, outer_cone_angle: f32 },
+}
+
+/// `KHR_lights_punctual` light (e.g. a sun lamp from Blender), positioned at the origin of its node
+/// and shining along its -Z axis (unless it's a point light)
+#[derive(Clone, Debug, PartialEq)]
+pub struct Light {
+    /// glTF index of the node
+    pub node: usize,
+    pub kind: LightKind,
+    /// linear color times intensity (lux for directional lights, candela otherwise)
+    pub color: Vector3,
+    /// distance at which point and spot lights fade out (`None`: inverse square law only)
+    pub range: Option<f32>,
+}
+
+impl LightKind {
+    /// `u_LightType` of the PBR shader
+    pub fn shader_index(self) -> i32 {
+        match self {
+            LightKind::Directional => 0,
+            LightKind::Point => 1,
+            LightKind::Spot { .. } => 2,
+        }
+    }
+
+    /// Scale and offset of the cosine of the angle to the spot axis for the falloff
+    /// (`u_LightConeScale` / `u_LightConeOffset`, as recommended by the extension)
+    pub fn cone_scale_offset(self) -> (f32, f32) {
+        match self {
+            LightKind::Spot { inner_cone_angle, outer_cone_angle } => {
+                let scale = 1.0 / (inner_cone_angle.cos() - outer_cone_angle.cos()).max(0.001);
+                (scale, -outer_cone_angle.cos() * scale)
+            },
+            _ => (0.0, 1.0),
+        }
+    }
+}
+
+impl Light {
+    /// The lights of all nodes of the glTF JSON
+    pub fn from_json(json: &serde_json::Value) -> Vec<Light> {
+        let lights = json["extensions"]["KHR_lights_punctual"]["lights"].as_array()
+            .map(|lights| lights.as_slice())
+            .unwrap_or_default();
+        let nodes = json["nodes"].as_array().map(|nodes| nodes.as_slice()).unwrap_or_default();
+        let mut result = vec![];
+        for (node, value) in nodes.iter().enumerate() {
+            let light = value["extensions"]["KHR_lights_punctual"]["light"].as_u64()
+                .and_then(|index| lights.get(index as usize));
+            let light = match light {
+                Some(light) => light,
+                None => continue,
+            };
+            let number = |value: &serde_json::Value, default: f32| value.as_f64().map(|v| v as f32).unwrap_or(default);
+            let kind = match light["type"].as_str() {
+                Some("directional") => LightKind::Directional,
+                Some("point") => LightKind::Point,
+                Some("spot") => LightKind::Spot {
+                    inner_cone_angle: number(&light["spot"]["innerConeAngle"], 0.0),
+                    outer_cone_angle: number(&light["spot"]["outerConeAngle"], FRAC_PI_4),
+                },
+                other => {
+                    debug!("Ignoring light of unknown type {:?} of node {}", other, node);
+                    continue
+                }
+            };
+            let component = |i: usize| number(&light["color"][i], 1.0);
+            result.push(Light {
+                node,
+                kind,
+                color: vec3(component(0), component(1), component(2)) * number(&light["intensity"], 1.0),
+                range: light["range"].as_f64().map(|range| range as f32).filter(|&range| range > 0.0),
+            });
+        }
+        result
+    }
+
+    /// Unit vector towards the light (or opposite to the spot direction), for the world transform of its node
+    pub fn direction_to_light(node_transform: &Matrix4) -> Vector3 {
+        (node_transform * vec4(0.0, 0.0, 1.0, 0.0)).truncate().normalize()
+    }
+
+    /// World position, for the world transform of its node
+    pub fn position(node_transform: &Matrix4) -> Vector3 {
+        node_transform.w.truncate()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Deg;
+
+    use super::*;
+
+    #[test]
+    fn directional_lights_from_json() {
+        let json = serde_json::json!({
+            "extensions": {
+                "KHR_lights_punctual": {
+                    "lights": [
+                        {"type": "area", "intensity": 10.0},
+                        {"type": "directional", "color": [1.0, 0.5, 0.0], "intensity": 2.0},
+                    ]
+                }
+            },
+            "nodes": [
+                {"extensions": {"KHR_lights_punctual": {"light": 0}}},
+                {"mesh": 0},
+                {"extensions": {"KHR_lights_punctual": {"light": 1}}},
+            ]
+        });
+        assert_eq!(Light::from_json(&json), vec![
+            Light { node: 2, kind: LightKind::Directional, color: vec3(2.0, 1.0, 0.0), range: None },
+        ]);
+
+        // pointing straight down: the light is above
+        let down = Matrix4::from_angle_x(Deg(-90.0)) * Matrix4::from_scale(3.0);
+        let direction = Light::direction_to_light(&down);
+        assert!((direction - vec3(0.0, 1.0, 0.0)).magnitude() < 1e-5, "{:?}", direction);
+    }
+
+    #[test]
+    fn colored_spot_light() {
+        let json = serde_json::json!({
+            "extensions": {
+                "KHR_lights_punctual": {
+                    "lights": [
+                        {"type": "spot", "color": [0.0, 0.0, 1.0], "intensity": 50.0, "range": 10.0,
+                         "spot": {"innerConeAngle": 0.5}},
+                        {"type": "point", "range": 0.0},
+                    ]
+                }
+            },
+            "nodes": [
+                {"extensions": {"KHR_lights_punctual": {"light": 1}}},
+                {"extensions": {"KHR_lights_punctual": {"light": 0}}},
+            ]
+        });
+        let spot = LightKind::Spot { inner_cone_angle: 0.5, outer_cone_angle: FRAC_PI_4 };
+        assert_eq!(Light::from_json(&json), vec![
+            Light { node: 0, kind: LightKind::Point, color: vec3(1.0, 1.0, 1.0), range: None },
+            Light { node: 1, kind: spot, color: vec3(0.0, 0.0, 50.0), range: Some(10.0) },
+        ]);
+
+        // full intensity at the inner cone angle, none at the outer one
+        let (scale, offset) = spot.cone_scale_offset();
+        assert!((0.5f32.cos() * scale + offset - 1.0).abs() < 1e-5);
+        assert!((FRAC_PI_4.cos() * scale + offset).abs() < 1e-5);
+
+        let transform = Matrix4::from_translation(vec3(1.0, 2.0, 3.0)) * Matrix4::from_angle_y(Deg(90.0));
+        assert_eq!(Light::position(&transform), vec3(1.0, 2.0, 3.0));
+    }
+}
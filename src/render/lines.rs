@@ -0,0 +1,112 @@
+use std::mem::{size_of, size_of_val};
+use std::os::raw::c_void;
+use std::ptr;
+
+use gl;
+use gl::types::GLenum;
+
+use crate::render::math::*;
+use crate::shader::Shader;
+
+/// Simple unlit line drawing for overlays and debug visualizations
+pub struct LineRenderer {
+    shader: Shader,
+    vao: u32,
+    vbo: u32,
+
+    u_mvp_matrix: i32,
+    u_color: i32,
+}
+
+impl LineRenderer {
+    pub fn new() -> Self {
+        let mut shader = Shader::from_source(
+            include_str!("../shaders/line-vert.glsl"),
+            include_str!("../shaders/line-frag.glsl"),
+            &[]);
+        let (mut vao, mut vbo) = (0, 0);
+        unsafe {
+            gl::GenVertexArrays(1, &mut vao);
+            gl::GenBuffers(1, &mut vbo);
+            gl::BindVertexArray(vao);
+            gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+            gl::EnableVertexAttribArray(0);
+            gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, size_of::<Vector3>() as i32, ptr::null());
+            gl::BindVertexArray(0);
+
+            LineRenderer {
+                u_mvp_matrix: shader.uniform_location("u_MVPMatrix"),
+                u_color: shader.uniform_location("u_Color"),
+                shader,
+                vao,
+                vbo,
+            }
+        }
+    }
+
+    /// Activates the line shader, e.g. for drawing other geometry (with positions at location 0) unlit
+    pub unsafe fn use_program(&self, color: &Vector4, mvp_matrix: &Matrix4) {
+        self.shader.use_program();
+        self.shader.set_mat4(self.u_mvp_matrix, mvp_matrix);
+        self.shader.set_vector4(self.u_color, color);
+    }
+
+    /// Draws `points` with the given primitive `mode` (`gl::LINES`, `gl::LINE_LOOP` etc.)
+    pub unsafe fn draw(&self, points: &[Vector3], mode: GLenum, color: &Vector4, mvp_matrix: &Matrix4) {
+        if points.is_empty() {
+            return
+        }
+        self.use_program(color, mvp_matrix);
+
+        gl::BindVertexArray(self.vao);
+        gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+        let size = size_of_val(points) as isize;
+        gl::BufferData(gl::ARRAY_BUFFER, size, points.as_ptr() as *const c_void, gl::STREAM_DRAW);
+        gl::DrawArrays(mode, 0, points.len() as i32);
+        gl::BindVertexArray(0);
+    }
+
+    /// Draws a wireframe sphere as three axis-aligned great circles
+    pub unsafe fn draw_sphere(&self, center: Point3, radius: f32, color: &Vector4, mvp_matrix: &Matrix4) {
+        const SEGMENTS: usize = 64;
+        for axis in 0..3 {
+            let circle: Vec<Vector3> = (0..SEGMENTS)
+                .map(|i| {
+                    let angle = i as f32 / SEGMENTS as f32 * 2.0 * std::f32::consts::PI;
+                    let (sin, cos) = (radius * angle.sin(), radius * angle.cos());
+                    let offset = match axis {
+                        0 => vec3(0.0, cos, sin),
+                        1 => vec3(cos, 0.0, sin),
+                        _ => vec3(cos, sin, 0.0),
+                    };
+                    center.to_vec() + offset
+                })
+                .collect();
+            self.draw(&circle, gl::LINE_LOOP, color, mvp_matrix);
+        }
+    }
+
+    /// Draws the edges of the frustum given by an inverse view-projection matrix
+    pub unsafe fn draw_frustum(&self, inverse_view_projection: &Matrix4, color: &Vector4, mvp_matrix: &Matrix4) {
+        // slightly in front of the far plane, which is at infinity for infinite projections
+        const FAR: f32 = 0.9999;
+        let corner = |x: f32, y: f32, z: f32| {
+            Point3::from_homogeneous(inverse_view_projection * vec4(x, y, z, 1.0)).to_vec()
+        };
+        let mut points = Vec::with_capacity(24);
+        for &(x, y) in &[(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)] {
+            // edge from near to far plane
+            points.push(corner(x, y, -1.0));
+            points.push(corner(x, y, FAR));
+        }
+        for &z in &[-1.0, FAR] {
+            // near and far rectangle
+            let rect = [corner(-1.0, -1.0, z), corner(1.0, -1.0, z), corner(1.0, 1.0, z), corner(-1.0, 1.0, z)];
+            for i in 0..4 {
+                points.push(rect[i]);
+                points.push(rect[(i + 1) % 4]);
+            }
+        }
+        self.draw(&points, gl::LINES, color, mvp_matrix);
+    }
+}
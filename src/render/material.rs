@@ -1,3 +1,4 @@
+use std::cell::Cell;
 use std::rc::Rc;
 use std::path::Path;
 
@@ -9,6 +10,60 @@ use crate::render::texture::Texture;
 use crate::shader::*;
 use crate::importdata::ImportData;
 
+/// `KHR_texture_transform` of a texture slot
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UvTransform {
+    pub matrix: Matrix3,
+    /// overrides the tex coord set of the texture info
+    pub tex_coord: Option<u32>,
+}
+
+impl Default for UvTransform {
+    fn default() -> Self {
+        UvTransform {
+            matrix: Matrix3::identity(),
+            tex_coord: None,
+        }
+    }
+}
+
+impl UvTransform {
+    /// From the (raw JSON) texture info of a slot, e.g. `normalTexture`
+    pub fn from_json(texture_info: &serde_json::Value) -> UvTransform {
+        let extension = &texture_info["extensions"]["KHR_texture_transform"];
+        if !extension.is_object() {
+            return UvTransform::default()
+        }
+        let vec2_or = |key: &str, default: f32| {
+            let component = |i: usize| extension[key][i].as_f64().map(|c| c as f32).unwrap_or(default);
+            vec2(component(0), component(1))
+        };
+        let offset = vec2_or("offset", 0.0);
+        let scale = vec2_or("scale", 1.0);
+        let rotation = extension["rotation"].as_f64().unwrap_or(0.0) as f32;
+
+        // translation * rotation * scale, see the extension spec
+        let (sin, cos) = rotation.sin_cos();
+        let translation = Matrix3::new(
+            1.0, 0.0, 0.0,
+            0.0, 1.0, 0.0,
+            offset.x, offset.y, 1.0);
+        let rotation = Matrix3::new(
+            cos, -sin, 0.0,
+            sin, cos, 0.0,
+            0.0, 0.0, 1.0);
+        let scale = Matrix3::new(
+            scale.x, 0.0, 0.0,
+            0.0, scale.y, 0.0,
+            0.0, 0.0, 1.0);
+
+        UvTransform {
+            matrix: translation * rotation * scale,
+            tex_coord: extension["texCoord"].as_u64().map(|t| t as u32),
+        }
+    }
+}
+
 pub struct Material {
     pub index: Option<usize>, /// glTF index
     pub name: Option<String>,
@@ -28,11 +83,23 @@ pub struct Material {
     pub emissive_factor: Vector3,
     pub emissive_texture: Option<Rc<Texture>>,
 
+    // KHR_texture_transform (per slot)
+    pub base_color_uv_transform: UvTransform,
+    pub metallic_roughness_uv_transform: UvTransform,
+    pub normal_uv_transform: UvTransform,
+    pub occlusion_uv_transform: UvTransform,
+    pub emissive_uv_transform: UvTransform,
+
     pub alpha_cutoff: f32,
     pub alpha_mode: gltf::material::AlphaMode,
 
     pub double_sided: bool,
+    /// `KHR_materials_unlit`: shaded with the base color only
+    pub unlit: bool,
 
+    // look-dev multipliers for metallic_factor/roughness_factor, adjustable at runtime
+    pub metallic_scale: Cell<f32>,
+    pub roughness_scale: Cell<f32>,
 }
 
 impl Material {
@@ -63,10 +130,20 @@ impl Material {
             emissive_factor: g_material.emissive_factor().into(),
             emissive_texture: None,
 
+            base_color_uv_transform: UvTransform::default(),
+            metallic_roughness_uv_transform: UvTransform::default(),
+            normal_uv_transform: UvTransform::default(),
+            occlusion_uv_transform: UvTransform::default(),
+            emissive_uv_transform: UvTransform::default(),
+
             alpha_cutoff: g_material.alpha_cutoff(),
             alpha_mode: g_material.alpha_mode(),
 
             double_sided: g_material.double_sided(),
+            unlit: false,
+
+            metallic_scale: Cell::new(1.0),
+            roughness_scale: Cell::new(1.0),
         };
 
         if let Some(color_info) = pbr.base_color_texture() {
@@ -91,6 +168,38 @@ impl Material {
             material.emissive_texture = Some(
                 load_texture(&em_info.texture(), em_info.tex_coord(), root, imp, base_path));
         }
+        if let Some(json) = g_material.index().and_then(|index| imp.json["materials"].get(index)) {
+            let pbr = &json["pbrMetallicRoughness"];
+            material.base_color_uv_transform = UvTransform::from_json(&pbr["baseColorTexture"]);
+            material.metallic_roughness_uv_transform = UvTransform::from_json(&pbr["metallicRoughnessTexture"]);
+            material.normal_uv_transform = UvTransform::from_json(&json["normalTexture"]);
+            material.occlusion_uv_transform = UvTransform::from_json(&json["occlusionTexture"]);
+            material.emissive_uv_transform = UvTransform::from_json(&json["emissiveTexture"]);
+        }
+        // primitives without a material: spec default, optionally overridden (`--default-*`)
+        if g_material.index().is_none() {
+            let options = &imp.options;
+            if let Some(color) = options.default_color {
+                material.base_color_factor = color.extend(1.0);
+            }
+            if let Some(metallic) = options.default_metallic {
+                material.metallic_factor = metallic;
+            }
+            if let Some(roughness) = options.default_roughness {
+                material.roughness_factor = roughness;
+            }
+        }
+        // may push the emissive color above 1.0 (HDR)
+        if let Some(strength) = g_material.index()
+            .and_then(|index| imp.extension("materials", index, "KHR_materials_emissive_strength"))
+            .and_then(|extension| extension.get("emissiveStrength"))
+            .and_then(|strength| strength.as_f64())
+        {
+            material.emissive_factor *= strength as f32;
+        }
+        material.unlit = g_material.index()
+            .and_then(|index| imp.extension("materials", index, "KHR_materials_unlit"))
+            .is_some();
 
         material
     }
@@ -112,6 +221,9 @@ impl Material {
         if self.occlusion_texture.is_some() {
             flags |= ShaderFlags::HAS_OCCLUSIONMAP;
         }
+        if self.unlit {
+            flags |= ShaderFlags::UNLIT;
+        }
         flags
     }
 
@@ -128,7 +240,30 @@ fn load_texture(
         return Rc::clone(tex)
     }
 
-    let texture = Rc::new(Texture::from_gltf(g_texture, tex_coord, imp, base_path));
+    let texture = Rc::new(Texture::from_gltf(g_texture, tex_coord, &root.texture_filtering, imp, base_path));
     root.textures.push(Rc::clone(&texture));
     texture
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::importdata::parse_json;
+
+    #[test]
+    fn per_slot_texture_transforms() {
+        let json = parse_json(include_bytes!("../../tests/TextureTransformPerSlot.gltf"));
+        let material = &json["materials"][0];
+        let base_color = UvTransform::from_json(&material["pbrMetallicRoughness"]["baseColorTexture"]);
+        let normal = UvTransform::from_json(&material["normalTexture"]);
+        let occlusion = UvTransform::from_json(&material["occlusionTexture"]);
+
+        // tiled 4x
+        assert_eq!(base_color.matrix * vec3(1.0, 1.0, 1.0), vec3(4.0, 4.0, 1.0));
+        // rotated by 90° and offset, not tiled
+        let uv = normal.matrix * vec3(1.0, 0.0, 1.0);
+        assert!((uv - vec3(0.5, -1.0, 1.0)).magnitude() < 1e-5);
+        // no transform
+        assert_eq!(occlusion, UvTransform::default());
+    }
+}
@@ -2,7 +2,7 @@ use std::f32;
 
 use cgmath;
 pub use cgmath::prelude::*;
-pub use cgmath::{vec3, vec4};
+pub use cgmath::{vec2, vec3, vec4};
 
 use num_traits::clamp;
 
@@ -14,6 +14,7 @@ pub type Vector4 = cgmath::Vector4<f32>;
 
 pub type Point3 = cgmath::Point3<f32>;
 
+pub type Matrix3 = cgmath::Matrix3<f32>;
 pub type Matrix4 = cgmath::Matrix4<f32>;
 pub type Quaternion = cgmath::Quaternion<f32>;
 
@@ -59,6 +60,69 @@ impl Spherical {
     }
 }
 
+/// Bounding box containing nothing (neutral element of `union_aabb`)
+pub fn empty_aabb() -> Aabb3 {
+    Aabb3 {
+        min: Point3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY),
+        max: Point3::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY),
+    }
+}
+
+pub fn is_empty_aabb(aabb: &Aabb3) -> bool {
+    aabb.min.x > aabb.max.x
+}
+
+/// `Union::union` that handles empty boxes (see `empty_aabb`)
+pub fn union_aabb(a: &Aabb3, b: &Aabb3) -> Aabb3 {
+    use collision::Union;
+    if is_empty_aabb(a) {
+        *b
+    } else if is_empty_aabb(b) {
+        *a
+    } else {
+        a.union(b)
+    }
+}
+
+/// Whether the (model space) bounding box is completely outside of the view frustum.
+/// Conservative: only checks if all corners are outside of the same clip plane.
+pub fn aabb_outside_frustum(aabb: &Aabb3, mvp_matrix: &Matrix4) -> bool {
+    let corners: Vec<Vector4> = aabb.to_corners().iter()
+        .map(|corner| mvp_matrix * corner.to_homogeneous())
+        .collect();
+    (0..3).any(|axis| {
+        corners.iter().all(|c| c[axis] < -c.w) || corners.iter().all(|c| c[axis] > c.w)
+    })
+}
+
+/// Distance along the ray to the first intersection with the box (slab method)
+pub fn ray_aabb_intersection(origin: Point3, direction: Vector3, aabb: &Aabb3) -> Option<f32> {
+    let mut t_min = f32::NEG_INFINITY;
+    let mut t_max = f32::INFINITY;
+    for i in 0..3 {
+        let inv = 1.0 / direction[i];
+        let mut t0 = (aabb.min[i] - origin[i]) * inv;
+        let mut t1 = (aabb.max[i] - origin[i]) * inv;
+        if inv < 0.0 {
+            std::mem::swap(&mut t0, &mut t1);
+        }
+        t_min = t_min.max(t0);
+        t_max = t_max.min(t1);
+    }
+    if t_max >= t_min.max(0.0) {
+        Some(t_min.max(0.0))
+    } else {
+        None
+    }
+}
+
+/// Local transform of glTF TRS properties: scaled first, then rotated and translated (T * R * S)
+pub fn trs_matrix(translation: Vector3, rotation: Quaternion, scale: Vector3) -> Matrix4 {
+    Matrix4::from_translation(translation) *
+        Matrix4::from(rotation) *
+        Matrix4::from_nonuniform_scale(scale.x, scale.y, scale.z)
+}
+
 use std::num::ParseFloatError;
 pub fn parse_vec3(s: &str) -> Result<Vector3, ParseFloatError> {
     let coords: Vec<&str> = s.split(',').collect();
@@ -69,3 +133,19 @@ pub fn parse_vec3(s: &str) -> Result<Vector3, ParseFloatError> {
 
     Ok(vec3(x, y, z))
 }
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Deg;
+
+    use super::*;
+
+    #[test]
+    fn trs_matrix_scales_before_rotating() {
+        // stretched along X, then turned by 90 degrees around Z: stretched along Y
+        let rotation = Quaternion::from_angle_z(Deg(90.0));
+        let matrix = trs_matrix(vec3(1.0, 0.0, 0.0), rotation, vec3(2.0, 1.0, 1.0));
+        let corner = matrix * vec4(1.0, 1.0, 0.0, 1.0);
+        assert!((corner - vec4(0.0, 2.0, 0.0, 1.0)).magnitude() < 1e-5, "{:?}", corner);
+    }
+}
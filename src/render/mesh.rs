@@ -1,19 +1,22 @@
 // use std::rc::Rc;
+use std::cell::RefCell;
 use std::path::Path;
 
-use collision::{Aabb, Aabb3, Union};
+use collision::Aabb3;
 
 use gltf;
 
 use crate::render::math::*;
-use crate::render::{Primitive, Root};
+use crate::render::{Instances, Primitive, RenderStats, Root};
 use crate::importdata::ImportData;
 
 pub struct Mesh {
     pub index: usize, // glTF index
     pub primitives: Vec<Primitive>,
-    // TODO: weights
-    // pub weights: Vec<Rc<?>>
+    /// Morph target weights: defaults from the glTF (`mesh.weights`), animated (see `Animation`)
+    pub weights: RefCell<Vec<f32>>,
+    /// Manually set weights (see `Root::set_morph_weight`), taking precedence over `weights`
+    pub weight_overrides: RefCell<Vec<Option<f32>>>,
     pub name: Option<String>,
 
     pub bounds: Aabb3<f32>,
@@ -26,27 +29,52 @@ impl Mesh {
         imp: &ImportData,
         base_path: &Path,
     ) -> Mesh {
+        // empty primitives are skipped (with a warning)
         let primitives: Vec<Primitive> = g_mesh.primitives()
             .enumerate()
-            .map(|(i, g_prim)| {
+            .filter_map(|(i, g_prim)| {
                 Primitive::from_gltf(&g_prim, i, g_mesh.index(), root, imp, base_path)
             })
             .collect();
 
         let bounds = primitives.iter()
-            .fold(Aabb3::zero(), |bounds, prim| prim.bounds.union(&bounds));
+            .fold(empty_aabb(), |bounds, prim| union_aabb(&prim.bounds, &bounds));
+
+        let target_count = primitives.iter().map(|prim| prim.morph_target_count()).max().unwrap_or(0);
+        let mut weights = g_mesh.weights().map(|w| w.to_vec()).unwrap_or_default();
+        weights.resize(target_count, 0.0);
 
         Mesh {
             index: g_mesh.index(),
             primitives,
+            weights: RefCell::new(weights),
+            weight_overrides: RefCell::new(vec![None; target_count]),
             name: g_mesh.name().map(|s| s.into()),
             bounds,
         }
     }
 
-    pub fn draw(&self, model_matrix: &Matrix4, mvp_matrix: &Matrix4, camera_position: &Vector3) {
-        for primitive in &self.primitives {
-            unsafe { primitive.draw(model_matrix, mvp_matrix, camera_position) }
+    pub fn morph_target_count(&self) -> usize {
+        self.weights.borrow().len()
+    }
+
+    /// The effective morph target weights (overrides applied)
+    pub fn morph_weights(&self) -> Vec<f32> {
+        self.weights.borrow().iter()
+            .zip(self.weight_overrides.borrow().iter())
+            .map(|(weight, weight_override)| weight_override.unwrap_or(*weight))
+            .collect()
+    }
+
+    pub fn draw(&self, model_matrix: &Matrix4, mvp_matrix: &Matrix4, camera_position: &Vector3,
+        instances: Option<&Instances>, stats: &mut RenderStats)
+    {
+        let morph_weights = self.morph_weights();
+        // the blended ones are drawn sorted afterwards (see `Scene::draw`)
+        let primitives = self.primitives.iter()
+            .filter(|primitive| primitive.material().alpha_mode != gltf::material::AlphaMode::Blend);
+        for primitive in primitives {
+            unsafe { primitive.draw_culled(model_matrix, mvp_matrix, camera_position, &morph_weights, instances, stats) }
         }
     }
 }
@@ -1,6 +1,8 @@
 pub mod math;
 // pub use self::math::*;
 
+mod accessor;
+
 mod root;
 pub use self::root::*;
 mod scene;
@@ -11,11 +13,40 @@ mod mesh;
 pub use self::mesh::*;
 mod primitive;
 pub use self::primitive::*;
+mod instancing;
+pub use self::instancing::*;
 
 mod material;
 pub use self::material::*;
 mod texture;
 pub use self::texture::*;
+mod ktx2;
+pub use self::ktx2::*;
 
 mod camera;
 pub use self::camera::*;
+mod light;
+pub use self::light::*;
+
+mod animation;
+pub use self::animation::*;
+mod skin;
+pub use self::skin::*;
+
+mod lines;
+pub use self::lines::*;
+mod background;
+pub use self::background::*;
+mod ssao;
+pub use self::ssao::*;
+mod shadow;
+pub use self::shadow::*;
+mod environment;
+pub use self::environment::*;
+mod overdraw;
+pub use self::overdraw::*;
+mod thumbnail;
+pub use self::thumbnail::*;
+pub mod text;
+mod stats;
+pub use self::stats::*;
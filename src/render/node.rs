@@ -3,11 +3,13 @@ use std::path::Path;
 
 use gltf;
 
-use collision::{Aabb, Union};
+use collision::Aabb;
 
 use crate::controls::CameraParams;
 use crate::render::math::*;
 use crate::render::mesh::Mesh;
+use crate::render::instancing::Instances;
+use crate::render::skin::JointMatrices;
 use crate::render::Root;
 use crate::render::camera::Camera;
 use crate::importdata::ImportData;
@@ -23,6 +25,13 @@ pub struct Node {
     // weights_id: usize,
     pub camera: Option<Camera>,
     pub name: Option<String>,
+    /// `MSFT_lod`: node indices of the lower levels of detail (replacing this node, see `Root::lod_node`)
+    pub lods: Vec<usize>,
+    /// `EXT_mesh_gpu_instancing`: the mesh is drawn once per instance
+    pub instances: Option<Instances>,
+    /// glTF index of the skin of the mesh (see `Root::skins`) and its current joint matrices
+    pub skin: Option<usize>,
+    pub joint_matrices: Option<JointMatrices>,
 
     pub final_transform: Matrix4, // including parent transforms
     pub bounds: Aabb3,
@@ -54,6 +63,10 @@ impl Node {
         let children: Vec<_> = g_node.children()
                 .map(|g_node| g_node.index())
                 .collect();
+        let lods: Vec<usize> = imp.extension("nodes", g_node.index(), "MSFT_lod")
+            .and_then(|lod| lod["ids"].as_array())
+            .map(|ids| ids.iter().filter_map(|id| id.as_u64()).map(|id| id as usize).collect())
+            .unwrap_or_default();
 
         Node {
             index: g_node.index(),
@@ -64,6 +77,10 @@ impl Node {
             translation: trans.into(),
             camera: g_node.camera().as_ref().map(Camera::from_gltf),
             name: g_node.name().map(|s| s.into()),
+            lods,
+            instances: Instances::from_gltf(g_node, imp, &imp.options.instance_color_attribute),
+            skin: g_node.skin().map(|skin| skin.index()),
+            joint_matrices: g_node.skin().map(|_| JointMatrices::new()),
 
             final_transform: Matrix4::identity(),
 
@@ -74,41 +91,48 @@ impl Node {
     pub fn update_transform(&mut self, root: &mut Root, parent_transform: &Matrix4) {
         self.final_transform = *parent_transform;
 
-        // TODO: cache local tranform when adding animations?
-        self.final_transform = self.final_transform *
-            Matrix4::from_translation(self.translation) *
-            Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z) *
-            Matrix4::from(self.rotation);
+        // NOTE: recomposed every time the (possibly animated) TRS properties change
+        self.final_transform = self.final_transform * trs_matrix(self.translation, self.rotation, self.scale);
 
         for node_id in &self.children {
-            let node = root.unsafe_get_node_mut(*node_id);
+            let node = root.unsafe_get_node_mut(root.lod_node(*node_id));
             node.update_transform(root, &self.final_transform);
         }
     }
 
     /// Should be called after update_transforms
     pub fn update_bounds(&mut self, root: &mut Root) {
-        self.bounds = Aabb3::zero();
+        self.bounds = empty_aabb();
         if let Some(ref mesh) = self.mesh {
-            self.bounds = mesh.bounds
-                .transform(&self.final_transform);
+            if !is_empty_aabb(&mesh.bounds) {
+                self.bounds = match self.instances {
+                    Some(ref instances) => instances.transforms.iter()
+                        .map(|transform| mesh.bounds.transform(&(self.final_transform * transform)))
+                        .fold(empty_aabb(), |bounds, instance_bounds| union_aabb(&bounds, &instance_bounds)),
+                    None => mesh.bounds.transform(&self.final_transform),
+                };
+            }
         }
 
         for node_id in &self.children {
-            let node = root.unsafe_get_node_mut(*node_id);
+            let node = root.unsafe_get_node_mut(root.lod_node(*node_id));
             node.update_bounds(root);
-            self.bounds = self.bounds.union(&node.bounds);
+            self.bounds = union_aabb(&self.bounds, &node.bounds);
         }
     }
 
     pub fn draw(&mut self, root: &mut Root, cam_params: &CameraParams) {
         if let Some(ref mesh) = self.mesh {
             let mvp_matrix = cam_params.projection_matrix * cam_params.view_matrix * self.final_transform;
+            if let Some(ref joint_matrices) = self.joint_matrices {
+                unsafe { joint_matrices.bind() }
+            }
 
-            (*mesh).draw(&self.final_transform, &mvp_matrix, &cam_params.position);
+            (*mesh).draw(&self.final_transform, &mvp_matrix, &cam_params.position, self.instances.as_ref(),
+                &mut root.stats);
         }
         for node_id in &self.children {
-            let node = root.unsafe_get_node_mut(*node_id);
+            let node = root.unsafe_get_node_mut(root.lod_node(*node_id));
             node.draw(root, cam_params);
         }
     }
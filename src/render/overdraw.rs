@@ -0,0 +1,117 @@
+use std::ptr;
+
+use gl;
+
+use crate::shader::Shader;
+
+/// Number of fragments per pixel shown as red (fewer: blue to yellow)
+pub const MAX_OVERDRAW: f32 = 8.0;
+
+/// Overdraw heatmap (F4): the scene geometry is drawn without depth test or face culling
+/// into a float target with additive blending (each fragment adds 1), which is then
+/// mapped to a color ramp.
+pub struct Overdraw {
+    width: u32,
+    height: u32,
+
+    fbo: u32,
+    texture: u32,
+
+    // empty, for the fullscreen triangle
+    vao: u32,
+    // of the scene, restored after clearing the target
+    clear_color: [f32; 4],
+
+    shader: Shader,
+    u_max_overdraw: i32,
+}
+
+impl Overdraw {
+    pub fn new(width: u32, height: u32) -> Self {
+        let mut shader = Shader::from_source(
+            include_str!("../shaders/fullscreen-vert.glsl"),
+            include_str!("../shaders/overdraw-frag.glsl"),
+            &[]);
+        unsafe {
+            let sampler = shader.uniform_location("u_OverdrawSampler");
+            shader.use_program();
+            shader.set_int(sampler, 0);
+
+            let mut overdraw = Overdraw {
+                width, height,
+                fbo: 0, texture: 0,
+                vao: 0,
+                clear_color: [0.0; 4],
+                u_max_overdraw: shader.uniform_location("u_MaxOverdraw"),
+                shader,
+            };
+            gl::GenVertexArrays(1, &mut overdraw.vao);
+            overdraw.create_target();
+            overdraw
+        }
+    }
+
+    /// Binds and clears the counting target (recreating it if the size changed) and sets up
+    /// the additive blending. Draw the scene unlit with a color of 1 after this, then call `resolve`.
+    pub unsafe fn begin(&mut self, width: u32, height: u32) {
+        if (width, height) != (self.width, self.height) {
+            self.delete_target();
+            self.width = width;
+            self.height = height;
+            self.create_target();
+        }
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.fbo);
+        gl::GetFloatv(gl::COLOR_CLEAR_VALUE, self.clear_color.as_mut_ptr());
+        gl::ClearColor(0.0, 0.0, 0.0, 0.0);
+        gl::Clear(gl::COLOR_BUFFER_BIT);
+        gl::ClearColor(self.clear_color[0], self.clear_color[1], self.clear_color[2], self.clear_color[3]);
+        gl::Disable(gl::DEPTH_TEST);
+        gl::Disable(gl::CULL_FACE);
+        gl::Enable(gl::BLEND);
+        gl::BlendFunc(gl::ONE, gl::ONE);
+    }
+
+    /// Draws the heatmap into the currently bound framebuffer and restores the render state
+    pub unsafe fn resolve(&self) {
+        gl::Disable(gl::BLEND);
+        gl::BindVertexArray(self.vao);
+        self.shader.use_program();
+        self.shader.set_float(self.u_max_overdraw, MAX_OVERDRAW);
+        gl::ActiveTexture(gl::TEXTURE0);
+        gl::BindTexture(gl::TEXTURE_2D, self.texture);
+        gl::DrawArrays(gl::TRIANGLES, 0, 3);
+        gl::BindVertexArray(0);
+        gl::Enable(gl::DEPTH_TEST);
+    }
+
+    unsafe fn create_target(&mut self) {
+        gl::GenTextures(1, &mut self.texture);
+        gl::BindTexture(gl::TEXTURE_2D, self.texture);
+        gl::TexImage2D(gl::TEXTURE_2D, 0, gl::R16F as i32, self.width as i32, self.height as i32, 0,
+            gl::RED, gl::FLOAT, ptr::null());
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+
+        gl::GenFramebuffers(1, &mut self.fbo);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.fbo);
+        gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, self.texture, 0);
+        if gl::CheckFramebufferStatus(gl::FRAMEBUFFER) != gl::FRAMEBUFFER_COMPLETE {
+            panic!("ERROR::FRAMEBUFFER:: overdraw framebuffer is not complete!");
+        }
+        gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+    }
+
+    unsafe fn delete_target(&self) {
+        gl::DeleteFramebuffers(1, &self.fbo);
+        gl::DeleteTextures(1, &self.texture);
+    }
+}
+
+impl Drop for Overdraw {
+    fn drop(&mut self) {
+        unsafe {
+            self.delete_target();
+            gl::DeleteVertexArrays(1, &self.vao);
+        }
+    }
+}
@@ -1,4 +1,6 @@
-use std::mem::size_of;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::mem::{size_of, size_of_val};
 use std::os::raw::c_void;
 use std::path::Path;
 use std::ptr;
@@ -7,12 +9,17 @@ use std::rc::Rc;
 use gl;
 use gl::types::GLenum;
 use gltf;
+use gltf::accessor::{DataType, Dimensions};
+use gltf::mesh::Semantic;
 use log::{warn, debug};
 
 use crate::render::math::*;
-use crate::render::{Material, Root};
+use crate::render::{Instances, Material, RenderStats, Root, UvTransform, reset_instance_attributes,
+    set_instance_attributes};
+use crate::render::accessor;
 use crate::shader::*;
 use crate::importdata::ImportData;
+use crate::utils::Capabilities;
 
 #[derive(Debug)]
 pub struct Vertex {
@@ -41,6 +48,17 @@ impl Default for Vertex {
     }
 }
 
+/// Maximum number of morph targets per primitive (see `u_MorphWeights` in the vertex shader);
+/// any further targets are ignored.
+pub const MAX_MORPH_TARGETS: usize = 8;
+
+/// Texture buffer with the morph target displacements
+struct MorphTargets {
+    buffer: u32,
+    texture: u32,
+    count: usize,
+}
+
 #[derive(Clone, Debug)]
 pub struct Texture {
     pub id: u32,
@@ -58,11 +76,23 @@ pub struct Primitive {
     ebo: Option<u32>,
     num_indices: u32,
 
-    mode: GLenum,
+    /// vertex buffer + number of components of the attribute given with `--debug-attribute`
+    debug_attribute: Option<(u32, i32)>,
+
+    morph_targets: Option<MorphTargets>,
+
+    /// vertex buffer with the further color sets (COLOR_1, ...) one after another + their number
+    /// (see `select_color_set`)
+    color_sets: Option<(u32, usize)>,
 
-    material: Rc<Material>,
+    mode: GLenum,
 
-    pbr_shader: Rc<PbrShader>,
+    /// The material (+ shader) of the primitive, followed by the ones of `KHR_materials_variants`
+    materials: Vec<(Rc<Material>, Rc<PbrShader>)>,
+    /// `KHR_materials_variants`: variant index -> index into `materials`
+    variant_materials: HashMap<usize, usize>,
+    /// index into `materials` (see `set_variant`)
+    active_material: Cell<usize>,
 
     // TODO!: mode, targets
 }
@@ -82,9 +112,13 @@ impl Primitive {
             num_vertices: vertices.len() as u32,
             num_indices: num_indices as u32,
             vao: 0, vbo: 0, ebo: None,
+            debug_attribute: None,
+            morph_targets: None,
+            color_sets: None,
             mode,
-            material,
-            pbr_shader: shader,
+            materials: vec![(material, shader)],
+            variant_materials: HashMap::new(),
+            active_material: Cell::new(0),
         };
 
         // now that we have all the required data, set the vertex buffers and its attribute pointers.
@@ -98,24 +132,56 @@ impl Primitive {
         mesh_index: usize,
         root: &mut Root,
         imp: &ImportData,
-        base_path: &Path) -> Primitive
+        base_path: &Path) -> Option<Primitive>
     {
+        if let Some(reason) = empty_primitive_reason(g_primitive) {
+            warn!("Skipping primitive {} of mesh {}: {}", primitive_index, mesh_index, reason);
+            return None
+        }
+
         let buffers = &imp.buffers;
-        let reader = g_primitive.reader(|buffer| Some(&buffers[buffer.index()]));
-        let positions = {
-            let iter = reader
-                .read_positions()
-                .unwrap_or_else(||
-                    panic!("primitives must have the POSITION attribute (mesh: {}, primitive: {})",
-                        mesh_index, primitive_index)
-                );
-            iter.collect::<Vec<_>>()
+        let reader = g_primitive.reader(|buffer| Some(&buffers[buffer.index()][..]));
+        // KHR_mesh_quantization: integer attributes (which the `gltf` reader would read as f32)
+        // are dequantized here; any remaining scale/offset is part of the node transform.
+        // Sparse accessors are read here too (the base values with the sparse ones applied).
+        // Attributes exceeding their buffer are zeroed (`read_f32x4` warns about them).
+        let quantized = |semantic: Semantic| g_primitive.get(&semantic)
+            .filter(|a| a.data_type() != DataType::F32 || a.sparse().is_some())
+            .map(|a| accessor::read_f32x4(&a, buffers).unwrap_or_else(|| vec![[0.0; 4]; a.count()]));
+
+        let quantized_positions = quantized(Semantic::Positions);
+        let positions_quantized = quantized_positions.is_some();
+        let positions: Vec<[f32; 3]> = match quantized_positions {
+            Some(values) => values.iter().map(|v| [v[0], v[1], v[2]]).collect(),
+            None => {
+                let iter = reader
+                    .read_positions()
+                    .unwrap_or_else(||
+                        panic!("primitives must have the POSITION attribute (mesh: {}, primitive: {})",
+                            mesh_index, primitive_index)
+                    );
+                iter.collect::<Vec<_>>()
+            }
         };
 
-        let bounds = g_primitive.bounding_box();
-        let bounds = Aabb3 {
-            min: bounds.min.into(),
-            max: bounds.max.into()
+        let position_accessor = g_primitive.get(&Semantic::Positions).unwrap();
+        let has_min_max = position_accessor.min().is_some() && position_accessor.max().is_some();
+        let bounds = if positions_quantized || !has_min_max {
+            // accessor min/max are in the quantized (integer) domain (or missing)
+            let mut bounds = empty_aabb();
+            for p in &positions {
+                for (c, &value) in p.iter().enumerate() {
+                    bounds.min[c] = bounds.min[c].min(value);
+                    bounds.max[c] = bounds.max[c].max(value);
+                }
+            }
+            bounds
+        } else {
+            let bounds = g_primitive.bounding_box();
+            Aabb3 {
+                min: bounds.min.into(),
+                max: bounds.max.into()
+            }
         };
 
         let mut vertices: Vec<Vertex> = positions
@@ -130,32 +196,40 @@ impl Primitive {
         let mut shader_flags = ShaderFlags::empty();
 
         // normals
-        if let Some(normals) = reader.read_normals() {
-            for (i, normal) in normals.enumerate() {
-                vertices[i].normal = Vector3::from(normal);
+        let normals: Option<Vec<Vector3>> = match quantized(Semantic::Normals) {
+            Some(values) => Some(values.iter().map(|v| vec3(v[0], v[1], v[2]).normalize()).collect()),
+            None => reader.read_normals().map(|normals| normals.map(Vector3::from).collect()),
+        };
+        if let Some(normals) = normals {
+            for (i, normal) in normals.into_iter().enumerate() {
+                vertices[i].normal = normal;
             }
             shader_flags |= ShaderFlags::HAS_NORMALS;
         }
         else {
-            debug!("Found no NORMALs for primitive {} of mesh {} \
-                   (flat normal calculation not implemented yet)", primitive_index, mesh_index);
+            debug!("Found no NORMALs for primitive {} of mesh {}", primitive_index, mesh_index);
         }
 
         // tangents
-        if let Some(tangents) = reader.read_tangents() {
-            for (i, tangent) in tangents.enumerate() {
-                vertices[i].tangent = Vector4::from(tangent);
+        let tangents: Option<Vec<Vector4>> = match quantized(Semantic::Tangents) {
+            Some(values) => Some(values.iter()
+                .map(|v| vec3(v[0], v[1], v[2]).normalize().extend(v[3].signum()))
+                .collect()),
+            None => reader.read_tangents().map(|tangents| tangents.map(Vector4::from).collect()),
+        };
+        if let Some(tangents) = tangents {
+            for (i, tangent) in tangents.into_iter().enumerate() {
+                vertices[i].tangent = tangent;
             }
             shader_flags |= ShaderFlags::HAS_TANGENTS;
         }
         else {
-            debug!("Found no TANGENTS for primitive {} of mesh {} \
-                   (tangent calculation not implemented yet)", primitive_index, mesh_index);
+            debug!("Found no TANGENTS for primitive {} of mesh {}", primitive_index, mesh_index);
         }
 
         // texture coordinates
         let mut tex_coord_set = 0;
-        while let Some(tex_coords) = reader.read_tex_coords(tex_coord_set) {
+        while g_primitive.get(&Semantic::TexCoords(tex_coord_set)).is_some() {
             if tex_coord_set > 1 {
                 warn!("Ignoring texture coordinate set {}, \
                         only supporting 2 sets at the moment. (mesh: {}, primitive: {})",
@@ -163,34 +237,59 @@ impl Primitive {
                 tex_coord_set += 1;
                 continue;
             }
-            for (i, tex_coord) in tex_coords.into_f32().enumerate() {
+            // the `gltf` reader only handles unsigned normalized integers
+            let tex_coords: Vec<Vector2> = match quantized(Semantic::TexCoords(tex_coord_set)) {
+                Some(values) => values.iter().map(|v| vec2(v[0], v[1])).collect(),
+                None => reader.read_tex_coords(tex_coord_set).unwrap()
+                    .into_f32().map(Vector2::from).collect(),
+            };
+            for (i, tex_coord) in tex_coords.into_iter().enumerate() {
                 match tex_coord_set {
-                    0 => vertices[i].tex_coord_0 = Vector2::from(tex_coord),
-                    1 => vertices[i].tex_coord_1 = Vector2::from(tex_coord),
+                    0 => vertices[i].tex_coord_0 = tex_coord,
+                    1 => vertices[i].tex_coord_1 = tex_coord,
                     _ => unreachable!()
                 }
             }
             shader_flags |= ShaderFlags::HAS_UV;
             tex_coord_set += 1;
         }
+        if tex_coord_set == 1 {
+            // textures referencing the missing TEXCOORD_1 fall back to set 0 (instead of sampling a single texel)
+            for vertex in &mut vertices {
+                vertex.tex_coord_1 = vertex.tex_coord_0;
+            }
+        }
 
-        // colors
+        // colors: COLOR_0 is part of the vertex, further sets can be selected for inspection
+        let mut extra_colors: Vec<[f32; 4]> = Vec::new();
+        let mut num_extra_color_sets = 0;
         if let Some(colors) = reader.read_colors(0) {
             let colors = colors.into_rgba_f32();
             for (i, c) in colors.enumerate() {
                 vertices[i].color_0 = c.into();
             }
             shader_flags |= ShaderFlags::HAS_COLORS;
+
+            while let Some(colors) = reader.read_colors(num_extra_color_sets + 1) {
+                extra_colors.extend(colors.into_rgba_f32());
+                num_extra_color_sets += 1;
+            }
+            if num_extra_color_sets > 0 {
+                debug!("Found {} further color set(s) (mesh: {}, primitive: {})",
+                    num_extra_color_sets, mesh_index, primitive_index);
+            }
         }
-        if reader.read_colors(1).is_some() {
-            warn!("Ignoring further color attributes, only supporting COLOR_0. (mesh: {}, primitive: {})",
+        else if reader.read_colors(1).is_some() {
+            warn!("Ignoring color attributes without COLOR_0 (mesh: {}, primitive: {})",
                 mesh_index, primitive_index);
         }
 
+        let mut has_joints = false;
         if let Some(joints) = reader.read_joints(0) {
             for (i, joint) in joints.into_u16().enumerate() {
                 vertices[i].joints_0 = joint;
             }
+            has_joints = true;
         }
         if reader.read_joints(1).is_some() {
             warn!("Ignoring further joint attributes, only supporting JOINTS_0. (mesh: {}, primitive: {})",
@@ -201,17 +300,114 @@ impl Primitive {
             for (i, weights) in weights.into_f32().enumerate() {
                 vertices[i].weights_0 = weights.into();
             }
+            if has_joints {
+                shader_flags |= ShaderFlags::HAS_SKINNING;
+            }
         }
         if reader.read_weights(1).is_some() {
             warn!("Ignoring further weight attributes, only supporting WEIGHTS_0. (mesh: {}, primitive: {})",
                 mesh_index, primitive_index);
         }
 
-        let indices = reader
-            .read_indices()
-            .map(|read_indices| {
-                read_indices.into_u32().collect::<Vec<_>>()
-            });
+        // custom attribute for debug visualization, normalized to [0, 1] per component
+        let mut debug_values = None;
+        if let Some(ref name) = imp.options.debug_attribute {
+            let attribute = g_primitive.attributes()
+                .find(|(semantic, _)| semantic.to_string() == *name);
+            match attribute {
+                Some((_, accessor)) => match accessor::read_f32x4(&accessor, buffers) {
+                    Some(mut values) => {
+                        let components = accessor.dimensions().multiplicity().min(4);
+                        for c in 0..components {
+                            let min = values.iter().map(|v| v[c]).fold(f32::INFINITY, f32::min);
+                            let max = values.iter().map(|v| v[c]).fold(f32::NEG_INFINITY, f32::max);
+                            let range = if max > min { max - min } else { 1.0 };
+                            for v in &mut values {
+                                v[c] = (v[c] - min) / range;
+                            }
+                        }
+                        debug_values = Some((values, components as i32));
+                        shader_flags |= ShaderFlags::HAS_DEBUG_ATTRIBUTE;
+                    },
+                    None => warn!("Not visualizing attribute {}: it exceeds its buffer view (mesh: {}, primitive: {})",
+                        name, mesh_index, primitive_index),
+                },
+                None => debug!("Attribute {} not found (mesh: {}, primitive: {})", name, mesh_index, primitive_index),
+            }
+        }
+
+        // morph targets: (position, normal, tangent) displacements, target-major
+        let num_targets = g_primitive.morph_targets().count();
+        if num_targets > MAX_MORPH_TARGETS {
+            warn!("Ignoring morph targets {}..{}, only supporting {} (mesh: {}, primitive: {})",
+                MAX_MORPH_TARGETS, num_targets, MAX_MORPH_TARGETS, mesh_index, primitive_index);
+        }
+        let num_targets = num_targets.min(MAX_MORPH_TARGETS);
+        let mut morph_displacements = None;
+        let mut bounds = bounds;
+        if num_targets > 0 {
+            let num_vertices = vertices.len();
+            let mut displacements = vec![[0.0f32; 4]; num_targets * num_vertices * 3];
+            // assuming weights in [0, 1], the bounds grow by the sum of the displacement extents
+            let (mut grow_min, mut grow_max) = (Vector3::zero(), Vector3::zero());
+            // read with `accessor::read_f32x4`: targets are often sparse (and may be quantized)
+            let read = |a: Option<gltf::Accessor<'_>>| a
+                .and_then(|a| accessor::read_f32x4(&a, buffers))
+                .unwrap_or_default();
+            for (target, g_target) in g_primitive.morph_targets().take(num_targets).enumerate() {
+                let positions = read(g_target.positions());
+                let normals = read(g_target.normals());
+                let tangents = read(g_target.tangents());
+                let base = target * num_vertices * 3;
+                let (mut target_min, mut target_max) = (Vector3::zero(), Vector3::zero());
+                for (i, p) in positions.into_iter().take(num_vertices).enumerate() {
+                    displacements[base + i * 3] = [p[0], p[1], p[2], 0.0];
+                    for c in 0..3 {
+                        target_min[c] = target_min[c].min(p[c]);
+                        target_max[c] = target_max[c].max(p[c]);
+                    }
+                }
+                for (i, n) in normals.into_iter().take(num_vertices).enumerate() {
+                    displacements[base + i * 3 + 1] = [n[0], n[1], n[2], 0.0];
+                }
+                for (i, t) in tangents.into_iter().take(num_vertices).enumerate() {
+                    displacements[base + i * 3 + 2] = [t[0], t[1], t[2], 0.0];
+                }
+                grow_min += target_min;
+                grow_max += target_max;
+            }
+            bounds.min += grow_min;
+            bounds.max += grow_max;
+            morph_displacements = Some((displacements, num_targets));
+            shader_flags |= ShaderFlags::HAS_MORPH_TARGETS;
+        }
+
+        let mut indices = g_primitive.indices().map(|accessor| {
+            let valid_type = matches!(accessor.data_type(), DataType::U8 | DataType::U16 | DataType::U32);
+            let mut indices = if valid_type && accessor.dimensions() == Dimensions::Scalar {
+                reader.read_indices().unwrap().into_u32().collect::<Vec<_>>()
+            } else {
+                // the `gltf` reader would panic on these
+                warn!("Converting indices of type {:?} {:?} to u32 (mesh: {}, primitive: {})",
+                    accessor.data_type(), accessor.dimensions(), mesh_index, primitive_index);
+                let (indices, invalid) = accessor::read_indices(&accessor, buffers);
+                if invalid > 0 {
+                    warn!("Replaced {} negative, fractional or out-of-bounds indices with 0 (mesh: {}, primitive: {})",
+                        invalid, mesh_index, primitive_index);
+                }
+                indices
+            };
+            let num_vertices = vertices.len() as u32;
+            let out_of_range = indices.iter().filter(|&&i| i >= num_vertices).count();
+            if out_of_range > 0 {
+                warn!("Replaced {} out-of-range indices (>= {} vertices) with 0 (mesh: {}, primitive: {})",
+                    out_of_range, num_vertices, mesh_index, primitive_index);
+                for index in indices.iter_mut().filter(|i| **i >= num_vertices) {
+                    *index = 0;
+                }
+            }
+            indices
+        });
 
         // TODO: spec:
         // Implementation note: When the 'mode' property is set to a non-triangular type
@@ -222,55 +418,200 @@ impl Primitive {
         //   For POINTS or LINES with no NORMAL property, don't calculate lighting and instead output the COLOR value for each pixel drawn.
         let mode = g_primitive.mode().as_gl_enum();
 
-        let g_material = g_primitive.material();
-
-        let mut material = None;
-        if let Some(mat) = root.materials.iter().find(|m| (***m).index == g_material.index()) {
-            material = Rc::clone(mat).into()
+        if imp.options.auto_fix_winding && mode == gl::TRIANGLES && shader_flags.contains(ShaderFlags::HAS_NORMALS) {
+            let (disagreeing, sampled) = winding_disagreement(&vertices, indices.as_deref());
+            if disagreeing * 2 > sampled {
+                // reordering the indices keeps per-vertex data (morph targets etc.) valid
+                let mut flipped = indices.take().unwrap_or_else(|| (0..vertices.len() as u32).collect());
+                for triangle in flipped.chunks_mut(3).filter(|t| t.len() == 3) {
+                    triangle.swap(1, 2);
+                }
+                indices = Some(flipped);
+                warn!("Flipped the triangle winding of primitive {} of mesh {} \
+                    ({} of {} sampled triangles disagreed with the vertex normals)",
+                    primitive_index, mesh_index, disagreeing, sampled);
+            }
         }
 
-        if material.is_none() { // no else due to borrow checker madness
-            let mat = Rc::new(Material::from_gltf(&g_material, root, imp, base_path));
-            root.materials.push(Rc::clone(&mat));
-            material = Some(mat);
-        };
-        let material = material.unwrap();
-        shader_flags |= material.shader_flags();
+        // without NORMALs, the fragment shader uses flat normals (per the spec), unless smoothing is requested
+        if imp.options.smooth_normals && mode == gl::TRIANGLES && !shader_flags.contains(ShaderFlags::HAS_NORMALS) {
+            generate_smooth_normals(&mut vertices, indices.as_deref());
+            shader_flags |= ShaderFlags::HAS_NORMALS;
+            debug!("Generated smooth normals for primitive {} of mesh {}", primitive_index, mesh_index);
+        }
 
-        let mut new_shader = false; // borrow checker workaround
-        let shader =
-            if let Some(shader) = root.shaders.get(&shader_flags) {
-                Rc::clone(shader)
+        // normal maps need tangents: generate them from the UVs of the normal texture
+        // (the set of `KHR_texture_transform` takes precedence, see `UvTransform`)
+        let normal_tex_coord = g_primitive.material().normal_texture().map(|info| {
+            let json = g_primitive.material().index().map(|index| &imp.json["materials"][index]["normalTexture"]);
+            json.and_then(|json| UvTransform::from_json(json).tex_coord).unwrap_or(info.tex_coord())
+        });
+        if let Some(tex_coord_set) = normal_tex_coord {
+            let can_generate = mode == gl::TRIANGLES && tex_coord_set < 2
+                && shader_flags.contains(ShaderFlags::HAS_NORMALS)
+                && g_primitive.get(&Semantic::TexCoords(tex_coord_set)).is_some();
+            if !shader_flags.contains(ShaderFlags::HAS_TANGENTS) && can_generate {
+                generate_tangents(&mut vertices, indices.as_deref(), tex_coord_set);
+                shader_flags |= ShaderFlags::HAS_TANGENTS;
+                debug!("Generated tangents for primitive {} of mesh {}", primitive_index, mesh_index);
             }
-            else {
-                new_shader = true;
-                PbrShader::new(shader_flags).into()
+        }
 
+        let (material, shader) = material_and_shader(&g_primitive.material(), shader_flags, root, imp, base_path);
+        let mut prim = Primitive::new(bounds, &vertices, indices, mode, material, shader);
+
+        // KHR_materials_variants: {"mappings": [{"material": 1, "variants": [0, 2]}, ...]}
+        let mappings = &imp.json["meshes"][mesh_index]["primitives"][primitive_index]
+            ["extensions"]["KHR_materials_variants"]["mappings"];
+        for mapping in mappings.as_array().map(|m| m.as_slice()).unwrap_or_default() {
+            let g_material = match mapping["material"].as_u64().and_then(|m| imp.doc.materials().nth(m as usize)) {
+                Some(g_material) => g_material,
+                None => {
+                    warn!("Ignoring invalid material variant mapping of primitive {} of mesh {}",
+                        primitive_index, mesh_index);
+                    continue
+                }
             };
-        if new_shader {
-            root.shaders.insert(shader_flags, Rc::clone(&shader));
+            prim.materials.push(material_and_shader(&g_material, shader_flags, root, imp, base_path));
+            let material_index = prim.materials.len() - 1;
+            for variant in mapping["variants"].as_array().map(|v| v.as_slice()).unwrap_or_default() {
+                if let Some(variant) = variant.as_u64() {
+                    prim.variant_materials.insert(variant as usize, material_index);
+                }
+            }
         }
 
-        Primitive::new(bounds, &vertices, indices, mode, material, shader)
+        if let Some((values, components)) = debug_values {
+            unsafe { prim.setup_debug_attribute(&values, components) }
+        }
+        if let Some((displacements, count)) = morph_displacements {
+            unsafe { prim.setup_morph_targets(&displacements, count) }
+        }
+        if num_extra_color_sets > 0 {
+            unsafe { prim.setup_color_sets(&extra_colors, num_extra_color_sets as usize) }
+        }
+        Some(prim)
     }
 
-    /// render the mesh
-    pub unsafe fn draw(&self, model_matrix: &Matrix4, mvp_matrix: &Matrix4, camera_position: &Vector3) {
-        // TODO!: determine if shader+material already active to reduce work...
+    /// The material in use (see `set_variant`)
+    pub fn material(&self) -> &Rc<Material> {
+        &self.materials[self.active_material.get()].0
+    }
 
-        if self.material.double_sided {
-            gl::Disable(gl::CULL_FACE);
-        } else {
-            gl::Enable(gl::CULL_FACE);
+    fn shader(&self) -> &Rc<PbrShader> {
+        &self.materials[self.active_material.get()].1
+    }
+
+    /// Use the material of a `KHR_materials_variants` variant, or the default one if the variant
+    /// doesn't change this primitive or for `None`
+    pub fn set_variant(&self, variant: Option<usize>) {
+        let index = variant
+            .and_then(|variant| self.variant_materials.get(&variant))
+            .cloned()
+            .unwrap_or(0);
+        self.active_material.set(index);
+    }
+
+    /// Number of (supported) morph targets
+    pub fn morph_target_count(&self) -> usize {
+        self.morph_targets.as_ref().map(|targets| targets.count).unwrap_or(0)
+    }
+
+    /// Number of vertex color sets (COLOR_n)
+    pub fn color_set_count(&self) -> usize {
+        if !self.shader().flags.contains(ShaderFlags::HAS_COLORS) {
+            return 0
+        }
+        1 + self.color_sets.map(|(_, count)| count).unwrap_or(0)
+    }
+
+    /// Use COLOR_`set` instead of COLOR_0 (falls back to COLOR_0 if the primitive has no such set)
+    pub unsafe fn select_color_set(&self, set: usize) {
+        gl::BindVertexArray(self.vao);
+        match self.color_sets {
+            Some((vbo, count)) if set > 0 && set <= count => {
+                gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+                let offset = (set - 1) * self.num_vertices as usize * size_of::<[f32; 4]>();
+                gl::VertexAttribPointer(5, 4, gl::FLOAT, gl::FALSE, size_of::<[f32; 4]>() as i32,
+                    offset as *const c_void);
+            },
+            _ => {
+                gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+                gl::VertexAttribPointer(5, 4, gl::FLOAT, gl::FALSE, size_of::<Vertex>() as i32,
+                    offset_of!(Vertex, color_0) as *const c_void);
+            }
+        }
+        gl::BindVertexArray(0);
+    }
+
+    /// Draws the primitive unless it's outside of the view frustum and counts it in `stats`
+    /// (instanced and skinned primitives aren't culled: their bounds don't include the instances / poses)
+    pub unsafe fn draw_culled(&self, model_matrix: &Matrix4, mvp_matrix: &Matrix4,
+        camera_position: &Vector3, morph_weights: &[f32], instances: Option<&Instances>, stats: &mut RenderStats)
+    {
+        let skinned = self.shader().flags.contains(ShaderFlags::HAS_SKINNING);
+        if instances.is_none() && !skinned && aabb_outside_frustum(&self.bounds, mvp_matrix) {
+            stats.primitives_culled += 1;
+            return
         }
+        self.draw(model_matrix, mvp_matrix, camera_position, morph_weights, instances);
+        stats.draw_calls += 1;
+        stats.primitives_drawn += 1;
+        stats.triangles += self.triangle_count() * instances.map(|i| i.count() as u32).unwrap_or(1);
+    }
+
+    pub fn triangle_count(&self) -> u32 {
+        let count = if self.ebo.is_some() { self.num_indices } else { self.num_vertices };
+        match self.mode {
+            gl::TRIANGLES => count / 3,
+            gl::TRIANGLE_STRIP | gl::TRIANGLE_FAN => count.saturating_sub(2),
+            _ => 0,
+        }
+    }
+
+    /// render the mesh (`morph_weights`: see `Mesh::morph_weights`), once per instance if given
+    pub unsafe fn draw(&self, model_matrix: &Matrix4, mvp_matrix: &Matrix4, camera_position: &Vector3,
+        morph_weights: &[f32], instances: Option<&Instances>)
+    {
+        // TODO!: determine if shader+material already active to reduce work...
+
+        self.set_face_culling();
 
         if self.mode == gl::POINTS {
             gl::PointSize(10.0);
         }
 
-        self.configure_shader(model_matrix, mvp_matrix, camera_position);
+        self.configure_shader(model_matrix, mvp_matrix, camera_position, morph_weights);
+
+        match instances {
+            Some(instances) => self.draw_instanced(instances),
+            None => self.draw_geometry(),
+        }
+        gl::ActiveTexture(gl::TEXTURE0);
+
+        if self.material().alpha_mode != gltf::material::AlphaMode::Opaque {
+            let shader = &self.shader().shader;
+
+            gl::Disable(gl::BLEND);
+            shader.set_float(self.shader().uniforms.u_AlphaBlend, 0.0);
+            if self.material().alpha_mode == gltf::material::AlphaMode::Mask {
+                shader.set_float(self.shader().uniforms.u_AlphaCutoff, 0.0);
+            }
+        }
+    }
+
+    /// Back faces are culled unless the material is double-sided
+    pub unsafe fn set_face_culling(&self) {
+        if self.material().double_sided {
+            gl::Disable(gl::CULL_FACE);
+        } else {
+            gl::Enable(gl::CULL_FACE);
+        }
+    }
 
-        // draw mesh
+    /// Issues the draw call with the currently active shader
+    /// (may be another one with the position at location 0, e.g. for wireframes)
+    pub unsafe fn draw_geometry(&self) {
         gl::BindVertexArray(self.vao);
         if self.ebo.is_some() {
             gl::DrawElements(self.mode, self.num_indices as i32, gl::UNSIGNED_INT, ptr::null());
@@ -278,35 +619,61 @@ impl Primitive {
         else {
             gl::DrawArrays(self.mode, 0, self.num_vertices as i32)
         }
-
         gl::BindVertexArray(0);
-        gl::ActiveTexture(gl::TEXTURE0);
-
-        if self.material.alpha_mode != gltf::material::AlphaMode::Opaque {
-            let shader = &self.pbr_shader.shader;
+    }
 
-            gl::Disable(gl::BLEND);
-            shader.set_float(self.pbr_shader.uniforms.u_AlphaBlend, 0.0);
-            if self.material.alpha_mode == gltf::material::AlphaMode::Mask {
-                shader.set_float(self.pbr_shader.uniforms.u_AlphaCutoff, 0.0);
+    /// Like `draw_geometry`, but with a single draw call for all instances
+    unsafe fn draw_instanced(&self, instances: &Instances) {
+        if !Capabilities::get().instancing {
+            for (transform, color) in instances.transforms.iter().zip(&instances.colors) {
+                set_instance_attributes(transform, color);
+                self.draw_geometry();
             }
+            reset_instance_attributes();
+            return
         }
+        gl::BindVertexArray(self.vao);
+        instances.bind_attributes();
+        let count = instances.count() as i32;
+        if self.ebo.is_some() {
+            gl::DrawElementsInstanced(self.mode, self.num_indices as i32, gl::UNSIGNED_INT, ptr::null(), count);
+        }
+        else {
+            gl::DrawArraysInstanced(self.mode, 0, self.num_vertices as i32, count)
+        }
+        instances.unbind_attributes();
+        gl::BindVertexArray(0);
     }
 
     unsafe fn configure_shader(&self, model_matrix: &Matrix4,
-        mvp_matrix: &Matrix4, camera_position: &Vector3)
+        mvp_matrix: &Matrix4, camera_position: &Vector3, morph_weights: &[f32])
     {
-        // let pbr_shader = &Rc::get_mut(&mut self.pbr_shader).unwrap();
-        let mat = &self.material;
-        let shader = &self.pbr_shader.shader;
-        let uniforms = &self.pbr_shader.uniforms;
-        self.pbr_shader.shader.use_program();
+        let mat = self.material();
+        let shader = &self.shader().shader;
+        let uniforms = &self.shader().uniforms;
+        shader.use_program();
 
         // camera params
         shader.set_mat4(uniforms.u_ModelMatrix, model_matrix);
         shader.set_mat4(uniforms.u_MVPMatrix, mvp_matrix);
         shader.set_vector3(uniforms.u_Camera, camera_position);
 
+        if let Some((_, components)) = self.debug_attribute {
+            shader.set_int(uniforms.u_DebugAttributeComponents, components);
+        }
+
+        if let Some(ref targets) = self.morph_targets {
+            let mut weights = [0.0; MAX_MORPH_TARGETS];
+            for (weight, value) in weights.iter_mut().zip(morph_weights) {
+                *weight = *value;
+            }
+            gl::ActiveTexture(gl::TEXTURE5);
+            gl::BindTexture(gl::TEXTURE_BUFFER, targets.texture);
+            shader.set_int(uniforms.u_MorphTargetCount, targets.count as i32);
+            shader.set_int(uniforms.u_MorphVertexCount, self.num_vertices as i32);
+            shader.set_float_array(uniforms.u_MorphWeights, &weights);
+        }
+
         // alpha blending
         if mat.alpha_mode != gltf::material::AlphaMode::Opaque {
             // BLEND + MASK
@@ -324,33 +691,44 @@ impl Primitive {
         if let Some(ref base_color_texture) = mat.base_color_texture {
             gl::ActiveTexture(gl::TEXTURE0);
             gl::BindTexture(gl::TEXTURE_2D, base_color_texture.id);
-            shader.set_int(uniforms.u_BaseColorTexCoord, base_color_texture.tex_coord as i32);
+            let uv_transform = &mat.base_color_uv_transform;
+            shader.set_int(uniforms.u_BaseColorTexCoord, uv_transform.tex_coord.unwrap_or(base_color_texture.tex_coord) as i32);
+            shader.set_mat3(uniforms.u_BaseColorUVTransform, &uv_transform.matrix);
         }
         if let Some(ref normal_texture) = mat.normal_texture {
             gl::ActiveTexture(gl::TEXTURE1);
             gl::BindTexture(gl::TEXTURE_2D, normal_texture.id);
-            shader.set_int(uniforms.u_NormalTexCoord, normal_texture.tex_coord as i32);
+            let uv_transform = &mat.normal_uv_transform;
+            shader.set_int(uniforms.u_NormalTexCoord, uv_transform.tex_coord.unwrap_or(normal_texture.tex_coord) as i32);
+            shader.set_mat3(uniforms.u_NormalUVTransform, &uv_transform.matrix);
             shader.set_float(uniforms.u_NormalScale, mat.normal_scale.unwrap_or(1.0));
         }
         if let Some(ref emissive_texture) = mat.emissive_texture {
             gl::ActiveTexture(gl::TEXTURE2);
             gl::BindTexture(gl::TEXTURE_2D, emissive_texture.id);
-            shader.set_int(uniforms.u_EmissiveTexCoord, emissive_texture.tex_coord as i32);
-            shader.set_vector3(uniforms.u_EmissiveFactor, &mat.emissive_factor);
+            let uv_transform = &mat.emissive_uv_transform;
+            shader.set_int(uniforms.u_EmissiveTexCoord, uv_transform.tex_coord.unwrap_or(emissive_texture.tex_coord) as i32);
+            shader.set_mat3(uniforms.u_EmissiveUVTransform, &uv_transform.matrix);
         }
+        shader.set_vector3(uniforms.u_EmissiveFactor, &mat.emissive_factor);
 
         if let Some(ref mr_texture) = mat.metallic_roughness_texture {
             gl::ActiveTexture(gl::TEXTURE3);
             gl::BindTexture(gl::TEXTURE_2D, mr_texture.id);
-            shader.set_int(uniforms.u_MetallicRoughnessTexCoord, mr_texture.tex_coord as i32);
+            let uv_transform = &mat.metallic_roughness_uv_transform;
+            shader.set_int(uniforms.u_MetallicRoughnessTexCoord, uv_transform.tex_coord.unwrap_or(mr_texture.tex_coord) as i32);
+            shader.set_mat3(uniforms.u_MetallicRoughnessUVTransform, &uv_transform.matrix);
         }
         shader.set_vec2(uniforms.u_MetallicRoughnessValues,
-            mat.metallic_factor, mat.roughness_factor);
+            mat.metallic_factor * mat.metallic_scale.get(),
+            mat.roughness_factor * mat.roughness_scale.get());
 
         if let Some(ref occlusion_texture) = mat.occlusion_texture {
             gl::ActiveTexture(gl::TEXTURE4);
             gl::BindTexture(gl::TEXTURE_2D, occlusion_texture.id);
-            shader.set_int(uniforms.u_OcclusionTexCoord, occlusion_texture.tex_coord as i32);
+            let uv_transform = &mat.occlusion_uv_transform;
+            shader.set_int(uniforms.u_OcclusionTexCoord, uv_transform.tex_coord.unwrap_or(occlusion_texture.tex_coord) as i32);
+            shader.set_mat3(uniforms.u_OcclusionUVTransform, &uv_transform.matrix);
             shader.set_float(uniforms.u_OcclusionStrength, mat.occlusion_strength);
         }
     }
@@ -410,4 +788,272 @@ impl Primitive {
 
         gl::BindVertexArray(0);
     }
+
+    /// Adds a separate vertex buffer for the `--debug-attribute` (location 8)
+    unsafe fn setup_debug_attribute(&mut self, values: &[[f32; 4]], components: i32) {
+        let mut vbo = 0;
+        gl::GenBuffers(1, &mut vbo);
+        gl::BindVertexArray(self.vao);
+        gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+        let size = size_of_val(values) as isize;
+        gl::BufferData(gl::ARRAY_BUFFER, size, values.as_ptr() as *const c_void, gl::STATIC_DRAW);
+        gl::EnableVertexAttribArray(8);
+        gl::VertexAttribPointer(8, 4, gl::FLOAT, gl::FALSE, size_of::<[f32; 4]>() as i32, ptr::null());
+        gl::BindVertexArray(0);
+
+        self.debug_attribute = Some((vbo, components));
+    }
+
+    /// Uploads the further color sets (COLOR_1, ...) to a separate vertex buffer
+    unsafe fn setup_color_sets(&mut self, colors: &[[f32; 4]], count: usize) {
+        let mut vbo = 0;
+        gl::GenBuffers(1, &mut vbo);
+        gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+        let size = size_of_val(colors) as isize;
+        gl::BufferData(gl::ARRAY_BUFFER, size, colors.as_ptr() as *const c_void, gl::STATIC_DRAW);
+        gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+
+        self.color_sets = Some((vbo, count));
+    }
+
+    /// Uploads the morph target displacements to a texture buffer (read with `texelFetch` in the vertex shader)
+    unsafe fn setup_morph_targets(&mut self, displacements: &[[f32; 4]], count: usize) {
+        let (mut buffer, mut texture) = (0, 0);
+        gl::GenBuffers(1, &mut buffer);
+        gl::BindBuffer(gl::TEXTURE_BUFFER, buffer);
+        let size = size_of_val(displacements) as isize;
+        gl::BufferData(gl::TEXTURE_BUFFER, size, displacements.as_ptr() as *const c_void, gl::STATIC_DRAW);
+        gl::GenTextures(1, &mut texture);
+        gl::BindTexture(gl::TEXTURE_BUFFER, texture);
+        gl::TexBuffer(gl::TEXTURE_BUFFER, gl::RGBA32F, buffer);
+        gl::BindTexture(gl::TEXTURE_BUFFER, 0);
+        gl::BindBuffer(gl::TEXTURE_BUFFER, 0);
+
+        self.morph_targets = Some(MorphTargets { buffer, texture, count });
+    }
+}
+
+/// The (shared) material and its shader program for a primitive with the given vertex attribute flags
+fn material_and_shader(
+    g_material: &gltf::Material<'_>,
+    mut shader_flags: ShaderFlags,
+    root: &mut Root,
+    imp: &ImportData,
+    base_path: &Path) -> (Rc<Material>, Rc<PbrShader>)
+{
+    let mut material = None;
+    if let Some(mat) = root.materials.iter().find(|m| (***m).index == g_material.index()) {
+        material = Rc::clone(mat).into()
+    }
+
+    if material.is_none() { // no else due to borrow checker madness
+        let mat = Rc::new(Material::from_gltf(g_material, root, imp, base_path));
+        root.materials.push(Rc::clone(&mat));
+        material = Some(mat);
+    };
+    let material = material.unwrap();
+    shader_flags |= material.shader_flags();
+    if imp.options.force_two_sided_normals {
+        shader_flags |= ShaderFlags::FORCE_TWO_SIDED_NORMALS;
+    }
+    if imp.options.ssao {
+        shader_flags |= ShaderFlags::USE_SSAO;
+    }
+    if imp.options.shadows.is_some() {
+        shader_flags |= ShaderFlags::USE_SHADOWS;
+    }
+    if imp.options.environment.is_some() {
+        shader_flags |= ShaderFlags::USE_IBL | ShaderFlags::USE_TEX_LOD;
+    }
+
+    // one program per permutation of defines, shared by all primitives/materials with the same flags
+    let shader = Rc::clone(root.shaders.entry(shader_flags)
+        .or_insert_with(|| Rc::new(PbrShader::new(shader_flags))));
+    (material, shader)
+}
+
+/// Maximum number of triangles compared by `winding_disagreement`
+const WINDING_SAMPLES: usize = 256;
+
+/// Compares the geometric normals of (a subset of) the triangles with their vertex normals.
+/// Returns how many of the sampled triangles face the opposite way and how many were sampled
+/// (degenerate triangles are skipped).
+fn winding_disagreement(vertices: &[Vertex], indices: Option<&[u32]>) -> (usize, usize) {
+    let num_triangles = indices.map(|i| i.len()).unwrap_or_else(|| vertices.len()) / 3;
+    let step = (num_triangles / WINDING_SAMPLES).max(1);
+    let vertex = |i: usize| match indices {
+        Some(indices) => &vertices[indices[i] as usize],
+        None => &vertices[i],
+    };
+
+    let (mut disagreeing, mut sampled) = (0, 0);
+    for triangle in (0..num_triangles).step_by(step) {
+        let (a, b, c) = (vertex(triangle * 3), vertex(triangle * 3 + 1), vertex(triangle * 3 + 2));
+        let geometric_normal = (b.position - a.position).cross(c.position - a.position);
+        let vertex_normal = a.normal + b.normal + c.normal;
+        if geometric_normal.magnitude2() <= f32::EPSILON * f32::EPSILON || vertex_normal.magnitude2() == 0.0 {
+            continue
+        }
+        sampled += 1;
+        if geometric_normal.dot(vertex_normal) < 0.0 {
+            disagreeing += 1;
+        }
+    }
+    (disagreeing, sampled)
+}
+
+/// Per-vertex tangents from the positions and texture coordinates of the (indexed) triangles:
+/// the UV gradients of the adjacent triangles are accumulated, orthogonalized against the
+/// normal (Gram-Schmidt) and the handedness of the bitangent is stored in `w` (1 unless mirrored).
+/// Vertices without usable UVs get an arbitrary tangent perpendicular to the normal.
+fn generate_tangents(vertices: &mut [Vertex], indices: Option<&[u32]>, tex_coord_set: u32) {
+    let num_indices = indices.map(|i| i.len()).unwrap_or_else(|| vertices.len());
+    let index = |i: usize| match indices {
+        Some(indices) => indices[i] as usize,
+        None => i,
+    };
+    let uv = |v: &Vertex| if tex_coord_set == 0 { v.tex_coord_0 } else { v.tex_coord_1 };
+
+    let mut tangents = vec![Vector3::zero(); vertices.len()];
+    let mut bitangents = vec![Vector3::zero(); vertices.len()];
+    for triangle in 0..num_indices / 3 {
+        let (a, b, c) = (index(triangle * 3), index(triangle * 3 + 1), index(triangle * 3 + 2));
+        let (e1, e2) = (vertices[b].position - vertices[a].position, vertices[c].position - vertices[a].position);
+        let (d1, d2) = (uv(&vertices[b]) - uv(&vertices[a]), uv(&vertices[c]) - uv(&vertices[a]));
+        let det = d1.x * d2.y - d2.x * d1.y;
+        if det.abs() <= f32::EPSILON {
+            continue
+        }
+        let tangent = (e1 * d2.y - e2 * d1.y) / det;
+        // towards -V, i.e. up in the image: the +Y of glTF normal maps
+        let bitangent = (e1 * d2.x - e2 * d1.x) / det;
+        for &i in &[a, b, c] {
+            tangents[i] += tangent;
+            bitangents[i] += bitangent;
+        }
+    }
+
+    for (vertex, (tangent, bitangent)) in vertices.iter_mut().zip(tangents.into_iter().zip(bitangents)) {
+        let n = vertex.normal;
+        let mut t = tangent - n * n.dot(tangent);
+        if t.magnitude2() <= f32::EPSILON * f32::EPSILON {
+            // any perpendicular axis
+            let axis = if n.x.abs() < 0.9 { Vector3::unit_x() } else { Vector3::unit_y() };
+            t = axis - n * n.dot(axis);
+        }
+        let handedness = if n.cross(t).dot(bitangent) < 0.0 { -1.0 } else { 1.0 };
+        vertex.tangent = t.normalize().extend(handedness);
+    }
+}
+
+/// Area-weighted average of the normals of the adjacent triangles (counter-clockwise front faces).
+/// Only vertices shared by index are smoothed, i.e. seams of duplicated vertices stay visible.
+fn generate_smooth_normals(vertices: &mut [Vertex], indices: Option<&[u32]>) {
+    let num_indices = indices.map(|i| i.len()).unwrap_or_else(|| vertices.len());
+    let index = |i: usize| match indices {
+        Some(indices) => indices[i] as usize,
+        None => i,
+    };
+
+    let mut normals = vec![Vector3::zero(); vertices.len()];
+    for triangle in 0..num_indices / 3 {
+        let (a, b, c) = (index(triangle * 3), index(triangle * 3 + 1), index(triangle * 3 + 2));
+        // twice the area as length
+        let normal = (vertices[b].position - vertices[a].position).cross(vertices[c].position - vertices[a].position);
+        for &i in &[a, b, c] {
+            normals[i] += normal;
+        }
+    }
+
+    for (vertex, normal) in vertices.iter_mut().zip(normals) {
+        vertex.normal = if normal.magnitude2() > 0.0 { normal.normalize() } else { Vector3::unit_z() };
+    }
+}
+
+/// Why a primitive has nothing to draw (some exporters emit such primitives),
+/// or `None` if it has geometry
+pub fn empty_primitive_reason(g_primitive: &gltf::Primitive<'_>) -> Option<&'static str> {
+    match g_primitive.get(&Semantic::Positions) {
+        None => Some("no POSITION attribute"),
+        Some(ref positions) if positions.count() == 0 => Some("no vertices"),
+        Some(_) => match g_primitive.indices() {
+            Some(ref indices) if indices.count() == 0 => Some("no indices"),
+            _ => None,
+        },
+    }
+}
+
+impl Drop for Primitive {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteVertexArrays(1, &self.vao);
+            gl::DeleteBuffers(1, &self.vbo);
+            if let Some(ebo) = self.ebo {
+                gl::DeleteBuffers(1, &ebo);
+            }
+            if let Some((vbo, _)) = self.debug_attribute {
+                gl::DeleteBuffers(1, &vbo);
+            }
+            if let Some((vbo, _)) = self.color_sets {
+                gl::DeleteBuffers(1, &vbo);
+            }
+            if let Some(ref targets) = self.morph_targets {
+                gl::DeleteTextures(1, &targets.texture);
+                gl::DeleteBuffers(1, &targets.buffer);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_empty_primitives() {
+        let (document, _, _) = gltf::import("tests/EmptyPrimitive.gltf").unwrap();
+        let reasons: Vec<_> = document.meshes().next().unwrap().primitives()
+            .map(|primitive| empty_primitive_reason(&primitive))
+            .collect();
+        assert_eq!(reasons, vec![None, Some("no vertices"), Some("no indices")]);
+    }
+
+    #[test]
+    fn generated_tangents_follow_the_uvs() {
+        // quad in the XY plane facing +Z, with U along +X and V along -Y (glTF UVs start at the top)
+        let corners = [(0.0, 0.0, 0.0, 1.0), (1.0, 0.0, 1.0, 1.0), (1.0, 1.0, 1.0, 0.0), (0.0, 1.0, 0.0, 0.0)];
+        let mut vertices: Vec<Vertex> = corners.iter()
+            .map(|&(x, y, u, v)| Vertex {
+                position: vec3(x, y, 0.0),
+                normal: vec3(0.0, 0.0, 1.0),
+                tex_coord_0: vec2(u, v),
+                ..Vertex::default()
+            })
+            .collect();
+        generate_tangents(&mut vertices, Some(&[0, 1, 2, 0, 2, 3]), 0);
+        for vertex in &vertices {
+            assert!((vertex.tangent - vec4(1.0, 0.0, 0.0, 1.0)).magnitude() < 1e-5, "{:?}", vertex.tangent);
+        }
+
+        // mirrored U flips the tangent and the handedness
+        for vertex in &mut vertices {
+            vertex.tex_coord_0.x = 1.0 - vertex.tex_coord_0.x;
+        }
+        generate_tangents(&mut vertices, Some(&[0, 1, 2, 0, 2, 3]), 0);
+        assert!((vertices[0].tangent - vec4(-1.0, 0.0, 0.0, -1.0)).magnitude() < 1e-5, "{:?}", vertices[0].tangent);
+    }
+
+    #[test]
+    fn smooth_normals_average_shared_vertices() {
+        // two triangles folded by 90 degrees along the X axis: facing +Z and +Y
+        let positions = [vec3(0.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0), vec3(0.0, 1.0, 0.0), vec3(0.0, 0.0, 1.0)];
+        let mut vertices: Vec<Vertex> = positions.iter()
+            .map(|&position| Vertex { position, ..Vertex::default() })
+            .collect();
+        generate_smooth_normals(&mut vertices, Some(&[0, 1, 2, 1, 0, 3]));
+        let diagonal = vec3(0.0, 1.0, 1.0).normalize();
+        for (vertex, expected) in vertices.iter().zip(&[diagonal, diagonal, Vector3::unit_z(), Vector3::unit_y()]) {
+            assert!((vertex.normal - expected).magnitude() < 1e-5, "{:?}", vertex.normal);
+        }
+    }
 }
@@ -2,34 +2,95 @@ use std::rc::Rc;
 use std::collections::HashMap;
 use std::path::Path;
 
+use log::{debug, info, warn};
+
 use crate::shader::*;
-use crate::render::{Mesh, Node, Material};
-use crate::render::texture::Texture;
+use crate::render::{Light, LightKind, Mesh, Node, Material, RenderStats, Skin, DEFAULT_LIGHT_DIRECTION, MAX_LIGHTS};
+use crate::render::reset_instance_attributes;
+use crate::render::math::*;
+use crate::render::texture::{Texture, TextureFiltering};
 use crate::importdata::ImportData;
+use crate::viewer::ToneMap;
 
 #[derive(Default)]
 pub struct Root {
     pub nodes: Vec<Node>,
     pub meshes: Vec<Rc<Mesh>>, // TODO!: use gltf indices; drop Rc?
     pub textures: Vec<Rc<Texture>>,
+    /// anisotropy and mipmap bias of all textures
+    pub texture_filtering: TextureFiltering,
     pub materials: Vec<Rc<Material>>,
     pub shaders: HashMap<ShaderFlags, Rc<PbrShader>>,
 
     pub camera_nodes: Vec<usize>, // indices of camera nodes
-    // TODO!: joint_nodes, mesh_nodes?
+    /// names and root nodes of all scenes of the file, by glTF index
+    pub scene_names: Vec<Option<String>>,
+    pub scene_nodes: Vec<Vec<usize>>,
+    /// by glTF index
+    pub skins: Vec<Skin>,
+    /// `KHR_lights_punctual` lights (replacing the default light)
+    pub lights: Vec<Light>,
+
+    /// counters of the current frame
+    pub stats: RenderStats,
+
+    /// `MSFT_lod` level to draw (0: highest detail)
+    pub lod_level: usize,
+
+    /// Vertex color set (COLOR_n) in use (see `select_color_set`)
+    pub color_set: usize,
+
+    /// `KHR_materials_variants`: names of the variants and the active one (`None`: default materials)
+    pub variants: Vec<String>,
+    pub variant: Option<usize>,
+
+    /// `extensionsUsed` / `extensionsRequired` of the file
+    pub extensions_used: Vec<String>,
+    pub extensions_required: Vec<String>,
+    // TODO!: mesh_nodes?
 }
 
 impl Root {
     pub fn from_gltf(imp: &ImportData, base_path: &Path) -> Self {
         let mut root = Root::default();
+        // identity transform + no tint for everything not drawn with `EXT_mesh_gpu_instancing`
+        unsafe { reset_instance_attributes() }
+        root.texture_filtering = unsafe { TextureFiltering::from_options(&imp.options) };
         let nodes = imp.doc.nodes()
             .map(|g_node| Node::from_gltf(&g_node, &mut root, imp, base_path))
             .collect();
         root.nodes = nodes;
+        root.lod_level = imp.options.lod;
+        let strings = |key: &str| -> Vec<String> {
+            imp.json[key].as_array()
+                .map(|names| names.iter().filter_map(|name| name.as_str()).map(|name| name.to_owned()).collect())
+                .unwrap_or_default()
+        };
+        root.variants = imp.json["extensions"]["KHR_materials_variants"]["variants"].as_array()
+            .map(|variants| variants.iter()
+                .enumerate()
+                .map(|(i, variant)| variant["name"].as_str().map(|name| name.to_owned())
+                    .unwrap_or_else(|| format!("variant {}", i)))
+                .collect())
+            .unwrap_or_default();
+        root.extensions_used = strings("extensionsUsed");
+        root.extensions_required = strings("extensionsRequired");
         root.camera_nodes = root.nodes.iter()
             .filter(|node| node.camera.is_some())
             .map(|node| node.index)
             .collect();
+        root.scene_names = imp.doc.scenes().map(|g_scene| g_scene.name().map(|s| s.to_owned())).collect();
+        root.scene_nodes = imp.doc.scenes()
+            .map(|g_scene| g_scene.nodes().map(|g_node| g_node.index()).collect())
+            .collect();
+        root.skins = imp.doc.skins()
+            .map(|g_skin| Skin::from_gltf(&g_skin, imp))
+            .collect();
+        root.lights = Light::from_json(&imp.json);
+        if root.lights.len() > MAX_LIGHTS {
+            warn!("Ignoring {} of {} lights, only supporting {}",
+                root.lights.len() - MAX_LIGHTS, root.lights.len(), MAX_LIGHTS);
+        }
         root
     }
 
@@ -42,6 +103,162 @@ impl Root {
         }
     }
 
+    /// The node to use instead of `index` at the current `lod_level`
+    /// (the lowest available level if the node has fewer)
+    pub fn lod_node(&self, index: usize) -> usize {
+        let lods = &self.nodes[index].lods;
+        if self.lod_level == 0 || lods.is_empty() {
+            index
+        } else {
+            lods[self.lod_level.min(lods.len()) - 1]
+        }
+    }
+
+    /// Number of `MSFT_lod` levels below the highest one
+    pub fn max_lod_level(&self) -> usize {
+        self.nodes.iter().map(|node| node.lods.len()).max().unwrap_or(0)
+    }
+
+    /// Maximum number of vertex color sets of any primitive
+    pub fn max_color_set_count(&self) -> usize {
+        self.meshes.iter()
+            .flat_map(|mesh| mesh.primitives.iter())
+            .map(|primitive| primitive.color_set_count())
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Use COLOR_`set` for all primitives that have it (the others keep COLOR_0)
+    pub fn select_color_set(&mut self, set: usize) {
+        self.color_set = set;
+        for primitive in self.meshes.iter().flat_map(|mesh| mesh.primitives.iter()) {
+            unsafe { primitive.select_color_set(set) }
+        }
+    }
+
+    /// Switch all primitives to the materials of a `KHR_materials_variants` variant
+    /// (`None`: the default materials)
+    pub fn set_variant(&mut self, variant: Option<usize>) {
+        self.variant = variant;
+        for primitive in self.meshes.iter().flat_map(|mesh| mesh.primitives.iter()) {
+            primitive.set_variant(variant);
+        }
+    }
+
+    /// Sets the tone mapping operator, exposure and output encoding of all PBR shaders
+    /// (`srgb_output: false` for linear float framebuffers)
+    pub fn set_tone_mapping(&self, tone_map: ToneMap, exposure: f32, srgb_output: bool) {
+        for shader in self.shaders.values() {
+            unsafe {
+                shader.shader.use_program();
+                shader.shader.set_int(shader.uniforms.u_ToneMap, tone_map.shader_index());
+                shader.shader.set_float(shader.uniforms.u_Exposure, exposure);
+                shader.shader.set_bool(shader.uniforms.u_SrgbOutput, srgb_output);
+            }
+        }
+    }
+
+    /// Sets the lights of all PBR shaders from the current node transforms
+    /// (the shaders keep the default light if there are none)
+    pub fn set_light_uniforms(&self) {
+        if self.lights.is_empty() {
+            return
+        }
+        let lights = &self.lights[..self.lights.len().min(MAX_LIGHTS)];
+        let transforms: Vec<&Matrix4> = lights.iter().map(|light| &self.nodes[light.node].final_transform).collect();
+        let types: Vec<i32> = lights.iter().map(|light| light.kind.shader_index()).collect();
+        let directions: Vec<Vector3> = transforms.iter().map(|t| Light::direction_to_light(t)).collect();
+        let positions: Vec<Vector3> = transforms.iter().map(|t| Light::position(t)).collect();
+        let colors: Vec<Vector3> = lights.iter().map(|light| light.color).collect();
+        let ranges: Vec<f32> = lights.iter().map(|light| light.range.unwrap_or(0.0)).collect();
+        let (cone_scales, cone_offsets): (Vec<f32>, Vec<f32>) = lights.iter()
+            .map(|light| light.kind.cone_scale_offset())
+            .unzip();
+        for shader in self.shaders.values() {
+            let uniforms = &shader.uniforms;
+            unsafe {
+                shader.shader.use_program();
+                shader.shader.set_int(uniforms.u_LightCount, lights.len() as i32);
+                shader.shader.set_int_array(uniforms.u_LightType, &types);
+                shader.shader.set_vector3_array(uniforms.u_LightDirection, &directions);
+                shader.shader.set_vector3_array(uniforms.u_LightPosition, &positions);
+                shader.shader.set_vector3_array(uniforms.u_LightColor, &colors);
+                shader.shader.set_float_array(uniforms.u_LightRange, &ranges);
+                shader.shader.set_float_array(uniforms.u_LightConeScale, &cone_scales);
+                shader.shader.set_float_array(uniforms.u_LightConeOffset, &cone_offsets);
+            }
+        }
+    }
+
+    /// Uploads the joint matrices of all skinned nodes for the current node transforms
+    pub fn update_skins(&self) {
+        for node in &self.nodes {
+            if let (Some(skin), Some(joint_matrices)) = (node.skin, node.joint_matrices.as_ref()) {
+                let matrices = self.skins[skin].joint_matrices(&node.final_transform,
+                    |joint| &self.nodes[joint].final_transform);
+                unsafe { joint_matrices.upload(&matrices) }
+            }
+        }
+    }
+
+    /// Index and direction (towards the light) of the light casting the shadows:
+    /// the first directional light, or the default light
+    pub fn shadow_light(&self) -> (usize, Vector3) {
+        self.lights.iter().take(MAX_LIGHTS).enumerate()
+            .find(|(_, light)| light.kind == LightKind::Directional)
+            .map(|(i, light)| (i, Light::direction_to_light(&self.nodes[light.node].final_transform)))
+            .unwrap_or_else(|| (0, Vector3::from(DEFAULT_LIGHT_DIRECTION).normalize()))
+    }
+
+    /// Sets the view-projection matrix of the shadow map (`ShadowMap::light_view`) and the depth bias
+    /// of all PBR shaders
+    pub fn set_shadow_uniforms(&self, light_space_matrix: &Matrix4, bias: f32) {
+        let (light, _) = self.shadow_light();
+        for shader in self.shaders.values() {
+            unsafe {
+                shader.shader.use_program();
+                shader.shader.set_mat4(shader.uniforms.u_LightSpaceMatrix, light_space_matrix);
+                shader.shader.set_float(shader.uniforms.u_ShadowBias, bias);
+                shader.shader.set_int(shader.uniforms.u_ShadowLight, light as i32);
+            }
+        }
+    }
+
+    /// Log how many shader programs were compiled for how many materials (and their defines)
+    pub fn log_shader_permutations(&self) {
+        info!("Compiled {} shader program(s) for {} material(s)", self.shaders.len(), self.materials.len());
+        for flags in self.shaders.keys() {
+            debug!("  {}", flags.as_strings().join(" "));
+        }
+    }
+
+    /// Manually set the weight of a morph target of the mesh with the given glTF index,
+    /// overriding the default weights (and animation). `None` removes the override.
+    /// Returns false if the mesh or target doesn't exist.
+    pub fn set_morph_weight(&self, mesh: usize, target: usize, weight: Option<f32>) -> bool {
+        let mesh = match self.meshes.iter().find(|m| m.index == mesh) {
+            Some(mesh) => mesh,
+            None => return false,
+        };
+        let mut overrides = mesh.weight_overrides.borrow_mut();
+        match overrides.get_mut(target) {
+            Some(weight_override) => {
+                *weight_override = weight;
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Remove all morph weight overrides
+    pub fn clear_morph_weights(&self) {
+        for mesh in &self.meshes {
+            for weight_override in mesh.weight_overrides.borrow_mut().iter_mut() {
+                *weight_override = None;
+            }
+        }
+    }
+
     /// Note: index refers to the vec of camera node indices!
     pub fn get_camera_node(&self, index: usize) -> &Node {
         &self.nodes[self.camera_nodes[index]]
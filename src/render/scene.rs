@@ -1,56 +1,199 @@
+use std::cmp::Ordering;
+use std::rc::Rc;
+
 use gltf;
 
-use collision::{Aabb, Union};
+use collision::Aabb;
 
 use crate::controls::CameraParams;
-use crate::render::{Root};
+use crate::render::{LineRenderer, Material, Node, Primitive, RenderStats, Root};
 use crate::render::math::*;
 
+/// Primitives to draw, each with its node
+type DrawList<'a> = Vec<(&'a Node, &'a Primitive)>;
+
 pub struct Scene {
+    pub index: usize, // glTF index
     pub name: Option<String>,
     pub nodes: Vec<usize>,
     pub bounds: Aabb3,
+    /// Applied to all root nodes (e.g. to convert Z-up models to Y-up)
+    pub root_transform: Matrix4,
 }
 
 impl Default for Scene {
     fn default() -> Self {
         Self {
+            index: 0,
             name: None,
             nodes: vec![],
-            bounds: Aabb3::zero()
+            bounds: Aabb3::zero(),
+            root_transform: Matrix4::identity(),
         }
     }
 }
 
 impl Scene {
-    pub fn from_gltf(g_scene: &gltf::Scene<'_>, root: &mut Root) -> Scene {
+    /// The scene with the given glTF index (see `Root::scene_nodes`), e.g. to switch scenes without reloading
+    pub fn from_root(index: usize, root: &mut Root, root_transform: Matrix4) -> Scene {
         let mut scene = Scene {
-            name: g_scene.name().map(|s| s.to_owned()),
+            index,
+            name: root.scene_names[index].clone(),
+            nodes: root.scene_nodes[index].clone(),
+            root_transform,
             ..Default::default()
         };
-        scene.nodes = g_scene.nodes()
-            .map(|g_node| g_node.index())
-            .collect();
-
-        // propagate transforms
-        let root_transform = Matrix4::identity();
-        for node_id in &scene.nodes {
-            let node = root.unsafe_get_node_mut(*node_id);
-            node.update_transform(root, &root_transform);
+
+        scene.update_transforms(root);
+
+        scene
+    }
+
+    /// Propagate the node transforms (e.g. after animating them) and update the bounds and skins
+    pub fn update_transforms(&mut self, root: &mut Root) {
+        self.bounds = empty_aabb();
+        for node_id in &self.nodes {
+            let node = root.unsafe_get_node_mut(root.lod_node(*node_id));
+            node.update_transform(root, &self.root_transform);
             node.update_bounds(root);
-            scene.bounds = scene.bounds.union(&node.bounds);
+            self.bounds = union_aabb(&self.bounds, &node.bounds);
+        }
+        root.update_skins();
+        if is_empty_aabb(&self.bounds) {
+            // no geometry
+            self.bounds = Aabb3::zero();
         }
+    }
 
-        scene
+    /// Bounding box dimensions in meters (the glTF unit), including all node transforms
+    pub fn size(&self) -> Vector3 {
+        self.bounds.max - self.bounds.min
+    }
+
+    /// e.g. "0.90 x 1.10 x 0.90 m (diagonal 1.68 m)"
+    pub fn size_description(&self) -> String {
+        let size = self.size();
+        format!("{:.2} x {:.2} x {:.2} m (diagonal {:.2} m)", size.x, size.y, size.z, size.magnitude())
     }
 
     // TODO: flatten draw call hierarchy (global Vec<Primitive>?)
+    /// Draws the opaque and masked primitives in hierarchy order, then the blended ones
+    /// sorted back-to-front (`AlphaMode::Blend` needs everything behind it drawn first)
     pub fn draw(&mut self, root: &mut Root, cam_params: &CameraParams) {
-        // TODO!: for correct alpha blending, sort by material alpha mode and
-        // render opaque objects first.
         for node_id in &self.nodes {
-            let node = root.unsafe_get_node_mut(*node_id);
+            let node = root.unsafe_get_node_mut(root.lod_node(*node_id));
             node.draw(root, cam_params);
         }
+        let (_, blended) = self.sorted_primitives(root, cam_params, false);
+        let mut stats = root.stats;
+        Self::draw_primitives(&blended, cam_params, &mut stats);
+        root.stats = stats;
+    }
+
+    /// Alternative to `draw`: draws opaque primitives front-to-back (for early depth rejection)
+    /// and blended ones back-to-front afterwards. The sorting costs some CPU time each frame.
+    pub fn draw_sorted(&self, root: &mut Root, cam_params: &CameraParams) {
+        let (opaque, blended) = self.sorted_primitives(root, cam_params, true);
+        let primitives: Vec<_> = opaque.into_iter().chain(blended).collect();
+        let mut stats = root.stats;
+        Self::draw_primitives(&primitives, cam_params, &mut stats);
+        root.stats = stats;
+    }
+
+    /// (opaque and masked primitives front-to-back (only `with_opaque`), blended ones back-to-front),
+    /// each with its node. The distances are to the center of the primitive bounds.
+    fn sorted_primitives<'a>(&self, root: &'a Root, cam_params: &CameraParams, with_opaque: bool)
+        -> (DrawList<'a>, DrawList<'a>)
+    {
+        let camera_position = Point3::from_vec(cam_params.position);
+        let mut opaque: Vec<(f32, &Node, &Primitive)> = Vec::new();
+        let mut blended: Vec<(f32, &Node, &Primitive)> = Vec::new();
+        let mut stack = self.nodes.clone();
+        while let Some(node_id) = stack.pop() {
+            let node = &root.nodes[root.lod_node(node_id)];
+            if let Some(ref mesh) = node.mesh {
+                for primitive in &mesh.primitives {
+                    let center = node.final_transform.transform_point(primitive.bounds.center());
+                    let distance = center.distance2(camera_position);
+                    if primitive.material().alpha_mode == gltf::material::AlphaMode::Blend {
+                        blended.push((distance, node, primitive));
+                    } else if with_opaque {
+                        opaque.push((distance, node, primitive));
+                    }
+                }
+            }
+            stack.extend(&node.children);
+        }
+        opaque.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+        blended.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(Ordering::Equal));
+        let strip = |list: Vec<(f32, &'a Node, &'a Primitive)>| -> Vec<(&'a Node, &'a Primitive)> {
+            list.into_iter().map(|(_, node, primitive)| (node, primitive)).collect()
+        };
+        (strip(opaque), strip(blended))
+    }
+
+    fn draw_primitives(primitives: &[(&Node, &Primitive)], cam_params: &CameraParams, stats: &mut RenderStats) {
+        for (node, primitive) in primitives {
+            let mvp_matrix = cam_params.projection_matrix * cam_params.view_matrix * node.final_transform;
+            let morph_weights = node.mesh.as_ref().map(|mesh| mesh.morph_weights()).unwrap_or_default();
+            if let Some(ref joint_matrices) = node.joint_matrices {
+                unsafe { joint_matrices.bind() }
+            }
+            unsafe { primitive.draw_culled(&node.final_transform, &mvp_matrix, &cam_params.position,
+                &morph_weights, node.instances.as_ref(), stats) }
+        }
+    }
+
+    /// Draws all primitives unlit with a single color, e.g. in `gl::LINE` polygon mode
+    /// or only into a depth buffer (SSAO). With `cull_faces`, single-sided materials are culled
+    /// like in `draw`, otherwise the current culling state is kept (e.g. disabled for overdraw).
+    /// NOTE: morph targets and skins aren't applied and instanced meshes are only drawn once (at the node).
+    pub fn draw_unlit(&self, root: &Root, cam_params: &CameraParams, lines: &LineRenderer, color: &Vector4,
+        cull_faces: bool)
+    {
+        let view_projection = cam_params.projection_matrix * cam_params.view_matrix;
+        let mut stack = self.nodes.clone();
+        while let Some(node_id) = stack.pop() {
+            let node = &root.nodes[root.lod_node(node_id)];
+            if let Some(ref mesh) = node.mesh {
+                let mvp_matrix = view_projection * node.final_transform;
+                for primitive in &mesh.primitives {
+                    unsafe {
+                        lines.use_program(color, &mvp_matrix);
+                        if cull_faces {
+                            primitive.set_face_culling();
+                        }
+                        primitive.draw_geometry();
+                    }
+                }
+            }
+            stack.extend(&node.children);
+        }
+    }
+
+    /// Find the material of the nearest primitive hit by the ray.
+    /// NOTE: only tests against the (transformed) bounding boxes of the primitives.
+    pub fn pick(&self, root: &Root, origin: Point3, direction: Vector3) -> Option<Rc<Material>> {
+        let mut nearest: Option<(f32, Rc<Material>)> = None;
+        let mut stack = self.nodes.clone();
+        while let Some(node_id) = stack.pop() {
+            let node = &root.nodes[root.lod_node(node_id)];
+            if let Some(ref mesh) = node.mesh {
+                for primitive in &mesh.primitives {
+                    let bounds = primitive.bounds.transform(&node.final_transform);
+                    if let Some(t) = ray_aabb_intersection(origin, direction, &bounds) {
+                        let is_nearer = match nearest {
+                            Some((nearest_t, _)) => t < nearest_t,
+                            None => true,
+                        };
+                        if is_nearer {
+                            nearest = Some((t, Rc::clone(primitive.material())));
+                        }
+                    }
+                }
+            }
+            stack.extend(&node.children);
+        }
+        nearest.map(|(_, material)| material)
     }
 }
@@ -0,0 +1,130 @@
+use std::ptr;
+
+use cgmath;
+use collision::Aabb;
+use gl;
+
+use crate::controls::CameraParams;
+use crate::render::math::*;
+
+/// Shadow mapping (`--shadows on`) for the primary light (see `Root::shadow_light`): a depth-only
+/// pass of the scene from the light into a depth texture, bound to texture unit 10 for the PBR
+/// shader (`USE_SHADOWS`), which compares against it with PCF.
+pub struct ShadowMap {
+    size: u32,
+    fbo: u32,
+    depth_texture: u32,
+}
+
+impl ShadowMap {
+    /// `size`: resolution of the (square) depth texture
+    pub fn new(size: u32) -> Self {
+        let mut shadow_map = ShadowMap { size, fbo: 0, depth_texture: 0 };
+        unsafe {
+            gl::GenTextures(1, &mut shadow_map.depth_texture);
+            gl::BindTexture(gl::TEXTURE_2D, shadow_map.depth_texture);
+            gl::TexImage2D(gl::TEXTURE_2D, 0, gl::DEPTH_COMPONENT24 as i32, size as i32, size as i32, 0,
+                gl::DEPTH_COMPONENT, gl::FLOAT, ptr::null());
+            // hardware depth comparison, bilinearly filtered (on top of the PCF in the shader)
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_COMPARE_MODE, gl::COMPARE_REF_TO_TEXTURE as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_COMPARE_FUNC, gl::LEQUAL as i32);
+            // everything outside of the light's frustum is lit
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_BORDER as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_BORDER as i32);
+            let border = [1.0f32; 4];
+            gl::TexParameterfv(gl::TEXTURE_2D, gl::TEXTURE_BORDER_COLOR, border.as_ptr());
+
+            gl::GenFramebuffers(1, &mut shadow_map.fbo);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, shadow_map.fbo);
+            gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::DEPTH_ATTACHMENT, gl::TEXTURE_2D,
+                shadow_map.depth_texture, 0);
+            gl::DrawBuffer(gl::NONE);
+            gl::ReadBuffer(gl::NONE);
+            if gl::CheckFramebufferStatus(gl::FRAMEBUFFER) != gl::FRAMEBUFFER_COMPLETE {
+                panic!("ERROR::FRAMEBUFFER:: shadow map framebuffer is not complete!");
+            }
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+        shadow_map
+    }
+
+    /// View of the directional light (`direction_to_light`) with an orthographic frustum
+    /// fitted tightly around the (world space) bounding box of the scene
+    pub fn light_view(bounds: &Aabb3, direction_to_light: Vector3) -> CameraParams {
+        let bounds = if is_empty_aabb(bounds) {
+            Aabb3::new(Point3::new(-1.0, -1.0, -1.0), Point3::new(1.0, 1.0, 1.0))
+        } else {
+            *bounds
+        };
+        let center = bounds.center();
+        let direction = direction_to_light.normalize();
+        let up = if direction.y.abs() < 0.99 { Vector3::unit_y() } else { Vector3::unit_z() };
+        let eye = center + direction;
+        let view_matrix = Matrix4::look_at(eye, center, up);
+
+        let corners: Vec<Vector3> = bounds.to_corners().iter()
+            .map(|corner| (view_matrix * corner.to_homogeneous()).truncate())
+            .collect();
+        let min = corners.iter().fold(corners[0], |min, c| vec3(min.x.min(c.x), min.y.min(c.y), min.z.min(c.z)));
+        let max = corners.iter().fold(corners[0], |max, c| vec3(max.x.max(c.x), max.y.max(c.y), max.z.max(c.z)));
+        // looking along -Z: near/far are the negated view space z, slightly padded against clipping
+        let padding = (max.z - min.z).max(0.001) * 0.01;
+        let projection_matrix = cgmath::ortho(min.x, max.x, min.y, max.y, -max.z - padding, -min.z + padding);
+
+        CameraParams {
+            position: eye.to_vec(),
+            view_matrix,
+            projection_matrix,
+        }
+    }
+
+    /// Binds the depth-only framebuffer and sets the viewport to its size.
+    /// Draw the scene from `light_view` after this, then call `bind`.
+    pub unsafe fn begin(&self) {
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.fbo);
+        gl::Viewport(0, 0, self.size as i32, self.size as i32);
+        gl::Clear(gl::DEPTH_BUFFER_BIT);
+    }
+
+    /// Binds the depth texture for the PBR shader
+    pub unsafe fn bind(&self) {
+        // NOTE: for sampler numbers, see also PbrShader constructor
+        gl::ActiveTexture(gl::TEXTURE10);
+        gl::BindTexture(gl::TEXTURE_2D, self.depth_texture);
+        gl::ActiveTexture(gl::TEXTURE0);
+    }
+}
+
+impl Drop for ShadowMap {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteFramebuffers(1, &self.fbo);
+            gl::DeleteTextures(1, &self.depth_texture);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn light_view_fits_the_bounds() {
+        let bounds = Aabb3::new(Point3::new(-1.0, 0.0, -3.0), Point3::new(2.0, 5.0, 1.0));
+        let light = ShadowMap::light_view(&bounds, vec3(0.3, 1.0, 0.2));
+        let light_space = light.projection_matrix * light.view_matrix;
+        let corners: Vec<Vector4> = bounds.to_corners().iter()
+            .map(|corner| light_space * corner.to_homogeneous())
+            .collect();
+        for c in &corners {
+            assert!(c.x.abs() <= 1.0 + 1e-4 && c.y.abs() <= 1.0 + 1e-4 && c.z.abs() <= 1.0, "{:?}", c);
+        }
+        // tight: the box touches all sides of the frustum
+        for axis in 0..2 {
+            assert!(corners.iter().any(|c| c[axis] > 1.0 - 1e-4));
+            assert!(corners.iter().any(|c| c[axis] < -1.0 + 1e-4));
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use std::mem::size_of_val;
+use std::os::raw::c_void;
+
+use gl;
+use gltf;
+use log::warn;
+
+use crate::importdata::ImportData;
+use crate::render::math::*;
+
+/// Joints and inverse bind matrices of a glTF skin, shared by all nodes using it
+pub struct Skin {
+    /// glTF node indices
+    pub joints: Vec<usize>,
+    /// one per joint (identity if the skin has none)
+    pub inverse_bind_matrices: Vec<Matrix4>,
+}
+
+impl Skin {
+    pub fn from_gltf(g_skin: &gltf::Skin<'_>, imp: &ImportData) -> Skin {
+        let joints: Vec<usize> = g_skin.joints().map(|joint| joint.index()).collect();
+        let buffers = &imp.buffers;
+        let reader = g_skin.reader(|buffer| Some(&buffers[buffer.index()][..]));
+        let mut inverse_bind_matrices: Vec<Matrix4> = reader.read_inverse_bind_matrices()
+            .map(|matrices| matrices.map(Matrix4::from).collect())
+            .unwrap_or_default();
+        if !inverse_bind_matrices.is_empty() && inverse_bind_matrices.len() != joints.len() {
+            warn!("Skin {} has {} inverse bind matrices for {} joints",
+                g_skin.index(), inverse_bind_matrices.len(), joints.len());
+        }
+        inverse_bind_matrices.resize(joints.len(), Matrix4::identity());
+
+        Skin {
+            joints,
+            inverse_bind_matrices,
+        }
+    }
+
+    /// The matrix of each joint for the vertex shader, relative to the node of the mesh
+    /// (whose transform is applied afterwards as the model matrix).
+    /// `joint_transform`: the world transform of a node by glTF index
+    pub fn joint_matrices<'a>(&self, node_transform: &Matrix4, joint_transform: impl Fn(usize) -> &'a Matrix4)
+        -> Vec<Matrix4>
+    {
+        let inverse_node_transform = node_transform.invert().unwrap_or_else(Matrix4::identity);
+        self.joints.iter()
+            .zip(&self.inverse_bind_matrices)
+            .map(|(&joint, inverse_bind_matrix)| inverse_node_transform * joint_transform(joint) * inverse_bind_matrix)
+            .collect()
+    }
+}
+
+/// Texture buffer with the joint matrices of a skinned node (4 texels, the columns, per joint),
+/// updated with the node transforms (see `Root::update_skins`) and bound to texture unit 11
+/// before drawing the mesh (read with `texelFetch` in the vertex shader, `HAS_SKINNING`)
+pub struct JointMatrices {
+    buffer: u32,
+    texture: u32,
+}
+
+impl JointMatrices {
+    pub fn new() -> Self {
+        let mut joint_matrices = JointMatrices { buffer: 0, texture: 0 };
+        unsafe {
+            gl::GenBuffers(1, &mut joint_matrices.buffer);
+            gl::GenTextures(1, &mut joint_matrices.texture);
+            gl::BindTexture(gl::TEXTURE_BUFFER, joint_matrices.texture);
+            gl::TexBuffer(gl::TEXTURE_BUFFER, gl::RGBA32F, joint_matrices.buffer);
+            gl::BindTexture(gl::TEXTURE_BUFFER, 0);
+        }
+        joint_matrices
+    }
+
+    pub unsafe fn upload(&self, matrices: &[Matrix4]) {
+        gl::BindBuffer(gl::TEXTURE_BUFFER, self.buffer);
+        let size = size_of_val(matrices) as isize;
+        gl::BufferData(gl::TEXTURE_BUFFER, size, matrices.as_ptr() as *const c_void, gl::DYNAMIC_DRAW);
+        gl::BindBuffer(gl::TEXTURE_BUFFER, 0);
+    }
+
+    pub unsafe fn bind(&self) {
+        // NOTE: for sampler numbers, see also PbrShader constructor
+        gl::ActiveTexture(gl::TEXTURE11);
+        gl::BindTexture(gl::TEXTURE_BUFFER, self.texture);
+        gl::ActiveTexture(gl::TEXTURE0);
+    }
+}
+
+impl Drop for JointMatrices {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteTextures(1, &self.texture);
+            gl::DeleteBuffers(1, &self.buffer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bind_pose_is_identity() {
+        let node_transform = Matrix4::from_translation(vec3(0.0, 0.0, 5.0));
+        let joint_transforms = [
+            node_transform * Matrix4::from_translation(vec3(0.0, 1.0, 0.0)),
+            node_transform * Matrix4::from_translation(vec3(0.0, 2.0, 0.0)),
+        ];
+        let skin = Skin {
+            joints: vec![1, 0],
+            inverse_bind_matrices: vec![
+                Matrix4::from_translation(vec3(0.0, -2.0, 0.0)),
+                Matrix4::from_translation(vec3(0.0, -1.0, 0.0)),
+            ],
+        };
+        let matrices = skin.joint_matrices(&node_transform, |joint| &joint_transforms[joint]);
+        assert_eq!(matrices, vec![Matrix4::identity(); 2]);
+
+        // moving a joint moves its vertices, independent of the node transform
+        let moved = [joint_transforms[0], Matrix4::from_translation(vec3(1.0, 0.0, 0.0)) * joint_transforms[1]];
+        let matrices = skin.joint_matrices(&node_transform, |joint| &moved[joint]);
+        assert_eq!(matrices[0], Matrix4::from_translation(vec3(1.0, 0.0, 0.0)));
+        assert_eq!(matrices[1], Matrix4::identity());
+    }
+}
@@ -0,0 +1,234 @@
+use std::os::raw::c_void;
+use std::ptr;
+
+use gl;
+use gl::types::GLenum;
+
+use crate::render::math::*;
+use crate::shader::Shader;
+
+/// Number of hemisphere samples per pixel (see `KERNEL_SIZE` in the SSAO shader)
+const KERNEL_SIZE: usize = 16;
+
+/// Screen-space ambient occlusion (`--ssao`): a depth-only pass of the scene, the occlusion
+/// computed from it and blurred. The result is bound to texture unit 6 for the PBR shader
+/// (`USE_SSAO`), which multiplies it into the ambient term.
+pub struct Ssao {
+    width: u32,
+    height: u32,
+
+    depth_fbo: u32,
+    depth_texture: u32,
+    occlusion_fbo: u32,
+    occlusion_texture: u32,
+    blur_fbo: u32,
+    blur_texture: u32,
+    noise_texture: u32,
+
+    // empty, for the fullscreen triangles
+    vao: u32,
+
+    shader: Shader,
+    blur_shader: Shader,
+    kernel: Vec<Vector3>,
+
+    u_projection_matrix: i32,
+    u_inverse_projection_matrix: i32,
+    u_kernel: i32,
+    u_radius: i32,
+    u_intensity: i32,
+}
+
+impl Ssao {
+    pub fn new(width: u32, height: u32) -> Self {
+        let mut shader = Shader::from_source(
+            include_str!("../shaders/fullscreen-vert.glsl"),
+            include_str!("../shaders/ssao-frag.glsl"),
+            &[]);
+        let mut blur_shader = Shader::from_source(
+            include_str!("../shaders/fullscreen-vert.glsl"),
+            include_str!("../shaders/ssao-blur-frag.glsl"),
+            &[]);
+
+        let mut random = Random(0x2545_F491);
+        let kernel = (0..KERNEL_SIZE)
+            .map(|i| {
+                let sample = vec3(random.next_f32() * 2.0 - 1.0, random.next_f32() * 2.0 - 1.0, random.next_f32())
+                    .normalize() * random.next_f32();
+                // more samples close to the center
+                let scale = i as f32 / KERNEL_SIZE as f32;
+                sample * (0.1 + 0.9 * scale * scale)
+            })
+            .collect();
+        let noise: Vec<[f32; 3]> = (0..16)
+            .map(|_| [random.next_f32() * 2.0 - 1.0, random.next_f32() * 2.0 - 1.0, 0.0])
+            .collect();
+
+        unsafe {
+            let (depth_sampler, noise_sampler) =
+                (shader.uniform_location("u_DepthSampler"), shader.uniform_location("u_NoiseSampler"));
+            shader.use_program();
+            shader.set_int(depth_sampler, 0);
+            shader.set_int(noise_sampler, 1);
+            let occlusion_sampler = blur_shader.uniform_location("u_OcclusionSampler");
+            blur_shader.use_program();
+            blur_shader.set_int(occlusion_sampler, 0);
+
+            let mut ssao = Ssao {
+                width, height,
+                depth_fbo: 0, depth_texture: 0,
+                occlusion_fbo: 0, occlusion_texture: 0,
+                blur_fbo: 0, blur_texture: 0,
+                noise_texture: 0,
+                vao: 0,
+                u_projection_matrix: shader.uniform_location("u_ProjectionMatrix"),
+                u_inverse_projection_matrix: shader.uniform_location("u_InverseProjectionMatrix"),
+                u_kernel: shader.uniform_location("u_Kernel"),
+                u_radius: shader.uniform_location("u_Radius"),
+                u_intensity: shader.uniform_location("u_Intensity"),
+                shader,
+                blur_shader,
+                kernel,
+            };
+
+            gl::GenVertexArrays(1, &mut ssao.vao);
+
+            gl::GenTextures(1, &mut ssao.noise_texture);
+            gl::BindTexture(gl::TEXTURE_2D, ssao.noise_texture);
+            gl::TexImage2D(gl::TEXTURE_2D, 0, gl::RGB16F as i32, 4, 4, 0, gl::RGB, gl::FLOAT,
+                noise.as_ptr() as *const c_void);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as i32);
+
+            ssao.create_targets();
+            ssao
+        }
+    }
+
+    /// Binds the depth-only framebuffer (recreating the targets if the size changed).
+    /// Draw the scene after this, then call `apply`.
+    pub unsafe fn begin_depth_pass(&mut self, width: u32, height: u32) {
+        if (width, height) != (self.width, self.height) {
+            self.delete_targets();
+            self.width = width;
+            self.height = height;
+            self.create_targets();
+        }
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.depth_fbo);
+        gl::Clear(gl::DEPTH_BUFFER_BIT);
+    }
+
+    /// Computes and blurs the occlusion from the depth pass and binds it to texture unit 6.
+    /// `radius` is in world units. Leaves the blur framebuffer bound.
+    pub unsafe fn apply(&self, projection_matrix: &Matrix4, radius: f32, intensity: f32) {
+        gl::Disable(gl::DEPTH_TEST);
+        gl::BindVertexArray(self.vao);
+
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.occlusion_fbo);
+        self.shader.use_program();
+        self.shader.set_mat4(self.u_projection_matrix, projection_matrix);
+        let inverse_projection = projection_matrix.invert().unwrap_or_else(Matrix4::identity);
+        self.shader.set_mat4(self.u_inverse_projection_matrix, &inverse_projection);
+        self.shader.set_vector3_array(self.u_kernel, &self.kernel);
+        self.shader.set_float(self.u_radius, radius);
+        self.shader.set_float(self.u_intensity, intensity);
+        gl::ActiveTexture(gl::TEXTURE0);
+        gl::BindTexture(gl::TEXTURE_2D, self.depth_texture);
+        gl::ActiveTexture(gl::TEXTURE1);
+        gl::BindTexture(gl::TEXTURE_2D, self.noise_texture);
+        gl::DrawArrays(gl::TRIANGLES, 0, 3);
+
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.blur_fbo);
+        self.blur_shader.use_program();
+        gl::ActiveTexture(gl::TEXTURE0);
+        gl::BindTexture(gl::TEXTURE_2D, self.occlusion_texture);
+        gl::DrawArrays(gl::TRIANGLES, 0, 3);
+
+        gl::BindVertexArray(0);
+        gl::Enable(gl::DEPTH_TEST);
+
+        // NOTE: for sampler numbers, see also PbrShader constructor
+        gl::ActiveTexture(gl::TEXTURE6);
+        gl::BindTexture(gl::TEXTURE_2D, self.blur_texture);
+        gl::ActiveTexture(gl::TEXTURE0);
+    }
+
+    unsafe fn create_targets(&mut self) {
+        let (width, height) = (self.width as i32, self.height as i32);
+
+        self.depth_texture = create_texture(gl::DEPTH_COMPONENT24, gl::DEPTH_COMPONENT, width, height);
+        gl::GenFramebuffers(1, &mut self.depth_fbo);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.depth_fbo);
+        gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::DEPTH_ATTACHMENT, gl::TEXTURE_2D, self.depth_texture, 0);
+        gl::DrawBuffer(gl::NONE);
+        gl::ReadBuffer(gl::NONE);
+        check_framebuffer("SSAO depth");
+
+        self.occlusion_texture = create_texture(gl::R8, gl::RED, width, height);
+        gl::GenFramebuffers(1, &mut self.occlusion_fbo);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.occlusion_fbo);
+        gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, self.occlusion_texture, 0);
+        check_framebuffer("SSAO occlusion");
+
+        self.blur_texture = create_texture(gl::R8, gl::RED, width, height);
+        gl::GenFramebuffers(1, &mut self.blur_fbo);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.blur_fbo);
+        gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, self.blur_texture, 0);
+        check_framebuffer("SSAO blur");
+
+        gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+    }
+
+    unsafe fn delete_targets(&self) {
+        gl::DeleteFramebuffers(1, &self.depth_fbo);
+        gl::DeleteFramebuffers(1, &self.occlusion_fbo);
+        gl::DeleteFramebuffers(1, &self.blur_fbo);
+        gl::DeleteTextures(1, &self.depth_texture);
+        gl::DeleteTextures(1, &self.occlusion_texture);
+        gl::DeleteTextures(1, &self.blur_texture);
+    }
+}
+
+impl Drop for Ssao {
+    fn drop(&mut self) {
+        unsafe {
+            self.delete_targets();
+            gl::DeleteTextures(1, &self.noise_texture);
+            gl::DeleteVertexArrays(1, &self.vao);
+        }
+    }
+}
+
+unsafe fn create_texture(internal_format: GLenum, format: GLenum, width: i32, height: i32) -> u32 {
+    let mut texture = 0;
+    gl::GenTextures(1, &mut texture);
+    gl::BindTexture(gl::TEXTURE_2D, texture);
+    let type_ = if format == gl::DEPTH_COMPONENT { gl::FLOAT } else { gl::UNSIGNED_BYTE };
+    gl::TexImage2D(gl::TEXTURE_2D, 0, internal_format as i32, width, height, 0, format, type_, ptr::null());
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+    texture
+}
+
+unsafe fn check_framebuffer(name: &str) {
+    if gl::CheckFramebufferStatus(gl::FRAMEBUFFER) != gl::FRAMEBUFFER_COMPLETE {
+        panic!("ERROR::FRAMEBUFFER:: {} framebuffer is not complete!", name);
+    }
+}
+
+/// Minimal xorshift generator for the (fixed) sample kernel and noise
+struct Random(u32);
+
+impl Random {
+    /// in [0, 1)
+    fn next_f32(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        (self.0 >> 8) as f32 / (1 << 24) as f32
+    }
+}
@@ -0,0 +1,19 @@
+/// Per-frame rendering counters (see the stats HUD)
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RenderStats {
+    pub draw_calls: u32,
+    pub primitives_drawn: u32,
+    /// outside of the view frustum
+    pub primitives_culled: u32,
+    pub triangles: u32,
+}
+
+impl RenderStats {
+    /// Accumulate the counters of another scene drawn in the same frame
+    pub fn add(&mut self, other: &RenderStats) {
+        self.draw_calls += other.draw_calls;
+        self.primitives_drawn += other.primitives_drawn;
+        self.primitives_culled += other.primitives_culled;
+        self.triangles += other.triangles;
+    }
+}
@@ -4,6 +4,7 @@ use std::{fs, io};
 
 use base64;
 use gl;
+use gl::types::GLenum;
 use gltf;
 use gltf::json::texture::MinFilter;
 use gltf::image::Source;
@@ -13,8 +14,62 @@ use image::ImageFormat::{JPEG, PNG};
 use image::DynamicImage::*;
 use image::GenericImageView;
 use image::FilterType;
+use log::{debug, warn};
+use num_traits::clamp;
 
-use crate::importdata::ImportData;
+use crate::importdata::{BufferData, ImportData};
+use crate::render::Ktx2;
+use crate::utils::Capabilities;
+use crate::viewer::LoadOptions;
+
+// `GL_EXT_texture_filter_anisotropic` (core only since OpenGL 4.6)
+const TEXTURE_MAX_ANISOTROPY: GLenum = 0x84FE;
+const MAX_TEXTURE_MAX_ANISOTROPY: GLenum = 0x84FF;
+
+/// Sampler settings applied to all textures (`--anisotropy`, `--mip-bias`),
+/// clamped to what the driver supports
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TextureFiltering {
+    /// maximum anisotropy (`None`: no anisotropic filtering)
+    pub anisotropy: Option<f32>,
+    /// added to the mipmap level: negative is sharper (but aliases), positive softer
+    pub mip_bias: f32,
+}
+
+impl TextureFiltering {
+    pub unsafe fn from_options(options: &LoadOptions) -> Self {
+        let anisotropy = options.anisotropy.and_then(|anisotropy| {
+            if !Capabilities::get().anisotropic_filtering {
+                warn!("Ignoring --anisotropy: anisotropic filtering is not supported by the driver");
+                return None
+            }
+            let mut max_anisotropy = 1.0;
+            gl::GetFloatv(MAX_TEXTURE_MAX_ANISOTROPY, &mut max_anisotropy);
+            if !(1.0..=max_anisotropy).contains(&anisotropy) {
+                warn!("Clamping --anisotropy {} to the supported range [1, {}]", anisotropy, max_anisotropy);
+            }
+            Some(clamp(anisotropy, 1.0, max_anisotropy))
+        });
+
+        let mut max_bias = 0.0;
+        gl::GetFloatv(gl::MAX_TEXTURE_LOD_BIAS, &mut max_bias);
+        if options.mip_bias.abs() > max_bias {
+            warn!("Clamping --mip-bias {} to the supported range [{}, {}]", options.mip_bias, -max_bias, max_bias);
+        }
+        TextureFiltering {
+            anisotropy,
+            mip_bias: clamp(options.mip_bias, -max_bias, max_bias),
+        }
+    }
+
+    /// Set on the currently bound texture
+    unsafe fn apply(&self) {
+        if let Some(anisotropy) = self.anisotropy {
+            gl::TexParameterf(gl::TEXTURE_2D, TEXTURE_MAX_ANISOTROPY, anisotropy);
+        }
+        gl::TexParameterf(gl::TEXTURE_2D, gl::TEXTURE_LOD_BIAS, self.mip_bias);
+    }
+}
 
 pub struct Texture {
     pub index: usize, // glTF index
@@ -25,53 +80,55 @@ pub struct Texture {
 }
 
 impl Texture {
-    pub fn from_gltf(g_texture: &gltf::Texture<'_>, tex_coord: u32, imp: &ImportData, base_path: &Path) -> Texture {
+    pub fn from_gltf(g_texture: &gltf::Texture<'_>, tex_coord: u32, filtering: &TextureFiltering,
+        imp: &ImportData, base_path: &Path) -> Texture
+    {
         let buffers = &imp.buffers;
         let mut texture_id = 0;
         unsafe {
             gl::GenTextures(1, &mut texture_id);
             gl::BindTexture(gl::TEXTURE_2D, texture_id);
         }
-        let (needs_power_of_two, generate_mip_maps) =
-            unsafe { Self::set_sampler_params(&g_texture.sampler()) };
+        let (needs_power_of_two, generate_mip_maps) = unsafe {
+            filtering.apply();
+            Self::set_sampler_params(&g_texture.sampler())
+        };
+
+        // `KHR_texture_basisu`: the KTX2 image if it can be used, otherwise the fallback `source`
+        let ktx2_source = imp.extension("textures", g_texture.index(), "KHR_texture_basisu")
+            .and_then(|basisu| basisu["source"].as_u64())
+            .and_then(|index| imp.doc.images().nth(index as usize));
+        if let Some(g_img) = ktx2_source {
+            match unsafe { Self::load_ktx2(&g_img, generate_mip_maps, buffers, base_path) } {
+                Ok(()) => {
+                    debug!("Uploaded KTX2 image {} (texture: {})", g_img.index(), g_texture.index());
+                    return Texture {
+                        index: g_texture.index(),
+                        name: g_texture.name().map(|s| s.into()),
+                        id: texture_id,
+                        tex_coord,
+                    }
+                },
+                Err(err) => warn!("Using the fallback image of texture {}, KTX2 image {}: {}",
+                    g_texture.index(), g_img.index(), err),
+            }
+        }
 
         // TODO!: share images via Rc? detect if occurs?
         // TODO!!: better I/O abstraction...
         let g_img = g_texture.source();
         let img = match g_img.source() {
             Source::View { view, mime_type } => {
-                let parent_buffer_data = &buffers[view.buffer().index()].0;
+                let parent_buffer_data: &[u8] = &buffers[view.buffer().index()];
                 let begin = view.offset();
                 let end = begin + view.length();
-                let data = &parent_buffer_data[begin..end];
-                match mime_type {
-                    "image/jpeg" => image::load_from_memory_with_format(data, JPEG),
-                    "image/png" => image::load_from_memory_with_format(data, PNG),
-                    _ => panic!(format!("unsupported image type (image: {}, mime_type: {})",
-                        g_img.index(), mime_type)),
-                }
+                load_from_memory(&parent_buffer_data[begin..end], mime_type)
             },
             Source::Uri { uri, mime_type } => {
                 if uri.starts_with("data:") {
-                    let encoded = uri.split(',').nth(1).unwrap();
-                    let data = base64::decode(&encoded).unwrap();
-                    let mime_type = if let Some(ty) = mime_type {
-                        ty
-                    } else {
-                        uri.split(',')
-                            .nth(0).unwrap()
-                            .split(':')
-                            .nth(1).unwrap()
-                            .split(';')
-                            .nth(0).unwrap()
-                    };
-
-                    match mime_type {
-                        "image/jpeg" => image::load_from_memory_with_format(&data, JPEG),
-                        "image/png" => image::load_from_memory_with_format(&data, PNG),
-                        _ => panic!(format!("unsupported image type (image: {}, mime_type: {})",
-                            g_img.index(), mime_type)),
-                    }
+                    let (uri_mime_type, data) = decode_data_uri(uri)
+                        .unwrap_or_else(|err| panic!("invalid data URI (image: {}): {}", g_img.index(), err));
+                    load_from_memory(&data, mime_type.unwrap_or(uri_mime_type))
                 }
                 else if let Some(mime_type) = mime_type {
                     let path = base_path.parent().unwrap_or_else(|| Path::new("./")).join(uri);
@@ -138,6 +195,29 @@ impl Texture {
         }
     }
 
+    /// Uploads a KTX2 image (see `Ktx2`) into the bound texture (no power-of-two resizing needed)
+    unsafe fn load_ktx2(g_img: &gltf::Image<'_>, generate_mip_maps: bool, buffers: &[BufferData], base_path: &Path)
+        -> Result<(), String>
+    {
+        let data = match g_img.source() {
+            Source::View { view, .. } => {
+                let buffer: &[u8] = &buffers[view.buffer().index()];
+                buffer[view.offset()..view.offset() + view.length()].to_vec()
+            },
+            Source::Uri { uri, .. } if uri.starts_with("data:") => decode_data_uri(uri)?.1,
+            Source::Uri { uri, .. } => {
+                let path = base_path.parent().unwrap_or_else(|| Path::new("./")).join(uri);
+                fs::read(&path).map_err(|err| format!("{}: {}", path.display(), err))?
+            },
+        };
+        let ktx2 = Ktx2::parse(&data)?;
+        if !ktx2.format.is_supported(&Capabilities::get()) {
+            return Err(format!("{:?} compression isn't supported by the OpenGL driver", ktx2.format))
+        }
+        ktx2.upload(generate_mip_maps);
+        Ok(())
+    }
+
     // Returns whether image needs to be Power-Of-Two-sized and whether mip maps should be generated
     // TODO: refactor return type into enum?
     unsafe fn set_sampler_params(sampler: &gltf::texture::Sampler<'_>) -> (bool, bool) {
@@ -181,3 +261,57 @@ impl Texture {
         (needs_power_of_two, mip_maps)
     }
 }
+
+impl Drop for Texture {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteTextures(1, &self.id) }
+    }
+}
+
+/// Media type and decoded payload of a base64 `data:[<media type>];base64,<data>` URI
+fn decode_data_uri(uri: &str) -> Result<(&str, Vec<u8>), String> {
+    let uri = uri.trim_start_matches("data:");
+    let comma = uri.find(',').ok_or("missing ','")?;
+    let (header, data) = (&uri[..comma], &uri[comma + 1..]);
+    let mime_type = match header.strip_suffix(";base64") {
+        Some(mime_type) => mime_type,
+        None => return Err("not base64 encoded".to_owned()),
+    };
+    // strict (unlike `base64::decode`): the length must be a multiple of 4, including the padding,
+    // which is only allowed at the end
+    let padding = data.len() - data.trim_end_matches('=').len();
+    if data.len() % 4 != 0 || padding > 2 || data[..data.len() - padding].contains('=') {
+        return Err("invalid base64 padding".to_owned())
+    }
+    let data = base64::decode(data).map_err(|err| format!("invalid base64 data: {}", err))?;
+    Ok((mime_type, data))
+}
+
+/// Decodes a JPEG or PNG image. Other media types (e.g. `application/octet-stream` of data URIs)
+/// are detected from the content.
+fn load_from_memory(data: &[u8], mime_type: &str) -> image::ImageResult<image::DynamicImage> {
+    match mime_type {
+        "image/jpeg" => image::load_from_memory_with_format(data, JPEG),
+        "image/png" => image::load_from_memory_with_format(data, PNG),
+        _ => image::load_from_memory(data),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_data_uris() {
+        assert_eq!(decode_data_uri("data:image/png;base64,iVBORw=="), Ok(("image/png", vec![0x89, 0x50, 0x4E, 0x47])));
+        assert_eq!(decode_data_uri("data:application/octet-stream;base64,"), Ok(("application/octet-stream", vec![])));
+        assert_eq!(decode_data_uri("data:;base64,QQ=="), Ok(("", vec![b'A'])));
+        // bad padding, not base64, no data
+        assert!(decode_data_uri("data:image/png;base64,QQ=").is_err());
+        assert!(decode_data_uri("data:image/png;base64,QQ").is_err());
+        assert!(decode_data_uri("data:image/png;base64,Q===").is_err());
+        assert!(decode_data_uri("data:image/png;base64,Q=Q=").is_err());
+        assert!(decode_data_uri("data:text/plain,hello").is_err());
+        assert!(decode_data_uri("data:image/png;base64").is_err());
+    }
+}
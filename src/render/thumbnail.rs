@@ -0,0 +1,141 @@
+use std::fs;
+use std::io::Read;
+use std::os::raw::c_void;
+use std::path::{Path, PathBuf};
+
+use base64;
+use gl;
+use image;
+use image::DynamicImage;
+use log::{info, warn};
+
+use crate::importdata::parse_json;
+use crate::shader::Shader;
+
+/// Finds and decodes the thumbnail of a glTF file, in this order:
+/// - an accompanying `<name>.thumb.png` (or `.jpg`) next to the file
+/// - `asset.extras.thumbnail`: a (relative or data) URI, or the index of an image with one
+pub fn load_thumbnail(source: &str) -> Option<DynamicImage> {
+    let path = Path::new(source);
+    let base_dir = path.parent().unwrap_or_else(|| Path::new("./"));
+    let stem = path.file_stem()?.to_string_lossy().into_owned();
+    for extension in &["png", "jpg"] {
+        let thumbnail = base_dir.join(format!("{}.thumb.{}", stem, extension));
+        if thumbnail.is_file() {
+            return decode(image::open(&thumbnail), &thumbnail.to_string_lossy())
+        }
+    }
+
+    let json = parse_json(&read_json_bytes(path)?);
+    let thumbnail = &json["asset"]["extras"]["thumbnail"];
+    let uri = match thumbnail.as_u64() {
+        Some(image) => json["images"][image as usize]["uri"].as_str()?,
+        None => thumbnail.as_str()?,
+    };
+    if uri.starts_with("data:") {
+        let data = base64::decode(uri.split(',').nth(1)?).ok()?;
+        decode(image::load_from_memory(&data), "embedded thumbnail")
+    } else {
+        let thumbnail: PathBuf = base_dir.join(uri);
+        decode(image::open(&thumbnail), &thumbnail.to_string_lossy())
+    }
+}
+
+fn decode(result: image::ImageResult<DynamicImage>, name: &str) -> Option<DynamicImage> {
+    match result {
+        Ok(image) => {
+            info!("Showing thumbnail {} while loading", name);
+            Some(image)
+        },
+        Err(err) => {
+            warn!("Failed to load thumbnail {}: {}", name, err);
+            None
+        }
+    }
+}
+
+/// The JSON of a .gltf file, or the header + JSON chunk of a .glb (without reading the binary chunk)
+fn read_json_bytes(path: &Path) -> Option<Vec<u8>> {
+    let mut file = fs::File::open(path).ok()?;
+    let mut header = [0; 20];
+    file.read_exact(&mut header).ok()?;
+    if !header.starts_with(b"glTF") {
+        return fs::read(path).ok()
+    }
+    let length = u32::from_le_bytes([header[12], header[13], header[14], header[15]]) as usize;
+    let mut bytes = header.to_vec();
+    file.take(length as u64).read_to_end(&mut bytes).ok()?;
+    Some(bytes)
+}
+
+/// Draws a thumbnail centered in the window (while the model is loading)
+pub struct ThumbnailPreview {
+    texture: u32,
+    width: u32,
+    height: u32,
+
+    // empty, for the fullscreen triangle
+    vao: u32,
+
+    shader: Shader,
+    u_scale: i32,
+}
+
+impl ThumbnailPreview {
+    pub fn new(image: &DynamicImage) -> Self {
+        let rgba = image.to_rgba();
+        let (width, height) = rgba.dimensions();
+        let mut shader = Shader::from_source(
+            include_str!("../shaders/fullscreen-vert.glsl"),
+            include_str!("../shaders/thumbnail-frag.glsl"),
+            &[]);
+        unsafe {
+            let sampler = shader.uniform_location("u_ThumbnailSampler");
+            shader.use_program();
+            shader.set_int(sampler, 0);
+
+            let mut preview = ThumbnailPreview {
+                texture: 0,
+                width, height,
+                vao: 0,
+                u_scale: shader.uniform_location("u_Scale"),
+                shader,
+            };
+            gl::GenVertexArrays(1, &mut preview.vao);
+            gl::GenTextures(1, &mut preview.texture);
+            gl::BindTexture(gl::TEXTURE_2D, preview.texture);
+            gl::TexImage2D(gl::TEXTURE_2D, 0, gl::RGBA8 as i32, width as i32, height as i32, 0,
+                gl::RGBA, gl::UNSIGNED_BYTE, rgba.as_ptr() as *const c_void);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+            preview
+        }
+    }
+
+    /// Clears the window and draws the thumbnail as large as possible (but not upscaled beyond 2x)
+    pub unsafe fn draw(&self, window_width: f32, window_height: f32) {
+        let fit = (window_width / self.width as f32).min(window_height / self.height as f32).min(2.0);
+        gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+        gl::Disable(gl::DEPTH_TEST);
+        gl::BindVertexArray(self.vao);
+        self.shader.use_program();
+        self.shader.set_vec2(self.u_scale,
+            fit * self.width as f32 / window_width, fit * self.height as f32 / window_height);
+        gl::ActiveTexture(gl::TEXTURE0);
+        gl::BindTexture(gl::TEXTURE_2D, self.texture);
+        gl::DrawArrays(gl::TRIANGLES, 0, 3);
+        gl::BindVertexArray(0);
+        gl::Enable(gl::DEPTH_TEST);
+    }
+}
+
+impl Drop for ThumbnailPreview {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteTextures(1, &self.texture);
+            gl::DeleteVertexArrays(1, &self.vao);
+        }
+    }
+}
@@ -0,0 +1,316 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::str::FromStr;
+
+use png;
+use png::HasParameters;
+
+/// Output color encoding for screenshots.
+/// Rendered pixels are display-referred (sRGB-encoded), so `Linear` and `Rec709`
+/// decode them first.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ColorSpace {
+    /// sRGB OETF, written as PNG with an sRGB chunk
+    Srgb,
+    /// Raw linear values, written as (32 bit float) OpenEXR
+    Linear,
+    /// Rec.709 OETF, written as PNG
+    Rec709,
+}
+
+impl FromStr for ColorSpace {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "srgb" => Ok(ColorSpace::Srgb),
+            "linear" => Ok(ColorSpace::Linear),
+            "rec709" => Ok(ColorSpace::Rec709),
+            _ => Err(format!("unknown color space: {}", s)),
+        }
+    }
+}
+
+impl ColorSpace {
+    pub fn file_extension(self) -> &'static str {
+        match self {
+            ColorSpace::Linear => "exr",
+            ColorSpace::Srgb | ColorSpace::Rec709 => "png",
+        }
+    }
+}
+
+pub fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn linear_to_rec709(l: f32) -> f32 {
+    if l < 0.018 {
+        4.5 * l
+    } else {
+        1.099 * l.powf(0.45) - 0.099
+    }
+}
+
+/// Saves RGBA8 `pixels` (top row first) in the given color space.
+pub fn save(pixels: &[u8], width: u32, height: u32, filename: &str, color_space: ColorSpace) -> io::Result<()> {
+    match color_space {
+        ColorSpace::Srgb => write_png(pixels, width, height, filename, true),
+        ColorSpace::Rec709 => {
+            let encoded: Vec<u8> = pixels.chunks(4)
+                .flat_map(|p| {
+                    let encode = |c: u8| {
+                        let l = srgb_to_linear(f32::from(c) / 255.0);
+                        (linear_to_rec709(l).clamp(0.0, 1.0) * 255.0).round() as u8
+                    };
+                    vec![encode(p[0]), encode(p[1]), encode(p[2]), p[3]]
+                })
+                .collect();
+            write_png(&encoded, width, height, filename, false)
+        }
+        ColorSpace::Linear => {
+            let linear: Vec<f32> = pixels.chunks(4)
+                .flat_map(|p| vec![
+                    srgb_to_linear(f32::from(p[0]) / 255.0),
+                    srgb_to_linear(f32::from(p[1]) / 255.0),
+                    srgb_to_linear(f32::from(p[2]) / 255.0),
+                    f32::from(p[3]) / 255.0,
+                ])
+                .collect();
+            write_exr(&linear, width, height, filename)
+        }
+    }
+}
+
+/// Saves float RGBA `pixels` (top row first) as OpenEXR. They are written as they are, i.e. they
+/// must already be linear (rendered without sRGB output, see `GltfViewer::screenshot_hdr`).
+/// Values above 1.0 (e.g. from strong emissives) are kept.
+pub fn save_hdr(pixels: &[f32], width: u32, height: u32, filename: &str) -> io::Result<()> {
+    write_exr(pixels, width, height, filename)
+}
+
+fn write_png(pixels: &[u8], width: u32, height: u32, filename: &str, srgb_chunk: bool) -> io::Result<()> {
+    let to_io_error = |err: png::EncodingError| io::Error::other(err.to_string());
+
+    let file = File::create(filename)?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+    encoder.set(png::ColorType::RGBA).set(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(to_io_error)?;
+    if srgb_chunk {
+        // rendering intent: perceptual
+        writer.write_chunk(*b"sRGB", &[0]).map_err(to_io_error)?;
+    }
+    writer.write_image_data(pixels).map_err(to_io_error)
+}
+
+/// Writes an uncompressed scanline OpenEXR file with FLOAT RGBA channels.
+/// `pixels` are RGBA, top row first (like EXR's INCREASING_Y line order).
+pub fn write_exr(pixels: &[f32], width: u32, height: u32, filename: &str) -> io::Result<()> {
+    fn attribute(out: &mut Vec<u8>, name: &str, type_: &str, value: &[u8]) {
+        out.extend_from_slice(name.as_bytes());
+        out.push(0);
+        out.extend_from_slice(type_.as_bytes());
+        out.push(0);
+        out.extend_from_slice(&(value.len() as i32).to_le_bytes());
+        out.extend_from_slice(value);
+    }
+
+    // channels must be sorted alphabetically
+    const CHANNELS: [(&str, usize); 4] = [("A", 3), ("B", 2), ("G", 1), ("R", 0)];
+
+    let mut out = Vec::new();
+    out.extend_from_slice(&[0x76, 0x2f, 0x31, 0x01]); // magic number
+    out.extend_from_slice(&[2, 0, 0, 0]); // version 2, single-part scanline
+
+    let mut channels = Vec::new();
+    for (name, _) in &CHANNELS {
+        channels.extend_from_slice(name.as_bytes());
+        channels.push(0);
+        channels.extend_from_slice(&2i32.to_le_bytes()); // FLOAT
+        channels.extend_from_slice(&[0, 0, 0, 0]); // pLinear + reserved
+        channels.extend_from_slice(&1i32.to_le_bytes()); // xSampling
+        channels.extend_from_slice(&1i32.to_le_bytes()); // ySampling
+    }
+    channels.push(0);
+    attribute(&mut out, "channels", "chlist", &channels);
+    attribute(&mut out, "compression", "compression", &[0]); // NO_COMPRESSION
+
+    let mut window = Vec::new();
+    for v in &[0, 0, width as i32 - 1, height as i32 - 1] {
+        window.extend_from_slice(&v.to_le_bytes());
+    }
+    attribute(&mut out, "dataWindow", "box2i", &window);
+    attribute(&mut out, "displayWindow", "box2i", &window);
+    attribute(&mut out, "lineOrder", "lineOrder", &[0]); // INCREASING_Y
+    attribute(&mut out, "pixelAspectRatio", "float", &1.0f32.to_le_bytes());
+    attribute(&mut out, "screenWindowCenter", "v2f", &[0; 8]);
+    attribute(&mut out, "screenWindowWidth", "float", &1.0f32.to_le_bytes());
+    out.push(0); // end of header
+
+    let line_size = 8 + width as usize * CHANNELS.len() * 4;
+    let table_end = out.len() + height as usize * 8;
+    for y in 0..height as usize {
+        out.extend_from_slice(&((table_end + y * line_size) as u64).to_le_bytes());
+    }
+
+    for y in 0..height as usize {
+        out.extend_from_slice(&(y as i32).to_le_bytes());
+        out.extend_from_slice(&((line_size - 8) as i32).to_le_bytes());
+        let row = &pixels[y * width as usize * 4..(y + 1) * width as usize * 4];
+        for (_, offset) in &CHANNELS {
+            for pixel in row.chunks(4) {
+                out.extend_from_slice(&pixel[*offset].to_le_bytes());
+            }
+        }
+    }
+
+    fs::write(filename, out)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    fn temp_file(name: &str) -> String {
+        env::temp_dir().join(name).to_string_lossy().into_owned()
+    }
+
+    /// Width, height and pixels of a PNG
+    fn read_png(path: &str) -> (u32, u32, Vec<u8>) {
+        let decoder = png::Decoder::new(File::open(path).unwrap());
+        let (info, mut reader) = decoder.read_info().unwrap();
+        let mut decoded = vec![0; info.buffer_size()];
+        reader.next_frame(&mut decoded).unwrap();
+        (info.width, info.height, decoded)
+    }
+
+    /// Mirrors `linearToSrgb` of the PBR fragment shader (the inverse of `srgb_to_linear`)
+    fn linear_to_srgb(l: f32) -> f32 {
+        if l <= 0.0031308 {
+            l * 12.92
+        } else {
+            1.055 * l.powf(1.0 / 2.4) - 0.055
+        }
+    }
+
+    #[test]
+    fn srgb_png_has_srgb_chunk() {
+        let path = temp_file("gltf-viewer-screenshot-test.png");
+        let pixels = [255, 128, 0, 255, 0, 0, 0, 0];
+        save(&pixels, 2, 1, &path, ColorSpace::Srgb).unwrap();
+
+        let data = fs::read(&path).unwrap();
+        // chunk length (1), type and rendering intent right after IHDR
+        assert!(data.windows(9).any(|chunk| chunk == b"\0\0\0\x01sRGB\0"));
+        assert_eq!(read_png(&path), (2, 1, pixels.to_vec()));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn srgb_transfer_functions_round_trip() {
+        for c in 0..=255u8 {
+            let srgb = f32::from(c) / 255.0;
+            assert!((linear_to_srgb(srgb_to_linear(srgb)) - srgb).abs() < 1e-5, "{}", c);
+        }
+        // sRGB mid gray is about 21% linear (decoding twice or not at all makes it too dark or washed out)
+        assert!((srgb_to_linear(0.5) - 0.2140).abs() < 1e-4);
+        assert!((linear_to_srgb(0.2140) - 0.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn srgb_ramp_matches_reference() {
+        // linear ramp from black to white, encoded for the display like the shader output
+        let pixels: Vec<u8> = (0..16)
+            .flat_map(|i| {
+                let c = (linear_to_srgb(i as f32 / 15.0) * 255.0).round() as u8;
+                vec![c, c, c, 255]
+            })
+            .collect();
+        let path = temp_file("gltf-viewer-srgb-ramp.png");
+        save(&pixels, 16, 1, &path, ColorSpace::Srgb).unwrap();
+        let saved = read_png(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(saved, read_png("tests/reference/srgb_ramp.png"));
+    }
+
+    fn i32_at(data: &[u8], offset: usize) -> i32 {
+        i32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+    }
+
+    /// Header attributes (name, type, value) and pixels (RGBA, top row first) of a one pixel wide
+    /// OpenEXR as written by `write_exr`
+    fn read_exr_column(data: &[u8]) -> (Vec<(String, String, &[u8])>, Vec<(f32, f32, f32, f32)>) {
+        assert_eq!(&data[..8], &[0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0]);
+
+        // attributes: name, type (both null-terminated), size and value, up to an empty name
+        let mut attributes = vec![];
+        let mut offset = 8;
+        let string = |start: usize| {
+            let end = start + data[start..].iter().position(|&b| b == 0).unwrap();
+            (String::from_utf8_lossy(&data[start..end]).into_owned(), end + 1)
+        };
+        while data[offset] != 0 {
+            let (name, type_start) = string(offset);
+            let (type_, size_start) = string(type_start);
+            let value_start = size_start + 4;
+            offset = value_start + i32_at(data, size_start) as usize;
+            attributes.push((name, type_, &data[value_start..offset]));
+        }
+        let window = attributes.iter().find(|(name, _, _)| name == "dataWindow").unwrap().2;
+        let height = (i32_at(window, 12) + 1) as usize;
+
+        // offset table (the lower half of each u64 suffices here), then per line:
+        // y, size and the channels A, B, G, R
+        let table = offset + 1;
+        let pixels = (0..height)
+            .map(|y| {
+                let start = i32_at(data, table + y * 8) as usize;
+                assert_eq!(i32_at(data, start), y as i32);
+                let channel = |c: usize| f32::from_bits(i32_at(data, start + 8 + c * 4) as u32);
+                (channel(3), channel(2), channel(1), channel(0))
+            })
+            .collect();
+        (attributes, pixels)
+    }
+
+    #[test]
+    fn linear_exr_header_and_pixels() {
+        let path = temp_file("gltf-viewer-screenshot-test.exr");
+        // sRGB white, mid gray and black, top row first
+        let pixels = [255, 255, 255, 255, 188, 188, 188, 255, 0, 0, 0, 0];
+        save(&pixels, 1, 3, &path, ColorSpace::Linear).unwrap();
+        let data = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let (attributes, pixels) = read_exr_column(&data);
+        let attribute = |name: &str| attributes.iter().find(|(n, _, _)| n == name).unwrap();
+        assert_eq!(attribute("channels").1, "chlist");
+        assert_eq!(attribute("compression").2, &[0]);
+        let window = attribute("dataWindow").2;
+        assert_eq!((0..4).map(|i| i32_at(window, i * 4)).collect::<Vec<_>>(), vec![0, 0, 0, 2]);
+
+        assert_eq!(pixels[0], (1.0, 1.0, 1.0, 1.0));
+        let (r, g, b, a) = pixels[1];
+        assert!((r - 0.5029).abs() < 1e-3 && r == g && g == b && a == 1.0, "{}", r);
+        assert_eq!(pixels[2], (0.0, 0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn hdr_exr_keeps_raw_values() {
+        let path = temp_file("gltf-viewer-screenshot-test-hdr.exr");
+        // superwhite (e.g. a strong emissive), linear mid gray, transparent black
+        let pixels = [2.0, 4.5, 16.0, 1.0, 0.5, 0.5, 0.5, 1.0, 0.0, 0.0, 0.0, 0.0];
+        save_hdr(&pixels, 1, 3, &path).unwrap();
+        let data = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let (_, saved) = read_exr_column(&data);
+        assert_eq!(saved, vec![(2.0, 4.5, 16.0, 1.0), (0.5, 0.5, 0.5, 1.0), (0.0, 0.0, 0.0, 0.0)]);
+    }
+}
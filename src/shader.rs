@@ -8,12 +8,14 @@ use std::str;
 use gl;
 use gl::types::*;
 
-use cgmath::{Matrix, Matrix4, Vector3, Vector4};
+use cgmath::{Matrix, Matrix3, Matrix4, Vector3, Vector4};
 use cgmath::prelude::*;
 
 use bitflags::bitflags;
 use log::{warn, trace};
 
+use crate::render::DEFAULT_LIGHT_DIRECTION;
+
 pub struct Shader {
     pub id: u32,
     uniform_location_cache: HashMap<&'static str, i32>
@@ -100,7 +102,6 @@ impl Shader {
 
     /// utility uniform functions
     /// ------------------------------------------------------------------------
-    #[allow(dead_code)]
     pub unsafe fn set_bool(&self, location: i32, value: bool) {
         gl::Uniform1i(location, value as i32);
     }
@@ -109,14 +110,26 @@ impl Shader {
         gl::Uniform1i(location, value);
     }
     /// ------------------------------------------------------------------------
+    pub unsafe fn set_int_array(&self, location: i32, values: &[i32]) {
+        gl::Uniform1iv(location, values.len() as i32, values.as_ptr());
+    }
+    /// ------------------------------------------------------------------------
     pub unsafe fn set_float(&self, location: i32, value: f32) {
         gl::Uniform1f(location, value);
     }
     /// ------------------------------------------------------------------------
+    pub unsafe fn set_float_array(&self, location: i32, values: &[f32]) {
+        gl::Uniform1fv(location, values.len() as i32, values.as_ptr());
+    }
+    /// ------------------------------------------------------------------------
     pub unsafe fn set_vector3(&self, location: i32, value: &Vector3<f32>) {
         gl::Uniform3fv(location, 1, value.as_ptr());
     }
     /// ------------------------------------------------------------------------
+    pub unsafe fn set_vector3_array(&self, location: i32, values: &[Vector3<f32>]) {
+        gl::Uniform3fv(location, values.len() as i32, values.as_ptr() as *const f32);
+    }
+    /// ------------------------------------------------------------------------
     pub unsafe fn set_vector4(&self, location: i32, value: &Vector4<f32>) {
         gl::Uniform4fv(location, 1, value.as_ptr());
     }
@@ -129,6 +142,10 @@ impl Shader {
         gl::Uniform3f(location, x, y, z);
     }
     /// ------------------------------------------------------------------------
+    pub unsafe fn set_mat3(&self, location: i32, mat: &Matrix3<f32>) {
+        gl::UniformMatrix3fv(location, 1, gl::FALSE, mat.as_ptr());
+    }
+    /// ------------------------------------------------------------------------
     pub unsafe fn set_mat4(&self, location: i32, mat: &Matrix4<f32>) {
         gl::UniformMatrix4fv(location, 1, gl::FALSE, mat.as_ptr());
     }
@@ -182,12 +199,15 @@ impl Shader {
 
 bitflags! {
     /// Flags matching the defines in the PBR shader
-    pub struct ShaderFlags: u16 {
+    pub struct ShaderFlags: u32 {
         // vertex shader + fragment shader
         const HAS_NORMALS           = 1;
         const HAS_TANGENTS          = 1 << 1;
         const HAS_UV                = 1 << 2;
         const HAS_COLORS            = 1 << 3;
+        const HAS_MORPH_TARGETS     = 1 << 13;
+        /// JOINTS_0 + WEIGHTS_0 (drawn with the joint matrices of the node's skin)
+        const HAS_SKINNING          = 1 << 17;
 
         // fragment shader only
         const USE_IBL               = 1 << 4;
@@ -197,13 +217,21 @@ bitflags! {
         const HAS_METALROUGHNESSMAP = 1 << 8;
         const HAS_OCCLUSIONMAP      = 1 << 9;
         const USE_TEX_LOD           = 1 << 10;
+        const FORCE_TWO_SIDED_NORMALS = 1 << 12;
+        const USE_SSAO              = 1 << 14;
+        /// `KHR_materials_unlit`
+        const UNLIT                 = 1 << 15;
+        const USE_SHADOWS           = 1 << 16;
+
+        // debugging
+        const HAS_DEBUG_ATTRIBUTE   = 1 << 11;
     }
 }
 
 impl ShaderFlags {
     pub fn as_strings(self) -> Vec<String> {
-        (0..15)
-            .map(|i| 1u16 << i)
+        (0..18)
+            .map(|i| 1u32 << i)
             .filter(|i| self.bits & i != 0)
             .map(|i| format!("{:?}", ShaderFlags::from_bits_truncate(i)))
             .collect()
@@ -218,13 +246,19 @@ pub struct PbrUniformLocations {
     pub u_ModelMatrix: i32,
     pub u_Camera: i32,
 
+    pub u_LightCount: i32,
+    pub u_LightType: i32,
     pub u_LightDirection: i32,
+    pub u_LightPosition: i32,
     pub u_LightColor: i32,
+    pub u_LightRange: i32,
+    pub u_LightConeScale: i32,
+    pub u_LightConeOffset: i32,
 
     pub u_AmbientLightColor: i32,
     pub u_AmbientLightIntensity: i32,
 
-    // TODO!: set when integrating IBL (unused now)
+    // `Environment` (`USE_IBL`)
     pub u_DiffuseEnvSampler: i32,
     pub u_SpecularEnvSampler: i32,
     pub u_brdfLUT: i32,
@@ -234,26 +268,54 @@ pub struct PbrUniformLocations {
     pub u_BaseColorSampler: i32,
     pub u_BaseColorTexCoord: i32,
     pub u_BaseColorFactor: i32,
+    pub u_BaseColorUVTransform: i32,
 
     pub u_NormalSampler: i32,
     pub u_NormalTexCoord: i32,
     pub u_NormalScale: i32,
+    pub u_NormalUVTransform: i32,
 
     pub u_EmissiveSampler: i32,
     pub u_EmissiveTexCoord: i32,
     pub u_EmissiveFactor: i32,
+    pub u_EmissiveUVTransform: i32,
 
     pub u_MetallicRoughnessSampler: i32,
     pub u_MetallicRoughnessTexCoord: i32,
     pub u_MetallicRoughnessValues: i32,
+    pub u_MetallicRoughnessUVTransform: i32,
 
     pub u_OcclusionSampler: i32,
     pub u_OcclusionTexCoord: i32,
     pub u_OcclusionStrength: i32,
+    pub u_OcclusionUVTransform: i32,
 
     pub u_AlphaBlend: i32,
     pub u_AlphaCutoff: i32,
 
+    pub u_DebugAttributeComponents: i32,
+
+    pub u_MorphTargets: i32,
+    pub u_MorphTargetCount: i32,
+    pub u_MorphVertexCount: i32,
+    pub u_MorphWeights: i32,
+
+    // see `JointMatrices`
+    pub u_JointMatrices: i32,
+
+    pub u_SsaoSampler: i32,
+
+    // `ShadowMap` (`USE_SHADOWS`), see `Root::set_shadow_uniforms`
+    pub u_ShadowMap: i32,
+    pub u_LightSpaceMatrix: i32,
+    pub u_ShadowBias: i32,
+    pub u_ShadowLight: i32,
+
+    // see `Root::set_tone_mapping`
+    pub u_ToneMap: i32,
+    pub u_Exposure: i32,
+    pub u_SrgbOutput: i32,
+
     // TODO!: use/remove debugging uniforms
     // debugging flags used for shader output of intermediate PBR variables
     pub u_ScaleDiffBaseMR: i32,
@@ -286,8 +348,14 @@ impl PbrShader {
                 u_ModelMatrix: shader.uniform_location("u_ModelMatrix"),
                 u_Camera: shader.uniform_location("u_Camera"),
 
+                u_LightCount: shader.uniform_location("u_LightCount"),
+                u_LightType: shader.uniform_location("u_LightType"),
                 u_LightDirection: shader.uniform_location("u_LightDirection"),
+                u_LightPosition: shader.uniform_location("u_LightPosition"),
                 u_LightColor: shader.uniform_location("u_LightColor"),
+                u_LightRange: shader.uniform_location("u_LightRange"),
+                u_LightConeScale: shader.uniform_location("u_LightConeScale"),
+                u_LightConeOffset: shader.uniform_location("u_LightConeOffset"),
 
                 u_AmbientLightColor: shader.uniform_location("u_AmbientLightColor"),
                 u_AmbientLightIntensity: shader.uniform_location("u_AmbientLightIntensity"),
@@ -299,26 +367,51 @@ impl PbrShader {
                 u_BaseColorSampler: shader.uniform_location("u_BaseColorSampler"),
                 u_BaseColorTexCoord: shader.uniform_location("u_BaseColorTexCoord"),
                 u_BaseColorFactor: shader.uniform_location("u_BaseColorFactor"),
+                u_BaseColorUVTransform: shader.uniform_location("u_BaseColorUVTransform"),
 
                 u_NormalSampler: shader.uniform_location("u_NormalSampler"),
                 u_NormalTexCoord: shader.uniform_location("u_NormalTexCoord"),
                 u_NormalScale: shader.uniform_location("u_NormalScale"),
+                u_NormalUVTransform: shader.uniform_location("u_NormalUVTransform"),
 
                 u_EmissiveSampler: shader.uniform_location("u_EmissiveSampler"),
                 u_EmissiveTexCoord: shader.uniform_location("u_EmissiveTexCoord"),
                 u_EmissiveFactor: shader.uniform_location("u_EmissiveFactor"),
+                u_EmissiveUVTransform: shader.uniform_location("u_EmissiveUVTransform"),
 
                 u_MetallicRoughnessSampler: shader.uniform_location("u_MetallicRoughnessSampler"),
                 u_MetallicRoughnessTexCoord: shader.uniform_location("u_MetallicRoughnessTexCoord"),
                 u_MetallicRoughnessValues: shader.uniform_location("u_MetallicRoughnessValues"),
+                u_MetallicRoughnessUVTransform: shader.uniform_location("u_MetallicRoughnessUVTransform"),
 
                 u_OcclusionSampler: shader.uniform_location("u_OcclusionSampler"),
                 u_OcclusionTexCoord: shader.uniform_location("u_OcclusionTexCoord"),
                 u_OcclusionStrength: shader.uniform_location("u_OcclusionStrength"),
+                u_OcclusionUVTransform: shader.uniform_location("u_OcclusionUVTransform"),
 
                 u_AlphaBlend: shader.uniform_location("u_AlphaBlend"),
                 u_AlphaCutoff: shader.uniform_location("u_AlphaCutoff"),
 
+                u_DebugAttributeComponents: shader.uniform_location("u_DebugAttributeComponents"),
+
+                u_MorphTargets: shader.uniform_location("u_MorphTargets"),
+                u_MorphTargetCount: shader.uniform_location("u_MorphTargetCount"),
+                u_MorphVertexCount: shader.uniform_location("u_MorphVertexCount"),
+                u_MorphWeights: shader.uniform_location("u_MorphWeights"),
+
+                u_JointMatrices: shader.uniform_location("u_JointMatrices"),
+
+                u_SsaoSampler: shader.uniform_location("u_SsaoSampler"),
+
+                u_ShadowMap: shader.uniform_location("u_ShadowMap"),
+                u_LightSpaceMatrix: shader.uniform_location("u_LightSpaceMatrix"),
+                u_ShadowBias: shader.uniform_location("u_ShadowBias"),
+                u_ShadowLight: shader.uniform_location("u_ShadowLight"),
+
+                u_ToneMap: shader.uniform_location("u_ToneMap"),
+                u_Exposure: shader.uniform_location("u_Exposure"),
+                u_SrgbOutput: shader.uniform_location("u_SrgbOutput"),
+
                 u_ScaleDiffBaseMR: shader.uniform_location("u_ScaleDiffBaseMR"),
                 u_ScaleFGDSpec: shader.uniform_location("u_ScaleFGDSpec"),
                 u_ScaleIBLAmbient: shader.uniform_location("u_ScaleIBLAmbient"),
@@ -330,14 +423,30 @@ impl PbrShader {
             shader.set_int(uniforms.u_EmissiveSampler, 2);
             shader.set_int(uniforms.u_MetallicRoughnessSampler, 3);
             shader.set_int(uniforms.u_OcclusionSampler, 4);
-
+            shader.set_int(uniforms.u_MorphTargets, 5);
+            shader.set_int(uniforms.u_SsaoSampler, 6);
+            shader.set_int(uniforms.u_DiffuseEnvSampler, 7);
+            shader.set_int(uniforms.u_SpecularEnvSampler, 8);
+            shader.set_int(uniforms.u_brdfLUT, 9);
+            shader.set_int(uniforms.u_ShadowMap, 10);
+            shader.set_int(uniforms.u_JointMatrices, 11);
+            // diffuse and specular IBL at full strength
+            shader.set_vector4(uniforms.u_ScaleIBLAmbient, &Vector4::new(1.0, 1.0, 1.0, 1.0));
+
+            // the default (directional) light, until `Root::set_light_uniforms` sets those of the scene
+            shader.set_int(uniforms.u_LightCount, 1);
+            shader.set_int(uniforms.u_LightType, 0);
             shader.set_vec3(uniforms.u_LightColor, 5.0, 5.0, 5.0);
             // TODO!: optional minus on z
-            shader.set_vec3(uniforms.u_LightDirection, 0.0, 0.5, 0.5);
+            let [x, y, z] = DEFAULT_LIGHT_DIRECTION;
+            shader.set_vec3(uniforms.u_LightDirection, x, y, z);
 
             shader.set_vec3(uniforms.u_AmbientLightColor, 1.0, 1.0, 1.0);
             shader.set_float(uniforms.u_AmbientLightIntensity, 0.2);
 
+            shader.set_float(uniforms.u_Exposure, 1.0);
+            shader.set_bool(uniforms.u_SrgbOutput, true);
+
             uniforms
         };
 
@@ -0,0 +1,68 @@
+#version 330 core
+// Behind the scene (see `BackgroundRenderer`), one variant per define:
+// GRADIENT from `u_Bottom` to `u_Top`, or the environment as SKYBOX
+
+in vec2 v_UV;
+
+out vec4 FragColor;
+
+#ifdef GRADIENT
+// display-referred, like the clear color
+uniform vec3 u_Top;
+uniform vec3 u_Bottom;
+
+void main()
+{
+    FragColor = vec4(mix(u_Bottom, u_Top, v_UV.y), 1.0);
+}
+#endif
+
+#ifdef SKYBOX
+uniform samplerCube u_EnvironmentSampler;
+// of the camera rotation only, so that the environment is infinitely far away
+uniform mat4 u_InverseViewProjection;
+
+// same output transform as the PBR shader (see `Root::set_tone_mapping`)
+uniform int u_ToneMap;
+uniform float u_Exposure;
+uniform bool u_SrgbOutput;
+
+vec3 toneMapACES(vec3 x)
+{
+    const float a = 2.51;
+    const float b = 0.03;
+    const float c = 2.43;
+    const float d = 0.59;
+    const float e = 0.14;
+    return clamp((x * (a * x + b)) / (x * (c * x + d) + e), 0.0, 1.0);
+}
+
+vec3 toneMap(vec3 color)
+{
+    color *= u_Exposure;
+    if (u_ToneMap == 1) {
+        return color / (1.0 + color);
+    }
+    if (u_ToneMap == 2) {
+        return toneMapACES(color);
+    }
+    return color;
+}
+
+vec3 linearToSrgb(vec3 linear)
+{
+    return mix(linear * 12.92, 1.055 * pow(linear, vec3(1.0 / 2.4)) - 0.055, step(vec3(0.0031308), linear));
+}
+
+void main()
+{
+    // any point on the view ray of the pixel: the camera is at the origin
+    vec4 p = u_InverseViewProjection * vec4(v_UV * 2.0 - 1.0, 0.0, 1.0);
+    vec3 d = normalize(p.xyz / p.w);
+    vec3 color = toneMap(textureLod(u_EnvironmentSampler, d, 0.0).rgb);
+    if (u_SrgbOutput) {
+        color = linearToSrgb(color);
+    }
+    FragColor = vec4(color, 1.0);
+}
+#endif
@@ -0,0 +1,179 @@
+#version 330 core
+// Precomputation of the image-based lighting (see `Environment`), one pass per define:
+// EQUIRECT_TO_CUBE, IRRADIANCE and PREFILTER render the cubemap face `u_Face`,
+// BRDF_LUT the lookup table of the split-sum approximation (x: NdotV, y: roughness).
+//
+// References:
+// [1] Real Shading in Unreal Engine 4
+//     http://blog.selfshadow.com/publications/s2013-shading-course/karis/s2013_pbs_epic_notes_v2.pdf
+// [2] GPU-Based Importance Sampling (GPU Gems 3, chapter 20)
+
+const float PI = 3.141592653589793;
+
+uniform int u_Face;
+
+#ifdef EQUIRECT_TO_CUBE
+uniform sampler2D u_EquirectangularSampler;
+#endif
+#if defined(IRRADIANCE) || defined(PREFILTER)
+uniform samplerCube u_EnvironmentSampler;
+#endif
+#ifdef PREFILTER
+uniform float u_Roughness;
+#endif
+
+in vec2 v_UV;
+
+out vec4 FragColor;
+
+// Direction through the texel of cubemap face `u_Face`, see the cube map face selection in the GL spec
+vec3 faceDirection(vec2 uv)
+{
+    vec2 p = uv * 2.0 - 1.0;
+    vec3 directions[6] = vec3[](
+        vec3(1.0, -p.y, -p.x),
+        vec3(-1.0, -p.y, p.x),
+        vec3(p.x, 1.0, p.y),
+        vec3(p.x, -1.0, -p.y),
+        vec3(p.x, -p.y, 1.0),
+        vec3(-p.x, -p.y, -1.0));
+    return normalize(directions[u_Face]);
+}
+
+// Low-discrepancy sample i of n
+vec2 hammersley(uint i, uint n)
+{
+    uint bits = i;
+    bits = (bits << 16u) | (bits >> 16u);
+    bits = ((bits & 0x55555555u) << 1u) | ((bits & 0xAAAAAAAAu) >> 1u);
+    bits = ((bits & 0x33333333u) << 2u) | ((bits & 0xCCCCCCCCu) >> 2u);
+    bits = ((bits & 0x0F0F0F0Fu) << 4u) | ((bits & 0xF0F0F0F0u) >> 4u);
+    bits = ((bits & 0x00FF00FFu) << 8u) | ((bits & 0xFF00FF00u) >> 8u);
+    return vec2(float(i) / float(n), float(bits) * 2.3283064365386963e-10);
+}
+
+// Half vector around n distributed like the GGX lobe of the (perceptual) roughness, see [1]
+vec3 importanceSampleGGX(vec2 xi, vec3 n, float roughness)
+{
+    float alpha = roughness * roughness;
+    float phi = 2.0 * PI * xi.x;
+    float cosTheta = sqrt((1.0 - xi.y) / (1.0 + (alpha * alpha - 1.0) * xi.y));
+    float sinTheta = sqrt(1.0 - cosTheta * cosTheta);
+    vec3 h = vec3(cos(phi) * sinTheta, sin(phi) * sinTheta, cosTheta);
+
+    vec3 up = abs(n.z) < 0.999 ? vec3(0.0, 0.0, 1.0) : vec3(1.0, 0.0, 0.0);
+    vec3 tangent = normalize(cross(up, n));
+    vec3 bitangent = cross(n, tangent);
+    return normalize(tangent * h.x + bitangent * h.y + n * h.z);
+}
+
+#ifdef EQUIRECT_TO_CUBE
+void main()
+{
+    vec3 d = faceDirection(v_UV);
+    // the first row of the panorama is the top (+Y)
+    vec2 uv = vec2(atan(d.z, d.x) / (2.0 * PI) + 0.5, acos(clamp(d.y, -1.0, 1.0)) / PI);
+    FragColor = vec4(texture(u_EquirectangularSampler, uv).rgb, 1.0);
+}
+#endif
+
+#ifdef IRRADIANCE
+// Cosine-weighted integral of the environment over the hemisphere around the normal,
+// so that the diffuse term is just irradiance * diffuseColor
+void main()
+{
+    vec3 n = faceDirection(v_UV);
+    vec3 up = abs(n.y) < 0.999 ? vec3(0.0, 1.0, 0.0) : vec3(0.0, 0.0, 1.0);
+    vec3 right = normalize(cross(up, n));
+    up = cross(n, right);
+
+    const float delta = 0.05;
+    vec3 irradiance = vec3(0.0);
+    float count = 0.0;
+    for (float phi = 0.0; phi < 2.0 * PI; phi += delta) {
+        for (float theta = 0.0; theta < 0.5 * PI; theta += delta) {
+            vec3 t = vec3(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
+            vec3 d = t.x * right + t.y * up + t.z * n;
+            // a low-resolution mip level is enough (and avoids aliasing)
+            irradiance += textureLod(u_EnvironmentSampler, d, 5.0).rgb * cos(theta) * sin(theta);
+            count += 1.0;
+        }
+    }
+    FragColor = vec4(PI * irradiance / count, 1.0);
+}
+#endif
+
+#ifdef PREFILTER
+const uint SAMPLE_COUNT = 128u;
+
+// The environment convolved with the GGX lobe of `u_Roughness`, assuming view = normal [1]
+void main()
+{
+    vec3 n = faceDirection(v_UV);
+    if (u_Roughness == 0.0) {
+        FragColor = vec4(textureLod(u_EnvironmentSampler, n, 0.0).rgb, 1.0);
+        return;
+    }
+
+    float resolution = float(textureSize(u_EnvironmentSampler, 0).x);
+    float texelSolidAngle = 4.0 * PI / (6.0 * resolution * resolution);
+    float alpha2 = pow(u_Roughness, 4.0);
+
+    vec3 color = vec3(0.0);
+    float weight = 0.0;
+    for (uint i = 0u; i < SAMPLE_COUNT; ++i) {
+        vec3 h = importanceSampleGGX(hammersley(i, SAMPLE_COUNT), n, u_Roughness);
+        vec3 l = normalize(2.0 * dot(n, h) * h - n);
+        float NdotL = dot(n, l);
+        if (NdotL > 0.0) {
+            // filtered importance sampling [2]: read the mip level matching the solid angle of the sample
+            float NdotH = max(dot(n, h), 0.0);
+            float f = NdotH * NdotH * (alpha2 - 1.0) + 1.0;
+            float pdf = alpha2 / (PI * f * f) / 4.0 + 0.0001; // D * NdotH / (4 * VdotH) with v = n
+            float sampleSolidAngle = 1.0 / (float(SAMPLE_COUNT) * pdf);
+            float lod = max(0.5 * log2(sampleSolidAngle / texelSolidAngle), 0.0);
+            color += textureLod(u_EnvironmentSampler, l, lod).rgb * NdotL;
+            weight += NdotL;
+        }
+    }
+    FragColor = vec4(color / max(weight, 0.0001), 1.0);
+}
+#endif
+
+#ifdef BRDF_LUT
+const uint SAMPLE_COUNT = 512u;
+
+float geometrySchlickGGX(float NdotV, float roughness)
+{
+    // k for IBL, see [1]
+    float k = roughness * roughness / 2.0;
+    return NdotV / (NdotV * (1.0 - k) + k);
+}
+
+// Scale and bias to F0 of the specular integral, see [1], Figure 3
+void main()
+{
+    float NdotV = max(v_UV.x, 0.0001);
+    float roughness = v_UV.y;
+    vec3 v = vec3(sqrt(1.0 - NdotV * NdotV), 0.0, NdotV);
+    vec3 n = vec3(0.0, 0.0, 1.0);
+
+    float scale = 0.0;
+    float bias = 0.0;
+    for (uint i = 0u; i < SAMPLE_COUNT; ++i) {
+        vec3 h = importanceSampleGGX(hammersley(i, SAMPLE_COUNT), n, roughness);
+        vec3 l = normalize(2.0 * dot(v, h) * h - v);
+        float NdotL = max(l.z, 0.0);
+        float NdotH = max(h.z, 0.0);
+        float VdotH = max(dot(v, h), 0.0);
+        if (NdotL > 0.0) {
+            float G = geometrySchlickGGX(NdotV, roughness) * geometrySchlickGGX(NdotL, roughness);
+            float visibility = G * VdotH / (NdotH * NdotV);
+            float fresnel = pow(1.0 - VdotH, 5.0);
+            scale += (1.0 - fresnel) * visibility;
+            bias += fresnel * visibility;
+        }
+    }
+    FragColor = vec4(scale / float(SAMPLE_COUNT), bias / float(SAMPLE_COUNT), 0.0, 1.0);
+}
+#endif
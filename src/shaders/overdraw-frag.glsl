@@ -0,0 +1,25 @@
+#version 330 core
+// Maps the number of fragments per pixel (accumulated with additive blending) to a heatmap
+
+in vec2 v_UV;
+out vec4 FragColor;
+
+uniform sampler2D u_OverdrawSampler;
+uniform float u_MaxOverdraw; // drawn red
+
+void main()
+{
+    float count = texture(u_OverdrawSampler, v_UV).r;
+    if (count < 0.5) {
+        FragColor = vec4(0.0, 0.0, 0.0, 1.0); // nothing drawn
+        return;
+    }
+    // 1 fragment: blue -> cyan -> green -> yellow -> red: u_MaxOverdraw or more
+    float t = clamp((count - 1.0) / (u_MaxOverdraw - 1.0), 0.0, 1.0) * 4.0;
+    vec3 color = vec3(0.0, 0.0, 1.0);
+    color = mix(color, vec3(0.0, 1.0, 1.0), clamp(t, 0.0, 1.0));
+    color = mix(color, vec3(0.0, 1.0, 0.0), clamp(t - 1.0, 0.0, 1.0));
+    color = mix(color, vec3(1.0, 1.0, 0.0), clamp(t - 2.0, 0.0, 1.0));
+    color = mix(color, vec3(1.0, 0.0, 0.0), clamp(t - 3.0, 0.0, 1.0));
+    FragColor = vec4(color, 1.0);
+}
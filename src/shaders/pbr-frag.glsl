@@ -20,8 +20,19 @@
 
 precision highp float;
 
-uniform vec3 u_LightDirection;
-uniform vec3 u_LightColor;
+// punctual lights (see `Light` and `MAX_LIGHTS` in light.rs)
+const int MAX_LIGHTS = 8;
+const int LIGHT_DIRECTIONAL = 0;
+const int LIGHT_POINT = 1;
+const int LIGHT_SPOT = 2;
+uniform int u_LightCount;
+uniform int u_LightType[MAX_LIGHTS];
+uniform vec3 u_LightDirection[MAX_LIGHTS]; // towards directional lights, against the spot direction
+uniform vec3 u_LightPosition[MAX_LIGHTS];
+uniform vec3 u_LightColor[MAX_LIGHTS];
+uniform float u_LightRange[MAX_LIGHTS]; // 0: unlimited
+uniform float u_LightConeScale[MAX_LIGHTS];
+uniform float u_LightConeOffset[MAX_LIGHTS];
 
 uniform vec3 u_AmbientLightColor;
 uniform float u_AmbientLightIntensity;
@@ -35,27 +46,45 @@ uniform sampler2D u_brdfLUT;
 #ifdef HAS_BASECOLORMAP
 uniform sampler2D u_BaseColorSampler;
 uniform int u_BaseColorTexCoord;
+uniform mat3 u_BaseColorUVTransform;
 #endif
 #ifdef HAS_NORMALMAP
 uniform sampler2D u_NormalSampler;
 uniform int u_NormalTexCoord;
+uniform mat3 u_NormalUVTransform;
 uniform float u_NormalScale;
 #endif
 #ifdef HAS_EMISSIVEMAP
 uniform sampler2D u_EmissiveSampler;
 uniform int u_EmissiveTexCoord;
-uniform vec3 u_EmissiveFactor;
+uniform mat3 u_EmissiveUVTransform;
 #endif
+// also without a texture (factor-only emissive)
+uniform vec3 u_EmissiveFactor;
 #ifdef HAS_METALROUGHNESSMAP
 uniform sampler2D u_MetallicRoughnessSampler;
 uniform int u_MetallicRoughnessTexCoord;
+uniform mat3 u_MetallicRoughnessUVTransform;
 #endif
 #ifdef HAS_OCCLUSIONMAP
 uniform sampler2D u_OcclusionSampler;
 uniform int u_OcclusionTexCoord;
+uniform mat3 u_OcclusionUVTransform;
 uniform float u_OcclusionStrength;
 #endif
 
+#ifdef USE_SSAO
+// screen-space ambient occlusion, same size as the framebuffer
+uniform sampler2D u_SsaoSampler;
+#endif
+#ifdef USE_SHADOWS
+// depth from the light u_ShadowLight (see `ShadowMap`)
+uniform sampler2DShadow u_ShadowMap;
+uniform mat4 u_LightSpaceMatrix;
+uniform float u_ShadowBias;
+uniform int u_ShadowLight;
+#endif
+
 uniform vec2 u_MetallicRoughnessValues;
 uniform vec4 u_BaseColorFactor;
 
@@ -64,6 +93,12 @@ uniform vec3 u_Camera;
 uniform float u_AlphaBlend;
 uniform float u_AlphaCutoff;
 
+// 0: none, 1: Reinhard, 2: ACES (see `ToneMap`)
+uniform int u_ToneMap;
+uniform float u_Exposure;
+// encode the output for the display (off for the linear float framebuffers of HDR screenshots)
+uniform bool u_SrgbOutput;
+
 // TODO!: remove or ifdef?
 // debugging flags used for shader output of intermediate PBR variables
 uniform vec4 u_ScaleDiffBaseMR;
@@ -74,8 +109,25 @@ in vec3 v_Position;
 
 in vec2 v_UV[2];
 
+// per texture slot: tex coord set + KHR_texture_transform (identity if not given)
+vec2 getUV(int texCoord, mat3 transform)
+{
+    return (transform * vec3(v_UV[texCoord], 1.0)).xy;
+}
+
 in vec4 v_Color;
 
+#ifdef HAS_DEBUG_ATTRIBUTE
+uniform int u_DebugAttributeComponents;
+in vec4 v_DebugAttribute;
+
+// blue -> cyan -> green -> yellow -> red
+vec3 colorRamp(float t)
+{
+    return clamp(vec3(4.0 * t - 2.0, t < 0.5 ? 4.0 * t : 4.0 - 4.0 * t, 2.0 - 4.0 * t), 0.0, 1.0);
+}
+#endif
+
 #ifdef HAS_NORMALS
 #ifdef HAS_TANGENTS
 in mat3 v_TBN;
@@ -134,7 +186,7 @@ vec3 getNormal()
 #endif
 
 #ifdef HAS_NORMALMAP
-    vec3 n = texture(u_NormalSampler, v_UV[u_NormalTexCoord]).rgb;
+    vec3 n = texture(u_NormalSampler, getUV(u_NormalTexCoord, u_NormalUVTransform)).rgb;
     n = normalize(tbn * ((2.0 * n - 1.0) * vec3(u_NormalScale, u_NormalScale, 1.0)));
 #else
     // The tbn matrix is linearly interpolated, so we need to re-normalize
@@ -154,16 +206,16 @@ vec3 getNormal()
 // See our README.md on Environment Maps [3] for additional discussion.
 vec3 getIBLContribution(PBRInfo pbrInputs, vec3 n, vec3 reflection)
 {
-    float mipCount = 9.0; // resolution of 512x512
+    float mipCount = 9.0; // resolution of 512x512 (see `SPECULAR_MIP_LEVELS` of `Environment`)
     float lod = (pbrInputs.perceptualRoughness * mipCount);
     // retrieve a scale and bias to F0. See [1], Figure 3
-    vec3 brdf = texture(u_brdfLUT, vec2(pbrInputs.NdotV, 1.0 - pbrInputs.perceptualRoughness)).rgb;
-    vec3 diffuseLight = textureCube(u_DiffuseEnvSampler, n).rgb;
+    vec3 brdf = texture(u_brdfLUT, vec2(pbrInputs.NdotV, pbrInputs.perceptualRoughness)).rgb;
+    vec3 diffuseLight = texture(u_DiffuseEnvSampler, n).rgb;
 
 #ifdef USE_TEX_LOD
-    vec3 specularLight = textureCubeLodEXT(u_SpecularEnvSampler, reflection, lod).rgb;
+    vec3 specularLight = textureLod(u_SpecularEnvSampler, reflection, lod).rgb;
 #else
-    vec3 specularLight = textureCube(u_SpecularEnvSampler, reflection).rgb;
+    vec3 specularLight = texture(u_SpecularEnvSampler, reflection).rgb;
 #endif
 
     vec3 diffuse = diffuseLight * pbrInputs.diffuseColor;
@@ -177,6 +229,42 @@ vec3 getIBLContribution(PBRInfo pbrInputs, vec3 n, vec3 reflection)
 }
 #endif
 
+// ACES filmic curve (fit by Krzysztof Narkowicz)
+vec3 toneMapACES(vec3 x)
+{
+    const float a = 2.51;
+    const float b = 0.03;
+    const float c = 2.43;
+    const float d = 0.59;
+    const float e = 0.14;
+    return clamp((x * (a * x + b)) / (x * (c * x + d) + e), 0.0, 1.0);
+}
+
+// Maps the HDR shading result to the displayable range
+vec3 toneMap(vec3 color)
+{
+    color *= u_Exposure;
+    if (u_ToneMap == 1) {
+        return color / (1.0 + color);
+    }
+    if (u_ToneMap == 2) {
+        return toneMapACES(color);
+    }
+    return color;
+}
+
+// sRGB transfer functions: color textures (base color, emissive) are sRGB-encoded,
+// the lighting is computed in linear space and encoded again for the display
+vec3 srgbToLinear(vec3 srgb)
+{
+    return mix(srgb / 12.92, pow((srgb + 0.055) / 1.055, vec3(2.4)), step(vec3(0.04045), srgb));
+}
+
+vec3 linearToSrgb(vec3 linear)
+{
+    return mix(linear * 12.92, 1.055 * pow(linear, vec3(1.0 / 2.4)) - 0.055, step(vec3(0.0031308), linear));
+}
+
 // Basic Lambertian diffuse
 // Implementation from Lambert's Photometria https://archive.org/details/lambertsphotome00lambgoog
 // See also [1], Equation 1
@@ -217,6 +305,56 @@ float microfacetDistribution(PBRInfo pbrInputs)
     return roughnessSq / (M_PI * f * f);
 }
 
+// Vector from surface point to light i
+vec3 lightDirection(int i)
+{
+    if (u_LightType[i] == LIGHT_DIRECTIONAL) {
+        return normalize(u_LightDirection[i]);
+    }
+    return normalize(u_LightPosition[i] - v_Position);
+}
+
+// Falloff of point and spot lights with the distance (inverse square law, windowed by the range)
+// and the angle to the spot direction, see the KHR_lights_punctual spec
+float lightAttenuation(int i, vec3 l)
+{
+    if (u_LightType[i] == LIGHT_DIRECTIONAL) {
+        return 1.0;
+    }
+    float distance = length(u_LightPosition[i] - v_Position);
+    float attenuation = 1.0 / max(distance * distance, 0.0001);
+    if (u_LightRange[i] > 0.0) {
+        attenuation *= clamp(1.0 - pow(distance / u_LightRange[i], 4.0), 0.0, 1.0);
+    }
+    if (u_LightType[i] == LIGHT_SPOT) {
+        float cone = clamp(dot(normalize(u_LightDirection[i]), l) * u_LightConeScale[i] + u_LightConeOffset[i], 0.0, 1.0);
+        attenuation *= cone * cone;
+    }
+    return attenuation;
+}
+
+#ifdef USE_SHADOWS
+// Fraction of the 3x3 neighbourhood in the shadow map that is lit (PCF)
+float shadowFactor(vec3 n, vec3 l)
+{
+    vec4 lightSpace = u_LightSpaceMatrix * vec4(v_Position, 1.0);
+    vec3 coords = lightSpace.xyz / lightSpace.w * 0.5 + 0.5;
+    if (coords.z > 1.0) {
+        return 1.0;
+    }
+    // against acne: more bias for surfaces at grazing angles to the light
+    float bias = u_ShadowBias * mix(1.0, 3.0, 1.0 - clamp(dot(n, l), 0.0, 1.0));
+    vec2 texel = 1.0 / vec2(textureSize(u_ShadowMap, 0));
+    float lit = 0.0;
+    for (int x = -1; x <= 1; ++x) {
+        for (int y = -1; y <= 1; ++y) {
+            lit += texture(u_ShadowMap, vec3(coords.xy + vec2(x, y) * texel, coords.z - bias));
+        }
+    }
+    return lit / 9.0;
+}
+#endif
+
 void main()
 {
     // Metallic and Roughness material properties are packed together
@@ -227,7 +365,7 @@ void main()
 #ifdef HAS_METALROUGHNESSMAP
     // Roughness is stored in the 'g' channel, metallic is stored in the 'b' channel.
     // This layout intentionally reserves the 'r' channel for (optional) occlusion map data
-    vec4 mrSample = texture(u_MetallicRoughnessSampler, v_UV[u_MetallicRoughnessTexCoord]);
+    vec4 mrSample = texture(u_MetallicRoughnessSampler, getUV(u_MetallicRoughnessTexCoord, u_MetallicRoughnessUVTransform));
     perceptualRoughness = mrSample.g * perceptualRoughness;
     metallic = mrSample.b * metallic;
 #endif
@@ -239,7 +377,8 @@ void main()
 
     // The albedo may be defined from a base texture or a flat color
 #ifdef HAS_BASECOLORMAP
-    vec4 baseColor = texture(u_BaseColorSampler, v_UV[u_BaseColorTexCoord]) * u_BaseColorFactor;
+    vec4 baseColorSample = texture(u_BaseColorSampler, getUV(u_BaseColorTexCoord, u_BaseColorUVTransform));
+    vec4 baseColor = vec4(srgbToLinear(baseColorSample.rgb), baseColorSample.a) * u_BaseColorFactor;
 #else
     vec4 baseColor = u_BaseColorFactor;
 #endif
@@ -262,22 +401,21 @@ void main()
 
     vec3 n = getNormal();                             // normal at surface point
     vec3 v = normalize(u_Camera - v_Position);        // Vector from surface point to camera
-    vec3 l = normalize(u_LightDirection);             // Vector from surface point to light
-    vec3 h = normalize(l+v);                          // Half vector between both l and v
+#ifdef FORCE_TWO_SIDED_NORMALS
+    // thin surfaces without doubleSided: shade the side facing away from the (first) light as if lit
+    n *= sign(dot(n, lightDirection(0)) + 1e-6);
+#endif
     vec3 reflection = -normalize(reflect(v, n));
 
-    float NdotL = clamp(dot(n, l), 0.001, 1.0);
     float NdotV = clamp(abs(dot(n, v)), 0.001, 1.0);
-    float NdotH = clamp(dot(n, h), 0.0, 1.0);
-    float LdotH = clamp(dot(l, h), 0.0, 1.0);
-    float VdotH = clamp(dot(v, h), 0.0, 1.0);
 
+    // the light-dependent terms are set per light below
     PBRInfo pbrInputs = PBRInfo(
-        NdotL,
+        0.0,
         NdotV,
-        NdotH,
-        LdotH,
-        VdotH,
+        0.0,
+        0.0,
+        0.0,
         perceptualRoughness,
         metallic,
         specularEnvironmentR0,
@@ -287,33 +425,61 @@ void main()
         specularColor
     );
 
-    // Calculate the shading terms for the microfacet specular shading model
-    vec3 F = specularReflection(pbrInputs);
-    float G = geometricOcclusion(pbrInputs);
-    float D = microfacetDistribution(pbrInputs);
-
-    // Calculation of analytical lighting contribution
-    vec3 diffuseContrib = (1.0 - F) * diffuse(pbrInputs);
-    vec3 specContrib = F * G * D / (4.0 * NdotL * NdotV);
-    vec3 color = NdotL * u_LightColor * (diffuseContrib + specContrib);
+    vec3 color = vec3(0.0);
+    for (int i = 0; i < u_LightCount; ++i) {
+        vec3 l = lightDirection(i);
+        vec3 h = normalize(l+v);                      // Half vector between both l and v
+        pbrInputs.NdotL = clamp(dot(n, l), 0.001, 1.0);
+        pbrInputs.NdotH = clamp(dot(n, h), 0.0, 1.0);
+        pbrInputs.LdotH = clamp(dot(l, h), 0.0, 1.0);
+        pbrInputs.VdotH = clamp(dot(v, h), 0.0, 1.0);
+
+        // Calculate the shading terms for the microfacet specular shading model
+        vec3 F = specularReflection(pbrInputs);
+        float G = geometricOcclusion(pbrInputs);
+        float D = microfacetDistribution(pbrInputs);
+
+        // Calculation of analytical lighting contribution
+        vec3 diffuseContrib = (1.0 - F) * diffuse(pbrInputs);
+        vec3 specContrib = F * G * D / (4.0 * pbrInputs.NdotL * NdotV);
+        float attenuation = lightAttenuation(i, l);
+#ifdef USE_SHADOWS
+        if (i == u_ShadowLight) {
+            attenuation *= shadowFactor(n, l);
+        }
+#endif
+        color += pbrInputs.NdotL * attenuation * u_LightColor[i] * (diffuseContrib + specContrib);
+    }
 
     // Calculate lighting contribution from image based lighting source (IBL)
 #ifdef USE_IBL
-    color += getIBLContribution(pbrInputs, n, reflection);
+    vec3 ambient = getIBLContribution(pbrInputs, n, reflection);
 #else
     // Add simple ambient light
-    color += u_AmbientLightColor * u_AmbientLightIntensity * baseColor.xyz;
+    vec3 ambient = u_AmbientLightColor * u_AmbientLightIntensity * baseColor.xyz;
+#endif
+#ifdef USE_SSAO
+    ambient *= texelFetch(u_SsaoSampler, ivec2(gl_FragCoord.xy), 0).r;
 #endif
 
-    // Apply optional PBR terms for additional (optional) shading
+    // the occlusion map only darkens the indirect (ambient/IBL) light: 1 + strength * (ao - 1)
 #ifdef HAS_OCCLUSIONMAP
-    float ao = texture(u_OcclusionSampler, v_UV[u_OcclusionTexCoord]).r;
-    color = mix(color, color * ao, u_OcclusionStrength);
+    float ao = texture(u_OcclusionSampler, getUV(u_OcclusionTexCoord, u_OcclusionUVTransform)).r;
+    ambient = mix(ambient, ambient * ao, u_OcclusionStrength);
 #endif
+    color += ambient;
 
+    // not affected by the lights (may exceed 1.0 with KHR_materials_emissive_strength)
 #ifdef HAS_EMISSIVEMAP
-    vec3 emissive = texture(u_EmissiveSampler, v_UV[u_EmissiveTexCoord]).rgb * u_EmissiveFactor;
+    vec3 emissive = srgbToLinear(texture(u_EmissiveSampler, getUV(u_EmissiveTexCoord, u_EmissiveUVTransform)).rgb) * u_EmissiveFactor;
+#else
+    vec3 emissive = u_EmissiveFactor;
+#endif
     color += emissive;
+
+#ifdef UNLIT
+    // KHR_materials_unlit: base color (incl. vertex color) only, no lighting
+    color = baseColor.rgb;
 #endif
 
     // // This section uses mix to override final color for reference app visualization
@@ -338,7 +504,18 @@ void main()
         discard;
     }
 
-    // TODO!: apply fix from reference shader:
-    // https://github.com/KhronosGroup/glTF-WebGL-PBR/pull/55/files#diff-f7232333b020880432a925d5a59e075d
+    color = toneMap(color);
+    if (u_SrgbOutput) {
+        color = linearToSrgb(color);
+    }
     FragColor = vec4(color, alpha);
+
+#ifdef HAS_DEBUG_ATTRIBUTE
+    // scalars are mapped to a color ramp, vectors are shown as RGB
+    if (u_DebugAttributeComponents == 1) {
+        FragColor = vec4(colorRamp(v_DebugAttribute.x), 1.0);
+    } else {
+        FragColor = vec4(v_DebugAttribute.rgb, 1.0);
+    }
+#endif
 }
@@ -13,14 +13,49 @@ layout (location = 2) in vec4 a_Tangent;
 layout (location = 3) in vec2 a_UV_0; // TEXCOORD_0
 layout (location = 4) in vec2 a_UV_1; // TEXCOORD_1
 #endif
-// TODO!: tex_coord_1, joints_0, weights_0
 #ifdef HAS_COLORS
 layout (location = 5) in vec4 a_Color; // COLOR_0
 #endif
+#ifdef HAS_SKINNING
+layout (location = 6) in vec4 a_Joint; // JOINTS_0
+layout (location = 7) in vec4 a_Weight; // WEIGHTS_0
+#endif
+#ifdef HAS_DEBUG_ATTRIBUTE
+layout (location = 8) in vec4 a_DebugAttribute; // --debug-attribute, normalized to [0, 1]
+out vec4 v_DebugAttribute;
+#endif
+// EXT_mesh_gpu_instancing: per-instance transform and tint (--instance-color),
+// constant identity + white when not instanced (see instancing.rs)
+layout (location = 9) in mat4 a_InstanceMatrix; // locations 9-12
+layout (location = 13) in vec4 a_InstanceColor;
 
 uniform mat4 u_MVPMatrix;
 uniform mat4 u_ModelMatrix;
 
+#ifdef HAS_MORPH_TARGETS
+#define MAX_MORPH_TARGETS 8 // see primitive.rs
+// (position, normal, tangent) displacement per target and vertex
+uniform samplerBuffer u_MorphTargets;
+uniform int u_MorphTargetCount;
+uniform int u_MorphVertexCount;
+uniform float u_MorphWeights[MAX_MORPH_TARGETS];
+#endif
+
+#ifdef HAS_SKINNING
+// 4 texels (the columns) per joint, see `JointMatrices`
+uniform samplerBuffer u_JointMatrices;
+
+mat4 jointMatrix(float joint)
+{
+  int texel = int(joint) * 4;
+  return mat4(
+    texelFetch(u_JointMatrices, texel),
+    texelFetch(u_JointMatrices, texel + 1),
+    texelFetch(u_JointMatrices, texel + 2),
+    texelFetch(u_JointMatrices, texel + 3));
+}
+#endif
+
 out vec3 v_Position;
 out vec2 v_UV[2];
 out vec4 v_Color;
@@ -36,18 +71,57 @@ out vec3 v_Normal;
 
 void main()
 {
-  vec4 pos = u_ModelMatrix * a_Position;
+  vec4 position = a_Position;
+  #ifdef HAS_NORMALS
+  vec3 normal = a_Normal.xyz;
+  #endif
+  #ifdef HAS_TANGENTS
+  vec3 tangent = a_Tangent.xyz;
+  #endif
+
+  #ifdef HAS_MORPH_TARGETS
+  for (int i = 0; i < u_MorphTargetCount; i++) {
+    int texel = (i * u_MorphVertexCount + gl_VertexID) * 3;
+    float weight = u_MorphWeights[i];
+    position.xyz += weight * texelFetch(u_MorphTargets, texel).xyz;
+    #ifdef HAS_NORMALS
+    normal += weight * texelFetch(u_MorphTargets, texel + 1).xyz;
+    #endif
+    #ifdef HAS_TANGENTS
+    tangent += weight * texelFetch(u_MorphTargets, texel + 2).xyz;
+    #endif
+  }
+  #endif
+
+  #ifdef HAS_SKINNING
+  // linear blend skinning (after morphing, like the reference implementation)
+  mat4 skinMatrix =
+    a_Weight.x * jointMatrix(a_Joint.x) +
+    a_Weight.y * jointMatrix(a_Joint.y) +
+    a_Weight.z * jointMatrix(a_Joint.z) +
+    a_Weight.w * jointMatrix(a_Joint.w);
+  position = skinMatrix * position;
+  #ifdef HAS_NORMALS
+  normal = mat3(skinMatrix) * normal;
+  #endif
+  #ifdef HAS_TANGENTS
+  tangent = mat3(skinMatrix) * tangent;
+  #endif
+  #endif
+
+  mat4 modelMatrix = u_ModelMatrix * a_InstanceMatrix;
+  vec4 pos = modelMatrix * position;
   v_Position = vec3(pos.xyz) / pos.w;
 
   #ifdef HAS_NORMALS
   #ifdef HAS_TANGENTS
   // TODO!: the reference shader was updated to use the normal matrix here
-  vec3 normalW = normalize(vec3(u_ModelMatrix * vec4(a_Normal.xyz, 0.0)));
-  vec3 tangentW = normalize(vec3(u_ModelMatrix * vec4(a_Tangent.xyz, 0.0)));
+  vec3 normalW = normalize(vec3(modelMatrix * vec4(normal, 0.0)));
+  vec3 tangentW = normalize(vec3(modelMatrix * vec4(tangent, 0.0)));
   vec3 bitangentW = cross(normalW, tangentW) * a_Tangent.w;
   v_TBN = mat3(tangentW, bitangentW, normalW);
   #else // HAS_TANGENTS != 1
-  v_Normal = normalize(vec3(u_ModelMatrix * vec4(a_Normal.xyz, 0.0)));
+  v_Normal = normalize(vec3(modelMatrix * vec4(normal, 0.0)));
   #endif
   #endif
 
@@ -60,12 +134,16 @@ void main()
   #endif
 
   #ifdef HAS_COLORS
-  v_Color = a_Color;
+  v_Color = a_Color * a_InstanceColor;
   #else
-  v_Color = vec4(1.0);
+  v_Color = a_InstanceColor;
+  #endif
+
+  #ifdef HAS_DEBUG_ATTRIBUTE
+  v_DebugAttribute = a_DebugAttribute;
   #endif
 
-  gl_Position = u_MVPMatrix * a_Position; // needs w for proper perspective correction
+  gl_Position = u_MVPMatrix * a_InstanceMatrix * position; // needs w for proper perspective correction
 }
 
 
@@ -0,0 +1,20 @@
+#version 330 core
+// 4x4 box blur of the ambient occlusion, removing the pattern of the (4x4) noise texture
+
+uniform sampler2D u_OcclusionSampler;
+
+in vec2 v_UV;
+
+out float FragColor;
+
+void main()
+{
+    vec2 texelSize = 1.0 / vec2(textureSize(u_OcclusionSampler, 0));
+    float result = 0.0;
+    for (int x = -2; x < 2; ++x) {
+        for (int y = -2; y < 2; ++y) {
+            result += texture(u_OcclusionSampler, v_UV + vec2(float(x), float(y)) * texelSize).r;
+        }
+    }
+    FragColor = result / 16.0;
+}
@@ -0,0 +1,59 @@
+#version 330 core
+// Screen-space ambient occlusion: hemisphere sampling around the view space position
+// reconstructed from the depth buffer (normals reconstructed from the position derivatives).
+// See also https://learnopengl.com/Advanced-Lighting/SSAO
+
+const int KERNEL_SIZE = 16;
+
+uniform sampler2D u_DepthSampler;
+// 4x4 random rotations (tiled over the screen)
+uniform sampler2D u_NoiseSampler;
+
+uniform mat4 u_ProjectionMatrix;
+uniform mat4 u_InverseProjectionMatrix;
+// sample offsets in the unit hemisphere around +Z
+uniform vec3 u_Kernel[KERNEL_SIZE];
+// view space
+uniform float u_Radius;
+uniform float u_Intensity;
+
+in vec2 v_UV;
+
+out float FragColor;
+
+vec3 viewPosition(vec2 uv)
+{
+    float depth = texture(u_DepthSampler, uv).r;
+    vec4 position = u_InverseProjectionMatrix * vec4(vec3(uv, depth) * 2.0 - 1.0, 1.0);
+    return position.xyz / position.w;
+}
+
+void main()
+{
+    if (texture(u_DepthSampler, v_UV).r == 1.0) {
+        // background
+        FragColor = 1.0;
+        return;
+    }
+    vec3 position = viewPosition(v_UV);
+    vec3 normal = normalize(cross(dFdx(position), dFdy(position)));
+
+    vec2 noiseScale = vec2(textureSize(u_DepthSampler, 0)) / 4.0;
+    vec3 random = texture(u_NoiseSampler, v_UV * noiseScale).xyz;
+    vec3 tangent = normalize(random - normal * dot(random, normal));
+    vec3 bitangent = cross(normal, tangent);
+    mat3 tbn = mat3(tangent, bitangent, normal);
+
+    float occlusion = 0.0;
+    for (int i = 0; i < KERNEL_SIZE; ++i) {
+        vec3 samplePosition = position + tbn * u_Kernel[i] * u_Radius;
+        vec4 offset = u_ProjectionMatrix * vec4(samplePosition, 1.0);
+        offset.xy = offset.xy / offset.w * 0.5 + 0.5;
+        float sampleDepth = viewPosition(offset.xy).z;
+        // ignore occluders far outside of the radius (e.g. silhouette edges)
+        float rangeCheck = smoothstep(0.0, 1.0, u_Radius / abs(position.z - sampleDepth));
+        float bias = 0.025 * u_Radius;
+        occlusion += (sampleDepth >= samplePosition.z + bias ? 1.0 : 0.0) * rangeCheck;
+    }
+    FragColor = clamp(1.0 - occlusion / float(KERNEL_SIZE) * u_Intensity, 0.0, 1.0);
+}
@@ -0,0 +1,17 @@
+#version 330 core
+// Loading preview: the thumbnail centered in the window, keeping its aspect ratio
+
+in vec2 v_UV;
+out vec4 FragColor;
+
+uniform sampler2D u_ThumbnailSampler;
+uniform vec2 u_Scale; // size of the thumbnail relative to the window
+
+void main()
+{
+    vec2 uv = (v_UV - 0.5) / u_Scale + 0.5;
+    if (any(lessThan(uv, vec2(0.0))) || any(greaterThan(uv, vec2(1.0)))) {
+        discard;
+    }
+    FragColor = texture(u_ThumbnailSampler, vec2(uv.x, 1.0 - uv.y)); // image rows are top to bottom
+}
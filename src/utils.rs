@@ -1,5 +1,6 @@
 #![macro_use]
 
+use std::cell::Cell;
 use std::ffi::CStr;
 use std::mem;
 use std::time::{Duration, Instant};
@@ -7,7 +8,7 @@ use std::time::{Duration, Instant};
 use gl;
 use gl::types::GLubyte;
 
-use log::{debug, info, error};
+use log::{debug, info, warn, error};
 
 pub fn elapsed(start_time: Instant) -> String {
     let elapsed = start_time.elapsed();
@@ -81,6 +82,135 @@ impl FrameTimer {
     }
 }
 
+/// Optional OpenGL features the viewer can do without (beyond the OpenGL 3.3 core profile
+/// it requests, drivers may lack extensions or entry points)
+#[derive(Clone, Copy, Debug)]
+pub struct Capabilities {
+    /// `--anisotropy`
+    pub anisotropic_filtering: bool,
+    /// GPU time in the HUD (otherwise the CPU time until the GPU is done)
+    pub timer_queries: bool,
+    /// `EXT_mesh_gpu_instancing` with a single draw call (otherwise one per instance)
+    pub instancing: bool,
+    /// block compression formats of KTX2 textures (`KHR_texture_basisu`) that can be uploaded directly:
+    /// BC1/BC3, BC7 and ETC2
+    pub s3tc: bool,
+    pub bptc: bool,
+    pub etc2: bool,
+}
+
+thread_local! {
+    // per thread, like the OpenGL context
+    static CAPABILITIES: Cell<Option<Capabilities>> = const { Cell::new(None) };
+}
+
+impl Capabilities {
+    /// The capabilities of the current OpenGL context (queried on first use, warning about missing ones)
+    pub fn get() -> Capabilities {
+        CAPABILITIES.with(|capabilities| {
+            capabilities.get().unwrap_or_else(|| {
+                let queried = unsafe { Self::query() };
+                capabilities.set(Some(queried));
+                queried
+            })
+        })
+    }
+
+    unsafe fn query() -> Capabilities {
+        let (mut major, mut minor) = (0, 0);
+        gl::GetIntegerv(gl::MAJOR_VERSION, &mut major);
+        gl::GetIntegerv(gl::MINOR_VERSION, &mut minor);
+        let version = (major, minor);
+        let extensions = gl_extensions();
+        let has_extension = |name: &str| extensions.iter().any(|extension| extension == name);
+
+        let capabilities = Capabilities {
+            anisotropic_filtering: version >= (4, 6) ||
+                has_extension("GL_EXT_texture_filter_anisotropic") ||
+                has_extension("GL_ARB_texture_filter_anisotropic"),
+            timer_queries: (version >= (3, 3) || has_extension("GL_ARB_timer_query")) &&
+                gl::BeginQuery::is_loaded() && gl::GetQueryObjectui64v::is_loaded(),
+            instancing: gl::VertexAttribDivisor::is_loaded() &&
+                gl::DrawElementsInstanced::is_loaded() && gl::DrawArraysInstanced::is_loaded(),
+            s3tc: has_extension("GL_EXT_texture_compression_s3tc"),
+            bptc: version >= (4, 2) || has_extension("GL_ARB_texture_compression_bptc"),
+            etc2: version >= (4, 3) || has_extension("GL_ARB_ES3_compatibility"),
+        };
+        if !capabilities.timer_queries {
+            warn!("OpenGL timer queries are not supported, measuring the CPU time until the GPU is done instead");
+        }
+        if !capabilities.instancing {
+            warn!("OpenGL instanced drawing is not supported, drawing mesh instances one by one");
+        }
+        debug!("Capabilities : {:?}", capabilities);
+        capabilities
+    }
+}
+
+/// Measures GPU time with two alternating timer queries,
+/// so that reading the result of the previous frame doesn't stall
+pub struct GpuTimer {
+    // `None`: no timer queries (see `Capabilities`), measure the CPU time until `glFinish` instead
+    queries: Option<[u32; 2]>,
+    used: [bool; 2],
+    current: usize,
+    cpu_start: Instant,
+    /// most recent result
+    pub last: Duration,
+}
+
+impl GpuTimer {
+    pub fn new() -> GpuTimer {
+        let queries = if Capabilities::get().timer_queries {
+            let mut queries = [0; 2];
+            unsafe { gl::GenQueries(2, queries.as_mut_ptr()) }
+            Some(queries)
+        } else {
+            None
+        };
+        GpuTimer {
+            queries,
+            used: [false; 2],
+            current: 0,
+            cpu_start: Instant::now(),
+            last: Duration::from_secs(0),
+        }
+    }
+
+    pub unsafe fn start(&mut self) {
+        match self.queries {
+            Some(queries) => gl::BeginQuery(gl::TIME_ELAPSED, queries[self.current]),
+            None => self.cpu_start = Instant::now(),
+        }
+    }
+
+    pub unsafe fn end(&mut self) {
+        let queries = match self.queries {
+            Some(queries) => queries,
+            None => {
+                gl::Finish();
+                self.last = self.cpu_start.elapsed();
+                return
+            }
+        };
+        gl::EndQuery(gl::TIME_ELAPSED);
+        self.used[self.current] = true;
+        self.current = 1 - self.current;
+
+        // read the other query (started one frame earlier) if it's ready
+        let query = queries[self.current];
+        if self.used[self.current] {
+            let mut available = 0;
+            gl::GetQueryObjectiv(query, gl::QUERY_RESULT_AVAILABLE, &mut available);
+            if available != 0 {
+                let mut nanos = 0;
+                gl::GetQueryObjectui64v(query, gl::QUERY_RESULT, &mut nanos);
+                self.last = Duration::from_nanos(nanos);
+            }
+        }
+    }
+}
+
 pub unsafe fn gl_check_error(file: &str, line: u32) -> u32 {
     let mut error_code = gl::GetError();
     while error_code != gl::NO_ERROR {
@@ -138,12 +268,15 @@ pub unsafe fn print_context_info() {
     };
     debug!("Context Flags: Debug: {}, Forward Compatible: {}", debug, forward_compatible);
 
+    debug!("Extensions   : {}", gl_extensions().join(", "))
+}
+
+unsafe fn gl_extensions() -> Vec<String> {
     let mut num_extensions = 0;
     gl::GetIntegerv(gl::NUM_EXTENSIONS, &mut num_extensions);
-    let extensions: Vec<_> = (0 .. num_extensions).map(|num| {
+    (0 .. num_extensions).map(|num| {
         gl_string(gl::GetStringi(gl::EXTENSIONS, num as gl::types::GLuint))
-    }).collect();
-    debug!("Extensions   : {}", extensions.join(", "))
+    }).collect()
 }
 
 pub unsafe fn gl_string(raw_string: *const GLubyte) -> String {
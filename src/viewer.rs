@@ -1,10 +1,16 @@
 use std::f32::consts::PI;
+use std::io::{self, BufRead};
 use std::os::raw::c_void;
+use std::fs;
 use std::path::Path;
 use std::process;
-use std::time::Instant;
+use std::rc::Rc;
+use std::str::FromStr;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
 
-use cgmath::{ Deg, Point3 };
+use cgmath::{ Deg, Point3, Rad };
 use collision::Aabb;
 use gl;
 use gltf;
@@ -16,6 +22,8 @@ use glutin::{
     GlContext,
     GlRequest,
     GlProfile,
+    Touch,
+    TouchPhase,
     VirtualKeyCode,
     WindowEvent,
 };
@@ -24,14 +32,20 @@ use glutin::ElementState::*;
 
 use image::{DynamicImage};
 use log::{error, warn, info};
+use num_traits::clamp;
 
-use crate::controls::{OrbitControls, NavState};
+use crate::audit::Audit;
+use crate::commands::{self, CameraUpdate, Command};
+use crate::controls::{CameraParams, OrbitControls, NavState, SmoothZoom};
 use crate::controls::CameraMovement::*;
 use crate::framebuffer::Framebuffer;
 use crate::importdata::ImportData;
+use crate::layout::Layout;
+use crate::playlist::Playlist;
 use crate::render::*;
 use crate::render::math::*;
-use crate::utils::{print_elapsed, FrameTimer, gl_check_error, print_context_info};
+use crate::screenshot::{self, ColorSpace};
+use crate::utils::{print_elapsed, FrameTimer, GpuTimer, gl_check_error, print_context_info};
 
 // TODO!: complete and pass through draw calls? or get rid of multiple shaders?
 // How about state ordering anyway?
@@ -40,13 +54,316 @@ use crate::utils::{print_elapsed, FrameTimer, gl_check_error, print_context_info
 //     back_face_culling_enabled: bool
 // }
 
+/// Size of the window (headless: of the framebuffer), see the note about `headless` and `visible`
+/// on `GltfViewer`
+#[derive(Copy, Clone)]
+pub struct WindowOptions {
+    pub width: u32,
+    pub height: u32,
+    pub headless: bool,
+    pub visible: bool,
+}
+
 #[derive(Copy, Clone)]
 pub struct CameraOptions {
     pub index: i32,
     pub position: Option<Vector3>,
     pub target: Option<Vector3>,
+    /// Up vector to orbit around (default: Y)
+    pub up: Option<Vector3>,
     pub fovy: Deg<f32>,
     pub straight: bool,
+    /// Keyboard movement at a constant height (see `OrbitControls::walk_mode`)
+    pub walk: bool,
+    /// Minimum camera height (Y)
+    pub ground_plane: Option<f32>,
+    /// Increment for snapped orbit rotation (Alt + drag)
+    pub snap_angle: Deg<f32>,
+    /// Endless turntable rotation (rotations per minute)
+    pub rpm: Option<f32>,
+    /// Distance factor per mouse wheel step (see `OrbitControls::zoom_factor`)
+    pub zoom_factor: f32,
+    /// Mouse sensitivity multipliers (see `OrbitControls::rotate_speed`)
+    pub rotate_speed: f32,
+    pub pan_speed: f32,
+    pub zoom_speed: f32,
+    /// Zoom by changing the field of view instead of the distance
+    pub fov_zoom: bool,
+    /// Ease scroll zooming to a stop instead of jumping
+    pub smooth_zoom: Option<SmoothZoom>,
+    /// Let rotating and panning coast to a stop, losing this part of the motion per frame
+    pub damping: Option<f32>,
+    /// Range of the vertical orbit angle (0: looking straight down)
+    pub polar_limits: Option<(Deg<f32>, Deg<f32>)>,
+    /// Range of the distance to the orbit target when zooming
+    pub min_distance: f32,
+    pub max_distance: f32,
+}
+
+#[derive(Clone)]
+pub struct RenderOptions {
+    /// Sort opaque primitives front-to-back (and blended ones back-to-front) before drawing
+    pub sort_front_to_back: bool,
+    /// Default: the environment if there is one, a dark blue otherwise (transparent for headless screenshots)
+    pub background: Option<Background>,
+    /// Initial wireframe mode
+    pub wireframe: WireframeMode,
+    /// Color of the wireframe overlays
+    pub wire_color: Vector3,
+    /// Line width (pixels) of the wireframe and the debug overlays
+    pub wire_width: f32,
+    /// SSAO sample radius in world units (default: 5% of the model diagonal)
+    pub ssao_radius: Option<f32>,
+    /// Strength of the SSAO darkening (1: full)
+    pub ssao_intensity: f32,
+    /// Depth bias of the shadow map lookups against acne (too large: shadows detach from their casters),
+    /// as a fraction of the scene depth seen from the light
+    pub shadow_bias: f32,
+    /// Samples per pixel for multisample anti-aliasing (0 or 1: off)
+    pub msaa: u32,
+    /// Resolution of the scene rendering relative to the window (upscaled with `upscale`)
+    pub render_scale: f32,
+    pub upscale: UpscaleFilter,
+    /// Clear the window immediately after resizing (see `GltfViewer::resize`)
+    pub clear_on_resize: bool,
+    /// Mapping of the HDR shading to the display, after multiplying with `exposure`
+    pub tone_map: ToneMap,
+    pub exposure: f32,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            sort_front_to_back: false,
+            background: None,
+            wireframe: WireframeMode::Off,
+            wire_color: vec3(1.0, 1.0, 1.0),
+            wire_width: 1.0,
+            ssao_radius: None,
+            ssao_intensity: 1.0,
+            shadow_bias: 0.005,
+            msaa: 0,
+            render_scale: 1.0,
+            upscale: UpscaleFilter::Linear,
+            clear_on_resize: true,
+            tone_map: ToneMap::Aces,
+            exposure: 1.0,
+        }
+    }
+}
+
+/// Filter for upscaling to the window with a `render_scale` below 1
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UpscaleFilter {
+    /// chunky pixels
+    Nearest,
+    Linear,
+}
+
+impl FromStr for UpscaleFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nearest" => Ok(UpscaleFilter::Nearest),
+            "linear" => Ok(UpscaleFilter::Linear),
+            _ => Err(format!("unknown upscale filter: {}", s)),
+        }
+    }
+}
+
+/// Behind the scene (`--background`)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Background {
+    /// clear color (display-referred RGBA): `r,g,b` or `r,g,b,a`
+    Color(Vector4),
+    /// vertical gradient: `r,g,b:r,g,b` (top:bottom)
+    Gradient { top: Vector3, bottom: Vector3 },
+    /// `skybox`: the environment (requires `LoadOptions::environment`)
+    Skybox,
+}
+
+impl FromStr for Background {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let components = |color: &str| -> Result<Vec<f32>, String> {
+            color.split(',')
+                .map(|c| c.trim().parse::<f32>().map_err(|err| format!("invalid color '{}': {}", color, err)))
+                .collect()
+        };
+        if s == "skybox" {
+            return Ok(Background::Skybox)
+        }
+        if let Some(index) = s.find(':') {
+            let (top, bottom) = (components(&s[..index])?, components(&s[index + 1..])?);
+            return match (top.len(), bottom.len()) {
+                (3, 3) => Ok(Background::Gradient {
+                    top: vec3(top[0], top[1], top[2]),
+                    bottom: vec3(bottom[0], bottom[1], bottom[2]),
+                }),
+                _ => Err("gradient colors must have 3 components".to_owned()),
+            }
+        }
+        let c = components(s)?;
+        match c.len() {
+            3 => Ok(Background::Color(vec4(c[0], c[1], c[2], 1.0))),
+            4 => Ok(Background::Color(vec4(c[0], c[1], c[2], c[3]))),
+            _ => Err("expected 3 or 4 comma-separated values, 'r,g,b:r,g,b' or 'skybox'".to_owned()),
+        }
+    }
+}
+
+/// Tone mapping operator (`--tonemap`)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ToneMap {
+    /// clamped (also used for HDR screenshots)
+    None,
+    Reinhard,
+    /// filmic, like most glTF reference viewers
+    Aces,
+}
+
+impl FromStr for ToneMap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(ToneMap::None),
+            "reinhard" => Ok(ToneMap::Reinhard),
+            "aces" => Ok(ToneMap::Aces),
+            _ => Err(format!("unknown tone mapping operator: {}", s)),
+        }
+    }
+}
+
+impl ToneMap {
+    /// `u_ToneMap` of the PBR shader
+    pub fn shader_index(self) -> i32 {
+        match self {
+            ToneMap::None => 0,
+            ToneMap::Reinhard => 1,
+            ToneMap::Aces => 2,
+        }
+    }
+}
+
+/// Wireframe rendering (`--wireframe`, cycled with F2)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WireframeMode {
+    Off,
+    Wireframe,
+    /// lines on top of the shaded scene
+    WireOnShaded,
+}
+
+impl FromStr for WireframeMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(WireframeMode::Off),
+            "on" => Ok(WireframeMode::Wireframe),
+            "overlay" => Ok(WireframeMode::WireOnShaded),
+            _ => Err(format!("unknown wireframe mode: {}", s)),
+        }
+    }
+}
+
+impl WireframeMode {
+    fn next(self) -> Self {
+        match self {
+            WireframeMode::Off => WireframeMode::Wireframe,
+            WireframeMode::Wireframe => WireframeMode::WireOnShaded,
+            WireframeMode::WireOnShaded => WireframeMode::Off,
+        }
+    }
+}
+
+/// Up axis of the model (glTF is Y-up by definition, but some exporters write Z-up)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UpAxis {
+    /// Y-up unless the file hints otherwise (see `ImportData::up_axis`)
+    Auto,
+    Y,
+    Z,
+}
+
+impl FromStr for UpAxis {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(UpAxis::Auto),
+            "y" => Ok(UpAxis::Y),
+            "z" => Ok(UpAxis::Z),
+            _ => Err(format!("unknown up axis: {}", s)),
+        }
+    }
+}
+
+impl UpAxis {
+    /// Rotation of the scene root to make the model Y-up
+    pub fn root_transform(self) -> Matrix4 {
+        match self {
+            // -90 degrees about X: Z-up -> Y-up
+            UpAxis::Z => Matrix4::from_angle_x(Deg(-90.0)),
+            UpAxis::Y | UpAxis::Auto => Matrix4::identity(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct LoadOptions {
+    /// glTF index of the scene to load (`None`: the default scene of the file, or the first one)
+    pub scene: Option<usize>,
+    /// Abort loading if the scene has more triangles
+    pub max_triangles: Option<usize>,
+    /// Name of a vertex attribute (e.g. `_BATCHID`) to visualize instead of shading
+    pub debug_attribute: Option<String>,
+    /// `EXT_mesh_gpu_instancing` attribute (e.g. `_COLOR`) to tint each instance with
+    pub instance_color_attribute: String,
+    /// Flip normals towards the light for all materials (rescue for thin surfaces missing `doubleSided`)
+    pub force_two_sided_normals: bool,
+    /// Rotate the scene with this up axis to Y-up
+    pub up: UpAxis,
+    /// Placement of the additional models when loading several files
+    pub layout: Layout,
+    /// `MSFT_lod` level to draw (0: highest detail)
+    pub lod: usize,
+    /// Print the declared but unused resources after loading
+    pub audit: bool,
+    /// Memory-map .glb files instead of reading them into memory (falls back for other files)
+    pub mmap: bool,
+    /// Overrides for the spec default material (white, metallic 1, roughness 1) of primitives without one
+    pub default_color: Option<Vector3>,
+    pub default_metallic: Option<f32>,
+    pub default_roughness: Option<f32>,
+    /// Flip the winding of triangle primitives whose vertex normals mostly point the other way
+    pub auto_fix_winding: bool,
+    /// Generate averaged per-vertex normals for triangle primitives without NORMALs
+    /// (otherwise they are shaded with flat normals)
+    pub smooth_normals: bool,
+    /// Maximum anisotropy of texture filtering (e.g. 16, clamped to the supported maximum)
+    pub anisotropy: Option<f32>,
+    /// Texture mipmap level bias (negative: sharper, positive: softer)
+    pub mip_bias: f32,
+    /// Screen-space ambient occlusion (compiled into the PBR shaders, see `RenderOptions::ssao_*`)
+    pub ssao: bool,
+    /// Equirectangular `.hdr` panorama for image-based lighting (compiled into the PBR shaders);
+    /// without one, a constant ambient light is used
+    pub environment: Option<String>,
+    /// Shadow map resolution for shadows of the primary light (compiled into the PBR shaders,
+    /// see `RenderOptions::shadow_bias`)
+    pub shadows: Option<u32>,
+}
+
+/// A model loaded together with the first one (`gltf-viewer a.glb b.glb`), with its own
+/// materials and animations and placed next to it according to `LoadOptions::layout`
+struct Model {
+    root: Root,
+    scene: Scene,
+    animator: Animator,
 }
 
 pub struct GltfViewer {
@@ -54,34 +371,107 @@ pub struct GltfViewer {
     dpi_factor: f64,
 
     orbit_controls: OrbitControls,
+    camera_options: CameraOptions,
+    load_options: LoadOptions,
+    render_options: RenderOptions,
     events_loop: Option<glutin::EventsLoop>,
     gl_window: Option<glutin::GlWindow>,
 
     // TODO!: get rid of scene?
     root: Root,
     scene: Scene,
+    animator: Animator,
+    // the other files given on the command line (the UI only affects the first one)
+    extra_models: Vec<Model>,
 
     delta_time: f64, // seconds
     last_frame: Instant,
 
     render_timer: FrameTimer,
+
+    line_renderer: LineRenderer,
+
+    background: Background,
+    background_renderer: BackgroundRenderer,
+
+    wireframe_mode: WireframeMode,
+
+    ssao: Option<Ssao>,
+    shadow_map: Option<ShadowMap>,
+    environment: Option<Environment>,
+    /// draw without tone mapping (HDR screenshots and the float pixel probe)
+    hdr_output: bool,
+    /// overdraw heatmap instead of the shaded scene (F4)
+    overdraw: Option<Overdraw>,
+
+    // offscreen target of `render_scale` (+ its size)
+    scaled_target: Option<(Framebuffer, u32, u32)>,
+    // multisampled target of `RenderOptions::msaa` (+ its size), with the supported sample count
+    msaa_target: Option<(Framebuffer, u32, u32)>,
+    msaa_samples: u32,
+
+    // hold `I` + click: print the pixel value under the cursor
+    pixel_probe_key_held: bool,
+
+    // render stats HUD
+    show_hud: bool,
+    gpu_timer: GpuTimer,
+    cpu_draw_time: Duration,
+
+    // framing debug: bounding sphere + frustum (captured when enabled)
+    show_framing_debug: bool,
+    debug_frustum: Matrix4, // inverse view-projection
+
+    // material look-dev
+    selected_material: Option<Rc<Material>>,
+
+    // manual morph target weights: (mesh index, target index)
+    selected_morph_target: Option<(usize, usize)>,
+
+    playlist: Option<Playlist>,
+
+    // user-defined home view is saved to/loaded from this file
+    home_file: Option<String>,
+
+    // glTF camera in use (see `cycle_camera`)
+    camera_index: Option<usize>,
 }
 
+// step size for the look-dev metallic/roughness multipliers
+const LOOK_DEV_STEP: f32 = 0.1;
+// step size for the animation speed multiplier
+const ANIMATION_SPEED_STEP: f32 = 0.1;
+// seconds per step when scrubbing through an animation (one frame at 30 fps)
+const ANIMATION_TIME_STEP: f32 = 1.0 / 30.0;
+// step size for manually set morph target weights
+const MORPH_WEIGHT_STEP: f32 = 0.1;
+// seconds for gliding to another glTF camera
+const CAMERA_TRANSITION: f32 = 1.0;
+// wire colors to cycle through at runtime
+const WIRE_COLORS: [[f32; 3]; 6] = [
+    [1.0, 1.0, 1.0],
+    [0.0, 0.0, 0.0],
+    [1.0, 1.0, 0.0],
+    [0.0, 1.0, 1.0],
+    [1.0, 0.0, 1.0],
+    [1.0, 0.0, 0.0],
+];
+
 /// Note about `headless` and `visible`: True headless rendering doesn't work on
 /// all operating systems, but an invisible window usually works
 impl GltfViewer {
+    /// All `sources` are shown together, the first one is the main model (cameras, UI)
     pub fn new(
-        source: &str,
-        width: u32,
-        height: u32,
-        headless: bool,
-        visible: bool,
+        sources: &[String],
+        window_options: WindowOptions,
         camera_options: CameraOptions,
-        scene_index: usize,
+        mut load_options: LoadOptions,
+        render_options: RenderOptions,
     ) -> GltfViewer {
+        let WindowOptions { width, height, headless, visible } = window_options;
         let gl_request = GlRequest::Specific(Api::OpenGl, (3, 3));
         let gl_profile = GlProfile::Core;
-        let (events_loop, gl_window, dpi_factor, inner_size) =
+        let (mut events_loop, gl_window, dpi_factor, inner_size) =
             if headless {
                 let headless_context = glutin::HeadlessRendererBuilder::new(width, height)
                     // .with_gl(gl_request)
@@ -130,7 +520,31 @@ impl GltfViewer {
             inner_size);
         orbit_controls.camera = Camera::default();
         orbit_controls.camera.fovy = camera_options.fovy;
-        orbit_controls.camera.update_aspect_ratio(inner_size.width as f32 / inner_size.height as f32); // updates projection matrix
+        orbit_controls.snap_angle = camera_options.snap_angle;
+        orbit_controls.walk_mode = camera_options.walk;
+        orbit_controls.ground_plane_y = camera_options.ground_plane;
+        if let Some(up) = camera_options.up {
+            orbit_controls.set_up(up);
+        }
+        if let Some(rpm) = camera_options.rpm {
+            orbit_controls.auto_rotate = true;
+            orbit_controls.auto_rotate_speed = rpm * 360.0 / 60.0;
+        }
+        orbit_controls.zoom_factor = camera_options.zoom_factor;
+        orbit_controls.rotate_speed = camera_options.rotate_speed;
+        orbit_controls.pan_speed = camera_options.pan_speed;
+        orbit_controls.zoom_speed = camera_options.zoom_speed;
+        orbit_controls.fov_zoom = camera_options.fov_zoom;
+        orbit_controls.smooth_zoom = camera_options.smooth_zoom;
+        if let Some(damping_factor) = camera_options.damping {
+            orbit_controls.enable_damping = true;
+            orbit_controls.damping_factor = damping_factor;
+        }
+        if let Some((min, max)) = camera_options.polar_limits {
+            orbit_controls.set_polar_limits(Rad::from(min).0, Rad::from(max).0);
+        }
+        orbit_controls.set_distance_limits(camera_options.min_distance, camera_options.max_distance);
+        orbit_controls.set_screen_size(inner_size); // updates projection matrix
 
         unsafe {
             print_context_info();
@@ -138,14 +552,6 @@ impl GltfViewer {
             gl::ClearColor(0.0, 1.0, 0.0, 1.0); // green for debugging
             gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
 
-            if headless || !visible {
-                // transparent background for screenshots
-                gl::ClearColor(0.0, 0.0, 0.0, 0.0);
-            }
-            else {
-                gl::ClearColor(0.1, 0.2, 0.3, 1.0);
-            }
-
             gl::Enable(gl::DEPTH_TEST);
 
             // TODO: keyboard switch?
@@ -153,25 +559,121 @@ impl GltfViewer {
             // gl::PolygonMode(gl::FRONT_AND_BACK, gl::LINE);
         };
 
-        let (root, scene) = Self::load(source, scene_index);
+        // before loading, as the PBR shaders only use it (`USE_IBL`) if it could be loaded
+        let environment = load_options.environment.as_ref().and_then(|path| {
+            Environment::from_hdr(path)
+                .map_err(|err| error!("Failed to load the environment, using ambient light instead: {}", err))
+                .ok()
+        });
+        if environment.is_none() {
+            load_options.environment = None;
+        }
+        let background = match render_options.background {
+            Some(Background::Skybox) if environment.is_none() => {
+                warn!("The skybox background requires an environment (--environment)");
+                Background::Color(vec4(0.1, 0.2, 0.3, 1.0))
+            },
+            Some(background) => background,
+            // transparent background for screenshots
+            None if headless || !visible => Background::Color(vec4(0.0, 0.0, 0.0, 0.0)),
+            None if environment.is_some() => Background::Skybox,
+            None => Background::Color(vec4(0.1, 0.2, 0.3, 1.0)),
+        };
+        if let Background::Color(color) = background {
+            unsafe { gl::ClearColor(color.x, color.y, color.z, color.w) }
+        }
+
+        let source = &sources[0];
+        let mut resized = None;
+        let (root, scene, animator) = match (&mut events_loop, &gl_window) {
+            (Some(events_loop), Some(gl_window)) if visible => {
+                let (imp, size) = Self::import_with_preview(source, &load_options, events_loop, gl_window, inner_size);
+                if size != inner_size {
+                    resized = Some(size);
+                }
+                Self::load_imported(source, &imp)
+            },
+            _ => Self::load(source, &load_options),
+        };
+        let ssao = if load_options.ssao {
+            Some(Ssao::new(inner_size.width as u32, inner_size.height as u32))
+        } else {
+            None
+        };
+        let shadow_map = load_options.shadows.map(ShadowMap::new);
+        let wireframe_mode = render_options.wireframe;
+        let max_samples = Framebuffer::max_samples();
+        let msaa_samples = if render_options.msaa > max_samples {
+            warn!("{}x MSAA is not supported, using {}x", render_options.msaa, max_samples);
+            max_samples
+        } else {
+            render_options.msaa
+        };
         let mut viewer = GltfViewer {
             size: inner_size,
             dpi_factor,
 
             orbit_controls,
+            camera_options,
+            load_options,
+            render_options,
 
             events_loop,
             gl_window,
 
             root,
             scene,
+            animator,
+            extra_models: vec![],
 
             delta_time: 0.0, // seconds
             last_frame: Instant::now(),
 
             render_timer: FrameTimer::new("rendering", 300),
+
+            line_renderer: LineRenderer::new(),
+
+            background,
+            background_renderer: BackgroundRenderer::new(),
+
+            wireframe_mode,
+
+            ssao,
+            shadow_map,
+            environment,
+            hdr_output: false,
+            overdraw: None,
+
+            scaled_target: None,
+            msaa_target: None,
+            msaa_samples,
+
+            pixel_probe_key_held: false,
+
+            show_hud: false,
+            gpu_timer: GpuTimer::new(),
+            cpu_draw_time: Duration::from_secs(0),
+
+            show_framing_debug: false,
+            debug_frustum: Matrix4::identity(),
+
+            selected_material: None,
+
+            selected_morph_target: None,
+
+            playlist: None,
+
+            home_file: None,
+
+            camera_index: None,
         };
+        if let Some(size) = resized {
+            viewer.resize(size); // while loading
+        }
+        viewer.add_models(&sources[1..]);
         unsafe { gl_check_error!(); };
+        let wire_width = viewer.render_options.wire_width;
+        viewer.set_wire_width(wire_width);
 
         if camera_options.index != 0 && camera_options.index >= viewer.root.camera_nodes.len() as i32 {
             error!("No camera with index {} found in glTF file (max: {})",
@@ -184,6 +686,7 @@ impl GltfViewer {
             let cam = cam_node.camera.as_ref().unwrap();
             info!("Using camera {} on node {}", cam.description(), cam_node_info);
             viewer.orbit_controls.set_camera(cam, &cam_node.final_transform);
+            viewer.camera_index = Some(camera_options.index as usize);
 
             if camera_options.position.is_some() || camera_options.target.is_some() {
                 warn!("Ignoring --cam-pos / --cam-target since --cam-index is given.")
@@ -199,12 +702,13 @@ impl GltfViewer {
                 viewer.orbit_controls.target = Point3::from_vec(target)
             }
         }
+        viewer.orbit_controls.set_auto_home();
 
         viewer
     }
 
-    pub fn load(source: &str, scene_index: usize) -> (Root, Scene) {
-        let mut start_time = Instant::now();
+    pub fn load(source: &str, options: &LoadOptions) -> (Root, Scene, Animator) {
+        let start_time = Instant::now();
         // TODO!: http source
         // let gltf =
         if source.starts_with("http") {
@@ -216,38 +720,400 @@ impl GltfViewer {
             // gltf
         }
         //     else {
-        let (doc, buffers, images) = match gltf::import(source) {
-            Ok(tuple) => tuple,
+        let imp = Self::check_import(ImportData::import(source, options));
+        print_elapsed("Imported glTF in ", start_time);
+
+        Self::load_imported(source, &imp)
+    }
+
+    /// Exits if the import failed
+    fn check_import(result: gltf::Result<ImportData>) -> ImportData {
+        match result {
+            Ok(imp) => imp,
             Err(err) => {
                 error!("glTF import failed: {:?}", err);
-                if let gltf::Error::Io(_) = err {
+                if matches!(err, gltf::Error::Io(ref io_err) if io_err.kind() == io::ErrorKind::NotFound) {
                     error!("Hint: Are the .bin file(s) referenced by the .gltf file available?")
                 }
                 process::exit(1)
             },
+        }
+    }
+
+    /// Imports `source` on a background thread while showing its thumbnail (see `load_thumbnail`),
+    /// if it has one. The OpenGL upload (`load_imported`) happens afterwards on this thread.
+    /// Also returns the window size, which may have changed in the meantime.
+    fn import_with_preview(source: &str, options: &LoadOptions, events_loop: &mut glutin::EventsLoop,
+        gl_window: &glutin::GlWindow, mut size: PhysicalSize) -> (ImportData, PhysicalSize)
+    {
+        let preview = match load_thumbnail(source) {
+            Some(image) => ThumbnailPreview::new(&image),
+            None => {
+                let start_time = Instant::now();
+                let imp = Self::check_import(ImportData::import(source, options));
+                print_elapsed("Imported glTF in ", start_time);
+                return (imp, size)
+            }
         };
-        let imp = ImportData { doc, buffers, images };
 
+        let start_time = Instant::now();
+        let (sender, receiver) = mpsc::channel();
+        let (thread_source, thread_options) = (source.to_owned(), options.clone());
+        thread::spawn(move || {
+            let _ = sender.send(ImportData::import(&thread_source, &thread_options));
+        });
+        let result = loop {
+            let mut closed = false;
+            events_loop.poll_events(|event| {
+                if let glutin::Event::WindowEvent { event, .. } = event {
+                    match event {
+                        WindowEvent::CloseRequested => closed = true,
+                        WindowEvent::Resized(logical) => {
+                            size = logical.to_physical(gl_window.get_hidpi_factor());
+                            gl_window.resize(size);
+                            unsafe { gl::Viewport(0, 0, size.width as i32, size.height as i32); }
+                        },
+                        _ => ()
+                    }
+                }
+            });
+            if closed {
+                process::exit(0)
+            }
+            unsafe { preview.draw(size.width as f32, size.height as f32); }
+            gl_window.swap_buffers().unwrap();
+
+            match receiver.try_recv() {
+                Ok(result) => break result,
+                Err(mpsc::TryRecvError::Empty) => thread::sleep(Duration::from_millis(10)),
+                Err(mpsc::TryRecvError::Disconnected) => panic!("import thread panicked"),
+            }
+        };
         print_elapsed("Imported glTF in ", start_time);
-        start_time = Instant::now();
+        (Self::check_import(result), size)
+    }
+
+    /// Creates the scene (and its OpenGL resources) from already imported data
+    pub fn load_imported(source: &str, imp: &ImportData) -> (Root, Scene, Animator) {
+        let start_time = Instant::now();
+        let options = &imp.options;
 
-        // load first scene
+        let scene_index = options.scene
+            .or_else(|| imp.doc.default_scene().map(|g_scene| g_scene.index()))
+            .unwrap_or(0);
         if scene_index >= imp.doc.scenes().len() {
             error!("Scene index too high - file has only {} scene(s)", imp.doc.scenes().len());
             process::exit(3)
         }
+
+        // check before uploading anything to the GPU
+        if let Some(max_triangles) = options.max_triangles {
+            let triangles = imp.triangle_count();
+            if triangles > max_triangles {
+                error!("File has {} triangles, more than the maximum of {} (see --max-triangles)",
+                    triangles, max_triangles);
+                process::exit(4)
+            }
+        }
+
+        if options.audit {
+            Audit::from_import(imp).print(&imp.doc);
+        }
+
         let base_path = Path::new(source);
-        let mut root = Root::from_gltf(&imp, base_path);
-        let scene = Scene::from_gltf(&imp.doc.scenes().nth(scene_index).unwrap(), &mut root);
-        print_elapsed(&format!("Loaded scene with {} nodes, {} meshes in ",
-                imp.doc.nodes().count(), imp.doc.meshes().len()), start_time);
+        let mut root = Root::from_gltf(imp, base_path);
+        let up_axis = imp.up_axis();
+        if up_axis != UpAxis::Y {
+            info!("Rotating the scene from {:?}-up to Y-up", up_axis);
+        }
+        let scene = Scene::from_root(scene_index, &mut root, up_axis.root_transform());
+        if root.scene_names.len() > 1 {
+            info!("Showing scene {} of {} (see --scene)", scene_index, root.scene_names.len());
+        }
+        let animator = Animator::from_gltf(imp);
+        info!("Scene size: {}", scene.size_description());
+        root.log_shader_permutations();
+        print_elapsed(&format!("Loaded scene with {} nodes, {} meshes, {} animations in ",
+                imp.doc.nodes().count(), imp.doc.meshes().len(), animator.animations.len()), start_time);
+
+        (root, scene, animator)
+    }
+
+    /// Loads more models into the scene and lays them out next to the first one
+    fn add_models(&mut self, sources: &[String]) {
+        for source in sources {
+            let (root, scene, animator) = Self::load(source, &self.load_options);
+            self.extra_models.push(Model { root, scene, animator });
+        }
+        if self.extra_models.is_empty() {
+            return
+        }
+        let bounds: Vec<Aabb3> = std::iter::once(self.scene.bounds)
+            .chain(self.extra_models.iter().map(|model| model.scene.bounds))
+            .collect();
+        let offsets = self.load_options.layout.offsets(&bounds);
+        for (model, offset) in self.extra_models.iter_mut().zip(&offsets[1..]) {
+            model.scene.root_transform = Matrix4::from_translation(*offset) * model.scene.root_transform;
+            model.scene.update_transforms(&mut model.root);
+        }
+        info!("Showing {} models ({:?} layout)", bounds.len(), self.load_options.layout);
+    }
+
+    /// Bounds of all models
+    fn bounds(&self) -> Aabb3 {
+        self.extra_models.iter()
+            .fold(self.scene.bounds, |bounds, model| union_aabb(&bounds, &model.scene.bounds))
+    }
+
+    /// `Scene::draw_unlit` for all models
+    fn draw_unlit(&self, cam_params: &CameraParams, color: &Vector4, cull_faces: bool) {
+        self.scene.draw_unlit(&self.root, cam_params, &self.line_renderer, color, cull_faces);
+        for model in &self.extra_models {
+            model.scene.draw_unlit(&model.root, cam_params, &self.line_renderer, color, cull_faces);
+        }
+    }
+
+    /// Enables stepping through the files of `playlist` (whose current file should be the loaded one)
+    pub fn set_playlist(&mut self, mut playlist: Playlist) {
+        playlist.prefetch_next();
+        self.playlist = Some(playlist);
+    }
+
+    /// Replace the scene with the next/previous file of the playlist and reframe the camera
+    fn switch_file(&mut self, forward: bool) {
+        let playlist = match self.playlist {
+            Some(ref mut playlist) => playlist,
+            None => return,
+        };
+        let result = playlist.step(forward);
+        let source = playlist.current().to_string_lossy().into_owned();
+        let title = format!("gltf-viewer [{}/{}] {}", playlist.position() + 1, playlist.len(), source);
+        match result {
+            Ok(imp) => {
+                self.replace_scene(&source, &imp);
+                if let Some(ref gl_window) = self.gl_window {
+                    gl_window.set_title(&title);
+                }
+                println!("{}", title);
+            },
+            Err(err) => error!("glTF import of {} failed: {:?}", source, err),
+        }
+    }
+
+    /// Tear down the current scene, load the imported one and reframe the camera
+    fn replace_scene(&mut self, source: &str, imp: &ImportData) {
+        let (root, scene, animator) = Self::load_imported(source, imp);
+        // dropping the old scene frees its OpenGL resources
+        self.root = root;
+        self.scene = scene;
+        self.animator = animator;
+        self.extra_models.clear();
+        self.camera_index = None;
+        self.selected_material = None;
+        self.selected_morph_target = None;
+        self.set_camera_from_bounds(self.camera_options.straight);
+        self.orbit_controls.set_auto_home();
+    }
+
+    /// Names of the scenes of the (first) model by glTF index ("scene <index>" if unnamed)
+    pub fn scene_names(&self) -> Vec<String> {
+        self.root.scene_names.iter()
+            .enumerate()
+            .map(|(i, name)| name.clone().unwrap_or_else(|| format!("scene {}", i)))
+            .collect()
+    }
+
+    /// Switch to another scene of the file without reloading it and reframe the camera
+    pub fn set_scene(&mut self, index: usize) -> Result<(), String> {
+        if index >= self.root.scene_nodes.len() {
+            return Err(format!("scene index {} too high - file has only {} scene(s)",
+                index, self.root.scene_nodes.len()))
+        }
+        self.scene = Scene::from_root(index, &mut self.root, self.scene.root_transform);
+        // the camera node might not be part of the new scene
+        self.camera_index = None;
+        info!("Scene {}: {}", index, self.scene_names()[index]);
+        self.set_camera_from_bounds(self.camera_options.straight);
+        self.orbit_controls.set_auto_home();
+        Ok(())
+    }
+
+    /// Persist the user-defined home view in `path` (same JSON format as the `camera` command).
+    /// If the file exists, its view becomes the home view.
+    pub fn set_home_file(&mut self, path: &str) {
+        if let Ok(json) = fs::read_to_string(path) {
+            match commands::parse_camera(&json) {
+                Ok(ref update @ CameraUpdate { position: Some(_), target: Some(_), .. }) => {
+                    let home = update.apply(self.orbit_controls.save_state());
+                    self.orbit_controls.set_home(Some(home));
+                    info!("Loaded home view from {}", path);
+                },
+                Ok(_) => warn!("Ignoring home view in {}: position and target are required", path),
+                Err(err) => warn!("Ignoring home view in {}: {}", path, err),
+            }
+        }
+        self.home_file = Some(path.to_owned());
+    }
+
+    /// Writes a JSON snapshot of what was loaded and how it's framed (`--report`): scene bounds
+    /// (box + sphere), camera, render stats of a frame and the glTF extensions.
+    pub fn write_report(&mut self, path: &str, source: &str) {
+        self.draw(); // for the stats
+        let bounds = self.bounds();
+        let vector = |v: Vector3| serde_json::json!([v.x, v.y, v.z]);
+        let point = |p: Point3<f32>| vector(p.to_vec());
+        let camera = &self.orbit_controls.camera;
+        let cam_params = self.orbit_controls.camera_params();
+        let matrix = |m: &Matrix4| -> Vec<f32> { AsRef::<[f32; 16]>::as_ref(m).to_vec() };
+        let stats = &self.root.stats;
+        let projection = if camera.is_perspective() { "perspective" } else { "orthographic" };
+
+        let report = serde_json::json!({
+            "file": source,
+            "scene": {
+                "index": self.scene.index,
+                "name": self.scene.name,
+                "names": self.scene_names(),
+            },
+            "bounds": {
+                "min": point(bounds.min),
+                "max": point(bounds.max),
+                "center": point(bounds.center()),
+                "size": vector(bounds.max - bounds.min),
+                // same as used for framing (see `set_camera_from_bounds`)
+                "sphere": {
+                    "center": point(bounds.center()),
+                    "radius": (bounds.max - bounds.min).magnitude() / 2.0,
+                },
+            },
+            "camera": {
+                "position": point(self.orbit_controls.position),
+                "target": point(self.orbit_controls.target),
+                "projection": projection,
+                "fovy": camera.fovy.0,
+                "xmag": camera.xmag,
+                "ymag": camera.ymag,
+                "aspect_ratio": camera.aspect_ratio(),
+                "znear": camera.znear,
+                "zfar": camera.zfar, // null: infinite
+                // column-major
+                "view_matrix": matrix(&cam_params.view_matrix),
+                "projection_matrix": matrix(&cam_params.projection_matrix),
+            },
+            "stats": {
+                "nodes": self.root.nodes.len(),
+                "meshes": self.root.meshes.len(),
+                "materials": self.root.materials.len(),
+                "textures": self.root.textures.len(),
+                "animations": self.animator.animations.len(),
+                "draw_calls": stats.draw_calls,
+                "primitives_drawn": stats.primitives_drawn,
+                "primitives_culled": stats.primitives_culled,
+                "triangles": stats.triangles,
+            },
+            "extensions_used": self.root.extensions_used,
+            "extensions_required": self.root.extensions_required,
+        });
+        match fs::write(path, format!("{:#}\n", report)) {
+            Ok(()) => println!("Saved report to {}", path),
+            Err(err) => error!("Failed to save report to {}: {}", path, err),
+        }
+    }
+
+    /// Executes newline-delimited commands (see `Command`) until `quit` or the end of the input.
+    /// Replies with `ok` or `error: <message>` for each line.
+    pub fn run_commands<R: BufRead>(&mut self, input: R) {
+        for line in input.lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(err) => {
+                    println!("error: {}", err);
+                    break
+                }
+            };
+            if line.trim().is_empty() || line.trim_start().starts_with('#') {
+                continue
+            }
+            let result = line.parse::<Command>().and_then(|command| {
+                if command == Command::Quit {
+                    return Ok(false)
+                }
+                self.execute(command).map(|_| true)
+            });
+            match result {
+                Ok(true) => println!("ok"),
+                Ok(false) => {
+                    println!("ok");
+                    break
+                },
+                Err(message) => println!("error: {}", message),
+            }
+        }
+    }
+
+    fn execute(&mut self, command: Command) -> Result<(), String> {
+        match command {
+            Command::Load(path) => {
+                let imp = ImportData::import(&path, &self.load_options)
+                    .map_err(|err| format!("glTF import of {} failed: {:?}", path, err))?;
+                self.replace_scene(&path, &imp);
+            },
+            Command::Camera(update) => {
+                let state = update.apply(self.orbit_controls.save_state());
+                self.orbit_controls.load_state(&state);
+            },
+            Command::PrintCamera => println!("{}", CameraUpdate::from(self.orbit_controls.save_state()).to_json()),
+            Command::Frame => self.set_camera_from_bounds(self.camera_options.straight),
+            Command::Screenshot(path) => {
+                let color_space = if path.to_lowercase().ends_with(".exr") {
+                    ColorSpace::Linear
+                } else {
+                    ColorSpace::Srgb
+                };
+                self.screenshot(&path, color_space);
+            },
+            Command::Scene(name) => {
+                let index = self.scene_names().iter()
+                    .position(|scene_name| *scene_name == name)
+                    .or_else(|| name.parse().ok())
+                    .ok_or_else(|| format!("no scene '{}'", name))?;
+                self.set_scene(index)?;
+            },
+            Command::Animation(name) => {
+                let index = self.animator.animations.iter()
+                    .position(|animation| animation.name.as_ref() == Some(&name))
+                    .or_else(|| name.parse().ok().filter(|&i| i < self.animator.animations.len()))
+                    .ok_or_else(|| format!("no animation '{}'", name))?;
+                self.set_animation_state(AnimationState {
+                    active: Some(index),
+                    time: 0.0,
+                    ..self.animation_state()
+                });
+            },
+            Command::Time(time) => self.set_animation_state(AnimationState { time, ..self.animation_state() }),
+            Command::Play => self.set_animation_state(AnimationState { playing: true, ..self.animation_state() }),
+            Command::Pause => self.set_animation_state(AnimationState { playing: false, ..self.animation_state() }),
+            Command::Quit => (),
+        }
+        Ok(())
+    }
 
-        (root, scene)
+    /// The animation playback state (for synchronizing with an external timeline)
+    pub fn animation_state(&self) -> AnimationState {
+        self.animator.state()
+    }
+
+    /// Changes the animation playback state, e.g. to scrub to a time. Applied right away.
+    pub fn set_animation_state(&mut self, state: AnimationState) {
+        self.animator.set_state(state);
+        if self.animator.update(0.0, &mut self.root) {
+            self.scene.update_transforms(&mut self.root);
+        }
     }
 
     /// determine "nice" camera perspective from bounding box. Inspired by donmccurdy/three-gltf-viewer
     fn set_camera_from_bounds(&mut self, straight: bool) {
-        let bounds = &self.scene.bounds;
+        let bounds = self.bounds();
         let size = (bounds.max - bounds.min).magnitude();
         let center = bounds.center();
 
@@ -281,18 +1147,23 @@ impl GltfViewer {
             self.last_frame = Instant::now();
 
             // events
-            let keep_running = process_events(
-                &mut self.events_loop.as_mut().unwrap(),
-                self.gl_window.as_mut().unwrap(),
-                &mut self.orbit_controls,
-                &mut self.dpi_factor,
-                &mut self.size);
+            let keep_running = self.process_events();
             if !keep_running {
                 unsafe { gl_check_error!(); } // final error check so errors don't go unnoticed
                 break
             }
 
             self.orbit_controls.frame_update(self.delta_time); // keyboard navigation
+            self.orbit_controls.update(self.delta_time as f32); // auto-rotation, damping
+
+            if self.animator.update(self.delta_time as f32, &mut self.root) {
+                self.scene.update_transforms(&mut self.root);
+            }
+            for model in &mut self.extra_models {
+                if model.animator.update(self.delta_time as f32, &mut model.root) {
+                    model.scene.update_transforms(&mut model.root);
+                }
+            }
 
             self.draw();
 
@@ -306,16 +1177,279 @@ impl GltfViewer {
         unsafe {
             self.render_timer.start();
 
+            let cam_params = self.orbit_controls.camera_params();
+            let (window_width, window_height) = (self.size.width as u32, self.size.height as u32);
+            let mut window_framebuffer = 0;
+            gl::GetIntegerv(gl::FRAMEBUFFER_BINDING, &mut window_framebuffer);
+            let (width, height) = self.scene_resolution();
+            let scaled = (width, height) != (window_width, window_height);
+            if scaled {
+                self.bind_scaled_target(width, height);
+            }
+            // resolved into the window (or the scaled target) after drawing the scene
+            let mut scene_framebuffer = 0;
+            gl::GetIntegerv(gl::FRAMEBUFFER_BINDING, &mut scene_framebuffer);
+            let multisampled = self.msaa_samples > 1 && !self.hdr_output;
+            if multisampled {
+                self.bind_msaa_target(width, height);
+            }
+
+            if let Some(ref environment) = self.environment {
+                environment.bind();
+            }
+            let tone_map = if self.hdr_output { ToneMap::None } else { self.render_options.tone_map };
+            let srgb_output = !self.hdr_output;
+            self.root.set_tone_mapping(tone_map, self.render_options.exposure, srgb_output);
+            self.root.set_light_uniforms();
+            for model in &self.extra_models {
+                model.root.set_tone_mapping(tone_map, self.render_options.exposure, srgb_output);
+                model.root.set_light_uniforms();
+            }
+
+            if self.shadow_map.is_some() && self.overdraw.is_none() {
+                // depth-only pass of the scene from the primary light
+                let mut framebuffer = 0;
+                gl::GetIntegerv(gl::FRAMEBUFFER_BINDING, &mut framebuffer);
+                let (_, direction) = self.root.shadow_light();
+                let light_view = ShadowMap::light_view(&self.bounds(), direction);
+                if let Some(ref shadow_map) = self.shadow_map {
+                    shadow_map.begin();
+                }
+                self.draw_unlit(&light_view, &Vector4::zero(), true);
+                gl::BindFramebuffer(gl::FRAMEBUFFER, framebuffer as u32);
+                gl::Viewport(0, 0, width as i32, height as i32);
+                if let Some(ref shadow_map) = self.shadow_map {
+                    shadow_map.bind();
+                }
+                let light_space_matrix = light_view.projection_matrix * light_view.view_matrix;
+                self.root.set_shadow_uniforms(&light_space_matrix, self.render_options.shadow_bias);
+                for model in &self.extra_models {
+                    model.root.set_shadow_uniforms(&light_space_matrix, self.render_options.shadow_bias);
+                }
+            }
+
+            if self.ssao.is_some() && self.overdraw.is_none() {
+                // depth-only pass of the scene for computing the occlusion
+                let mut framebuffer = 0;
+                gl::GetIntegerv(gl::FRAMEBUFFER_BINDING, &mut framebuffer);
+                if let Some(ref mut ssao) = self.ssao {
+                    ssao.begin_depth_pass(width, height);
+                }
+                self.draw_unlit(&cam_params, &Vector4::zero(), true);
+                let bounds = self.bounds();
+                let radius = self.render_options.ssao_radius
+                    .unwrap_or_else(|| (bounds.max - bounds.min).magnitude() * 0.05);
+                if let Some(ref ssao) = self.ssao {
+                    ssao.apply(&cam_params.projection_matrix, radius, self.render_options.ssao_intensity);
+                }
+                gl::BindFramebuffer(gl::FRAMEBUFFER, framebuffer as u32);
+            }
+
             gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+            match self.background {
+                Background::Color(_) => (),
+                Background::Gradient { top, bottom } => self.background_renderer.draw_gradient(&top, &bottom),
+                Background::Skybox => self.background_renderer.draw_skybox(&cam_params, tone_map,
+                    self.render_options.exposure, srgb_output),
+            }
 
-            let cam_params = self.orbit_controls.camera_params();
-            self.scene.draw(&mut self.root, &cam_params);
+            self.root.stats = RenderStats::default();
+            let cpu_start = Instant::now();
+            self.gpu_timer.start();
+            if self.overdraw.is_some() {
+                let mut framebuffer = 0;
+                gl::GetIntegerv(gl::FRAMEBUFFER_BINDING, &mut framebuffer);
+                if let Some(ref mut overdraw) = self.overdraw {
+                    overdraw.begin(width, height);
+                }
+                self.draw_unlit(&cam_params, &vec4(1.0, 1.0, 1.0, 1.0), false);
+                gl::BindFramebuffer(gl::FRAMEBUFFER, framebuffer as u32);
+                if let Some(ref overdraw) = self.overdraw {
+                    overdraw.resolve();
+                }
+            }
+            else if self.wireframe_mode != WireframeMode::Wireframe {
+                let sorted = self.render_options.sort_front_to_back;
+                if sorted {
+                    self.scene.draw_sorted(&mut self.root, &cam_params);
+                } else {
+                    self.scene.draw(&mut self.root, &cam_params);
+                }
+                for model in &mut self.extra_models {
+                    model.root.stats = RenderStats::default();
+                    if sorted {
+                        model.scene.draw_sorted(&mut model.root, &cam_params);
+                    } else {
+                        model.scene.draw(&mut model.root, &cam_params);
+                    }
+                    self.root.stats.add(&model.root.stats);
+                }
+            }
+            if self.wireframe_mode != WireframeMode::Off && self.overdraw.is_none() {
+                let on_shaded = self.wireframe_mode == WireframeMode::WireOnShaded;
+                if on_shaded {
+                    // pull the lines in front of the shaded surfaces
+                    gl::Enable(gl::POLYGON_OFFSET_LINE);
+                    gl::PolygonOffset(-1.0, -1.0);
+                }
+                gl::PolygonMode(gl::FRONT_AND_BACK, gl::LINE);
+                let color = self.render_options.wire_color.extend(1.0);
+                self.draw_unlit(&cam_params, &color, true);
+                gl::PolygonMode(gl::FRONT_AND_BACK, gl::FILL);
+                if on_shaded {
+                    gl::Disable(gl::POLYGON_OFFSET_LINE);
+                }
+            }
+            self.gpu_timer.end();
+            self.cpu_draw_time = cpu_start.elapsed();
+
+            if let (true, Some((target, _, _))) = (multisampled, &self.msaa_target) {
+                gl::BindFramebuffer(gl::READ_FRAMEBUFFER, target.id);
+                gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, scene_framebuffer as u32);
+                gl::BlitFramebuffer(0, 0, width as i32, height as i32,
+                    0, 0, width as i32, height as i32, gl::COLOR_BUFFER_BIT, gl::NEAREST);
+                gl::BindFramebuffer(gl::FRAMEBUFFER, scene_framebuffer as u32);
+                // like with the scaled target, the overlays are drawn on top
+                gl::Clear(gl::DEPTH_BUFFER_BIT);
+            }
+
+            if let (true, Some((target, _, _))) = (scaled, &self.scaled_target) {
+                let filter = match self.render_options.upscale {
+                    UpscaleFilter::Nearest => gl::NEAREST,
+                    UpscaleFilter::Linear => gl::LINEAR,
+                };
+                gl::BindFramebuffer(gl::READ_FRAMEBUFFER, target.id);
+                gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, window_framebuffer as u32);
+                gl::BlitFramebuffer(0, 0, width as i32, height as i32,
+                    0, 0, window_width as i32, window_height as i32, gl::COLOR_BUFFER_BIT, filter);
+                gl::BindFramebuffer(gl::FRAMEBUFFER, window_framebuffer as u32);
+                gl::Viewport(0, 0, window_width as i32, window_height as i32);
+                // the overlays are drawn on top at full resolution
+                gl::Clear(gl::DEPTH_BUFFER_BIT);
+            }
+
+            if let Some((min, max)) = self.orbit_controls.box_zoom_rect() {
+                let rect = [
+                    vec3(min.x, min.y, 0.0),
+                    vec3(max.x, min.y, 0.0),
+                    vec3(max.x, max.y, 0.0),
+                    vec3(min.x, max.y, 0.0),
+                ];
+                gl::Disable(gl::DEPTH_TEST);
+                self.line_renderer.draw(&rect, gl::LINE_LOOP, &vec4(1.0, 1.0, 1.0, 1.0), &Matrix4::identity());
+                gl::Enable(gl::DEPTH_TEST);
+            }
+
+            if self.show_framing_debug {
+                // same bounding sphere as used in `set_camera_from_bounds`
+                let bounds = self.bounds();
+                let radius = (bounds.max - bounds.min).magnitude() / 2.0;
+                let view_projection = cam_params.projection_matrix * cam_params.view_matrix;
+                self.line_renderer.draw_sphere(bounds.center(), radius, &vec4(1.0, 1.0, 0.0, 1.0), &view_projection);
+                self.line_renderer.draw_frustum(&self.debug_frustum, &vec4(0.0, 1.0, 1.0, 1.0), &view_projection);
+            }
+
+            if self.show_hud {
+                self.draw_hud();
+            }
 
             self.render_timer.end();
         }
     }
 
-    pub fn screenshot(&mut self, filename: &str) {
+    /// Size of the scene rendering (the window size scaled by `render_scale`)
+    fn scene_resolution(&self) -> (u32, u32) {
+        let scale = self.render_options.render_scale;
+        let scaled = |size: f64| ((size as f32 * scale).round() as u32).max(1);
+        (scaled(self.size.width), scaled(self.size.height))
+    }
+
+    /// Binds the offscreen target for a `render_scale` below 1, (re)creating it if the size changed.
+    /// NOTE: 8 bit per channel, so HDR screenshots are clamped when scaled.
+    unsafe fn bind_scaled_target(&mut self, width: u32, height: u32) {
+        let outdated = match self.scaled_target {
+            Some((_, target_width, target_height)) => (target_width, target_height) != (width, height),
+            None => true,
+        };
+        if outdated {
+            if let Some((target, _, _)) = self.scaled_target.take() {
+                target.delete();
+            }
+            self.scaled_target = Some((Framebuffer::new(width, height), width, height));
+        }
+        if let Some((ref target, _, _)) = self.scaled_target {
+            target.bind();
+        }
+        gl::Viewport(0, 0, width as i32, height as i32);
+    }
+
+    /// Binds the multisampled target (`RenderOptions::msaa`), (re)creating it if the size changed.
+    /// NOTE: 8 bit per channel like `bind_scaled_target`, so HDR screenshots are drawn without MSAA.
+    unsafe fn bind_msaa_target(&mut self, width: u32, height: u32) {
+        let outdated = match self.msaa_target {
+            Some((_, target_width, target_height)) => (target_width, target_height) != (width, height),
+            None => true,
+        };
+        if outdated {
+            if let Some((target, _, _)) = self.msaa_target.take() {
+                target.delete();
+            }
+            self.msaa_target = Some((Framebuffer::new_multisampled(width, height, self.msaa_samples), width, height));
+        }
+        if let Some((ref target, _, _)) = self.msaa_target {
+            target.bind();
+        }
+    }
+
+    /// Text overlay with frame timings and the counters of `RenderStats`
+    unsafe fn draw_hud(&self) {
+        let to_ms = |duration: Duration| duration.as_secs() as f64 * 1000.0 + f64::from(duration.subsec_nanos()) / 1_000_000.0;
+        let stats = &self.root.stats;
+        let frame_ms = self.delta_time * 1000.0;
+        let fps = if self.delta_time > 0.0 { 1.0 / self.delta_time } else { 0.0 };
+        let hud_text = format!(
+            "Frame      {:6.2} ms ({:.0} fps)\n\
+             CPU        {:6.2} ms\n\
+             GPU        {:6.2} ms\n\
+             Draw calls {}\n\
+             Primitives {} drawn, {} culled\n\
+             Triangles  {}\n\
+             Size       {}\n\
+             Shaders    {} programs, {} materials",
+            frame_ms, fps,
+            to_ms(self.cpu_draw_time),
+            to_ms(self.gpu_timer.last),
+            stats.draw_calls,
+            stats.primitives_drawn, stats.primitives_culled,
+            stats.triangles,
+            self.scene.size_description(),
+            self.root.shaders.len(), self.root.materials.len());
+
+        // pixel coordinates with the origin at the top left
+        let pixel_size = (2.0 * self.dpi_factor).round() as f32;
+        let (width, height) = (self.size.width as f32, self.size.height as f32);
+        let projection = cgmath::ortho(0.0, width, height, 0.0, -1.0, 1.0);
+        let margin = 4.0 * pixel_size;
+        let size = text::text_size(&hud_text, pixel_size);
+
+        gl::Disable(gl::DEPTH_TEST);
+        gl::Enable(gl::BLEND);
+        gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+        let background = text::rect_triangles(0.0, 0.0, size.x + 2.0 * margin, size.y + 2.0 * margin);
+        self.line_renderer.draw(&background, gl::TRIANGLES, &vec4(0.0, 0.0, 0.0, 0.6), &projection);
+        gl::Disable(gl::BLEND);
+        let glyphs = text::text_triangles(&hud_text, margin, margin, pixel_size);
+        self.line_renderer.draw(&glyphs, gl::TRIANGLES, &vec4(1.0, 1.0, 1.0, 1.0), &projection);
+        gl::Enable(gl::DEPTH_TEST);
+    }
+
+    pub fn screenshot(&mut self, filename: &str, color_space: ColorSpace) {
+        if color_space == ColorSpace::Linear {
+            self.screenshot_hdr(filename);
+            return
+        }
+
+        // NOTE: values are clamped to [0, 1] by the 8 bit framebuffer
         self.draw();
 
         let mut img = DynamicImage::new_rgba8(self.size.width as u32, self.size.height as u32);
@@ -327,15 +1461,84 @@ impl GltfViewer {
             gl_check_error!();
         }
 
-        let img = img.flipv();
-        if let Err(err) = img.save(filename) {
+        let img = img.flipv().to_rgba();
+        if let Err(err) = screenshot::save(&img, img.width(), img.height(), filename, color_space) {
             error!("{}", err);
         }
         else {
             println!("Saved {}x{} screenshot to {}", self.size.width, self.size.height, filename);
         }
     }
-    pub fn multiscreenshot(&mut self, filename: &str, count: u32) {
+    /// Render a frame and print the value of the pixel under the cursor: as displayed (8 bit)
+    /// and as rendered into a float framebuffer (linear, not clamped or tone mapped)
+    fn print_pixel_under_cursor(&mut self) {
+        let (width, height) = (self.size.width as u32, self.size.height as u32);
+        let cursor = self.orbit_controls.cursor_position();
+        let x = cursor.x.max(0.0).min(width as f32 - 1.0) as i32;
+        // OpenGL rows start at the bottom
+        let y = height as i32 - 1 - cursor.y.max(0.0).min(height as f32 - 1.0) as i32;
+
+        let mut display = [0u8; 4];
+        let mut hdr = [0.0f32; 4];
+        unsafe {
+            self.draw();
+            gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+            gl::ReadPixels(x, y, 1, 1, gl::RGBA, gl::UNSIGNED_BYTE, display.as_mut_ptr() as *mut c_void);
+
+            let mut previous_framebuffer = 0;
+            gl::GetIntegerv(gl::FRAMEBUFFER_BINDING, &mut previous_framebuffer);
+            let framebuffer = Framebuffer::new_hdr(width, height);
+            framebuffer.bind();
+            self.hdr_output = true;
+            self.draw();
+            self.hdr_output = false;
+            gl::ReadPixels(x, y, 1, 1, gl::RGBA, gl::FLOAT, hdr.as_mut_ptr() as *mut c_void);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, previous_framebuffer as u32);
+            framebuffer.delete();
+            gl_check_error!();
+        }
+
+        let float: Vec<String> = display.iter().map(|&c| format!("{:.3}", f32::from(c) / 255.0)).collect();
+        let bytes: Vec<String> = display.iter().map(|c| c.to_string()).collect();
+        let linear: Vec<String> = hdr.iter().map(|c| format!("{:.4}", c)).collect();
+        println!("Pixel ({}, {}): RGBA {} ({}), linear HDR {}",
+            x, height as i32 - 1 - y, float.join(" "), bytes.join(" "), linear.join(" "));
+    }
+
+    /// Renders into a float framebuffer and saves an OpenEXR file, so that HDR values
+    /// (e.g. from `KHR_materials_emissive_strength`) aren't clamped or tone mapped.
+    fn screenshot_hdr(&mut self, filename: &str) {
+        let (width, height) = (self.size.width as u32, self.size.height as u32);
+        let mut pixels = vec![0.0f32; width as usize * height as usize * 4];
+        unsafe {
+            let mut previous_framebuffer = 0;
+            gl::GetIntegerv(gl::FRAMEBUFFER_BINDING, &mut previous_framebuffer);
+            let framebuffer = Framebuffer::new_hdr(width, height);
+            framebuffer.bind();
+
+            self.hdr_output = true;
+            self.draw();
+            self.hdr_output = false;
+
+            gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+            gl::ReadPixels(0, 0, width as i32, height as i32, gl::RGBA,
+                gl::FLOAT, pixels.as_mut_ptr() as *mut c_void);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, previous_framebuffer as u32);
+            framebuffer.delete();
+            gl_check_error!();
+        }
+
+        // OpenGL rows start at the bottom
+        let flipped: Vec<f32> = pixels.chunks(width as usize * 4).rev().flatten().cloned().collect();
+        if let Err(err) = screenshot::save_hdr(&flipped, width, height, filename) {
+            error!("{}", err);
+        }
+        else {
+            println!("Saved {}x{} HDR screenshot to {}", width, height, filename);
+        }
+    }
+
+    pub fn multiscreenshot(&mut self, filename: &str, count: u32, color_space: ColorSpace) {
         let min_angle : f32 = 0.0 ;
         let max_angle : f32 =  2.0 * PI ;
         let increment_angle : f32 = ((max_angle - min_angle)/(count as f32)) as f32;
@@ -345,106 +1548,447 @@ impl GltfViewer {
             let dot = filename.rfind('.').unwrap_or_else(|| filename.len());
             let mut actual_name = filename.to_string();
             actual_name.insert_str(dot, &format!("_{:0suffix_length$}", i, suffix_length = suffix_length));
-            self.screenshot(&actual_name[..]);
+            self.screenshot(&actual_name[..], color_space);
         }
     }
-}
 
-#[allow(clippy::too_many_arguments)]
-fn process_events(
-    events_loop: &mut glutin::EventsLoop,
-    gl_window: &glutin::GlWindow,
-    mut orbit_controls: &mut OrbitControls,
-    dpi_factor: &mut f64,
-    size: &mut PhysicalSize) -> bool
-{
-    let mut keep_running = true;
+    /// Applies a new window size before the next frame is drawn: the GL viewport, camera aspect ratio
+    /// and controls together. Unless disabled (`--no-clear-on-resize`), the resized window is cleared
+    /// right away, so that neither the stretched last frame nor garbage shows in the meantime.
+    fn resize(&mut self, size: PhysicalSize) {
+        if size.width < 1.0 || size.height < 1.0 {
+            return // minimized
+        }
+        let gl_window = self.gl_window.as_ref().unwrap();
+        gl_window.resize(size);
+
+        unsafe {
+            // This doesn't seem to be needed on macOS but linux X11, Wayland and Windows
+            // do need it.
+            gl::Viewport(0, 0, size.width as i32, size.height as i32);
+
+            if self.render_options.clear_on_resize {
+                gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+                gl_window.swap_buffers().unwrap();
+            }
+        }
+
+        self.size = size;
+        self.orbit_controls.set_screen_size(size);
+    }
+
+    /// Returns whether to keep running
     #[allow(clippy::single_match)]
-    events_loop.poll_events(|event| {
-        match event {
-            glutin::Event::WindowEvent{ event, .. } => match event {
-                WindowEvent::CloseRequested => {
-                    keep_running = false;
-                },
-                WindowEvent::Destroyed => {
-                    // Log and exit?
-                    panic!("WindowEvent::Destroyed, unimplemented.");
+    fn process_events(&mut self) -> bool {
+        // collect first, so that the handlers can borrow `self`
+        let mut events = Vec::new();
+        self.events_loop.as_mut().unwrap().poll_events(|event| events.push(event));
+
+        let mut keep_running = true;
+        for event in events {
+            match event {
+                glutin::Event::WindowEvent{ event, .. } => match event {
+                    WindowEvent::CloseRequested => {
+                        keep_running = false;
+                    },
+                    WindowEvent::Destroyed => {
+                        // Log and exit?
+                        panic!("WindowEvent::Destroyed, unimplemented.");
+                    },
+                    WindowEvent::Resized(logical) => {
+                        let ph = logical.to_physical(self.dpi_factor);
+                        self.resize(ph);
+                    },
+                    WindowEvent::HiDpiFactorChanged(f) => {
+                        self.dpi_factor = f;
+                        // same logical size, but a different number of pixels
+                        if let Some(logical) = self.gl_window.as_ref().unwrap().get_inner_size() {
+                            self.resize(logical.to_physical(f));
+                        }
+                    },
+                    WindowEvent::DroppedFile(_path_buf) => {
+                        // TODO: drag file in
+                    }
+                    WindowEvent::MouseInput { button, state: Pressed, modifiers, ..} => {
+                        match button {
+                            MouseButton::Left if modifiers.ctrl => {
+                                self.pick_material();
+                            },
+                            MouseButton::Left if self.pixel_probe_key_held => {
+                                self.print_pixel_under_cursor();
+                            },
+                            MouseButton::Left if modifiers.shift => {
+                                self.orbit_controls.start_box_zoom();
+                            },
+                            MouseButton::Left => {
+                                self.orbit_controls.state = NavState::Rotating;
+                            },
+                            MouseButton::Right => {
+                                self.orbit_controls.state = NavState::Panning;
+                            },
+                            _ => ()
+                        }
+                    },
+                    WindowEvent::MouseInput { button, state: Released, ..} => {
+                        match (button, self.orbit_controls.state.clone()) {
+                            (MouseButton::Left, NavState::Rotating) | (MouseButton::Right, NavState::Panning) => {
+                                self.orbit_controls.state = NavState::None;
+                                self.orbit_controls.handle_mouse_up();
+                            },
+                            (MouseButton::Left, NavState::BoxZooming) => {
+                                self.orbit_controls.finish_box_zoom();
+                            },
+                            _ => ()
+                        }
+                    }
+                    WindowEvent::CursorMoved { position, modifiers, .. } => {
+                        let ph = position.to_physical(self.dpi_factor);
+                        // Alt: snap rotation to fixed increments
+                        self.orbit_controls.handle_mouse_move(ph, modifiers.alt)
+                    },
+                    WindowEvent::MouseWheel { delta: MouseScrollDelta::PixelDelta(logical), .. } => {
+                        let ph = logical.to_physical(self.dpi_factor);
+                        self.orbit_controls.process_mouse_scroll(ph.y as f32);
+                    }
+                    WindowEvent::MouseWheel { delta: MouseScrollDelta::LineDelta(_rows, lines), .. } => {
+                        self.orbit_controls.process_mouse_scroll(lines * 3.0);
+                    }
+                    WindowEvent::Touch(Touch { phase, location, id, .. }) => {
+                        let ph = location.to_physical(self.dpi_factor);
+                        match phase {
+                            TouchPhase::Started => self.orbit_controls.handle_touch_start(id, ph),
+                            TouchPhase::Moved => self.orbit_controls.handle_touch_move(id, ph),
+                            TouchPhase::Ended | TouchPhase::Cancelled => self.orbit_controls.handle_touch_end(id),
+                        }
+                    }
+                    WindowEvent::KeyboardInput { input, .. } => {
+                        keep_running &= self.process_input(input);
+                    }
+                    _ => ()
                 },
-                WindowEvent::Resized(logical) => {
-                    let ph = logical.to_physical(*dpi_factor);
-                    gl_window.resize(ph);
+                _ => ()
+            }
+        }
 
-                    // This doesn't seem to be needed on macOS but linux X11, Wayland and Windows
-                    // do need it.
-                    unsafe { gl::Viewport(0, 0, ph.width as i32, ph.height as i32); }
+        keep_running
+    }
 
-                    *size = ph;
-                    orbit_controls.camera.update_aspect_ratio((ph.width / ph.height) as f32);
-                    orbit_controls.screen_size = ph;
+    fn process_input(&mut self, input: glutin::KeyboardInput) -> bool {
+        let pressed = match input.state {
+            Pressed => true,
+            Released => false
+        };
+        let controls = &mut self.orbit_controls;
+        if let Some(code) = input.virtual_keycode {
+            match code {
+                VirtualKeyCode::Escape if pressed => return false,
+                VirtualKeyCode::W | VirtualKeyCode::Up    => controls.process_keyboard(FORWARD, pressed),
+                VirtualKeyCode::S | VirtualKeyCode::Down  => controls.process_keyboard(BACKWARD, pressed),
+                VirtualKeyCode::A | VirtualKeyCode::Left  => controls.process_keyboard(LEFT, pressed),
+                VirtualKeyCode::D | VirtualKeyCode::Right => controls.process_keyboard(RIGHT, pressed),
+                VirtualKeyCode::E                         => controls.process_keyboard(UP, pressed),
+                VirtualKeyCode::Q                         => controls.process_keyboard(DOWN, pressed),
+                // material look-dev (Ctrl + click to select a material)
+                VirtualKeyCode::T if pressed => self.adjust_material(0.0, LOOK_DEV_STEP),
+                VirtualKeyCode::G if pressed => self.adjust_material(0.0, -LOOK_DEV_STEP),
+                VirtualKeyCode::Y if pressed => self.adjust_material(LOOK_DEV_STEP, 0.0),
+                VirtualKeyCode::H if pressed => self.adjust_material(-LOOK_DEV_STEP, 0.0),
+                VirtualKeyCode::Back if pressed => self.reset_material(),
+                VirtualKeyCode::PageDown if pressed => self.switch_file(true),
+                VirtualKeyCode::PageUp if pressed => self.switch_file(false),
+                VirtualKeyCode::Home if pressed && input.modifiers.shift => self.set_home(),
+                VirtualKeyCode::Home if pressed => self.orbit_controls.reset(),
+                VirtualKeyCode::F if pressed => {
+                    let bounds = self.bounds();
+                    self.orbit_controls.frame_bounds(bounds.min.to_vec(), bounds.max.to_vec());
                 },
-                WindowEvent::HiDpiFactorChanged(f) => {
-                    *dpi_factor = f;
+                VirtualKeyCode::Period if pressed => {
+                    self.orbit_controls.recenter(self.bounds().center());
+                    println!("Recentered on the scene");
                 },
-                WindowEvent::DroppedFile(_path_buf) => {
-                    // TODO: drag file in
-                }
-                WindowEvent::MouseInput { button, state: Pressed, ..} => {
-                    match button {
-                        MouseButton::Left => {
-                            orbit_controls.state = NavState::Rotating;
-                        },
-                        MouseButton::Right => {
-                            orbit_controls.state = NavState::Panning;
-                        },
-                        _ => ()
-                    }
+                VirtualKeyCode::I => self.pixel_probe_key_held = pressed,
+                VirtualKeyCode::L if pressed => self.cycle_lod_level(),
+                VirtualKeyCode::K if pressed => self.cycle_camera(),
+                VirtualKeyCode::C if pressed => self.cycle_color_set(),
+                VirtualKeyCode::V if pressed => self.cycle_variant(!input.modifiers.shift),
+                VirtualKeyCode::P if pressed => {
+                    self.orbit_controls.toggle_projection();
+                    let projection = if self.orbit_controls.camera.is_perspective() { "perspective" } else { "orthographic" };
+                    println!("Projection: {}", projection);
                 },
-                WindowEvent::MouseInput { button, state: Released, ..} => {
-                    match (button, orbit_controls.state.clone()) {
-                        (MouseButton::Left, NavState::Rotating) | (MouseButton::Right, NavState::Panning) => {
-                            orbit_controls.state = NavState::None;
-                            orbit_controls.handle_mouse_up();
-                        },
-                        _ => ()
-                    }
-                }
-                WindowEvent::CursorMoved { position, .. } => {
-                    let ph = position.to_physical(*dpi_factor);
-                    orbit_controls.handle_mouse_move(ph)
+                VirtualKeyCode::F1 if pressed => self.show_hud = !self.show_hud,
+                VirtualKeyCode::F2 if pressed => {
+                    self.wireframe_mode = self.wireframe_mode.next();
+                    println!("Wireframe: {:?}", self.wireframe_mode);
                 },
-                WindowEvent::MouseWheel { delta: MouseScrollDelta::PixelDelta(logical), .. } => {
-                    let ph = logical.to_physical(*dpi_factor);
-                    orbit_controls.process_mouse_scroll(ph.y as f32);
-                }
-                WindowEvent::MouseWheel { delta: MouseScrollDelta::LineDelta(_rows, lines), .. } => {
-                    orbit_controls.process_mouse_scroll(lines * 3.0);
-                }
-                WindowEvent::KeyboardInput { input, .. } => {
-                    keep_running = process_input(input, &mut orbit_controls);
-                }
+                VirtualKeyCode::Key8 if pressed => self.cycle_wire_color(),
+                VirtualKeyCode::Key9 if pressed => self.set_wire_width(self.render_options.wire_width - 1.0),
+                VirtualKeyCode::Key0 if pressed => self.set_wire_width(self.render_options.wire_width + 1.0),
+                VirtualKeyCode::F3 if pressed => self.toggle_framing_debug(),
+                VirtualKeyCode::F4 if pressed => self.toggle_overdraw(),
+                VirtualKeyCode::M if pressed => self.select_morph_target(!input.modifiers.shift),
+                VirtualKeyCode::Equals | VirtualKeyCode::Add if pressed => self.adjust_morph_weight(MORPH_WEIGHT_STEP),
+                VirtualKeyCode::Minus | VirtualKeyCode::Subtract if pressed => self.adjust_morph_weight(-MORPH_WEIGHT_STEP),
+                VirtualKeyCode::N if pressed => self.reset_morph_weights(),
+                VirtualKeyCode::LBracket if pressed => self.adjust_animation_speed(-ANIMATION_SPEED_STEP),
+                VirtualKeyCode::RBracket if pressed => self.adjust_animation_speed(ANIMATION_SPEED_STEP),
+                VirtualKeyCode::Space if pressed => self.toggle_animation_playing(),
+                VirtualKeyCode::O if pressed => self.toggle_animation_looping(),
+                VirtualKeyCode::Z if pressed => self.step_animation(-ANIMATION_TIME_STEP),
+                VirtualKeyCode::X if pressed => self.step_animation(ANIMATION_TIME_STEP),
+                VirtualKeyCode::Tab if pressed => self.cycle_animation(!input.modifiers.shift),
                 _ => ()
+            }
+        }
+        true
+    }
+
+    /// Select the material of the primitive under the cursor (bounding box based)
+    fn pick_material(&mut self) {
+        let ndc = self.orbit_controls.cursor_ndc();
+        let picked = self.orbit_controls.unproject_ray(ndc)
+            .and_then(|(origin, direction)| self.scene.pick(&self.root, origin, direction));
+        match picked {
+            Some(material) => {
+                println!("Selected material {:?} ({:?})", material.index, material.name);
+                self.selected_material = Some(material);
             },
-            _ => ()
+            None => {
+                println!("No material under cursor");
+                self.selected_material = None;
+            }
         }
-    });
+    }
 
-    keep_running
-}
+    /// Change the look-dev multipliers of the selected material
+    fn adjust_material(&mut self, metallic_delta: f32, roughness_delta: f32) {
+        if let Some(ref material) = self.selected_material {
+            let metallic = (material.metallic_scale.get() + metallic_delta).max(0.0);
+            let roughness = (material.roughness_scale.get() + roughness_delta).max(0.0);
+            material.metallic_scale.set(metallic);
+            material.roughness_scale.set(roughness);
+            println!("Material {:?}: metallic x{:.1}, roughness x{:.1}", material.index, metallic, roughness);
+        }
+    }
+
+    fn reset_material(&mut self) {
+        if let Some(ref material) = self.selected_material {
+            material.metallic_scale.set(1.0);
+            material.roughness_scale.set(1.0);
+            println!("Material {:?}: reset metallic/roughness", material.index);
+        }
+    }
+
+    /// Toggle drawing the scene bounding sphere and the camera frustum.
+    /// The frustum is captured when enabling, so that it can be inspected from another view.
+    fn toggle_framing_debug(&mut self) {
+        self.show_framing_debug = !self.show_framing_debug;
+        if self.show_framing_debug {
+            let cam_params = self.orbit_controls.camera_params();
+            self.debug_frustum = (cam_params.projection_matrix * cam_params.view_matrix)
+                .invert()
+                .unwrap_or_else(Matrix4::identity);
+        }
+    }
+
+    /// Toggle the overdraw heatmap (replacing the shaded scene and wireframes)
+    fn toggle_overdraw(&mut self) {
+        if self.overdraw.take().is_some() {
+            println!("Overdraw: off");
+            return
+        }
+        let (width, height) = self.scene_resolution();
+        self.overdraw = Some(Overdraw::new(width, height));
+        println!("Overdraw: on (fragments per pixel: blue 1, green {}, red {} or more)",
+            (1.0 + MAX_OVERDRAW) / 2.0, MAX_OVERDRAW);
+    }
+
+    /// Set the line width, clamped to the range supported by the OpenGL implementation
+    fn set_wire_width(&mut self, width: f32) {
+        let mut range = [1.0f32; 2];
+        unsafe { gl::GetFloatv(gl::ALIASED_LINE_WIDTH_RANGE, range.as_mut_ptr()) };
+        let supported = clamp(width, range[0], range[1]);
+        if supported != width {
+            warn!("Line width {} not supported, using {} (supported range: {} - {})",
+                width, supported, range[0], range[1]);
+        }
+        self.render_options.wire_width = supported;
+        unsafe { gl::LineWidth(supported) };
+    }
+
+    fn cycle_wire_color(&mut self) {
+        let current: [f32; 3] = self.render_options.wire_color.into();
+        let index = WIRE_COLORS.iter().position(|c| *c == current)
+            .map(|i| (i + 1) % WIRE_COLORS.len())
+            .unwrap_or(0);
+        self.render_options.wire_color = WIRE_COLORS[index].into();
+        println!("Wire color: {:?}", WIRE_COLORS[index]);
+    }
+
+    /// Glide to the next glTF camera (wrapping around)
+    fn cycle_camera(&mut self) {
+        let count = self.root.camera_nodes.len();
+        if count == 0 {
+            println!("No glTF cameras");
+            return
+        }
+        let index = self.camera_index.map(|i| (i + 1) % count).unwrap_or(0);
+        self.camera_index = Some(index);
+        let cam_node = self.root.get_camera_node(index);
+        let cam = cam_node.camera.as_ref().unwrap();
+        // `set_camera` jumps (and also takes over the projection), the transition starts where we were
+        let from = self.orbit_controls.save_state();
+        self.orbit_controls.set_camera(cam, &cam_node.final_transform);
+        let to = self.orbit_controls.save_state();
+        self.orbit_controls.load_state(&from);
+        self.orbit_controls.transition_to(to, CAMERA_TRANSITION);
+        println!("Camera {} of {}: {}", index, count, cam.description());
+    }
+
+    /// Switch to the next `MSFT_lod` level (wrapping around)
+    fn cycle_lod_level(&mut self) {
+        let max_level = self.root.max_lod_level();
+        if max_level == 0 {
+            println!("No MSFT_lod levels");
+            return
+        }
+        self.root.lod_level = (self.root.lod_level.min(max_level) + 1) % (max_level + 1);
+        self.scene.update_transforms(&mut self.root);
+        println!("LOD level {} (of 0 - {})", self.root.lod_level, max_level);
+    }
+
+    /// Switch to the next vertex color set (COLOR_n) for inspecting the data of further sets
+    fn cycle_color_set(&mut self) {
+        let count = self.root.max_color_set_count();
+        if count < 2 {
+            println!("No further vertex color sets (found {})", count);
+            return
+        }
+        let set = (self.root.color_set + 1) % count;
+        self.root.select_color_set(set);
+        println!("Vertex colors: COLOR_{} (of 0 - {})", set, count - 1);
+    }
+
+    /// Select the next (or previous) `KHR_materials_variants` variant, with the default materials
+    /// between the last and the first one
+    fn cycle_variant(&mut self, forward: bool) {
+        let count = self.root.variants.len();
+        if count == 0 {
+            println!("No material variants");
+            return
+        }
+        // 0: default materials
+        let current = self.root.variant.map(|variant| variant + 1).unwrap_or(0);
+        let selected = if forward { (current + 1) % (count + 1) } else { (current + count) % (count + 1) };
+        let variant = if selected == 0 { None } else { Some(selected - 1) };
+        self.root.set_variant(variant);
+        match variant {
+            Some(variant) => println!("Material variant: {} ({}/{})", self.root.variants[variant], variant + 1, count),
+            None => println!("Material variant: none (default materials)"),
+        }
+    }
 
-fn process_input(input: glutin::KeyboardInput, controls: &mut OrbitControls) -> bool {
-    let pressed = match input.state {
-        Pressed => true,
-        Released => false
-    };
-    if let Some(code) = input.virtual_keycode {
-        match code {
-            VirtualKeyCode::Escape if pressed => return false,
-            VirtualKeyCode::W | VirtualKeyCode::Up    => controls.process_keyboard(FORWARD, pressed),
-            VirtualKeyCode::S | VirtualKeyCode::Down  => controls.process_keyboard(BACKWARD, pressed),
-            VirtualKeyCode::A | VirtualKeyCode::Left  => controls.process_keyboard(LEFT, pressed),
-            VirtualKeyCode::D | VirtualKeyCode::Right => controls.process_keyboard(RIGHT, pressed),
-            _ => ()
-        }
-    }
-    true
+    /// Use the current view as home view (restored with `Home`)
+    fn set_home(&mut self) {
+        self.orbit_controls.set_home(None);
+        println!("Set home view");
+        if let Some(ref path) = self.home_file {
+            let json = CameraUpdate::from(self.orbit_controls.save_state()).to_json();
+            match fs::write(path, format!("{}\n", json)) {
+                Ok(()) => println!("Saved home view to {}", path),
+                Err(err) => error!("Failed to save home view to {}: {}", path, err),
+            }
+        }
+    }
+
+    /// Select the next (or previous) morph target, going through the targets of all meshes
+    fn select_morph_target(&mut self, forward: bool) {
+        let targets: Vec<(usize, usize)> = self.root.meshes.iter()
+            .flat_map(|mesh| (0..mesh.morph_target_count()).map(move |target| (mesh.index, target)))
+            .collect();
+        if targets.is_empty() {
+            println!("No morph targets");
+            return
+        }
+        let len = targets.len();
+        let position = self.selected_morph_target
+            .and_then(|selected| targets.iter().position(|t| *t == selected));
+        let index = match position {
+            Some(i) if forward => (i + 1) % len,
+            Some(i) => (i + len - 1) % len,
+            None if forward => 0,
+            None => len - 1,
+        };
+        let (mesh_index, target) = targets[index];
+        self.selected_morph_target = Some((mesh_index, target));
+        let mesh = self.root.meshes.iter().find(|m| m.index == mesh_index).unwrap();
+        println!("Selected morph target {} of mesh {} ({:?}): weight {:.1}",
+            target, mesh_index, mesh.name, mesh.morph_weights()[target]);
+    }
+
+    fn adjust_morph_weight(&mut self, delta: f32) {
+        let (mesh_index, target) = match self.selected_morph_target {
+            Some(selected) => selected,
+            None => return
+        };
+        let current = self.root.meshes.iter()
+            .find(|m| m.index == mesh_index)
+            .map(|mesh| mesh.morph_weights()[target])
+            .unwrap_or(0.0);
+        // round to avoid accumulating float errors (see `adjust_animation_speed`)
+        let weight = clamp(((current + delta) * 10.0).round() / 10.0, 0.0, 1.0);
+        self.root.set_morph_weight(mesh_index, target, Some(weight));
+        println!("Morph target {} of mesh {}: weight {:.1}", target, mesh_index, weight);
+    }
+
+    fn reset_morph_weights(&mut self) {
+        self.root.clear_morph_weights();
+        println!("Reset morph target weights");
+    }
+
+    fn adjust_animation_speed(&mut self, delta: f32) {
+        // round to avoid accumulating float errors (e.g. 0.1 steps not reaching exactly 0)
+        let speed = ((self.animator.speed() + delta) * 10.0).round() / 10.0;
+        self.animator.set_speed(speed);
+        println!("Animation speed: {:.1}x", self.animator.speed());
+    }
+
+    fn toggle_animation_playing(&mut self) {
+        let state = self.animation_state();
+        self.set_animation_state(AnimationState { playing: !state.playing, ..state });
+        println!("Animation {}", if state.playing { "paused" } else { "playing" });
+    }
+
+    fn toggle_animation_looping(&mut self) {
+        let state = self.animation_state();
+        self.set_animation_state(AnimationState { looping: !state.looping, ..state });
+        println!("Animation looping: {}", if state.looping { "off" } else { "on" });
+    }
+
+    /// Pauses and moves the animation time by `delta` seconds (clamped to the animation)
+    fn step_animation(&mut self, delta: f32) {
+        let state = self.animation_state();
+        let duration = match state.active {
+            Some(index) => self.animator.animations[index].duration,
+            None => return,
+        };
+        let time = (state.time + delta).max(0.0).min(duration);
+        self.set_animation_state(AnimationState { time, playing: false, ..state });
+        println!("Animation time: {:.3} / {:.3} s", time, duration);
+    }
+
+    /// Switch to the next / previous animation of the file, from the start
+    fn cycle_animation(&mut self, forward: bool) {
+        let count = self.animator.animations.len();
+        let state = self.animation_state();
+        let current = match state.active {
+            Some(index) if count > 1 => index,
+            _ => return,
+        };
+        let active = if forward { (current + 1) % count } else { (current + count - 1) % count };
+        self.set_animation_state(AnimationState { active: Some(active), time: 0.0, ..state });
+        let animation = &self.animator.animations[active];
+        println!("Animation {}/{}: {} ({:.2} s)", active + 1, count,
+            animation.name.as_deref().unwrap_or("unnamed"), animation.duration);
+    }
 }
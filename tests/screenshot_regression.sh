@@ -0,0 +1,48 @@
+#!/bin/bash
+# Renders sample models and compares them with reference screenshots (e.g. to catch sRGB/gamma
+# regressions: washed out or too dark colors). Requires ImageMagick's `compare`.
+# The references depend on the OpenGL driver, so they are created locally with --update; the
+# shader-independent part (transfer functions, PNG output) is covered by `cargo test` against
+# tests/reference/srgb_ramp.png.
+# NOTE: should be called from crate root! Uses xvfb like `run_xvfb.sh` (Linux).
+# Parameters: [--update] (re-create the references instead of comparing) [glTF-Sample-Models/2.0 directory]
+update=false
+if [[ "$1" == "--update" ]]; then
+    update=true
+    shift
+fi
+base_dir=${1:-../../glTF-Sample-Models/2.0}
+reference_dir=tests/reference
+# textured (sRGB base color, linear metallic-roughness/normal) and vertex-colored models
+models="BoxTextured BoxVertexColors MetalRoughSpheres"
+
+set -e
+result_dir=target/screenshots/regression
+mkdir -p "$result_dir" "$reference_dir"
+cargo build --release
+
+failed=0
+for model in $models; do
+    xvfb-run --auto-servernum --server-args="-screen 0 640x480x24" \
+        target/release/gltf-viewer "$base_dir/$model/glTF/$model.gltf" -w 320 -h 240 -s "$result_dir/$model.png"
+    if $update; then
+        cp "$result_dir/$model.png" "$reference_dir/$model.png"
+        echo "$model: updated reference"
+        continue
+    fi
+    if [[ ! -f "$reference_dir/$model.png" ]]; then
+        echo "$model: no reference screenshot (create them with --update)"
+        failed=1
+        continue
+    fi
+    # number of pixels differing by more than 2%
+    different=$(compare -metric AE -fuzz 2% "$reference_dir/$model.png" "$result_dir/$model.png" \
+        "$result_dir/$model-diff.png" 2>&1 || true)
+    if [[ "$different" == "0" ]]; then
+        echo "$model: ok"
+    else
+        echo "$model: $different pixels differ (see $result_dir/$model-diff.png)"
+        failed=1
+    fi
+done
+exit $failed